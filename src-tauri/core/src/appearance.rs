@@ -0,0 +1,461 @@
+//! Where and how the visible watermark is drawn on signed pages.
+
+use crate::error::Result;
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, Local, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// How the watermark writes the signing time unless told otherwise.
+pub const DEFAULT_TIMESTAMP_FORMAT: &str = "%Y-%m-%d %H:%M:%S %Z";
+
+/// Corner (or middle) of the page the watermark block is anchored to.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Position {
+    #[default]
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+    Center,
+}
+
+impl Position {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Position::TopLeft => "top-left",
+            Position::TopRight => "top-right",
+            Position::BottomLeft => "bottom-left",
+            Position::BottomRight => "bottom-right",
+            Position::Center => "center",
+        }
+    }
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Position {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "top-left" => Ok(Position::TopLeft),
+            "top-right" => Ok(Position::TopRight),
+            "bottom-left" => Ok(Position::BottomLeft),
+            "bottom-right" => Ok(Position::BottomRight),
+            "center" => Ok(Position::Center),
+            other => Err(format!(
+                "Unknown position '{}' (expected top-left, top-right, bottom-left, bottom-right or center)",
+                other
+            )),
+        }
+    }
+}
+
+/// Which pages get the watermark.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PageSelection {
+    #[default]
+    All,
+    First,
+    Last,
+    /// 1-based page numbers.
+    Pages(Vec<u32>),
+    /// 1-based page numbers and the last page, whatever its number.
+    PagesAndLast(Vec<u32>),
+}
+
+impl PageSelection {
+    /// Whether page `number` (1-based) of a `page_count`-page document is selected.
+    pub fn includes(&self, number: u32, page_count: u32) -> bool {
+        match self {
+            PageSelection::All => true,
+            PageSelection::First => number == 1,
+            PageSelection::Last => number == page_count,
+            PageSelection::Pages(pages) => pages.contains(&number),
+            PageSelection::PagesAndLast(pages) => number == page_count || pages.contains(&number),
+        }
+    }
+
+    /// The first listed page a `page_count`-page document does not have.
+    pub fn missing_page(&self, page_count: u32) -> Option<u32> {
+        match self {
+            PageSelection::Pages(pages) | PageSelection::PagesAndLast(pages) => {
+                pages.iter().copied().find(|&page| page > page_count)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for PageSelection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PageSelection::All => f.write_str("all"),
+            PageSelection::First => f.write_str("first"),
+            PageSelection::Last => f.write_str("last"),
+            PageSelection::Pages(pages) => {
+                let pages: Vec<String> = pages.iter().map(u32::to_string).collect();
+                f.write_str(&pages.join(","))
+            }
+            PageSelection::PagesAndLast(pages) => {
+                let mut pages: Vec<String> = pages.iter().map(u32::to_string).collect();
+                pages.push("last".to_string());
+                f.write_str(&pages.join(","))
+            }
+        }
+    }
+}
+
+/// `all`, `first`, `last`, or a list of pages and ranges such as `1,3-5`,
+/// in which `first` and `last` may also appear, as in `first,last`.
+impl FromStr for PageSelection {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.trim() {
+            "all" => return Ok(PageSelection::All),
+            "first" => return Ok(PageSelection::First),
+            "last" => return Ok(PageSelection::Last),
+            _ => {}
+        }
+        let invalid = || format!("Invalid page selection '{}' (expected all, first, last or a list like 1,3-5)", s);
+        let page = |p: &str| p.trim().parse::<u32>().ok().filter(|&p| p > 0).ok_or_else(invalid);
+
+        let mut pages = Vec::new();
+        let mut last = false;
+        for part in s.split(',') {
+            match part.trim() {
+                "first" => {
+                    pages.push(1);
+                    continue;
+                }
+                "last" => {
+                    last = true;
+                    continue;
+                }
+                _ => {}
+            }
+            match part.split_once('-') {
+                Some((start, end)) => {
+                    let (start, end) = (page(start)?, page(end)?);
+                    if start > end {
+                        return Err(invalid());
+                    }
+                    pages.extend(start..=end);
+                }
+                None => pages.push(page(part)?),
+            }
+        }
+        if last {
+            return Ok(PageSelection::PagesAndLast(pages));
+        }
+        Ok(PageSelection::Pages(pages))
+    }
+}
+
+/// RGB colour, written as `#rrggbb`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Color(pub [u8; 3]);
+
+impl Color {
+    /// The components scaled to 0–1, as content stream operators take them.
+    pub fn to_unit(self) -> [f32; 3] {
+        self.0.map(|c| f32::from(c) / 255.0)
+    }
+}
+
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [r, g, b] = self.0;
+        write!(f, "#{:02x}{:02x}{:02x}", r, g, b)
+    }
+}
+
+impl FromStr for Color {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let hex = s.trim().trim_start_matches('#');
+        let bytes = hex::decode(hex)
+            .ok()
+            .and_then(|bytes| <[u8; 3]>::try_from(bytes).ok())
+            .ok_or_else(|| format!("Invalid color '{}' (expected #rrggbb)", s))?;
+        Ok(Color(bytes))
+    }
+}
+
+impl TryFrom<String> for Color {
+    type Error = String;
+
+    fn try_from(s: String) -> std::result::Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<Color> for String {
+    fn from(color: Color) -> String {
+        color.to_string()
+    }
+}
+
+/// The time zone the watermark shows the signing time in, written as `utc`,
+/// `local` for the computer's own, or an IANA name such as `Europe/Paris`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum StampTimeZone {
+    #[default]
+    Utc,
+    Local,
+    Named(Tz),
+}
+
+impl fmt::Display for StampTimeZone {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StampTimeZone::Utc => f.write_str("utc"),
+            StampTimeZone::Local => f.write_str("local"),
+            StampTimeZone::Named(tz) => f.write_str(tz.name()),
+        }
+    }
+}
+
+impl FromStr for StampTimeZone {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.trim() {
+            zone if zone.eq_ignore_ascii_case("utc") => Ok(StampTimeZone::Utc),
+            zone if zone.eq_ignore_ascii_case("local") => Ok(StampTimeZone::Local),
+            zone => zone
+                .parse()
+                .map(StampTimeZone::Named)
+                .map_err(|_| format!("Unknown time zone '{}' (expected utc, local or a name such as Europe/Paris)", s)),
+        }
+    }
+}
+
+impl TryFrom<String> for StampTimeZone {
+    type Error = String;
+
+    fn try_from(s: String) -> std::result::Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<StampTimeZone> for String {
+    fn from(zone: StampTimeZone) -> String {
+        zone.to_string()
+    }
+}
+
+/// Checks that `format` is a strftime format chrono can write, such as
+/// `DEFAULT_TIMESTAMP_FORMAT`.
+pub fn check_timestamp_format(format: &str) -> Result<()> {
+    if format.trim().is_empty() {
+        return Err("The timestamp format is empty".into());
+    }
+    if StrftimeItems::new(format).any(|item| matches!(item, Item::Error)) {
+        return Err(format!("Invalid timestamp format '{}'", format).into());
+    }
+    Ok(())
+}
+
+/// `time` as the watermark shows it: in `zone`, written with `format`.
+pub fn format_timestamp(time: DateTime<Utc>, format: &str, zone: StampTimeZone) -> Result<String> {
+    check_timestamp_format(format)?;
+    Ok(match zone {
+        StampTimeZone::Utc => time.format(format).to_string(),
+        StampTimeZone::Local => time.with_timezone(&Local).format(format).to_string(),
+        StampTimeZone::Named(tz) => time.with_timezone(&tz).format(format).to_string(),
+    })
+}
+
+/// Which side of the watermark text a stamp image goes on.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum StampPlacement {
+    #[default]
+    Left,
+    Right,
+    Above,
+    Below,
+}
+
+impl StampPlacement {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            StampPlacement::Left => "left",
+            StampPlacement::Right => "right",
+            StampPlacement::Above => "above",
+            StampPlacement::Below => "below",
+        }
+    }
+}
+
+impl fmt::Display for StampPlacement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for StampPlacement {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "left" => Ok(StampPlacement::Left),
+            "right" => Ok(StampPlacement::Right),
+            "above" => Ok(StampPlacement::Above),
+            "below" => Ok(StampPlacement::Below),
+            other => Err(format!(
+                "Unknown stamp placement '{}' (expected left, right, above or below)",
+                other
+            )),
+        }
+    }
+}
+
+/// An image such as a company seal or a scanned handwritten signature, drawn
+/// next to the watermark text.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Stamp {
+    /// The image as a PNG file.
+    pub png: Vec<u8>,
+    /// Width on the page in points; the height keeps the image's proportions.
+    #[serde(default = "default_stamp_width")]
+    pub width: f32,
+    #[serde(default)]
+    pub placement: StampPlacement,
+}
+
+/// A rectangle picked on one page, such as by dragging in a viewer. The
+/// watermark is fitted into it, and a standard PDF signature's field covers
+/// it, instead of the block being anchored to a corner of every page.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SignaturePlacement {
+    /// 1-based page number.
+    pub page: u32,
+    /// `[x, y, width, height]` in points, measured from the top-left corner
+    /// of the page as it is displayed, that is with its rotation applied.
+    pub rect: [f32; 4],
+}
+
+impl SignaturePlacement {
+    pub fn validate(&self) -> Result<()> {
+        if self.page == 0 {
+            return Err("Page numbers start at 1".into());
+        }
+        let [x, y, width, height] = self.rect;
+        if ![x, y, width, height].iter().all(|n| n.is_finite()) || x < 0.0 || y < 0.0 {
+            return Err("The signature rectangle must start inside the page".into());
+        }
+        if !(width > 0.0 && height > 0.0) {
+            return Err("The signature rectangle must have a width and a height".into());
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for SignaturePlacement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [x, y, width, height] = self.rect;
+        write!(f, "{}:{},{},{},{}", self.page, x, y, width, height)
+    }
+}
+
+/// `PAGE:X,Y,WIDTH,HEIGHT`, such as `1:350,700,200,60`.
+impl FromStr for SignaturePlacement {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let invalid = || format!("Invalid placement '{}' (expected PAGE:X,Y,WIDTH,HEIGHT)", s);
+        let (page, rect) = s.split_once(':').ok_or_else(invalid)?;
+        let rect: Vec<f32> = rect
+            .split(',')
+            .map(|n| n.trim().parse().map_err(|_| invalid()))
+            .collect::<std::result::Result<_, _>>()?;
+        Ok(SignaturePlacement {
+            page: page.trim().parse().map_err(|_| invalid())?,
+            rect: rect.try_into().map_err(|_| invalid())?,
+        })
+    }
+}
+
+pub const DEFAULT_STAMP_WIDTH: f32 = 60.0;
+
+fn default_stamp_width() -> f32 {
+    DEFAULT_STAMP_WIDTH
+}
+
+/// How the watermark looks. The default is the original black 8pt block in
+/// the top-left corner of every page.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Appearance {
+    pub pages: PageSelection,
+    pub position: Position,
+    pub font_size: f32,
+    /// Degrees counter-clockwise, turning the block about the start of its
+    /// first line.
+    pub rotation: f32,
+    pub color: Color,
+    /// From 0 (invisible) to 1 (opaque).
+    pub opacity: f32,
+    pub stamp: Option<Stamp>,
+    /// Draws a QR code of the signature details to the right of the block, so
+    /// a printed copy can be checked by scanning it.
+    pub qr_code: bool,
+}
+
+impl Default for Appearance {
+    fn default() -> Self {
+        Appearance {
+            pages: PageSelection::All,
+            position: Position::TopLeft,
+            font_size: 8.0,
+            rotation: 0.0,
+            color: Color::default(),
+            opacity: 1.0,
+            stamp: None,
+            qr_code: false,
+        }
+    }
+}
+
+impl Appearance {
+    pub fn validate(&self) -> Result<()> {
+        if !(self.font_size > 0.0 && self.font_size <= 72.0) {
+            return Err("Font size must be between 0 and 72 points".into());
+        }
+        if !(0.0..=1.0).contains(&self.opacity) {
+            return Err("Opacity must be between 0 and 1".into());
+        }
+        if !self.rotation.is_finite() {
+            return Err("Rotation must be a number of degrees".into());
+        }
+        if matches!(&self.pages, PageSelection::Pages(pages) if pages.is_empty()) {
+            return Err("The page list is empty".into());
+        }
+        if let PageSelection::Pages(pages) | PageSelection::PagesAndLast(pages) = &self.pages {
+            if pages.contains(&0) {
+                return Err("Page numbers start at 1".into());
+            }
+        }
+        if let Some(stamp) = &self.stamp {
+            if !(stamp.width > 0.0 && stamp.width <= 600.0) {
+                return Err("Stamp width must be between 0 and 600 points".into());
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,147 @@
+use crate::api::{DetachedSignature, SignatureInfo};
+use crate::error::Result;
+use crate::keys::PublicKey;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs::{self, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+const AUDIT_LOG_FILE: &str = "audit.jsonl";
+
+/// One signing operation, as recorded in the audit log.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+    /// Name of the signed file, without its directory.
+    pub file_name: String,
+    /// SHA-256 of the file as it was before signing, as hex.
+    pub input_sha256: String,
+    /// SHA-256 of the signed PDF, or of the signature file for a detached
+    /// signature, as hex.
+    pub output_sha256: String,
+    /// Whether the signature went into a separate `.sig` file.
+    #[serde(default)]
+    pub detached: bool,
+    pub signer: String,
+    pub timestamp: String,
+    /// Fingerprint of the public key that made the signature.
+    pub key_fingerprint: String,
+}
+
+impl AuditEntry {
+    /// Records signing `input` into `output`; `file_name` may be a full path.
+    pub fn new(file_name: &str, input: &[u8], output: &[u8], info: &SignatureInfo) -> Result<AuditEntry> {
+        Ok(AuditEntry {
+            file_name: self::file_name(Path::new(file_name)),
+            input_sha256: hex::encode(Sha256::digest(input)),
+            output_sha256: hex::encode(Sha256::digest(output)),
+            detached: false,
+            signer: info.signer_name.clone(),
+            timestamp: info.timestamp.clone(),
+            key_fingerprint: key_fingerprint(info.public_key.as_deref())?,
+        })
+    }
+
+    /// Like `new`, hashing the input and output files on disk.
+    pub fn from_files(input: &Path, output: &Path, info: &SignatureInfo) -> Result<AuditEntry> {
+        Ok(AuditEntry {
+            file_name: file_name(input),
+            input_sha256: file_sha256(input)?,
+            output_sha256: file_sha256(output)?,
+            detached: false,
+            signer: info.signer_name.clone(),
+            timestamp: info.timestamp.clone(),
+            key_fingerprint: key_fingerprint(info.public_key.as_deref())?,
+        })
+    }
+
+    /// Records signing the file at `input` into the signature file at
+    /// `signature_path`.
+    pub fn detached(input: &Path, signature_path: &Path, signature: &DetachedSignature) -> Result<AuditEntry> {
+        Ok(AuditEntry {
+            file_name: file_name(input),
+            input_sha256: file_sha256(input)?,
+            output_sha256: file_sha256(signature_path)?,
+            detached: true,
+            signer: signature.signer.clone(),
+            timestamp: signature.timestamp.clone(),
+            key_fingerprint: key_fingerprint(signature.public_key.as_deref())?,
+        })
+    }
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map_or_else(|| path.to_string_lossy(), |name| name.to_string_lossy())
+        .into_owned()
+}
+
+fn file_sha256(path: &Path) -> Result<String> {
+    let mut file = fs::File::open(path).map_err(|e| format!("Read error: {}", e))?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher).map_err(|e| format!("Read error: {}", e))?;
+    Ok(hex::encode(hasher.finalize()))
+}
+
+fn key_fingerprint(public_key_pem: Option<&str>) -> Result<String> {
+    match public_key_pem {
+        Some(pem) => PublicKey::from_public_key_pem(pem)?.fingerprint(),
+        None => Ok(String::new()),
+    }
+}
+
+/// Every signing operation, one JSON object per line in `audit.jsonl` in the
+/// app data directory. Entries are only ever appended.
+pub struct AuditLog {
+    path: PathBuf,
+}
+
+impl AuditLog {
+    pub fn open(dir: &Path) -> AuditLog {
+        AuditLog {
+            path: dir.join(AUDIT_LOG_FILE),
+        }
+    }
+
+    pub fn record(&self, entry: &AuditEntry) -> Result<()> {
+        let mut line = serde_json::to_string(entry).map_err(|e| format!("JSON error: {}", e))?;
+        line.push('\n');
+        // One write per entry, so entries from parallel signers do not
+        // interleave.
+        Ok(OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut file| file.write_all(line.as_bytes()))
+            .map_err(|e| format!("Failed to write audit log: {}", e))?)
+    }
+
+    /// All entries, oldest first.
+    pub fn entries(&self) -> Result<Vec<AuditEntry>> {
+        if !self.path.exists() {
+            return Ok(Vec::new());
+        }
+        let file = fs::File::open(&self.path).map_err(|e| format!("Failed to read audit log: {}", e))?;
+        let mut entries = Vec::new();
+        for (index, line) in BufReader::new(file).lines().enumerate() {
+            let line = line.map_err(|e| format!("Failed to read audit log: {}", e))?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let entry = serde_json::from_str(&line)
+                .map_err(|e| format!("Invalid audit log entry on line {}: {}", index + 1, e))?;
+            entries.push(entry);
+        }
+        Ok(entries)
+    }
+
+    /// Copies the log, as it is on disk, to `destination`.
+    pub fn export(&self, destination: &Path) -> Result<()> {
+        if !self.path.exists() {
+            return Ok(fs::write(destination, "").map_err(|e| format!("Failed to export audit log: {}", e))?);
+        }
+        Ok(fs::copy(&self.path, destination)
+            .map(|_| ())
+            .map_err(|e| format!("Failed to export audit log: {}", e))?)
+    }
+}
@@ -0,0 +1,197 @@
+//! Passphrase-encrypted bundles of every key profile, the trusted keys and the
+//! settings, for moving a signing identity to another machine.
+
+use crate::api::KeyPair;
+use crate::error::Result;
+use crate::key_store::KeyStore;
+use crate::settings::Settings;
+use crate::trust_store::{TrustStore, TrustedKey};
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::{Aes256Gcm, Nonce};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use zeroize::Zeroizing;
+
+const BACKUP_FORMAT: &str = "sigillum-backup";
+const BACKUP_VERSION: u32 = 1;
+
+/// scrypt cost for new backups; the one used is stored in the file.
+const SCRYPT_LOG_N: u8 = 15;
+const SCRYPT_R: u32 = 8;
+const SCRYPT_P: u32 = 1;
+/// Highest cost a backup may ask for, so a crafted file cannot tie up the
+/// machine opening it.
+const MAX_SCRYPT_LOG_N: u8 = 20;
+
+/// The file as written: the encrypted contents and what is needed to derive
+/// the key that opens them.
+#[derive(Serialize, Deserialize)]
+struct BackupFile {
+    format: String,
+    version: u32,
+    scrypt_log_n: u8,
+    scrypt_r: u32,
+    scrypt_p: u32,
+    /// Base64 scrypt salt.
+    salt: String,
+    /// Base64 AES-256-GCM nonce.
+    nonce: String,
+    /// Base64 AES-256-GCM ciphertext of the JSON `BackupContents`.
+    ciphertext: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct BackupContents {
+    profiles: Vec<BackupProfile>,
+    active_profile: String,
+    trusted_keys: Vec<TrustedKey>,
+    tsa_url: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct BackupProfile {
+    name: String,
+    /// Stored as it is on disk, so a key with a passphrase keeps it.
+    keypair: KeyPair,
+}
+
+/// What `restore` brought back. Profiles and trusted keys that already exist
+/// here are left alone and listed as skipped.
+#[derive(Debug, Clone, Serialize)]
+pub struct RestoreSummary {
+    pub profiles: Vec<String>,
+    pub skipped_profiles: Vec<String>,
+    pub trusted_keys: Vec<String>,
+    pub skipped_trusted_keys: Vec<String>,
+}
+
+/// Bundles everything in the app data directory `dir` into a backup file
+/// encrypted with `passphrase`.
+pub fn backup(dir: &Path, passphrase: &str) -> Result<Vec<u8>> {
+    if passphrase.is_empty() {
+        return Err("A backup needs a passphrase".into());
+    }
+    let mut store = KeyStore::open(dir.to_path_buf())?;
+    let mut profiles = Vec::new();
+    for name in store.profiles().to_vec() {
+        store.select(&name)?;
+        profiles.push(BackupProfile {
+            keypair: store.load()?,
+            name,
+        });
+    }
+    let contents = BackupContents {
+        profiles,
+        active_profile: store.active_profile().to_string(),
+        trusted_keys: TrustStore::open(dir.to_path_buf())?.keys().to_vec(),
+        tsa_url: Settings::load(dir)?.tsa_url,
+    };
+    let plaintext = Zeroizing::new(serde_json::to_vec(&contents).map_err(|e| format!("JSON error: {}", e))?);
+
+    let mut salt = [0u8; 16];
+    let mut nonce = [0u8; 12];
+    rand::thread_rng().fill_bytes(&mut salt);
+    rand::thread_rng().fill_bytes(&mut nonce);
+    let cipher = cipher(passphrase, &salt, SCRYPT_LOG_N, SCRYPT_R, SCRYPT_P)?;
+    let ciphertext = cipher
+        .encrypt(Nonce::from_slice(&nonce), plaintext.as_slice())
+        .map_err(|_| "Failed to encrypt backup".to_string())?;
+
+    let file = BackupFile {
+        format: BACKUP_FORMAT.to_string(),
+        version: BACKUP_VERSION,
+        scrypt_log_n: SCRYPT_LOG_N,
+        scrypt_r: SCRYPT_R,
+        scrypt_p: SCRYPT_P,
+        salt: BASE64.encode(salt),
+        nonce: BASE64.encode(nonce),
+        ciphertext: BASE64.encode(ciphertext),
+    };
+    Ok(serde_json::to_vec_pretty(&file).map_err(|e| format!("JSON error: {}", e))?)
+}
+
+/// Restores a backup made by `backup` into the app data directory `dir`.
+/// Keys go into whichever storage `dir` is set up to use.
+pub fn restore(dir: &Path, data: &[u8], passphrase: &str) -> Result<RestoreSummary> {
+    let file: BackupFile = serde_json::from_slice(data).map_err(|_| "Not a Sigillum backup".to_string())?;
+    if file.format != BACKUP_FORMAT {
+        return Err("Not a Sigillum backup".into());
+    }
+    if file.version > BACKUP_VERSION {
+        return Err(format!("Backup version {} is newer than this version of Sigillum supports", file.version).into());
+    }
+    if file.scrypt_log_n > MAX_SCRYPT_LOG_N || file.scrypt_r > SCRYPT_R || file.scrypt_p > SCRYPT_P {
+        return Err("Corrupt backup: key derivation cost is too high".into());
+    }
+    let decode = |field: &str| BASE64.decode(field).map_err(|e| format!("Corrupt backup: {}", e));
+    let nonce = decode(&file.nonce)?;
+    if nonce.len() != 12 {
+        return Err("Corrupt backup: bad nonce".into());
+    }
+    let cipher = cipher(passphrase, &decode(&file.salt)?, file.scrypt_log_n, file.scrypt_r, file.scrypt_p)?;
+    // GCM authenticates the contents, so a wrong passphrase and a tampered
+    // file fail the same way.
+    let plaintext = cipher
+        .decrypt(Nonce::from_slice(&nonce), decode(&file.ciphertext)?.as_slice())
+        .map(Zeroizing::new)
+        .map_err(|_| "Wrong passphrase, or the backup is damaged".to_string())?;
+    let contents: BackupContents =
+        serde_json::from_slice(&plaintext).map_err(|e| format!("Corrupt backup: {}", e))?;
+
+    let mut summary = RestoreSummary {
+        profiles: Vec::new(),
+        skipped_profiles: Vec::new(),
+        trusted_keys: Vec::new(),
+        skipped_trusted_keys: Vec::new(),
+    };
+
+    let mut store = KeyStore::open(dir.to_path_buf())?;
+    let had_profiles = !store.profiles().is_empty();
+    for profile in &contents.profiles {
+        if store.profiles().contains(&profile.name) {
+            summary.skipped_profiles.push(profile.name.clone());
+            continue;
+        }
+        store.create_profile(&profile.name, &profile.keypair)?;
+        summary.profiles.push(profile.name.clone());
+    }
+    // Only take over the backup's active profile on a machine that had none.
+    if !had_profiles && summary.profiles.contains(&contents.active_profile) {
+        store.set_active_profile(&contents.active_profile)?;
+    }
+
+    let mut trust_store = TrustStore::open(dir.to_path_buf())?;
+    for key in &contents.trusted_keys {
+        let known = trust_store
+            .keys()
+            .iter()
+            .any(|k| k.name == key.name || k.public_key == key.public_key);
+        if known {
+            summary.skipped_trusted_keys.push(key.name.clone());
+            continue;
+        }
+        trust_store.add(&key.name, &key.public_key)?;
+        if let Some(revocation) = &key.revoked {
+            trust_store.revoke(&key.name, &revocation.reason, Some(revocation.date))?;
+        }
+        summary.trusted_keys.push(key.name.clone());
+    }
+
+    let mut settings = Settings::load(dir)?;
+    if settings.tsa_url.is_none() && contents.tsa_url.is_some() {
+        settings.tsa_url = contents.tsa_url;
+        settings.save(dir)?;
+    }
+
+    Ok(summary)
+}
+
+fn cipher(passphrase: &str, salt: &[u8], log_n: u8, r: u32, p: u32) -> Result<Aes256Gcm> {
+    let params = scrypt::Params::new(log_n, r, p, 32).map_err(|e| format!("Corrupt backup: {}", e))?;
+    let mut key = Zeroizing::new([0u8; 32]);
+    scrypt::scrypt(passphrase.as_bytes(), salt, &params, key.as_mut())
+        .map_err(|e| format!("Failed to derive backup key: {}", e))?;
+    Ok(Aes256Gcm::new_from_slice(key.as_ref()).map_err(|e| format!("Failed to derive backup key: {}", e))?)
+}
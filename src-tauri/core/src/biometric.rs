@@ -0,0 +1,345 @@
+//! Unlocking a passphrase-protected key with Touch ID or Windows Hello. The
+//! key's passphrase is kept where only the platform's biometric check can
+//! release it, so another program running as the user cannot read it:
+//!
+//! - on macOS, in a Keychain item whose access control asks for Touch ID
+//!   with the fingers enrolled when it was saved;
+//! - on Windows, encrypted with a key derived from a Windows Hello
+//!   signature, which Hello only makes once it has confirmed the user.
+
+use crate::error::Result;
+use zeroize::Zeroizing;
+
+/// Name the passphrase of `profile`'s key is kept under. Profile names
+/// cannot contain '.', so it never clashes with a key kept in the keychain.
+#[cfg(any(target_os = "macos", windows))]
+fn entry_name(profile: &str) -> String {
+    format!("{}.passphrase", profile)
+}
+
+#[cfg(any(target_os = "macos", windows))]
+fn not_kept(profile: &str) -> String {
+    format!("No passphrase is kept for profile '{}'", profile)
+}
+
+/// Whether this device can confirm the user with Touch ID or Windows Hello.
+pub fn is_available() -> bool {
+    platform::is_available()
+}
+
+/// Shows the platform's biometric prompt, with `reason` saying what it is
+/// for, and waits until the user is confirmed or it fails.
+pub fn verify(reason: &str) -> Result<()> {
+    platform::verify(reason)
+}
+
+/// Keeps `passphrase` of `profile`'s key for `unlock_passphrase`, replacing
+/// any kept before.
+pub fn save_passphrase(profile: &str, passphrase: &str) -> Result<()> {
+    platform::save_passphrase(profile, passphrase)
+}
+
+/// Whether a passphrase for `profile`'s key is kept. Asks for nothing.
+pub fn has_passphrase(profile: &str) -> bool {
+    platform::has_passphrase(profile)
+}
+
+/// Gives the passphrase kept for `profile`'s key, once the platform's
+/// biometric prompt has confirmed the user.
+pub fn unlock_passphrase(profile: &str) -> Result<Zeroizing<String>> {
+    platform::unlock_passphrase(profile)
+}
+
+/// Removes the passphrase kept for `profile`'s key, if any.
+pub fn forget_passphrase(profile: &str) -> Result<()> {
+    platform::forget_passphrase(profile)
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use crate::error::{Result, SigillumError};
+    use crate::key_store::KEYCHAIN_SERVICE;
+    use block2::RcBlock;
+    use objc2::rc::Retained;
+    use objc2::runtime::{AnyObject, Bool};
+    use objc2::{class, msg_send};
+    use objc2_foundation::NSString;
+    use security_framework::access_control::{ProtectionMode, SecAccessControl};
+    use security_framework::item::{ItemClass, ItemSearchOptions};
+    use security_framework::passwords::{self, AccessControlOptions, PasswordOptions};
+    use std::sync::mpsc;
+    use zeroize::Zeroizing;
+
+    /// `LAPolicyDeviceOwnerAuthenticationWithBiometrics`.
+    const POLICY_BIOMETRICS: isize = 1;
+    /// `errSecItemNotFound`.
+    const ITEM_NOT_FOUND: i32 = -25300;
+    /// `errSecUserCanceled`.
+    const USER_CANCELED: i32 = -128;
+
+    #[link(name = "LocalAuthentication", kind = "framework")]
+    extern "C" {}
+
+    fn context() -> Retained<AnyObject> {
+        unsafe { msg_send![class!(LAContext), new] }
+    }
+
+    pub fn is_available() -> bool {
+        let context = context();
+        let available: Bool = unsafe {
+            msg_send![&*context, canEvaluatePolicy: POLICY_BIOMETRICS, error: std::ptr::null_mut::<*mut AnyObject>()]
+        };
+        available.as_bool()
+    }
+
+    pub fn verify(reason: &str) -> Result<()> {
+        if !is_available() {
+            return Err("Touch ID is not available on this Mac".into());
+        }
+        let context = context();
+        let reason = NSString::from_str(reason);
+        // The reply comes on a queue of LocalAuthentication's own.
+        let (sender, receiver) = mpsc::channel();
+        let reply = RcBlock::new(move |success: Bool, _error: *mut AnyObject| {
+            let _ = sender.send(success.as_bool());
+        });
+        unsafe {
+            let _: () = msg_send![&*context, evaluatePolicy: POLICY_BIOMETRICS, localizedReason: &*reason, reply: &*reply];
+        }
+        match receiver.recv() {
+            Ok(true) => Ok(()),
+            Ok(false) => Err("Touch ID did not confirm it is you".into()),
+            Err(_) => Err("Touch ID gave no answer".into()),
+        }
+    }
+
+    /// The passphrase item, in the data protection keychain, which is the
+    /// one that enforces access control.
+    fn options(profile: &str) -> PasswordOptions {
+        let mut options = PasswordOptions::new_generic_password(KEYCHAIN_SERVICE, &super::entry_name(profile));
+        options.use_protected_keychain();
+        options
+    }
+
+    pub fn save_passphrase(profile: &str, passphrase: &str) -> Result<()> {
+        // An item's access control cannot be changed, only set on a new one.
+        forget_passphrase(profile)?;
+        // Enrolling another finger, or removing the passcode, makes the item
+        // unreadable rather than letting someone else in.
+        let access = SecAccessControl::create_with_protection(
+            Some(ProtectionMode::AccessibleWhenPasscodeSetThisDeviceOnly),
+            AccessControlOptions::BIOMETRY_CURRENT_SET.bits(),
+        )
+        .map_err(|e| format!("Failed to set up the passphrase's access control: {}", e))?;
+        let mut options = options(profile);
+        options.set_access_control(access);
+        Ok(passwords::set_generic_password_options(passphrase.as_bytes(), options)
+            .map_err(|e| format!("Failed to keep the passphrase in the Keychain: {}", e))?)
+    }
+
+    /// Looks at the item's attributes only, which Touch ID does not guard.
+    pub fn has_passphrase(profile: &str) -> bool {
+        ItemSearchOptions::new()
+            .class(ItemClass::generic_password())
+            .service(KEYCHAIN_SERVICE)
+            .account(&super::entry_name(profile))
+            .ignore_legacy_keychains()
+            .load_attributes(true)
+            .search()
+            .is_ok_and(|results| !results.is_empty())
+    }
+
+    /// Reading the item is what shows the Touch ID prompt.
+    pub fn unlock_passphrase(profile: &str) -> Result<Zeroizing<String>> {
+        match passwords::generic_password(options(profile)) {
+            Ok(passphrase) => String::from_utf8(passphrase)
+                .map(Zeroizing::new)
+                .map_err(|_| "The kept passphrase is corrupt".into()),
+            Err(e) if e.code() == ITEM_NOT_FOUND => Err(super::not_kept(profile).into()),
+            Err(e) if e.code() == USER_CANCELED => Err(SigillumError::Cancelled("Touch ID was cancelled".to_string())),
+            Err(e) => Err(format!("Touch ID did not release the passphrase: {}", e).into()),
+        }
+    }
+
+    pub fn forget_passphrase(profile: &str) -> Result<()> {
+        match passwords::delete_generic_password_options(options(profile)) {
+            Ok(()) => Ok(()),
+            Err(e) if e.code() == ITEM_NOT_FOUND => Ok(()),
+            Err(e) => Err(format!("Keychain error: {}", e).into()),
+        }
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use crate::error::{Result, SigillumError};
+    use crate::key_store::{keychain_entry, keychain_error};
+    use aes_gcm::aead::{Aead, KeyInit};
+    use aes_gcm::{Aes256Gcm, Nonce};
+    use rand::RngCore;
+    use sha2::{Digest, Sha256};
+    use windows::core::{Array, HSTRING};
+    use windows::Security::Credentials::UI::{UserConsentVerificationResult, UserConsentVerifier, UserConsentVerifierAvailability};
+    use windows::Security::Credentials::{KeyCredential, KeyCredentialCreationOption, KeyCredentialManager, KeyCredentialStatus};
+    use windows::Security::Cryptography::CryptographicBuffer;
+    use zeroize::Zeroizing;
+
+    /// Random bytes Hello signs to give the key the passphrase is encrypted
+    /// with; kept with the ciphertext.
+    const CHALLENGE_LENGTH: usize = 32;
+    const NONCE_LENGTH: usize = 12;
+    const CORRUPT: &str = "The kept passphrase is corrupt";
+
+    pub fn is_available() -> bool {
+        UserConsentVerifier::CheckAvailabilityAsync()
+            .and_then(|availability| availability.get())
+            .is_ok_and(|availability| availability == UserConsentVerifierAvailability::Available)
+    }
+
+    pub fn verify(reason: &str) -> Result<()> {
+        if !is_available() {
+            return Err("Windows Hello is not set up on this device".into());
+        }
+        let result = UserConsentVerifier::RequestVerificationAsync(&HSTRING::from(reason))
+            .and_then(|verification| verification.get())
+            .map_err(|e| format!("Windows Hello failed: {}", e))?;
+        match result {
+            UserConsentVerificationResult::Verified => Ok(()),
+            UserConsentVerificationResult::Canceled => Err(SigillumError::Cancelled("Windows Hello was cancelled".to_string())),
+            _ => Err("Windows Hello did not confirm it is you".into()),
+        }
+    }
+
+    /// The Windows Hello key of `profile`, which is only ever used to wrap
+    /// its passphrase.
+    fn credential_name(profile: &str) -> HSTRING {
+        HSTRING::from(format!("Sigillum {}", profile))
+    }
+
+    fn hello_error(e: windows::core::Error) -> SigillumError {
+        SigillumError::Other(format!("Windows Hello failed: {}", e))
+    }
+
+    fn check(status: KeyCredentialStatus) -> Result<()> {
+        match status {
+            KeyCredentialStatus::Success => Ok(()),
+            KeyCredentialStatus::UserCanceled => Err(SigillumError::Cancelled("Windows Hello was cancelled".to_string())),
+            KeyCredentialStatus::NotFound => Err("Windows Hello no longer has the key the passphrase was kept with".into()),
+            _ => Err("Windows Hello did not confirm it is you".into()),
+        }
+    }
+
+    /// Has Hello sign `challenge`, which it does only once the user is
+    /// confirmed, and hashes the signature into an AES key. Hello keys are
+    /// RSA keys signing with PKCS#1 v1.5, so the same challenge always gives
+    /// the same key.
+    fn wrapping_key(credential: &KeyCredential, challenge: &[u8]) -> Result<Aes256Gcm> {
+        let challenge = CryptographicBuffer::CreateFromByteArray(challenge).map_err(hello_error)?;
+        let result = credential
+            .RequestSignAsync(&challenge)
+            .and_then(|operation| operation.get())
+            .map_err(hello_error)?;
+        check(result.Status().map_err(hello_error)?)?;
+        let mut signature = Array::<u8>::new();
+        CryptographicBuffer::CopyToByteArray(&result.Result().map_err(hello_error)?, &mut signature).map_err(hello_error)?;
+        let mut key = Zeroizing::new([0u8; 32]);
+        key.copy_from_slice(&Sha256::digest(&signature[..]));
+        Ok(Aes256Gcm::new_from_slice(key.as_ref()).map_err(|e| format!("Failed to derive key: {}", e))?)
+    }
+
+    /// Creates a new Hello key for `profile`, confirming the user, and keeps
+    /// the challenge, nonce and encrypted passphrase in the credential
+    /// manager, where they are of no use without that key.
+    pub fn save_passphrase(profile: &str, passphrase: &str) -> Result<()> {
+        let result = KeyCredentialManager::RequestCreateAsync(&credential_name(profile), KeyCredentialCreationOption::ReplaceExisting)
+            .and_then(|operation| operation.get())
+            .map_err(hello_error)?;
+        check(result.Status().map_err(hello_error)?)?;
+        let credential = result.Credential().map_err(hello_error)?;
+
+        let mut challenge = [0u8; CHALLENGE_LENGTH];
+        let mut nonce = [0u8; NONCE_LENGTH];
+        rand::thread_rng().fill_bytes(&mut challenge);
+        rand::thread_rng().fill_bytes(&mut nonce);
+        let ciphertext = wrapping_key(&credential, &challenge)?
+            .encrypt(Nonce::from_slice(&nonce), passphrase.as_bytes())
+            .map_err(|_| "Failed to encrypt the passphrase".to_string())?;
+
+        let mut data = Vec::with_capacity(CHALLENGE_LENGTH + NONCE_LENGTH + ciphertext.len());
+        data.extend(challenge);
+        data.extend(nonce);
+        data.extend(ciphertext);
+        keychain_entry(&super::entry_name(profile))?
+            .set_secret(&data)
+            .map_err(keychain_error)
+    }
+
+    pub fn has_passphrase(profile: &str) -> bool {
+        keychain_entry(&super::entry_name(profile)).is_ok_and(|entry| entry.get_secret().is_ok())
+    }
+
+    pub fn unlock_passphrase(profile: &str) -> Result<Zeroizing<String>> {
+        let data = match keychain_entry(&super::entry_name(profile))?.get_secret() {
+            Ok(data) => data,
+            Err(keyring::Error::NoEntry) => return Err(super::not_kept(profile).into()),
+            Err(e) => return Err(keychain_error(e)),
+        };
+        if data.len() < CHALLENGE_LENGTH + NONCE_LENGTH {
+            return Err(CORRUPT.into());
+        }
+        let (challenge, rest) = data.split_at(CHALLENGE_LENGTH);
+        let (nonce, ciphertext) = rest.split_at(NONCE_LENGTH);
+
+        let result = KeyCredentialManager::OpenAsync(&credential_name(profile))
+            .and_then(|operation| operation.get())
+            .map_err(hello_error)?;
+        check(result.Status().map_err(hello_error)?)?;
+        let passphrase = wrapping_key(&result.Credential().map_err(hello_error)?, challenge)?
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map(Zeroizing::new)
+            .map_err(|_| "Windows Hello's key no longer decrypts the kept passphrase".to_string())?;
+        Ok(String::from_utf8(passphrase.to_vec()).map(Zeroizing::new).map_err(|_| CORRUPT)?)
+    }
+
+    pub fn forget_passphrase(profile: &str) -> Result<()> {
+        match keychain_entry(&super::entry_name(profile))?.delete_credential() {
+            Ok(()) | Err(keyring::Error::NoEntry) => {}
+            Err(e) => return Err(keychain_error(e)),
+        }
+        // Fails when there is no such key, which is all right here.
+        let _ = KeyCredentialManager::DeleteAsync(&credential_name(profile)).and_then(|action| action.get());
+        Ok(())
+    }
+}
+
+#[cfg(not(any(target_os = "macos", windows)))]
+mod platform {
+    use crate::error::Result;
+    use zeroize::Zeroizing;
+
+    const UNAVAILABLE: &str = "Biometric unlock needs Touch ID or Windows Hello, which this platform lacks";
+
+    pub fn is_available() -> bool {
+        false
+    }
+
+    pub fn verify(_reason: &str) -> Result<()> {
+        Err(UNAVAILABLE.into())
+    }
+
+    pub fn save_passphrase(_profile: &str, _passphrase: &str) -> Result<()> {
+        Err(UNAVAILABLE.into())
+    }
+
+    pub fn has_passphrase(_profile: &str) -> bool {
+        false
+    }
+
+    pub fn unlock_passphrase(_profile: &str) -> Result<Zeroizing<String>> {
+        Err(UNAVAILABLE.into())
+    }
+
+    pub fn forget_passphrase(_profile: &str) -> Result<()> {
+        Ok(())
+    }
+}
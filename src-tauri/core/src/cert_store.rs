@@ -0,0 +1,286 @@
+//! Certificates in the Windows user certificate store ("Personal"), whose
+//! private keys sign through CNG wherever Windows keeps them: its software
+//! key store, the TPM or a smartcard enrolled by the organisation. CNG is
+//! only handed digests, so no key is exported; a key profile records the
+//! certificate's thumbprint, as a `KmsKey::CertStore`.
+//!
+//! RSA keys sign with PKCS#1 v1.5 and P-256 keys with ECDSA over the digest
+//! bytes, as file keys of the same type do. A smartcard may show its own PIN
+//! prompt the first time its key signs.
+
+use crate::certificate::{self, CertificateInfo};
+use crate::error::Result;
+use crate::kms::RemoteSigner;
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+use x509_cert::Certificate;
+
+/// A certificate in the store with a private key to sign with.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoreCertificate {
+    /// SHA-1 of the DER certificate in upper-case hex, which Windows shows
+    /// as the certificate's thumbprint.
+    pub thumbprint: String,
+    pub certificate: CertificateInfo,
+}
+
+/// The certificates in the user's Personal store that have a private key,
+/// which are the ones that can sign.
+pub fn certificates() -> Result<Vec<StoreCertificate>> {
+    platform::signing_certificates()?
+        .iter()
+        .map(|der| {
+            Ok(StoreCertificate {
+                thumbprint: thumbprint(der),
+                certificate: certificate::info(&certificate::from_der(der)?)?,
+            })
+        })
+        .collect()
+}
+
+/// The certificate with `thumbprint`, as given by [`parse_thumbprint`].
+pub fn certificate(thumbprint: &str) -> Result<Certificate> {
+    let der = platform::signing_certificates()?
+        .into_iter()
+        .find(|der| self::thumbprint(der) == thumbprint)
+        .ok_or_else(|| not_found(thumbprint))?;
+    certificate::from_der(&der)
+}
+
+/// Opens the private key of the certificate with `thumbprint`, as given by
+/// [`parse_thumbprint`], for signing.
+pub fn open(thumbprint: &str) -> Result<Box<dyn RemoteSigner>> {
+    platform::open(thumbprint)
+}
+
+/// A thumbprint as Windows shows it, with or without spaces or colons, in
+/// the form [`StoreCertificate::thumbprint`] has.
+pub fn parse_thumbprint(s: &str) -> Result<String> {
+    let thumbprint: String = s.chars().filter(|c| !c.is_whitespace() && *c != ':').collect();
+    if thumbprint.len() != 40 || !thumbprint.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err("Give the certificate by its thumbprint, 40 hex digits".into());
+    }
+    Ok(thumbprint.to_ascii_uppercase())
+}
+
+fn thumbprint(der: &[u8]) -> String {
+    hex::encode_upper(Sha1::digest(der))
+}
+
+fn not_found(thumbprint: &str) -> String {
+    format!("No certificate with thumbprint {} and a private key in your certificate store", thumbprint)
+}
+
+#[cfg(windows)]
+mod platform {
+    use crate::certificate;
+    use crate::error::Result;
+    use crate::hash::HashAlgorithm;
+    use crate::keys::PublicKey;
+    use crate::kms::RemoteSigner;
+    use std::ffi::c_void;
+    use windows::core::{w, PCWSTR};
+    use windows::Win32::Foundation::BOOL;
+    use windows::Win32::Security::Cryptography::{
+        CertCloseStore, CertDuplicateCertificateContext, CertEnumCertificatesInStore, CertFreeCertificateContext, CertGetCertificateContextProperty, CertOpenStore,
+        CryptAcquireCertificatePrivateKey, NCryptFreeObject, NCryptSignHash, BCRYPT_PKCS1_PADDING_INFO, BCRYPT_SHA256_ALGORITHM,
+        BCRYPT_SHA384_ALGORITHM, BCRYPT_SHA512_ALGORITHM, CERT_CONTEXT, CERT_KEY_PROV_INFO_PROP_ID, CERT_OPEN_STORE_FLAGS,
+        CERT_QUERY_ENCODING_TYPE, CERT_STORE_OPEN_EXISTING_FLAG, CERT_STORE_PROV_SYSTEM_W, CERT_STORE_READONLY_FLAG,
+        CERT_SYSTEM_STORE_CURRENT_USER, CRYPT_ACQUIRE_ONLY_NCRYPT_KEY_FLAG, HCERTSTORE, HCRYPTPROV_LEGACY, HCRYPTPROV_OR_NCRYPT_KEY_HANDLE,
+        NCRYPT_FLAGS, NCRYPT_KEY_HANDLE, NCRYPT_PAD_PKCS1_FLAG,
+    };
+
+    /// The current user's Personal store, open for reading.
+    struct Store(HCERTSTORE);
+
+    impl Store {
+        fn open() -> Result<Store> {
+            let flags = CERT_OPEN_STORE_FLAGS(CERT_SYSTEM_STORE_CURRENT_USER) | CERT_STORE_OPEN_EXISTING_FLAG | CERT_STORE_READONLY_FLAG;
+            let store = unsafe {
+                CertOpenStore(
+                    CERT_STORE_PROV_SYSTEM_W,
+                    CERT_QUERY_ENCODING_TYPE(0),
+                    HCRYPTPROV_LEGACY(0),
+                    flags,
+                    Some(w!("MY").as_ptr().cast()),
+                )
+            }
+            .map_err(|e| format!("Failed to open your certificate store: {}", e))?;
+            Ok(Store(store))
+        }
+
+        /// Calls `f` with each certificate that has a private key, and its
+        /// DER, until `f` gives something back.
+        fn find_map<T>(&self, mut f: impl FnMut(&CERT_CONTEXT, &[u8]) -> Option<T>) -> Option<T> {
+            let mut context: *mut CERT_CONTEXT = std::ptr::null_mut();
+            loop {
+                // Each call frees the context it is given.
+                let previous = (!context.is_null()).then_some(context as *const CERT_CONTEXT);
+                context = unsafe { CertEnumCertificatesInStore(self.0, previous) };
+                let Some(certificate) = (unsafe { context.as_ref() }) else {
+                    return None;
+                };
+                if !has_private_key(certificate) {
+                    continue;
+                }
+                let der = unsafe { std::slice::from_raw_parts(certificate.pbCertEncoded, certificate.cbCertEncoded as usize) };
+                if let Some(found) = f(certificate, der) {
+                    unsafe {
+                        let _ = CertFreeCertificateContext(Some(context.cast_const()));
+                    }
+                    return Some(found);
+                }
+            }
+        }
+    }
+
+    impl Drop for Store {
+        fn drop(&mut self) {
+            unsafe {
+                let _ = CertCloseStore(self.0, 0);
+            }
+        }
+    }
+
+    fn has_private_key(certificate: &CERT_CONTEXT) -> bool {
+        let mut size = 0;
+        unsafe { CertGetCertificateContextProperty(certificate, CERT_KEY_PROV_INFO_PROP_ID, None, &mut size) }.is_ok()
+    }
+
+    pub fn signing_certificates() -> Result<Vec<Vec<u8>>> {
+        let mut certificates = Vec::new();
+        Store::open()?.find_map(|_, der| {
+            certificates.push(der.to_vec());
+            None::<()>
+        });
+        Ok(certificates)
+    }
+
+    pub fn open(thumbprint: &str) -> Result<Box<dyn RemoteSigner>> {
+        let store = Store::open()?;
+        let certificate = store
+            .find_map(|certificate, der| (super::thumbprint(der) == thumbprint).then(|| Context::duplicate(certificate)))
+            .ok_or_else(|| super::not_found(thumbprint))?;
+        Ok(Box::new(CngSigner::open(certificate, store)?))
+    }
+
+    /// A certificate context of our own, freed when dropped.
+    struct Context(*mut CERT_CONTEXT);
+
+    impl Context {
+        fn duplicate(certificate: &CERT_CONTEXT) -> Context {
+            Context(unsafe { CertDuplicateCertificateContext(Some(certificate as *const CERT_CONTEXT)) })
+        }
+
+        fn der(&self) -> &[u8] {
+            let certificate = unsafe { &*self.0 };
+            unsafe { std::slice::from_raw_parts(certificate.pbCertEncoded, certificate.cbCertEncoded as usize) }
+        }
+    }
+
+    impl Drop for Context {
+        fn drop(&mut self) {
+            unsafe {
+                let _ = CertFreeCertificateContext(Some(self.0.cast_const()));
+            }
+        }
+    }
+
+    struct CngSigner {
+        key: NCRYPT_KEY_HANDLE,
+        /// Whether the handle is ours to free, rather than cached with the
+        /// certificate.
+        owned: bool,
+        public_key: PublicKey,
+        /// A cached handle belongs to the certificate, so it and the store it
+        /// came from are held for as long as the signer; fields drop in
+        /// order, after the handle is freed.
+        _certificate: Context,
+        _store: Store,
+    }
+
+    // SAFETY: CNG key handles and certificate contexts may be used from any
+    // thread, and signing does not change the signer.
+    unsafe impl Send for CngSigner {}
+    unsafe impl Sync for CngSigner {}
+
+    impl CngSigner {
+        fn open(certificate: Context, store: Store) -> Result<CngSigner> {
+            let public_key = certificate::public_key(&certificate::from_der(certificate.der())?)?;
+            let mut key = HCRYPTPROV_OR_NCRYPT_KEY_HANDLE(0);
+            let mut owned = BOOL::default();
+            unsafe { CryptAcquireCertificatePrivateKey(certificate.0, CRYPT_ACQUIRE_ONLY_NCRYPT_KEY_FLAG, None, &mut key, None, Some(&mut owned)) }
+                .map_err(|e| format!("Failed to open the certificate's private key: {}", e))?;
+            Ok(CngSigner {
+                key: NCRYPT_KEY_HANDLE(key.0),
+                owned: owned.as_bool(),
+                public_key,
+                _certificate: certificate,
+                _store: store,
+            })
+        }
+    }
+
+    impl Drop for CngSigner {
+        fn drop(&mut self) {
+            // A handle that is not ours goes with the certificate.
+            if self.owned {
+                unsafe {
+                    let _ = NCryptFreeObject(self.key);
+                }
+            }
+        }
+    }
+
+    impl RemoteSigner for CngSigner {
+        fn public_key(&self) -> &PublicKey {
+            &self.public_key
+        }
+
+        fn sign_digest(&self, hash: HashAlgorithm, digest: &[u8]) -> Result<Vec<u8>> {
+            let (padding, flags, digest) = match &self.public_key {
+                PublicKey::Rsa(_) => {
+                    let algorithm = match hash {
+                        HashAlgorithm::Sha256 => BCRYPT_SHA256_ALGORITHM,
+                        HashAlgorithm::Sha384 => BCRYPT_SHA384_ALGORITHM,
+                        HashAlgorithm::Sha512 => BCRYPT_SHA512_ALGORITHM,
+                        // PKCS#1 has no identifier for BLAKE3, so none is
+                        // added.
+                        HashAlgorithm::Blake3 => PCWSTR::null(),
+                    };
+                    (Some(BCRYPT_PKCS1_PADDING_INFO { pszAlgId: algorithm }), NCRYPT_PAD_PKCS1_FLAG, digest)
+                }
+                // CNG gives r || s, as local keys do; digests longer than the
+                // curve are cut to its size, as local keys cut them.
+                PublicKey::P256(_) => (None, NCRYPT_FLAGS(0), &digest[..digest.len().min(32)]),
+                PublicKey::Ed25519(_) => return Err("Windows cannot sign with Ed25519 keys".into()),
+            };
+            let padding = padding.as_ref().map(|padding| padding as *const BCRYPT_PKCS1_PADDING_INFO as *const c_void);
+            let sign_error = |e: windows::core::Error| format!("Failed to sign document: {}", e);
+
+            // Asked first for the signature's size, then for the signature.
+            let mut size = 0;
+            unsafe { NCryptSignHash(self.key, padding, digest, None, &mut size, flags) }.map_err(sign_error)?;
+            let mut signature = vec![0; size as usize];
+            unsafe { NCryptSignHash(self.key, padding, digest, Some(&mut signature), &mut size, flags) }.map_err(sign_error)?;
+            signature.truncate(size as usize);
+            Ok(signature)
+        }
+    }
+}
+
+#[cfg(not(windows))]
+mod platform {
+    use crate::error::Result;
+    use crate::kms::RemoteSigner;
+
+    const UNAVAILABLE: &str = "The Windows certificate store can only be used on Windows";
+
+    pub fn signing_certificates() -> Result<Vec<Vec<u8>>> {
+        Err(UNAVAILABLE.into())
+    }
+
+    pub fn open(_thumbprint: &str) -> Result<Box<dyn RemoteSigner>> {
+        Err(UNAVAILABLE.into())
+    }
+}
@@ -0,0 +1,312 @@
+//! Self-signed X.509 certificates that say who owns a keypair. They travel
+//! inside signed documents so recipients can see who signed without having to
+//! exchange keys first; nothing vouches for them beyond the key itself.
+
+use crate::error::Result;
+use crate::hash::HashAlgorithm;
+use crate::keys::{fingerprint_hex, PrivateKey, PublicKey};
+use crate::kms::CmsSigner;
+use rand::RngCore;
+use rsa::pkcs1v15::{Signature, SigningKey};
+use rsa::signature::{Keypair, Signer};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::str::FromStr;
+use std::time::Duration;
+use x509_cert::builder::{Builder, CertificateBuilder, Profile};
+use x509_cert::der::asn1::{BitString, PrintableStringRef, Utf8StringRef};
+use x509_cert::der::oid::db::{rfc4519, rfc5912, rfc8410};
+use x509_cert::der::pem::LineEnding;
+use x509_cert::der::{DateTime, Decode, DecodePem, Encode, EncodePem};
+use x509_cert::name::Name;
+use x509_cert::serial_number::SerialNumber;
+use x509_cert::spki::{AlgorithmIdentifierOwned, DynSignatureAlgorithmIdentifier, EncodePublicKey, SignatureBitStringEncoding, SubjectPublicKeyInfoOwned};
+use x509_cert::time::Validity;
+use x509_cert::Certificate;
+
+pub const DEFAULT_VALIDITY_DAYS: u32 = 365;
+
+/// Lifetime of the throwaway certificate made for a PAdES signature when the
+/// key has none of its own.
+const AD_HOC_VALIDITY: Duration = Duration::from_secs(10 * 365 * 24 * 60 * 60);
+
+/// Who a certificate is issued to.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CertificateSubject {
+    pub common_name: String,
+    #[serde(default)]
+    pub organization: Option<String>,
+    #[serde(default)]
+    pub email: Option<String>,
+}
+
+/// The parts of a certificate worth showing to a person.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CertificateInfo {
+    pub subject: String,
+    pub issuer: String,
+    /// SHA-256 of the DER certificate, as colon-separated hex.
+    pub fingerprint: String,
+    pub not_before: String,
+    pub not_after: String,
+}
+
+/// Issues a self-signed certificate for `private_key`, valid from now for
+/// `validity_days`.
+pub fn generate(private_key: &PrivateKey, subject: &CertificateSubject, validity_days: u32) -> Result<Certificate> {
+    let name = subject_name(subject)?;
+    let validity = Duration::from_secs(u64::from(validity_days) * 24 * 60 * 60);
+    let mut serial = [0u8; 16];
+    rand::rngs::OsRng.fill_bytes(&mut serial);
+    sign_certificate(private_key, name, validity, Some(&serial))
+}
+
+/// Certificate naming just `signer_name`, for PAdES signatures made with a key
+/// that has no certificate of its own. Viewers will show it as untrusted until
+/// the user trusts that certificate, but the signature itself validates.
+pub fn ad_hoc(private_key: &PrivateKey, signer_name: &str) -> Result<Certificate> {
+    let common_name = if signer_name.trim().is_empty() { "Sigillum" } else { signer_name };
+    let name = subject_name(&CertificateSubject {
+        common_name: common_name.to_string(),
+        ..Default::default()
+    })?;
+    sign_certificate(private_key, name, AD_HOC_VALIDITY, None)
+}
+
+pub fn from_pem(pem: &str) -> Result<Certificate> {
+    Ok(Certificate::from_pem(pem.trim()).map_err(|e| format!("Invalid certificate: {}", e))?)
+}
+
+pub fn from_der(der: &[u8]) -> Result<Certificate> {
+    Ok(Certificate::from_der(der).map_err(|e| format!("Invalid certificate: {}", e))?)
+}
+
+pub fn to_pem(certificate: &Certificate) -> Result<String> {
+    Ok(certificate
+        .to_pem(LineEnding::LF)
+        .map_err(|e| format!("Failed to encode certificate: {}", e))?)
+}
+
+pub fn to_der(certificate: &Certificate) -> Result<Vec<u8>> {
+    Ok(certificate
+        .to_der()
+        .map_err(|e| format!("Failed to encode certificate: {}", e))?)
+}
+
+pub fn public_key(certificate: &Certificate) -> Result<PublicKey> {
+    let der = certificate
+        .tbs_certificate
+        .subject_public_key_info
+        .to_der()
+        .map_err(|e| format!("Invalid certificate: {}", e))?;
+    PublicKey::from_public_key_der(&der)
+}
+
+/// Checks that `certificate` is signed with its own key, which shows it was
+/// issued by whoever holds that key, and returns the key.
+pub fn verify_self_signed(certificate: &Certificate) -> Result<PublicKey> {
+    let public_key = public_key(certificate)?;
+    let tbs = &certificate.tbs_certificate;
+    if tbs.issuer != tbs.subject {
+        return Err("Certificate is not self-signed".into());
+    }
+    let tbs_der = tbs.to_der().map_err(|e| format!("Invalid certificate: {}", e))?;
+    let signature = certificate
+        .signature
+        .as_bytes()
+        .ok_or("Invalid certificate: malformed signature")?;
+
+    let valid = match (&public_key, certificate.signature_algorithm.oid) {
+        (PublicKey::Rsa(_), rfc5912::SHA_256_WITH_RSA_ENCRYPTION) => {
+            public_key.verify_digest(HashAlgorithm::Sha256, &Sha256::digest(&tbs_der), signature)
+        }
+        (PublicKey::P256(_), rfc5912::ECDSA_WITH_SHA_256) => p256::ecdsa::Signature::from_der(signature)
+            .is_ok_and(|signature| public_key.verify_digest(HashAlgorithm::Sha256, &Sha256::digest(&tbs_der), &signature.to_bytes())),
+        // Ed25519 signs the message itself, which `verify_digest` passes through.
+        (PublicKey::Ed25519(_), rfc8410::ID_ED_25519) => public_key.verify_digest(HashAlgorithm::Sha256, &tbs_der, signature),
+        _ => false,
+    };
+    if valid {
+        Ok(public_key)
+    } else {
+        Err("Certificate signature does not match its key".into())
+    }
+}
+
+/// The key a certificate embedded in a signature is for. A self-signed one
+/// must be signed with that key; one a CA issued can only be checked against
+/// its issuer, which is not at hand, so its key is taken as it is.
+pub fn signer_key(certificate: &Certificate) -> Result<PublicKey> {
+    let tbs = &certificate.tbs_certificate;
+    if tbs.issuer == tbs.subject {
+        verify_self_signed(certificate)
+    } else {
+        public_key(certificate)
+    }
+}
+
+pub fn info(certificate: &Certificate) -> Result<CertificateInfo> {
+    let tbs = &certificate.tbs_certificate;
+    Ok(CertificateInfo {
+        subject: tbs.subject.to_string(),
+        issuer: tbs.issuer.to_string(),
+        fingerprint: fingerprint_hex(&Sha256::digest(to_der(certificate)?)),
+        not_before: format_time(tbs.validity.not_before.to_date_time()),
+        not_after: format_time(tbs.validity.not_after.to_date_time()),
+    })
+}
+
+/// The subject's common name, if it has one.
+pub fn common_name(certificate: &Certificate) -> Option<String> {
+    let value = &certificate
+        .tbs_certificate
+        .subject
+        .0
+        .iter()
+        .flat_map(|rdn| rdn.0.iter())
+        .find(|attribute| attribute.oid == rfc4519::CN)?
+        .value;
+    value
+        .decode_as::<Utf8StringRef>()
+        .map(|name| name.to_string())
+        .or_else(|_| value.decode_as::<PrintableStringRef>().map(|name| name.to_string()))
+        .ok()
+}
+
+pub(crate) fn format_time(time: DateTime) -> String {
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+        time.year(),
+        time.month(),
+        time.day(),
+        time.hour(),
+        time.minutes(),
+        time.seconds()
+    )
+}
+
+fn subject_name(subject: &CertificateSubject) -> Result<Name> {
+    let common_name = subject.common_name.trim();
+    if common_name.is_empty() {
+        return Err("Certificate needs a common name".into());
+    }
+    // RFC 4514 lists the most specific part first, so this comes out as
+    // O, CN, emailAddress in the certificate itself.
+    let mut parts = Vec::new();
+    if let Some(email) = subject.email.as_deref().map(str::trim).filter(|e| !e.is_empty()) {
+        // emailAddress (PKCS #9)
+        parts.push(format!("1.2.840.113549.1.9.1={}", escape_dn_value(email)));
+    }
+    parts.push(format!("CN={}", escape_dn_value(common_name)));
+    if let Some(organization) = subject.organization.as_deref().map(str::trim).filter(|o| !o.is_empty()) {
+        parts.push(format!("O={}", escape_dn_value(organization)));
+    }
+    Ok(Name::from_str(&parts.join(",")).map_err(|e| format!("Failed to create certificate: {}", e))?)
+}
+
+/// Without a `serial`, one is derived from the key so the same key always
+/// yields the same certificate identity.
+fn sign_certificate(private_key: &PrivateKey, subject: Name, validity: Duration, serial: Option<&[u8]>) -> Result<Certificate> {
+    match private_key {
+        PrivateKey::Rsa(key) => {
+            let signing_key = SigningKey::<Sha256>::new(key.clone());
+            self_signed::<_, Signature>(&signing_key, subject, validity, serial)
+        }
+        PrivateKey::P256(key) => self_signed::<_, p256::ecdsa::DerSignature>(key, subject, validity, serial),
+        PrivateKey::Pkcs11(key) => self_signed::<_, Signature>(&key.rsa_signer(), subject, validity, serial),
+        PrivateKey::Ed25519(key) => {
+            self_signed::<_, Ed25519Signature>(&Ed25519Signer(key), subject, validity, serial)
+        }
+        PrivateKey::SshAgent(key) => {
+            self_signed::<_, Ed25519Signature>(&Ed25519Signer(key), subject, validity, serial)
+        }
+        PrivateKey::Kms(key) => match key.cms_signer() {
+            CmsSigner::Rsa(signer) => self_signed::<_, Signature>(&signer, subject, validity, serial),
+            CmsSigner::P256(signer) => self_signed::<_, p256::ecdsa::DerSignature>(&signer, subject, validity, serial),
+            CmsSigner::Ed25519(signer) => {
+                self_signed::<_, Ed25519Signature>(&Ed25519Signer(&signer), subject, validity, serial)
+            }
+        },
+    }
+}
+
+fn self_signed<S, Sig>(signing_key: &S, subject: Name, validity: Duration, serial: Option<&[u8]>) -> Result<Certificate>
+where
+    S: Keypair + DynSignatureAlgorithmIdentifier + Signer<Sig>,
+    S::VerifyingKey: EncodePublicKey,
+    Sig: SignatureBitStringEncoding,
+{
+    let cert_error = |e: &dyn std::fmt::Display| format!("Failed to create certificate: {}", e);
+
+    let public_key = SubjectPublicKeyInfoOwned::from_key(signing_key.verifying_key())
+        .map_err(|e| cert_error(&e))?;
+    let validity = Validity::from_now(validity).map_err(|e| cert_error(&e))?;
+    let mut serial = match serial {
+        Some(serial) => serial.to_vec(),
+        None => Sha256::digest(public_key.to_der().map_err(|e| cert_error(&e))?)[..16].to_vec(),
+    };
+    // Clear the top bit to keep the serial number positive.
+    serial[0] &= 0x7f;
+    let serial_number = SerialNumber::new(&serial).map_err(|e| cert_error(&e))?;
+
+    Ok(CertificateBuilder::new(Profile::Root, serial_number, validity, subject, public_key, signing_key)
+        .map_err(|e| cert_error(&e))?
+        .build::<Sig>()
+        .map_err(|e| cert_error(&e))?)
+}
+
+/// Signs certificates with an Ed25519 key, our own or one in ssh-agent or
+/// Vault. The upstream signature type has no bit string encoding, so it is
+/// wrapped in `Ed25519Signature`.
+struct Ed25519Signer<'a, K>(&'a K);
+
+struct Ed25519Signature(ed25519_dalek::Signature);
+
+impl<K> Keypair for Ed25519Signer<'_, K>
+where
+    K: Keypair<VerifyingKey = ed25519_dalek::VerifyingKey>,
+{
+    type VerifyingKey = ed25519_dalek::VerifyingKey;
+
+    fn verifying_key(&self) -> Self::VerifyingKey {
+        self.0.verifying_key()
+    }
+}
+
+impl<K> DynSignatureAlgorithmIdentifier for Ed25519Signer<'_, K>
+where
+    K: Keypair<VerifyingKey = ed25519_dalek::VerifyingKey>,
+{
+    fn signature_algorithm_identifier(&self) -> x509_cert::spki::Result<AlgorithmIdentifierOwned> {
+        self.0.verifying_key().signature_algorithm_identifier()
+    }
+}
+
+impl<K> Signer<Ed25519Signature> for Ed25519Signer<'_, K>
+where
+    K: Signer<ed25519_dalek::Signature>,
+{
+    fn try_sign(&self, msg: &[u8]) -> std::result::Result<Ed25519Signature, rsa::signature::Error> {
+        self.0.try_sign(msg).map(Ed25519Signature)
+    }
+}
+
+impl SignatureBitStringEncoding for Ed25519Signature {
+    fn to_bitstring(&self) -> x509_cert::der::Result<BitString> {
+        BitString::from_bytes(&self.0.to_bytes())
+    }
+}
+
+/// Escapes a value for use in an RFC 4514 distinguished name string.
+fn escape_dn_value(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for (i, c) in value.chars().enumerate() {
+        let leading = i == 0 && (c == ' ' || c == '#');
+        let trailing = i == value.chars().count() - 1 && c == ' ';
+        if leading || trailing || matches!(c, ',' | '+' | '"' | '\\' | '<' | '>' | ';' | '=') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
@@ -0,0 +1,347 @@
+//! Compares a signed or otherwise modified PDF with the original, to tell
+//! "only Sigillum's stamp was added" apart from "the content was altered".
+
+use crate::error::Result;
+use crate::{encryption, pdf_utils};
+use lopdf::{Dictionary, Document, Object, ObjectId};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Catalog entries signing sets.
+const SIGNATURE_CATALOG_KEYS: [&[u8]; 4] = [b"SigillumInfo", b"Metadata", b"AcroForm", b"Perms"];
+
+/// What differs between two versions of a document, object by object.
+#[derive(Debug, Clone, Serialize)]
+pub struct PdfDiff {
+    pub original_page_count: u32,
+    pub modified_page_count: u32,
+    /// Whether the modified file starts with every byte of the original, as it
+    /// does after an incremental update such as signing.
+    pub original_bytes_kept: bool,
+    pub added_objects: Vec<ObjectDiff>,
+    pub removed_objects: Vec<ObjectDiff>,
+    pub changed_objects: Vec<ObjectDiff>,
+    /// Whether every difference is one that signing makes: watermarks and
+    /// their fonts and images, signature payloads and fields, and the
+    /// signature metadata.
+    pub only_signature_changes: bool,
+}
+
+/// One object that differs.
+#[derive(Debug, Clone, Serialize)]
+pub struct ObjectDiff {
+    /// Object number and generation, as in `12 0 R`.
+    pub id: String,
+    /// Its `/Type`, or what kind of object it is when it has none.
+    pub kind: String,
+    /// Whether this is a difference signing makes. New objects nothing
+    /// refers to, such as an earlier signature's metadata once a co-signature
+    /// replaced it, count too: they cannot change the document.
+    pub signature_change: bool,
+}
+
+/// Compares `modified` with `original`. Objects are matched by number, which
+/// incremental updates keep; a document that was rewritten from scratch shows
+/// up as mostly changed.
+pub fn diff_pdfs(original: &[u8], modified: &[u8], password: Option<&str>) -> Result<PdfDiff> {
+    let load = |data: &[u8], which: &str| -> Result<Document> {
+        let mut doc = Document::load_mem(data).map_err(|e| format!("Failed to load {} PDF: {}", which, e))?;
+        encryption::decrypt(&mut doc, password)?;
+        Ok(doc)
+    };
+    let before = load(original, "original")?;
+    let after = load(modified, "modified")?;
+
+    let original_page_count = before.get_pages().len() as u32;
+    let modified_page_count = after.get_pages().len() as u32;
+
+    // Objects signing is allowed to have touched, and what it added.
+    let mut allowed_changes = BTreeSet::new();
+    let mut signature_roots = Vec::new();
+    if let (Ok(before_root), Ok(after_root)) = (root_id(&before), root_id(&after)) {
+        if before_root == after_root && catalog_change_allowed(&before, &after, before_root, &mut signature_roots) {
+            allowed_changes.insert(before_root);
+        }
+    }
+    if let (Ok(Object::Reference(before_info)), Ok(Object::Reference(after_info))) =
+        (before.trailer.get(b"Info"), after.trailer.get(b"Info"))
+    {
+        if before_info == after_info && info_change_allowed(&before, &after, *before_info) {
+            allowed_changes.insert(*before_info);
+        }
+    }
+    if let Ok(Object::Reference(info)) = after.trailer.get(b"Info") {
+        signature_roots.push(*info);
+    }
+    let before_pages: BTreeMap<u32, ObjectId> = before.get_pages();
+    for (number, page_id) in after.get_pages() {
+        if before_pages.get(&number) == Some(&page_id) && page_change_allowed(&before, &after, page_id, &mut signature_roots) {
+            allowed_changes.insert(page_id);
+        }
+    }
+
+    // Everything the allowed changes point to that is new is part of the
+    // signature too: fonts, images, payloads, fields.
+    let mut signature_objects = BTreeSet::new();
+    while let Some(id) = signature_roots.pop() {
+        if before.objects.contains_key(&id) || !signature_objects.insert(id) {
+            continue;
+        }
+        if let Some(object) = after.objects.get(&id) {
+            collect_references(object, &mut signature_roots);
+        }
+    }
+
+    let mut in_use = BTreeSet::new();
+    let mut pending = Vec::new();
+    after.trailer.iter().for_each(|(_, value)| collect_references(value, &mut pending));
+    while let Some(id) = pending.pop() {
+        if in_use.insert(id) {
+            if let Some(object) = after.objects.get(&id) {
+                collect_references(object, &mut pending);
+            }
+        }
+    }
+
+    let mut added_objects = Vec::new();
+    let mut changed_objects = Vec::new();
+    for (id, object) in &after.objects {
+        match before.objects.get(id) {
+            None => {
+                let signature_change = signature_objects.contains(id) || !in_use.contains(id);
+                added_objects.push(object_diff(*id, object, signature_change));
+            }
+            Some(previous) if previous != object => {
+                changed_objects.push(object_diff(*id, object, allowed_changes.contains(id)))
+            }
+            Some(_) => {}
+        }
+    }
+    let removed_objects: Vec<ObjectDiff> = before
+        .objects
+        .iter()
+        .filter(|(id, _)| !after.objects.contains_key(id))
+        .map(|(id, object)| object_diff(*id, object, false))
+        .collect();
+
+    let only_signature_changes = original_page_count == modified_page_count
+        && removed_objects.is_empty()
+        && added_objects.iter().chain(&changed_objects).all(|diff| diff.signature_change);
+    Ok(PdfDiff {
+        original_page_count,
+        modified_page_count,
+        original_bytes_kept: modified.starts_with(original),
+        added_objects,
+        removed_objects,
+        changed_objects,
+        only_signature_changes,
+    })
+}
+
+fn root_id(doc: &Document) -> Result<ObjectId> {
+    Ok(doc.trailer
+        .get(b"Root")
+        .and_then(Object::as_reference)
+        .map_err(|e| format!("Failed to find catalog: {}", e))?)
+}
+
+/// The catalog may only gain or change the entries signing sets.
+fn catalog_change_allowed(before: &Document, after: &Document, id: ObjectId, roots: &mut Vec<ObjectId>) -> bool {
+    let (Ok(old), Ok(new)) = (before.get_dictionary(id), after.get_dictionary(id)) else {
+        return false;
+    };
+    if !same_except(old, new, |key| SIGNATURE_CATALOG_KEYS.contains(&key)) {
+        return false;
+    }
+    for key in SIGNATURE_CATALOG_KEYS {
+        if let Ok(value) = new.get(key) {
+            collect_references(value, roots);
+        }
+    }
+    true
+}
+
+/// The information dictionary may only gain the Sigillum entries and a new
+/// modification date.
+fn info_change_allowed(before: &Document, after: &Document, id: ObjectId) -> bool {
+    match (before.get_dictionary(id), after.get_dictionary(id)) {
+        (Ok(old), Ok(new)) => same_except(old, new, |key| key.starts_with(b"Sigillum") || key == b"ModDate"),
+        _ => false,
+    }
+}
+
+/// A page may only gain watermark content streams, the resources they use and
+/// signature field widgets; everything it had must still be there.
+fn page_change_allowed(before: &Document, after: &Document, id: ObjectId, roots: &mut Vec<ObjectId>) -> bool {
+    let (Ok(old), Ok(new)) = (before.get_dictionary(id), after.get_dictionary(id)) else {
+        return false;
+    };
+    if !same_except(old, new, |key| matches!(key, b"Contents" | b"Resources" | b"Annots")) {
+        return false;
+    }
+
+    let old_contents = references(before, old.get(b"Contents").ok());
+    let new_contents = references(after, new.get(b"Contents").ok());
+    let Some(added_contents) = new_contents.strip_prefix(old_contents.as_slice()) else {
+        return false;
+    };
+    for &content_id in added_contents {
+        let is_watermark = after
+            .get_object(content_id)
+            .and_then(Object::as_stream)
+            .map(|stream| {
+                let content = stream.decompressed_content().unwrap_or_else(|_| stream.content.clone());
+                pdf_utils::is_watermark_content(&content)
+            })
+            .unwrap_or(false);
+        if !is_watermark {
+            return false;
+        }
+        roots.push(content_id);
+    }
+
+    let old_annots = references(before, old.get(b"Annots").ok());
+    let new_annots = references(after, new.get(b"Annots").ok());
+    let Some(added_annots) = new_annots.strip_prefix(old_annots.as_slice()) else {
+        return false;
+    };
+    for &annot_id in added_annots {
+        let is_signature_field = after
+            .get_dictionary(annot_id)
+            .and_then(|annot| annot.get(b"FT"))
+            .and_then(Object::as_name)
+            .map(|field_type| field_type == b"Sig")
+            .unwrap_or(false);
+        if !is_signature_field {
+            return false;
+        }
+        roots.push(annot_id);
+    }
+
+    resources_change_allowed(before, after, old, new, roots)
+}
+
+/// Every resource the page had must still resolve to the same object; new
+/// ones must be watermark resources.
+fn resources_change_allowed(
+    before: &Document,
+    after: &Document,
+    old_page: &Dictionary,
+    new_page: &Dictionary,
+    roots: &mut Vec<ObjectId>,
+) -> bool {
+    let old = page_resources(before, old_page);
+    let new = page_resources(after, new_page);
+    for (category, old_entries) in &old {
+        let Some(new_entries) = new.get(category) else {
+            return false;
+        };
+        let kept = old_entries.iter().all(|(name, value)| {
+            pdf_utils::is_watermark_resource(name) || new_entries.get(name) == Some(value)
+        });
+        if !kept {
+            return false;
+        }
+    }
+    for entries in new.values() {
+        for (name, value) in entries {
+            if old.values().any(|old_entries| old_entries.get(name) == Some(value)) {
+                continue;
+            }
+            if !pdf_utils::is_watermark_resource(name) {
+                return false;
+            }
+            collect_references(value, roots);
+        }
+    }
+    true
+}
+
+/// The page's resources, inherited ones included, as category -> name ->
+/// value with the category dictionaries resolved.
+fn page_resources(doc: &Document, page: &Dictionary) -> BTreeMap<Vec<u8>, BTreeMap<Vec<u8>, Object>> {
+    let mut node = Some(page);
+    let mut depth = 0;
+    let mut resources = None;
+    while let Some(dict) = node {
+        if let Ok(found) = dict.get(b"Resources") {
+            resources = doc.dereference(found).ok().and_then(|(_, object)| object.as_dict().ok());
+            break;
+        }
+        depth += 1;
+        node = dict
+            .get(b"Parent")
+            .and_then(Object::as_reference)
+            .and_then(|id| doc.get_dictionary(id))
+            .ok()
+            .filter(|_| depth < 32);
+    }
+
+    let mut categories = BTreeMap::new();
+    for (category, value) in resources.into_iter().flat_map(Dictionary::iter) {
+        let entries = match doc.dereference(value) {
+            Ok((_, Object::Dictionary(entries))) => entries.iter().map(|(name, value)| (name.clone(), value.clone())).collect(),
+            // ProcSet and the like: compare the value as a whole.
+            Ok((_, other)) => BTreeMap::from([(Vec::new(), other.clone())]),
+            Err(_) => BTreeMap::new(),
+        };
+        categories.insert(category.clone(), entries);
+    }
+    categories
+}
+
+/// Whether `old` and `new` agree on every key `may_differ` does not accept.
+fn same_except(old: &Dictionary, new: &Dictionary, may_differ: impl Fn(&[u8]) -> bool) -> bool {
+    let keys: BTreeSet<&Vec<u8>> = old.iter().chain(new.iter()).map(|(key, _)| key).collect();
+    keys.into_iter()
+        .filter(|key| !may_differ(key))
+        .all(|key| old.get(key).ok() == new.get(key).ok())
+}
+
+/// The objects `value` lists, whether it is a single reference, an array of
+/// them, or a reference to such an array.
+fn references(doc: &Document, value: Option<&Object>) -> Vec<ObjectId> {
+    match value {
+        Some(Object::Reference(id)) => match doc.get_object(*id) {
+            Ok(Object::Array(items)) => items.iter().filter_map(|item| item.as_reference().ok()).collect(),
+            _ => vec![*id],
+        },
+        Some(Object::Array(items)) => items.iter().filter_map(|item| item.as_reference().ok()).collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn collect_references(object: &Object, into: &mut Vec<ObjectId>) {
+    match object {
+        Object::Reference(id) => into.push(*id),
+        Object::Array(items) => items.iter().for_each(|item| collect_references(item, into)),
+        Object::Dictionary(dict) => dict.iter().for_each(|(_, value)| collect_references(value, into)),
+        Object::Stream(stream) => stream.dict.iter().for_each(|(_, value)| collect_references(value, into)),
+        _ => {}
+    }
+}
+
+fn object_diff(id: ObjectId, object: &Object, signature_change: bool) -> ObjectDiff {
+    let dict = match object {
+        Object::Dictionary(dict) => Some(dict),
+        Object::Stream(stream) => Some(&stream.dict),
+        _ => None,
+    };
+    let kind = dict
+        .and_then(|dict| dict.get(b"Type").and_then(Object::as_name).ok())
+        .map(|name| String::from_utf8_lossy(name).into_owned())
+        .unwrap_or_else(|| {
+            match object {
+                Object::Stream(_) => "stream",
+                Object::Dictionary(_) => "dictionary",
+                Object::Array(_) => "array",
+                _ => "value",
+            }
+            .to_string()
+        });
+    ObjectDiff {
+        id: format!("{} {} R", id.0, id.1),
+        kind,
+        signature_change,
+    }
+}
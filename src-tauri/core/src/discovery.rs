@@ -0,0 +1,268 @@
+//! Public keys published by their owners over HTTPS, e.g. at
+//! `https://example.com/.well-known/sigillum/jane.pem`. A signature can name
+//! that URL; verifying can then fetch the key there and report whether it is
+//! the one that made the signature, which ties the signature to whoever
+//! controls the site. Fetched keys are cached for a day.
+//!
+//! Organizations can instead list their signing keys in DNS, the way DKIM
+//! does for mail: a TXT record at `sigillum._domainkey.example.com` holding
+//! `v=sigillum1; fp=<SHA-256 of the key, in hex>`, one record per key. A
+//! signature can name an email address or domain, and verifying then looks
+//! the record up and reports whether it lists the key that signed.
+
+use crate::error::Result;
+use crate::identity::Identity;
+use crate::keys::{fingerprint_hex, PublicKey};
+use hickory_resolver::error::ResolveErrorKind;
+use hickory_resolver::Resolver;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+const CACHE_DIR: &str = "key_cache";
+/// How long a fetched key is used before it is fetched again.
+const CACHE_MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(15);
+/// Far more than any public key or identity file needs.
+const MAX_KEY_BYTES: u64 = 64 * 1024;
+/// Longest key URL a signature may name.
+const MAX_URL_LENGTH: usize = 2048;
+/// Label the TXT records go under, like a DKIM selector.
+const DNS_SELECTOR: &str = "sigillum._domainkey";
+const DNS_RECORD_VERSION: &str = "sigillum1";
+
+/// What fetching the key a signature names found.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyDiscovery {
+    pub url: String,
+    /// Fingerprint of the key published there, if one could be fetched.
+    pub fingerprint: Option<String>,
+    /// Whether that key is the one the signature embeds.
+    pub matches: bool,
+    /// Whether the key came from the cache rather than the site.
+    pub cached: bool,
+    /// Why no key could be fetched, or why it does not match.
+    pub problem: Option<String>,
+}
+
+/// What looking up the keys a domain lists in DNS found.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DomainKeyDiscovery {
+    /// The email address or domain the signature names.
+    pub identity: String,
+    /// The TXT record looked up, e.g. `sigillum._domainkey.example.com`.
+    pub record: String,
+    /// Fingerprints of the keys the record lists, as colon-separated hex.
+    pub fingerprints: Vec<String>,
+    /// Whether one of them is the key the signature embeds.
+    pub matches: bool,
+    /// Why no keys could be looked up, or why none match.
+    pub problem: Option<String>,
+}
+
+/// Where fetched keys are cached under the app data directory `dir`.
+pub fn cache_dir(dir: &Path) -> PathBuf {
+    dir.join(CACHE_DIR)
+}
+
+/// Checks that `url` can be named as where a key is published: HTTPS only,
+/// so what is fetched comes from the site it says.
+pub fn check_key_url(url: &str) -> Result<()> {
+    if !url.starts_with("https://") || url.len() <= "https://".len() {
+        return Err("The key URL must start with https://".into());
+    }
+    if url.len() > MAX_URL_LENGTH || url.chars().any(|c| c.is_whitespace() || c.is_control()) {
+        return Err("Invalid key URL".into());
+    }
+    Ok(())
+}
+
+/// Fetches the key published at `url` and compares it with
+/// `signer_fingerprint`, the fingerprint of the key the signature embeds.
+/// With `cache`, a key fetched there within the last day is used again.
+pub fn discover(url: &str, signer_fingerprint: Option<&str>, cache: Option<&Path>) -> KeyDiscovery {
+    let mut discovery = KeyDiscovery {
+        url: url.to_string(),
+        fingerprint: None,
+        matches: false,
+        cached: false,
+        problem: None,
+    };
+    match fetch_public_key(url, cache) {
+        Ok((key, cached)) => {
+            discovery.cached = cached;
+            match key.fingerprint() {
+                Ok(fingerprint) => {
+                    discovery.matches = signer_fingerprint == Some(fingerprint.as_str());
+                    if !discovery.matches {
+                        discovery.problem = Some(match signer_fingerprint {
+                            Some(_) => "The key published at the URL is not the one that signed".to_string(),
+                            None => "The signature embeds no key to compare the published one with".to_string(),
+                        });
+                    }
+                    discovery.fingerprint = Some(fingerprint);
+                }
+                Err(e) => discovery.problem = Some(e.to_string()),
+            }
+        }
+        Err(e) => discovery.problem = Some(e.to_string()),
+    }
+    discovery
+}
+
+/// The key published at `url`, as a PEM public key or a `.sigillum-id`
+/// identity file, and whether it came from `cache`.
+pub fn fetch_public_key(url: &str, cache: Option<&Path>) -> Result<(PublicKey, bool)> {
+    check_key_url(url)?;
+    let cache_path = cache.map(|dir| dir.join(format!("{}.key", hex::encode(Sha256::digest(url.as_bytes())))));
+    if let Some(path) = &cache_path {
+        let fresh = fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+            .is_some_and(|age| age < CACHE_MAX_AGE);
+        if fresh {
+            if let Some(key) = fs::read(path).ok().and_then(|data| parse_key(&data).ok()) {
+                return Ok((key, true));
+            }
+        }
+    }
+
+    let fetch_error = |e: &dyn std::fmt::Display| format!("Failed to fetch the key from {}: {}", url, e);
+    let agent = ureq::AgentBuilder::new().timeout(REQUEST_TIMEOUT).build();
+    // ureq's errors already name the URL.
+    let response = agent.get(url).call().map_err(|e| format!("Failed to fetch the key: {}", e))?;
+    // Redirects are followed, but only to other HTTPS pages.
+    if !response.get_url().starts_with("https://") {
+        return Err(fetch_error(&"it redirects away from HTTPS").into());
+    }
+    let mut data = Vec::new();
+    response
+        .into_reader()
+        .take(MAX_KEY_BYTES + 1)
+        .read_to_end(&mut data)
+        .map_err(|e| fetch_error(&e))?;
+    if data.len() as u64 > MAX_KEY_BYTES {
+        return Err(format!("{} holds more than a public key", url).into());
+    }
+    let key = parse_key(&data).map_err(|e| format!("{} holds no public key: {}", url, e))?;
+
+    if let (Some(dir), Some(path)) = (cache, &cache_path) {
+        let saved = fs::create_dir_all(dir).and_then(|_| fs::write(path, &data));
+        if let Err(e) = saved {
+            log::warn!("Failed to cache the key from {}: {}", url, e);
+        }
+    }
+    Ok((key, false))
+}
+
+fn parse_key(data: &[u8]) -> Result<PublicKey> {
+    if data.trim_ascii_start().starts_with(b"{") {
+        let identity = Identity::from_json(data)?;
+        return PublicKey::from_public_key_pem(identity.public_key.trim());
+    }
+    let pem = std::str::from_utf8(data).map_err(|_| "not a PEM public key".to_string())?;
+    PublicKey::from_public_key_pem(pem.trim())
+}
+
+/// The domain whose DNS lists the keys of `identity`, an email address or a
+/// domain, in lower case.
+pub fn key_domain(identity: &str) -> Result<String> {
+    let domain = match identity.rsplit_once('@') {
+        Some((local, domain)) if !local.is_empty() => domain,
+        Some(_) => return Err(format!("Invalid email address '{}'", identity).into()),
+        None => identity,
+    };
+    let domain = domain.trim_end_matches('.').to_ascii_lowercase();
+    let valid_label = |label: &str| {
+        !label.is_empty()
+            && label.len() <= 63
+            && !label.starts_with('-')
+            && !label.ends_with('-')
+            && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+    };
+    if domain.len() > 253 || !domain.contains('.') || !domain.split('.').all(valid_label) {
+        return Err(format!("'{}' is not an email address or domain", identity).into());
+    }
+    Ok(domain)
+}
+
+/// Name of the TXT record listing the keys of `identity`.
+pub fn dns_record_name(identity: &str) -> Result<String> {
+    Ok(format!("{}.{}", DNS_SELECTOR, key_domain(identity)?))
+}
+
+/// The TXT record value that lists `key`, for publishing under
+/// `dns_record_name`.
+pub fn dns_record(key: &PublicKey) -> Result<String> {
+    let fingerprint = key.fingerprint()?.replace(':', "").to_ascii_lowercase();
+    Ok(format!("v={}; k={}; fp={}", DNS_RECORD_VERSION, key.algorithm()?, fingerprint))
+}
+
+/// Looks up the keys `identity`'s domain lists in DNS and checks whether
+/// `signer_fingerprint`, the fingerprint of the key the signature embeds, is
+/// one of them.
+pub fn discover_dns(identity: &str, signer_fingerprint: Option<&str>) -> DomainKeyDiscovery {
+    let mut discovery = DomainKeyDiscovery {
+        identity: identity.to_string(),
+        record: String::new(),
+        fingerprints: Vec::new(),
+        matches: false,
+        problem: None,
+    };
+    let record = match dns_record_name(identity) {
+        Ok(record) => record,
+        Err(e) => {
+            discovery.problem = Some(e.to_string());
+            return discovery;
+        }
+    };
+    match lookup_txt(&record) {
+        Ok(values) => {
+            discovery.fingerprints = values.iter().filter_map(|value| listed_fingerprint(value)).collect();
+            discovery.matches = signer_fingerprint.is_some_and(|signer| discovery.fingerprints.iter().any(|listed| listed == signer));
+            if discovery.fingerprints.is_empty() {
+                discovery.problem = Some(format!("{} lists no Sigillum keys", record));
+            } else if signer_fingerprint.is_none() {
+                discovery.problem = Some("The signature embeds no key to compare the listed ones with".to_string());
+            } else if !discovery.matches {
+                discovery.problem = Some(format!("{} does not list the key that signed", record));
+            }
+        }
+        Err(e) => discovery.problem = Some(e.to_string()),
+    }
+    discovery.record = record;
+    discovery
+}
+
+/// The TXT record values at `name`, each put back together from the
+/// strings it is split into.
+fn lookup_txt(name: &str) -> Result<Vec<String>> {
+    let resolver = Resolver::from_system_conf().map_err(|e| format!("Failed to set up DNS: {}", e))?;
+    let lookup = match resolver.txt_lookup(format!("{}.", name)) {
+        Ok(lookup) => lookup,
+        Err(e) if matches!(e.kind(), ResolveErrorKind::NoRecordsFound { .. }) => return Ok(Vec::new()),
+        Err(e) => return Err(format!("Failed to look up {}: {}", name, e).into()),
+    };
+    Ok(lookup
+        .iter()
+        .map(|txt| txt.txt_data().iter().map(|part| String::from_utf8_lossy(part)).collect())
+        .collect())
+}
+
+/// The fingerprint a `v=sigillum1` record lists, as colon-separated
+/// upper-case hex like `PublicKey::fingerprint`. Other TXT records that
+/// share the name, and records for keys that were withdrawn with an empty
+/// `fp=`, list none.
+fn listed_fingerprint(value: &str) -> Option<String> {
+    let mut tags = value.split(';').filter_map(|tag| tag.split_once('=')).map(|(name, value)| (name.trim(), value.trim()));
+    if tags.next() != Some(("v", DNS_RECORD_VERSION)) {
+        return None;
+    }
+    let (_, fingerprint) = tags.find(|(name, _)| *name == "fp")?;
+    let digest = hex::decode(fingerprint.replace(':', "")).ok().filter(|digest| digest.len() == 32)?;
+    Some(fingerprint_hex(&digest))
+}
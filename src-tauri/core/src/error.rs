@@ -0,0 +1,92 @@
+//! Errors that say what kind of failure they are, so the app and CLI can tell
+//! "no key yet" from "corrupt PDF" from "wrong passphrase" without matching
+//! on messages.
+//!
+//! A plain message converts to [`SigillumError::Other`], for failures that
+//! are of no particular kind.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+pub type Result<T> = std::result::Result<T, SigillumError>;
+
+/// A failure and its message. Serializes as `{"code": "...", "message": "..."}`,
+/// where the codes are stable.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "code", content = "message", rename_all = "snake_case")]
+pub enum SigillumError {
+    /// No key has been generated or imported for the profile yet.
+    NoKey(String),
+    /// The private key is protected and no passphrase was given.
+    PassphraseRequired(String),
+    WrongPassphrase(String),
+    /// The file is not a PDF that can be read.
+    InvalidPdf(String),
+    /// The PDF is encrypted and no password was given.
+    PasswordRequired(String),
+    WrongPassword(String),
+    /// The PDF is signed already and the re-signing policy refuses it, or it
+    /// was locked when it was signed.
+    AlreadySigned(String),
+    /// The signing key is past its expiry date and the settings refuse to
+    /// sign with it.
+    KeyExpired(String),
+    Cancelled(String),
+    /// A file could not be read or written.
+    Io(String),
+    Other(String),
+}
+
+impl SigillumError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            SigillumError::NoKey(_) => "no_key",
+            SigillumError::PassphraseRequired(_) => "passphrase_required",
+            SigillumError::WrongPassphrase(_) => "wrong_passphrase",
+            SigillumError::InvalidPdf(_) => "invalid_pdf",
+            SigillumError::PasswordRequired(_) => "password_required",
+            SigillumError::WrongPassword(_) => "wrong_password",
+            SigillumError::AlreadySigned(_) => "already_signed",
+            SigillumError::KeyExpired(_) => "key_expired",
+            SigillumError::Cancelled(_) => "cancelled",
+            SigillumError::Io(_) => "io",
+            SigillumError::Other(_) => "other",
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        match self {
+            SigillumError::NoKey(message)
+            | SigillumError::PassphraseRequired(message)
+            | SigillumError::WrongPassphrase(message)
+            | SigillumError::InvalidPdf(message)
+            | SigillumError::PasswordRequired(message)
+            | SigillumError::WrongPassword(message)
+            | SigillumError::AlreadySigned(message)
+            | SigillumError::KeyExpired(message)
+            | SigillumError::Cancelled(message)
+            | SigillumError::Io(message)
+            | SigillumError::Other(message) => message,
+        }
+    }
+}
+
+impl fmt::Display for SigillumError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.message())
+    }
+}
+
+impl std::error::Error for SigillumError {}
+
+impl From<String> for SigillumError {
+    fn from(message: String) -> Self {
+        SigillumError::Other(message)
+    }
+}
+
+impl From<&str> for SigillumError {
+    fn from(message: &str) -> Self {
+        SigillumError::Other(message.to_string())
+    }
+}
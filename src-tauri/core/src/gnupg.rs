@@ -0,0 +1,288 @@
+//! OpenPGP signatures made and checked by GnuPG, for signers whose key lives
+//! in their GnuPG keyring or on an OpenPGP card. gpg and gpg-agent do the
+//! signing, so the key and its passphrase never pass through Sigillum, and
+//! signatures are checked against the keyring and its web of trust.
+
+use crate::error::Result;
+use crate::hash::HashAlgorithm;
+use crate::openpgp;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::process::{Child, Command, Output, Stdio};
+
+const GPG: &str = "gpg";
+const STATUS_PREFIX: &str = "[GNUPG:] ";
+/// `ERRSIG` return code for a signature whose key is not in the keyring.
+const ERRSIG_NO_PUBLIC_KEY: &str = "9";
+
+/// A secret key in the GnuPG keyring, as `secret_keys` lists it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GpgKey {
+    /// Fingerprint of the primary key, as upper-case hex.
+    pub fingerprint: String,
+    /// The primary user ID, e.g. "Jane Doe <jane@example.com>".
+    pub user_id: String,
+    /// Whether the key can make signatures now: neither expired, revoked nor
+    /// disabled, and with a signing subkey or primary key.
+    pub usable: bool,
+}
+
+/// Outcome of checking an OpenPGP signature with gpg.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GpgVerification {
+    /// Whether the signature matches the data and a key in the keyring,
+    /// even one that has since expired or been revoked.
+    pub valid: bool,
+    /// Whether the keyring vouches for that key, fully or ultimately.
+    pub trusted: bool,
+    /// Fingerprint of the signer's primary key, or failing that the key ID
+    /// the signature names, as upper-case hex.
+    pub fingerprint: Option<String>,
+    /// The signer's primary user ID, when the key is in the keyring.
+    pub user_id: Option<String>,
+    /// When the signature says it was made.
+    pub created: Option<DateTime<Utc>>,
+    /// Why a signature that matches still should not be relied on, or why
+    /// it could not be checked.
+    pub problem: Option<String>,
+}
+
+/// Whether gpg can be run.
+pub fn is_available() -> bool {
+    Command::new(GPG)
+        .arg("--version")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+/// The secret keys in the user's keyring.
+pub fn secret_keys() -> Result<Vec<GpgKey>> {
+    let output = Command::new(GPG)
+        .args(["--batch", "--with-colons", "--fixed-list-mode", "--list-secret-keys"])
+        .output()
+        .map_err(spawn_error)?;
+    if !output.status.success() {
+        return Err(gpg_error("list the keys", &output).into());
+    }
+
+    // Each key is a "sec" record followed by its "fpr", "uid" and "ssb"
+    // records; the capabilities field of "sec" sums up the whole key.
+    let mut keys: Vec<GpgKey> = Vec::new();
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        let fields: Vec<&str> = line.split(':').collect();
+        match fields.as_slice() {
+            ["sec", validity, _, _, _, _, _, _, _, _, _, capabilities, ..] => keys.push(GpgKey {
+                fingerprint: String::new(),
+                user_id: String::new(),
+                usable: !matches!(*validity, "e" | "r" | "d" | "i") && capabilities.contains('S'),
+            }),
+            ["fpr", _, _, _, _, _, _, _, _, fingerprint, ..] => {
+                if let Some(key) = keys.last_mut().filter(|key| key.fingerprint.is_empty()) {
+                    key.fingerprint = fingerprint.to_string();
+                }
+            }
+            ["uid", _, _, _, _, _, _, _, _, user_id, ..] => {
+                if let Some(key) = keys.last_mut().filter(|key| key.user_id.is_empty()) {
+                    key.user_id = unescape(user_id);
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(keys)
+}
+
+/// Signs everything read from `data` with the GnuPG key `key_id` (a
+/// fingerprint, key ID or user ID), giving an ASCII-armored detached
+/// signature. gpg-agent asks for the key's passphrase itself.
+pub fn sign_detached<R: Read>(data: R, key_id: &str, hash: HashAlgorithm) -> Result<String> {
+    openpgp::check_hash(hash)?;
+    let child = Command::new(GPG)
+        .args(["--armor", "--detach-sign", "--output", "-", "--digest-algo", hash.label(), "--local-user", key_id])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(spawn_error)?;
+    let output = feed(child, data)?;
+    if !output.status.success() {
+        return Err(gpg_error("sign", &output).into());
+    }
+    Ok(String::from_utf8(output.stdout).map_err(|_| "gpg gave a signature that is not ASCII-armored".to_string())?)
+}
+
+/// Checks the armored OpenPGP signature `armored` over everything read from
+/// `data` against the user's GnuPG keyring.
+pub fn verify_detached<R: Read>(data: R, armored: &str) -> Result<GpgVerification> {
+    if !openpgp::is_armored_signature(armored) {
+        return Err("Not an OpenPGP signature".into());
+    }
+    // gpg reads the signature from a file and the data from stdin.
+    let signature = SignatureFile::create(armored)?;
+    let child = Command::new(GPG)
+        .args(["--batch", "--status-fd", "1", "--verify"])
+        .arg(&signature.0)
+        .arg("-")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(spawn_error)?;
+    let output = feed(child, data)?;
+    Ok(parse_status(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Reads what gpg's `--status-fd` output says about the one signature checked.
+fn parse_status(status: &str) -> GpgVerification {
+    let mut verification = GpgVerification {
+        valid: false,
+        trusted: false,
+        fingerprint: None,
+        user_id: None,
+        created: None,
+        problem: None,
+    };
+    // Whether gpg found the signature to match, once it says.
+    let mut good = None;
+    for line in status.lines().filter_map(|line| line.strip_prefix(STATUS_PREFIX)) {
+        let (keyword, rest) = line.split_once(' ').unwrap_or((line, ""));
+        let fields: Vec<&str> = rest.split(' ').collect();
+        match keyword {
+            "GOODSIG" | "EXPSIG" | "EXPKEYSIG" | "REVKEYSIG" | "BADSIG" => {
+                good = Some(keyword != "BADSIG");
+                verification.fingerprint.get_or_insert_with(|| fields[0].to_string());
+                if let Some((_, user_id)) = rest.split_once(' ') {
+                    verification.user_id = Some(unescape(user_id));
+                }
+                verification.problem = match keyword {
+                    "EXPSIG" => Some("The signature has expired".to_string()),
+                    "EXPKEYSIG" => Some("The signer's key has expired".to_string()),
+                    "REVKEYSIG" => Some("The signer's key has been revoked".to_string()),
+                    _ => verification.problem.take(),
+                };
+            }
+            "VALIDSIG" => {
+                // The primary key's fingerprint comes last; older gpg leaves
+                // it out, giving only the signing subkey's.
+                if let Some(fingerprint) = fields.get(9).or(fields.first()).filter(|f| !f.is_empty()) {
+                    verification.fingerprint = Some(fingerprint.to_string());
+                }
+                verification.created = fields.get(2).and_then(|time| time.parse().ok()).and_then(|time| DateTime::from_timestamp(time, 0));
+            }
+            "ERRSIG" => {
+                verification.fingerprint.get_or_insert_with(|| {
+                    fields.get(6).filter(|f| !f.is_empty() && **f != "-").unwrap_or(&fields[0]).to_string()
+                });
+                verification.created = fields.get(4).and_then(|time| time.parse().ok()).and_then(|time| DateTime::from_timestamp(time, 0));
+                verification.problem = Some(if fields.get(5) == Some(&ERRSIG_NO_PUBLIC_KEY) {
+                    "The signer's key is not in the GnuPG keyring".to_string()
+                } else {
+                    "gpg could not check the signature".to_string()
+                });
+            }
+            "TRUST_FULLY" | "TRUST_ULTIMATE" => verification.trusted = true,
+            _ => {}
+        }
+    }
+    match good {
+        Some(good) => verification.valid = good,
+        None if verification.problem.is_none() => {
+            verification.problem = Some("gpg found no signature it could read".to_string());
+        }
+        None => {}
+    }
+    verification
+}
+
+/// Writes all of `data` to gpg, then waits for it to finish. gpg only says
+/// a few lines until its input ends, so the pipes cannot fill up meanwhile.
+fn feed<R: Read>(mut child: Child, mut data: R) -> Result<Output> {
+    let written = match child.stdin.take() {
+        Some(mut stdin) => std::io::copy(&mut data, &mut stdin).and_then(|_| stdin.flush()),
+        None => Ok(()),
+    };
+    let output = child.wait_with_output().map_err(|e| format!("Failed to run gpg: {}", e))?;
+    // gpg may stop reading early, e.g. when the key is unknown; its own
+    // message says more than the broken pipe.
+    if let Err(e) = written {
+        if output.status.success() {
+            return Err(format!("Failed to pass the data to gpg: {}", e).into());
+        }
+    }
+    Ok(output)
+}
+
+fn spawn_error(e: std::io::Error) -> String {
+    if e.kind() == std::io::ErrorKind::NotFound {
+        "GnuPG is not installed (gpg was not found)".to_string()
+    } else {
+        format!("Failed to run gpg: {}", e)
+    }
+}
+
+/// The last line gpg wrote to stderr, which says what went wrong.
+fn gpg_error(action: &str, output: &Output) -> String {
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    match stderr.lines().rev().find(|line| !line.trim().is_empty()) {
+        Some(line) => format!("gpg could not {}: {}", action, line.trim()),
+        None => format!("gpg could not {}", action),
+    }
+}
+
+/// Undoes the `%XX` and `\xXX` escaping gpg applies to user IDs.
+fn unescape(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escape = match bytes[i] {
+            b'%' => Some(1),
+            b'\\' if bytes.get(i + 1) == Some(&b'x') => Some(2),
+            _ => None,
+        };
+        let byte = escape.and_then(|skip| {
+            let hex = value.get(i + skip..i + skip + 2)?;
+            Some((u8::from_str_radix(hex, 16).ok()?, skip + 2))
+        });
+        match byte {
+            Some((byte, length)) => {
+                decoded.push(byte);
+                i += length;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// A signature written to a temporary file for gpg, removed when dropped.
+struct SignatureFile(PathBuf);
+
+impl SignatureFile {
+    fn create(armored: &str) -> Result<SignatureFile> {
+        let path = std::env::temp_dir().join(format!("sigillum-{}.asc", hex::encode(rand::random::<[u8; 8]>())));
+        let mut file = fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)
+            .map_err(|e| format!("Failed to write the signature for gpg: {}", e))?;
+        let signature = SignatureFile(path);
+        file.write_all(armored.as_bytes())
+            .map_err(|e| format!("Failed to write the signature for gpg: {}", e))?;
+        Ok(signature)
+    }
+}
+
+impl Drop for SignatureFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
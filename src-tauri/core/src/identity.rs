@@ -0,0 +1,97 @@
+//! `.sigillum-id` identity files: a signer's public key with their name and
+//! certificate, for colleagues to add to their trusted keys. The fingerprint
+//! is written out so it can be compared over the phone, and is checked
+//! against the key when the file is read.
+
+use crate::api::KeyPair;
+use crate::certificate;
+use crate::error::Result;
+use crate::keys::{KeyAlgorithm, PublicKey};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+const IDENTITY_FORMAT: &str = "sigillum-identity";
+const IDENTITY_VERSION: u32 = 1;
+/// Extension of identity files, without the dot.
+pub const IDENTITY_EXTENSION: &str = "sigillum-id";
+
+/// A signer's identity, as saved to a `.sigillum-id` file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Identity {
+    format: String,
+    version: u32,
+    /// The name the signer goes by, offered as the trusted key's name.
+    pub name: String,
+    /// PEM public key.
+    pub public_key: String,
+    pub algorithm: KeyAlgorithm,
+    /// SHA-256 of the public key, as colon-separated hex.
+    pub fingerprint: String,
+    /// PEM certificate naming the key's owner, if one was issued.
+    #[serde(default)]
+    pub certificate: Option<String>,
+    /// When the key was created; `None` for keys saved before this was
+    /// recorded.
+    #[serde(default)]
+    pub created: Option<DateTime<Utc>>,
+}
+
+impl Identity {
+    /// The identity of `keypair`'s owner, who goes by `name`.
+    pub fn new(keypair: &KeyPair, name: &str) -> Result<Identity> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err("Give the name the identity goes by".into());
+        }
+        let public_key = PublicKey::from_public_key_pem(&keypair.public_key)?;
+        Ok(Identity {
+            format: IDENTITY_FORMAT.to_string(),
+            version: IDENTITY_VERSION,
+            name: name.to_string(),
+            public_key: public_key.to_public_key_pem()?,
+            algorithm: public_key.algorithm()?,
+            fingerprint: public_key.fingerprint()?,
+            certificate: keypair.certificate.clone(),
+            created: keypair.created,
+        })
+    }
+
+    /// Reads an identity file, checking that its fingerprint and certificate
+    /// belong to its key.
+    pub fn from_json(data: &[u8]) -> Result<Identity> {
+        let identity: Identity = serde_json::from_slice(data).map_err(|_| "Not a Sigillum identity file".to_string())?;
+        if identity.format != IDENTITY_FORMAT {
+            return Err("Not a Sigillum identity file".into());
+        }
+        if identity.version > IDENTITY_VERSION {
+            return Err(format!("Identity file version {} is newer than this version of Sigillum supports", identity.version).into());
+        }
+        let public_key = PublicKey::from_public_key_pem(identity.public_key.trim())?;
+        let fingerprint = public_key.fingerprint()?;
+        if fingerprint != identity.fingerprint {
+            return Err("The identity file's fingerprint does not match its key".into());
+        }
+        if let Some(pem) = &identity.certificate {
+            let certified = certificate::public_key(&certificate::from_pem(pem)?)?.fingerprint()?;
+            if certified != fingerprint {
+                return Err("The identity file's certificate is for another key".into());
+            }
+        }
+        Ok(identity)
+    }
+
+    pub fn to_json(&self) -> Result<Vec<u8>> {
+        Ok(serde_json::to_vec_pretty(self).map_err(|e| format!("JSON error: {}", e))?)
+    }
+
+    /// File name for the identity, e.g. "Jane Doe.sigillum-id", keeping
+    /// only characters that are safe in file names.
+    pub fn file_name(&self) -> String {
+        let stem: String = self
+            .name
+            .chars()
+            .map(|c| if c.is_alphanumeric() || " -_.".contains(c) { c } else { '_' })
+            .collect();
+        format!("{}.{}", stem.trim_matches(|c: char| c == '.' || c.is_whitespace()), IDENTITY_EXTENSION)
+    }
+}
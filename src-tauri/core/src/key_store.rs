@@ -0,0 +1,356 @@
+use crate::api::{KeyPair, SigningDefaults};
+use crate::error::{Result, SigillumError};
+use crate::discovery;
+use crate::settings::{default_profile, Settings};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+use std::str::FromStr;
+use zeroize::Zeroizing;
+
+/// Single-key layout used before profiles existed; moved to the default
+/// profile the first time the store is opened.
+const LEGACY_KEY_FILE: &str = "keypair.json";
+const LEGACY_KEYCHAIN_ENTRY: &str = "keypair";
+
+const KEYS_DIR: &str = "keys";
+
+pub(crate) const KEYCHAIN_SERVICE: &str = "com.sigillum.app";
+
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// Where the keypairs are kept.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyStorage {
+    /// `keys/<profile>.json` in the app data directory.
+    #[default]
+    File,
+    /// The platform secret store: Keychain on macOS, Credential Manager on
+    /// Windows and the Secret Service (GNOME Keyring, KWallet) on Linux.
+    Keychain,
+}
+
+impl fmt::Display for KeyStorage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeyStorage::File => write!(f, "file"),
+            KeyStorage::Keychain => write!(f, "keychain"),
+        }
+    }
+}
+
+impl FromStr for KeyStorage {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "file" => Ok(KeyStorage::File),
+            "keychain" => Ok(KeyStorage::Keychain),
+            other => Err(format!("Unknown key storage '{}' (expected 'file' or 'keychain')", other)),
+        }
+    }
+}
+
+/// Loads and saves keypairs through whichever backend `settings.json`
+/// selects, so the GUI and the CLI always agree on where keys live.
+///
+/// Each signing identity is a named profile. `load`/`save`/`exists` act on the
+/// selected profile, which starts out as the active one from the settings.
+pub struct KeyStore {
+    dir: PathBuf,
+    settings: Settings,
+    selected: String,
+}
+
+impl KeyStore {
+    /// Opens the store rooted at the app data directory `dir`.
+    pub fn open(dir: PathBuf) -> Result<KeyStore> {
+        let settings = Settings::load(&dir)?;
+
+        let mut store = KeyStore {
+            dir,
+            selected: settings.active_profile.clone(),
+            settings,
+        };
+        store.migrate_legacy_key()?;
+        Ok(store)
+    }
+
+    pub fn storage(&self) -> KeyStorage {
+        self.settings.key_storage
+    }
+
+    pub fn profiles(&self) -> &[String] {
+        &self.settings.profiles
+    }
+
+    pub fn active_profile(&self) -> &str {
+        &self.settings.active_profile
+    }
+
+    /// Works on `profile` for this session without changing the active one.
+    pub fn select(&mut self, profile: &str) -> Result<()> {
+        validate_profile_name(profile)?;
+        self.selected = profile.to_string();
+        Ok(())
+    }
+
+    pub fn selected_profile(&self) -> &str {
+        &self.selected
+    }
+
+    pub fn set_active_profile(&mut self, profile: &str) -> Result<()> {
+        if !self.has_profile(profile) {
+            return Err(format!("No profile named '{}'", profile).into());
+        }
+        self.settings.active_profile = profile.to_string();
+        self.selected = profile.to_string();
+        self.save_settings()
+    }
+
+    pub fn exists(&self) -> bool {
+        self.has_profile(&self.selected) && self.read(self.storage(), &self.selected).is_ok()
+    }
+
+    pub fn load(&self) -> Result<KeyPair> {
+        if !self.has_profile(&self.selected) {
+            return Err(SigillumError::NoKey(format!("No key for profile '{}'; generate or import one", self.selected)));
+        }
+        self.read(self.storage(), &self.selected)
+    }
+
+    /// Saves `keypair` to the selected profile, creating it if needed. A
+    /// profile that already exists keeps its signing defaults, and its
+    /// retired keys unless `keypair` brings its own.
+    pub fn save(&mut self, keypair: &KeyPair) -> Result<()> {
+        let mut keypair = keypair.clone();
+        if self.has_profile(&self.selected) {
+            if let Ok(existing) = self.read(self.storage(), &self.selected) {
+                keypair.defaults = existing.defaults;
+                if keypair.retired.is_empty() {
+                    keypair.retired = existing.retired;
+                }
+            }
+        }
+        self.write(self.storage(), &self.selected, &keypair)?;
+        if !self.has_profile(&self.selected) {
+            let profile = self.selected.clone();
+            self.add_profile(profile)?;
+        }
+        Ok(())
+    }
+
+    /// Adds a new profile holding `keypair`; fails if the name is taken.
+    pub fn create_profile(&mut self, profile: &str, keypair: &KeyPair) -> Result<()> {
+        validate_profile_name(profile)?;
+        if self.has_profile(profile) {
+            return Err(format!("A profile named '{}' already exists", profile).into());
+        }
+        self.write(self.storage(), profile, keypair)?;
+        self.add_profile(profile.to_string())
+    }
+
+    /// Makes `keypair` the selected profile's key, keeping the current one as
+    /// a retired version. Returns the new key's version.
+    pub fn rotate(&mut self, keypair: KeyPair) -> Result<u32> {
+        let keypair = self.load()?.rotate(keypair);
+        self.write(self.storage(), &self.selected, &keypair)?;
+        Ok(keypair.version())
+    }
+
+    /// Changes the selected profile's signer name, extra text, key URL and
+    /// key domain defaults.
+    pub fn set_defaults(&mut self, mut defaults: SigningDefaults) -> Result<()> {
+        defaults.key_url = defaults.key_url.map(|url| url.trim().to_string()).filter(|url| !url.is_empty());
+        if let Some(url) = &defaults.key_url {
+            discovery::check_key_url(url)?;
+        }
+        defaults.key_domain = defaults.key_domain.map(|identity| identity.trim().to_string()).filter(|identity| !identity.is_empty());
+        if let Some(identity) = &defaults.key_domain {
+            discovery::key_domain(identity)?;
+        }
+        let keypair = KeyPair {
+            defaults,
+            ..self.load()?
+        };
+        self.write(self.storage(), &self.selected, &keypair)
+    }
+
+    /// Gives a profile a new name, keeping its key, defaults and whether it
+    /// is the active one.
+    pub fn rename_profile(&mut self, profile: &str, new_name: &str) -> Result<()> {
+        validate_profile_name(new_name)?;
+        if !self.has_profile(profile) {
+            return Err(format!("No profile named '{}'", profile).into());
+        }
+        if self.has_profile(new_name) {
+            return Err(format!("A profile named '{}' already exists", new_name).into());
+        }
+        let keypair = self.read(self.storage(), profile)?;
+        self.write(self.storage(), new_name, &keypair)?;
+        self.remove(self.storage(), profile)?;
+        for name in self.settings.profiles.iter_mut().filter(|name| *name == profile) {
+            *name = new_name.to_string();
+        }
+        if self.settings.active_profile == profile {
+            self.settings.active_profile = new_name.to_string();
+        }
+        if self.selected == profile {
+            self.selected = new_name.to_string();
+        }
+        self.save_settings()
+    }
+
+    /// Removes a profile and its key. Deleting the active profile makes the
+    /// first remaining one active.
+    pub fn delete_profile(&mut self, profile: &str) -> Result<()> {
+        if !self.has_profile(profile) {
+            return Err(format!("No profile named '{}'", profile).into());
+        }
+        self.remove(self.storage(), profile)?;
+        self.settings.profiles.retain(|p| p != profile);
+        if self.settings.active_profile == profile {
+            self.settings.active_profile = self.settings.profiles.first().cloned().unwrap_or_else(default_profile);
+        }
+        if self.selected == profile {
+            self.selected = self.settings.active_profile.clone();
+        }
+        self.save_settings()
+    }
+
+    /// Moves every profile's keypair to `target` and makes it the configured
+    /// backend. Old copies are only removed once all new ones have been read
+    /// back successfully.
+    pub fn migrate(&mut self, target: KeyStorage) -> Result<()> {
+        let source = self.storage();
+        if target == source {
+            return Ok(());
+        }
+
+        let mut keypairs = Vec::new();
+        for profile in &self.settings.profiles {
+            keypairs.push((profile.clone(), self.read(source, profile)?));
+        }
+        for (profile, keypair) in &keypairs {
+            self.write(target, profile, keypair)?;
+            if self.read(target, profile)?.private_key != keypair.private_key {
+                return Err(format!("Key for '{}' could not be read back from the new storage", profile).into());
+            }
+        }
+        for (profile, _) in &keypairs {
+            self.remove(source, profile)?;
+        }
+
+        self.settings.key_storage = target;
+        self.save_settings()
+    }
+
+    /// Records a new profile, making it active if the active one is missing
+    /// (as on a fresh install, or after the last profile was deleted).
+    fn add_profile(&mut self, profile: String) -> Result<()> {
+        if !self.has_profile(&self.settings.active_profile) {
+            self.settings.active_profile = profile.clone();
+        }
+        self.settings.profiles.push(profile);
+        self.save_settings()
+    }
+
+    fn has_profile(&self, profile: &str) -> bool {
+        self.settings.profiles.iter().any(|p| p == profile)
+    }
+
+    fn read(&self, storage: KeyStorage, profile: &str) -> Result<KeyPair> {
+        match storage {
+            KeyStorage::File => KeyPair::load(&self.key_path(profile)),
+            KeyStorage::Keychain => {
+                let json = Zeroizing::new(keychain_entry(profile)?.get_password().map_err(keychain_error)?);
+                Ok(serde_json::from_str(&json).map_err(|e| format!("JSON error: {}", e))?)
+            }
+        }
+    }
+
+    fn write(&self, storage: KeyStorage, profile: &str, keypair: &KeyPair) -> Result<()> {
+        match storage {
+            KeyStorage::File => {
+                fs::create_dir_all(self.dir.join(KEYS_DIR)).map_err(|e| format!("Failed to create dir: {}", e))?;
+                keypair.save(&self.key_path(profile))
+            }
+            KeyStorage::Keychain => {
+                let json = Zeroizing::new(serde_json::to_string(keypair).map_err(|e| format!("JSON error: {}", e))?);
+                keychain_entry(profile)?.set_password(&json).map_err(keychain_error)
+            }
+        }
+    }
+
+    fn remove(&self, storage: KeyStorage, profile: &str) -> Result<()> {
+        match storage {
+            KeyStorage::File => Ok(fs::remove_file(self.key_path(profile)).map_err(|e| format!("Failed to remove key file: {}", e))?),
+            KeyStorage::Keychain => keychain_entry(profile)?.delete_credential().map_err(keychain_error),
+        }
+    }
+
+    /// Turns a key saved by an older version into the default profile.
+    fn migrate_legacy_key(&mut self) -> Result<()> {
+        if !self.settings.profiles.is_empty() {
+            return Ok(());
+        }
+
+        match self.storage() {
+            KeyStorage::File => {
+                let legacy_path = self.dir.join(LEGACY_KEY_FILE);
+                if !legacy_path.exists() {
+                    return Ok(());
+                }
+                fs::create_dir_all(self.dir.join(KEYS_DIR)).map_err(|e| format!("Failed to create dir: {}", e))?;
+                fs::rename(&legacy_path, self.key_path(DEFAULT_PROFILE))
+                    .map_err(|e| format!("Failed to move key file: {}", e))?;
+            }
+            KeyStorage::Keychain => {
+                let legacy = keychain_entry(LEGACY_KEYCHAIN_ENTRY)?;
+                let Ok(json) = legacy.get_password().map(Zeroizing::new) else {
+                    return Ok(());
+                };
+                keychain_entry(DEFAULT_PROFILE)?.set_password(&json).map_err(keychain_error)?;
+                legacy.delete_credential().map_err(keychain_error)?;
+            }
+        }
+
+        self.settings.profiles.push(default_profile());
+        self.settings.active_profile = default_profile();
+        self.selected = default_profile();
+        self.save_settings()
+    }
+
+    fn save_settings(&self) -> Result<()> {
+        self.settings.save(&self.dir)
+    }
+
+    fn key_path(&self, profile: &str) -> PathBuf {
+        self.dir.join(KEYS_DIR).join(format!("{}.json", profile))
+    }
+}
+
+/// Profile names become file names, so keep them to a safe character set.
+fn validate_profile_name(name: &str) -> Result<()> {
+    let valid = !name.is_empty()
+        && name.len() <= 64
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if valid {
+        Ok(())
+    } else {
+        Err(format!(
+            "Invalid profile name '{}': use letters, digits, '-' or '_' (at most 64)",
+            name
+        ).into())
+    }
+}
+
+pub(crate) fn keychain_entry(name: &str) -> Result<keyring::Entry> {
+    keyring::Entry::new(KEYCHAIN_SERVICE, name).map_err(keychain_error)
+}
+
+pub(crate) fn keychain_error(e: keyring::Error) -> SigillumError {
+    SigillumError::Other(format!("Keychain error: {}", e))
+}
@@ -0,0 +1,201 @@
+//! Keys kept in the macOS Keychain, including P-256 keys made in the Secure
+//! Enclave. The Keychain signs each digest itself, so the private key never
+//! leaves it; a key profile only records the key's label, as a
+//! `KmsKey::Keychain`.
+//!
+//! RSA keys sign with PKCS#1 v1.5 and P-256 keys with ECDSA over the digest
+//! bytes, as file keys of the same type do. Secure Enclave keys are kept in
+//! the data protection keychain, which only a code-signed app with a keychain
+//! access group may use.
+
+use crate::error::Result;
+use crate::kms::RemoteSigner;
+
+/// Looks up the private key labelled `label`, in the data protection
+/// keychain and then in the login keychain.
+pub fn open(label: &str) -> Result<Box<dyn RemoteSigner>> {
+    platform::open(label)
+}
+
+/// Generates a P-256 key labelled `label` in the Secure Enclave, from where
+/// it cannot be read out. Signing with it asks for nothing more than the Mac
+/// being unlocked.
+pub fn generate(label: &str) -> Result<()> {
+    platform::generate(label)
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use crate::error::Result;
+    use crate::hash::HashAlgorithm;
+    use crate::keys::PublicKey;
+    use crate::kms::RemoteSigner;
+    use rsa::pkcs1::DecodeRsaPublicKey;
+    use rsa::RsaPublicKey;
+    use security_framework::access_control::{ProtectionMode, SecAccessControl};
+    use security_framework::item::{ItemClass, ItemSearchOptions, KeyClass, Location, Reference, SearchResult};
+    use security_framework::key::{Algorithm, GenerateKeyOptions, KeyType, SecKey, Token};
+
+    /// `kSecAccessControlPrivateKeyUsage`, which lets a Secure Enclave key
+    /// sign.
+    const PRIVATE_KEY_USAGE: u64 = 1 << 30;
+
+    pub fn open(label: &str) -> Result<Box<dyn RemoteSigner>> {
+        Ok(Box::new(KeychainSigner::open(label)?))
+    }
+
+    struct KeychainSigner {
+        label: String,
+        key: SecKey,
+        public_key: PublicKey,
+    }
+
+    impl KeychainSigner {
+        fn open(label: &str) -> Result<KeychainSigner> {
+            let key = find(label, true)
+                .or_else(|| find(label, false))
+                .ok_or_else(|| format!("No private key labelled '{}' in the Keychain", label))?;
+            let public_key = public_key(&key).map_err(|e| format!("Keychain key '{}': {}", label, e))?;
+            Ok(KeychainSigner {
+                label: label.to_string(),
+                key,
+                public_key,
+            })
+        }
+    }
+
+    fn find(label: &str, data_protection: bool) -> Option<SecKey> {
+        let mut search = ItemSearchOptions::new();
+        search
+            .class(ItemClass::key())
+            .key_class(KeyClass::private())
+            .label(label)
+            .load_refs(true)
+            .limit(1);
+        if data_protection {
+            search.ignore_legacy_keychains();
+        }
+        search.search().ok()?.into_iter().find_map(|result| match result {
+            SearchResult::Ref(Reference::Key(key)) => Some(key),
+            _ => None,
+        })
+    }
+
+    /// The Keychain gives RSA public keys as PKCS#1 and EC ones as an
+    /// uncompressed X9.63 point.
+    fn public_key(key: &SecKey) -> Result<PublicKey> {
+        let bytes = key
+            .public_key()
+            .and_then(|public_key| public_key.external_representation())
+            .ok_or("its public key cannot be read")?
+            .to_vec();
+        if bytes.len() == 65 && bytes[0] == 0x04 {
+            return Ok(p256::ecdsa::VerifyingKey::from_sec1_bytes(&bytes)
+                .map(PublicKey::P256)
+                .map_err(|e| format!("invalid P-256 key: {}", e))?);
+        }
+        Ok(RsaPublicKey::from_pkcs1_der(&bytes)
+            .map(PublicKey::Rsa)
+            .map_err(|_| "only RSA and P-256 keys can sign")?)
+    }
+
+    impl RemoteSigner for KeychainSigner {
+        fn public_key(&self) -> &PublicKey {
+            &self.public_key
+        }
+
+        fn sign_digest(&self, hash: HashAlgorithm, digest: &[u8]) -> Result<Vec<u8>> {
+            let algorithm = signature_algorithm(&self.public_key, hash)?;
+            let signature = self
+                .key
+                .create_signature(algorithm, digest)
+                .map_err(|e| format!("The Keychain could not sign with '{}': {}", self.label, e))?;
+            match self.public_key {
+                // ECDSA signatures come back in DER; local P-256 keys sign as
+                // fixed-size r || s.
+                PublicKey::P256(_) => Ok(p256::ecdsa::Signature::from_der(&signature)
+                    .map(|signature| signature.to_bytes().to_vec())
+                    .map_err(|e| format!("Invalid signature from the Keychain: {}", e))?),
+                _ => Ok(signature),
+            }
+        }
+    }
+
+    /// How the Keychain signs a `hash` digest with a key like `public_key`,
+    /// to match what a file key of that type signs.
+    fn signature_algorithm(public_key: &PublicKey, hash: HashAlgorithm) -> Result<Algorithm> {
+        Ok(match (public_key, hash) {
+            (PublicKey::Rsa(_), HashAlgorithm::Sha256) => Algorithm::RSASignatureDigestPKCS1v15SHA256,
+            (PublicKey::Rsa(_), HashAlgorithm::Sha384) => Algorithm::RSASignatureDigestPKCS1v15SHA384,
+            (PublicKey::Rsa(_), HashAlgorithm::Sha512) => Algorithm::RSASignatureDigestPKCS1v15SHA512,
+            // PKCS#1 has no identifier for BLAKE3, so none is added.
+            (PublicKey::Rsa(_), HashAlgorithm::Blake3) => Algorithm::RSASignatureDigestPKCS1v15Raw,
+            // Digests longer than the curve are truncated, as local keys
+            // truncate them.
+            (PublicKey::P256(_), _) => Algorithm::ECDSASignatureDigestX962,
+            (PublicKey::Ed25519(_), _) => return Err("The Keychain cannot sign with Ed25519 keys".into()),
+        })
+    }
+
+    pub fn generate(label: &str) -> Result<()> {
+        if find(label, true).is_some() {
+            return Err(format!("The Keychain already has a key labelled '{}'", label).into());
+        }
+        let access = SecAccessControl::create_with_protection(Some(ProtectionMode::AccessibleWhenUnlockedThisDeviceOnly), PRIVATE_KEY_USAGE)
+            .map_err(|e| format!("Failed to set up the key's access control: {}", e))?;
+        let mut options = GenerateKeyOptions::default();
+        options
+            .set_key_type(KeyType::ec_sec_prime_random())
+            .set_size_in_bits(256)
+            .set_label(label)
+            .set_token(Token::SecureEnclave)
+            .set_location(Location::DataProtectionKeychain)
+            .set_access_control(access);
+        SecKey::new(&options).map_err(|e| format!("Failed to generate a key in the Secure Enclave: {}", e))?;
+        Ok(())
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use rand::rngs::OsRng;
+
+        #[test]
+        fn rsa_keys_sign_with_pkcs1_over_each_digest() {
+            let key = PublicKey::Rsa(RsaPublicKey::from(&rsa::RsaPrivateKey::new(&mut OsRng, 2048).unwrap()));
+            let algorithm = |hash| signature_algorithm(&key, hash).unwrap();
+            assert!(matches!(algorithm(HashAlgorithm::Sha256), Algorithm::RSASignatureDigestPKCS1v15SHA256));
+            assert!(matches!(algorithm(HashAlgorithm::Sha384), Algorithm::RSASignatureDigestPKCS1v15SHA384));
+            assert!(matches!(algorithm(HashAlgorithm::Sha512), Algorithm::RSASignatureDigestPKCS1v15SHA512));
+            assert!(matches!(algorithm(HashAlgorithm::Blake3), Algorithm::RSASignatureDigestPKCS1v15Raw));
+        }
+
+        #[test]
+        fn p256_keys_sign_with_ecdsa_over_any_digest() {
+            let key = PublicKey::P256(*p256::ecdsa::SigningKey::random(&mut OsRng).verifying_key());
+            for hash in [HashAlgorithm::Sha256, HashAlgorithm::Sha512, HashAlgorithm::Blake3] {
+                assert!(matches!(signature_algorithm(&key, hash), Ok(Algorithm::ECDSASignatureDigestX962)));
+            }
+        }
+
+        #[test]
+        fn ed25519_keys_cannot_sign() {
+            let key = PublicKey::Ed25519(ed25519_dalek::SigningKey::generate(&mut OsRng).verifying_key());
+            assert!(signature_algorithm(&key, HashAlgorithm::Sha256).is_err());
+        }
+    }
+}
+
+#[cfg(not(target_os = "macos"))]
+mod platform {
+    use crate::error::Result;
+    use crate::kms::RemoteSigner;
+
+    pub fn open(_label: &str) -> Result<Box<dyn RemoteSigner>> {
+        Err("Keychain keys can only be used on macOS".into())
+    }
+
+    pub fn generate(_label: &str) -> Result<()> {
+        Err("Secure Enclave keys can only be made on a Mac".into())
+    }
+}
@@ -0,0 +1,847 @@
+//! Keys kept in a key management service: AWS KMS, Google Cloud KMS, Azure
+//! Key Vault or the transit engine of HashiCorp Vault, or in the macOS
+//! Keychain (see [`crate::keychain`]) or Windows certificate store (see
+//! [`crate::cert_store`]). Only the digest being signed is sent to the
+//! service, and the key never leaves it; a key profile records which key to
+//! use.
+//!
+//! Credentials come from each provider's usual configuration, so whatever
+//! its own tools are signed in with is used:
+//! - AWS: `AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY` and `AWS_SESSION_TOKEN`,
+//!   or else the `AWS_PROFILE` (or default) profile of `~/.aws/credentials`.
+//! - Google Cloud: `GOOGLE_OAUTH_ACCESS_TOKEN`, or else `gcloud auth
+//!   print-access-token`.
+//! - Azure: `AZURE_ACCESS_TOKEN`, or else `az account get-access-token`.
+//! - Vault: `VAULT_TOKEN`, an AppRole login with `VAULT_ROLE_ID` and
+//!   `VAULT_SECRET_ID`, or else the token `vault login` left in
+//!   `~/.vault-token`. `VAULT_NAMESPACE` is honoured.
+//!
+//! RSA keys sign with PKCS#1 v1.5, P-256 keys with ECDSA and Ed25519 keys
+//! (in Vault only) over the digest bytes, as file keys of the same type do,
+//! so signatures made either way verify alike.
+
+use crate::cert_store;
+use crate::error::Result;
+use crate::hash::HashAlgorithm;
+use crate::keychain;
+use crate::keys::PublicKey;
+use base64::engine::general_purpose::{STANDARD as BASE64, URL_SAFE_NO_PAD as BASE64_URL};
+use base64::Engine;
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use rsa::pkcs1v15::{Signature, VerifyingKey};
+use rsa::signature::{Keypair, Signer};
+use rsa::{BigUint, RsaPublicKey};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io::Read;
+use std::path::PathBuf;
+use std::process::Command;
+use std::str::FromStr;
+use std::time::Duration;
+use x509_cert::der::asn1::Any;
+use x509_cert::der::oid::db::rfc5912;
+use x509_cert::spki::{AlgorithmIdentifierOwned, DynSignatureAlgorithmIdentifier};
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+const MAX_RESPONSE_SIZE: u64 = 1024 * 1024;
+
+const AWS: &str = "AWS KMS";
+const GCP: &str = "Google Cloud KMS";
+const AZURE: &str = "Azure Key Vault";
+const VAULT: &str = "Vault";
+const KEYCHAIN: &str = "macOS Keychain";
+const CERT_STORE: &str = "Windows certificate store";
+
+const AZURE_API_VERSION: &str = "7.4";
+const AZURE_RESOURCE: &str = "https://vault.azure.net";
+
+const DEFAULT_APPROLE_MOUNT: &str = "approle";
+
+/// Which key in which service to sign with, as stored in a key profile.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "provider", rename_all = "lowercase")]
+pub enum KmsKey {
+    /// A key ID, alias or ARN, in the region whose KMS endpoint is used.
+    Aws { key_id: String, region: String },
+    /// A key version, "projects/.../cryptoKeyVersions/N".
+    Gcp { key_version: String },
+    /// A key identifier, "https://<vault>.vault.azure.net/keys/<name>", with
+    /// or without a version; without one, the current version is used.
+    Azure { key_id: String },
+    /// A named key of the transit engine mounted at `mount` on the Vault
+    /// server at `address`; the latest version of the key signs.
+    Vault { address: String, mount: String, key_name: String },
+    /// The label of a private key in the macOS Keychain or Secure Enclave.
+    Keychain { label: String },
+    /// The thumbprint of a certificate in the Windows user certificate
+    /// store, whose private key CNG signs with.
+    CertStore { thumbprint: String },
+}
+
+/// Parses "aws:<key ID or ARN>", "gcp:projects/...", "azure:https://...",
+/// "vault:[<address>/]<mount>/<key name>", "keychain:<label>" or
+/// "certstore:<thumbprint>". An AWS key given by its ID or
+/// alias is taken to be in the region set by `AWS_REGION` or
+/// `AWS_DEFAULT_REGION`, and a Vault key without an address to be on the
+/// server at `VAULT_ADDR`.
+impl FromStr for KmsKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let (provider, key) = s
+            .split_once(':')
+            .ok_or("Give the key as aws:<key>, gcp:<key version>, azure:<key URL>, vault:<mount>/<key name>, keychain:<label> or certstore:<thumbprint>")?;
+        let key = key.trim();
+        match provider.to_ascii_lowercase().as_str() {
+            "aws" => {
+                // arn:aws:kms:<region>:<account>:key/<id>
+                let region = match key.strip_prefix("arn:") {
+                    Some(arn) => arn.split(':').nth(2).map(str::to_string),
+                    None => env::var("AWS_REGION").or_else(|_| env::var("AWS_DEFAULT_REGION")).ok(),
+                };
+                let region = region
+                    .filter(|region| !region.is_empty())
+                    .ok_or("Give the AWS key as an ARN, or set AWS_REGION")?;
+                Ok(KmsKey::Aws {
+                    key_id: key.to_string(),
+                    region,
+                })
+            }
+            "gcp" => {
+                if !key.starts_with("projects/") || !key.contains("/cryptoKeyVersions/") {
+                    return Err("Give the Google Cloud key as projects/.../cryptoKeyVersions/N".to_string());
+                }
+                Ok(KmsKey::Gcp {
+                    key_version: key.to_string(),
+                })
+            }
+            "azure" => {
+                if !key.starts_with("https://") || !key.contains("/keys/") {
+                    return Err("Give the Azure key as https://<vault>.vault.azure.net/keys/<name>".to_string());
+                }
+                Ok(KmsKey::Azure {
+                    key_id: key.trim_end_matches('/').to_string(),
+                })
+            }
+            "vault" => {
+                // An address is a URL with no path of its own.
+                let (address, path) = match key.split_once("://") {
+                    Some((scheme, rest)) => {
+                        let (host, path) = rest.split_once('/').unwrap_or((rest, ""));
+                        (format!("{}://{}", scheme, host), path.to_string())
+                    }
+                    None => {
+                        let address = env::var("VAULT_ADDR")
+                            .ok()
+                            .filter(|address| !address.trim().is_empty())
+                            .ok_or("Give the Vault key as https://<server>/<mount>/<key name>, or set VAULT_ADDR")?;
+                        (address.trim().trim_end_matches('/').to_string(), key.to_string())
+                    }
+                };
+                let path = path.trim_matches('/');
+                let (mount, key_name) = path
+                    .rsplit_once('/')
+                    .filter(|(mount, key_name)| !mount.is_empty() && !key_name.is_empty())
+                    .ok_or("Give the Vault key as <mount>/<key name>, e.g. transit/signing")?;
+                Ok(KmsKey::Vault {
+                    address,
+                    mount: mount.to_string(),
+                    key_name: key_name.to_string(),
+                })
+            }
+            "keychain" => {
+                if key.is_empty() {
+                    return Err("Give the Keychain key as keychain:<label>".to_string());
+                }
+                Ok(KmsKey::Keychain { label: key.to_string() })
+            }
+            "certstore" => Ok(KmsKey::CertStore {
+                thumbprint: cert_store::parse_thumbprint(key).map_err(|e| e.to_string())?,
+            }),
+            _ => Err(format!(
+                "Unknown key management service '{}'; use aws, gcp, azure, vault, keychain or certstore",
+                provider
+            )),
+        }
+    }
+}
+
+impl fmt::Display for KmsKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KmsKey::Aws { key_id, .. } => write!(f, "aws:{}", key_id),
+            KmsKey::Gcp { key_version } => write!(f, "gcp:{}", key_version),
+            KmsKey::Azure { key_id } => write!(f, "azure:{}", key_id),
+            KmsKey::Vault { address, mount, key_name } => write!(f, "vault:{}/{}/{}", address, mount, key_name),
+            KmsKey::Keychain { label } => write!(f, "keychain:{}", label),
+            KmsKey::CertStore { thumbprint } => write!(f, "certstore:{}", thumbprint),
+        }
+    }
+}
+
+impl KmsKey {
+    /// The name of the service the key is in.
+    pub fn service(&self) -> &'static str {
+        match self {
+            KmsKey::Aws { .. } => AWS,
+            KmsKey::Gcp { .. } => GCP,
+            KmsKey::Azure { .. } => AZURE,
+            KmsKey::Vault { .. } => VAULT,
+            KmsKey::Keychain { .. } => KEYCHAIN,
+            KmsKey::CertStore { .. } => CERT_STORE,
+        }
+    }
+
+    /// Looks the key up in its service, which must let the current
+    /// credentials read its public key.
+    pub fn open(&self) -> Result<KmsSigner> {
+        let signer: Box<dyn RemoteSigner> = match self {
+            KmsKey::Aws { key_id, region } => Box::new(AwsSigner::open(key_id, region)?),
+            KmsKey::Gcp { key_version } => Box::new(GcpSigner::open(key_version)?),
+            KmsKey::Azure { key_id } => Box::new(AzureSigner::open(key_id)?),
+            KmsKey::Vault { address, mount, key_name } => Box::new(VaultSigner::open(address, mount, key_name)?),
+            KmsKey::Keychain { label } => keychain::open(label)?,
+            KmsKey::CertStore { thumbprint } => cert_store::open(thumbprint)?,
+        };
+        Ok(KmsSigner {
+            key: self.clone(),
+            signer,
+        })
+    }
+}
+
+/// A key that signs somewhere else, which only ever sees digests. Each
+/// service is one implementation; `KmsKey::open` picks it.
+pub trait RemoteSigner: Send + Sync {
+    fn public_key(&self) -> &PublicKey;
+
+    /// Signs a `digest` made with `hash`, giving the signature in the form
+    /// `PrivateKey::sign_digest` gives for a local key of the same type.
+    fn sign_digest(&self, hash: HashAlgorithm, digest: &[u8]) -> Result<Vec<u8>>;
+}
+
+/// A key in a key management service, opened for signing.
+pub struct KmsSigner {
+    key: KmsKey,
+    signer: Box<dyn RemoteSigner>,
+}
+
+impl KmsSigner {
+    pub fn key(&self) -> &KmsKey {
+        &self.key
+    }
+
+    pub fn public_key(&self) -> &PublicKey {
+        self.signer.public_key()
+    }
+
+    pub fn sign_digest(&self, hash: HashAlgorithm, digest: &[u8]) -> Result<Vec<u8>> {
+        self.signer.sign_digest(hash, digest)
+    }
+
+    /// Signs through the `signature` traits, with SHA-256 unless the key is
+    /// Ed25519, for building certificates and CMS signatures.
+    pub(crate) fn cms_signer(&self) -> CmsSigner<'_> {
+        match self.public_key() {
+            PublicKey::Rsa(key) => CmsSigner::Rsa(RsaSigner(self, key)),
+            PublicKey::P256(key) => CmsSigner::P256(P256Signer(self, key)),
+            PublicKey::Ed25519(key) => CmsSigner::Ed25519(Ed25519Signer(self, key)),
+        }
+    }
+}
+
+/// A KMS key as a signer of whichever type it is.
+pub(crate) enum CmsSigner<'a> {
+    Rsa(RsaSigner<'a>),
+    P256(P256Signer<'a>),
+    Ed25519(Ed25519Signer<'a>),
+}
+
+pub(crate) struct RsaSigner<'a>(&'a KmsSigner, &'a RsaPublicKey);
+
+impl Keypair for RsaSigner<'_> {
+    type VerifyingKey = VerifyingKey<Sha256>;
+
+    fn verifying_key(&self) -> Self::VerifyingKey {
+        VerifyingKey::new(self.1.clone())
+    }
+}
+
+impl DynSignatureAlgorithmIdentifier for RsaSigner<'_> {
+    fn signature_algorithm_identifier(&self) -> x509_cert::spki::Result<AlgorithmIdentifierOwned> {
+        Ok(AlgorithmIdentifierOwned {
+            oid: rfc5912::SHA_256_WITH_RSA_ENCRYPTION,
+            parameters: Some(Any::null()),
+        })
+    }
+}
+
+impl Signer<Signature> for RsaSigner<'_> {
+    fn try_sign(&self, msg: &[u8]) -> std::result::Result<Signature, rsa::signature::Error> {
+        let signature = self
+            .0
+            .sign_digest(HashAlgorithm::Sha256, &Sha256::digest(msg))
+            .map_err(rsa::signature::Error::from_source)?;
+        Signature::try_from(signature.as_slice())
+    }
+}
+
+pub(crate) struct P256Signer<'a>(&'a KmsSigner, &'a p256::ecdsa::VerifyingKey);
+
+impl Keypair for P256Signer<'_> {
+    type VerifyingKey = p256::ecdsa::VerifyingKey;
+
+    fn verifying_key(&self) -> Self::VerifyingKey {
+        *self.1
+    }
+}
+
+impl DynSignatureAlgorithmIdentifier for P256Signer<'_> {
+    fn signature_algorithm_identifier(&self) -> x509_cert::spki::Result<AlgorithmIdentifierOwned> {
+        Ok(AlgorithmIdentifierOwned {
+            oid: rfc5912::ECDSA_WITH_SHA_256,
+            parameters: None,
+        })
+    }
+}
+
+impl Signer<p256::ecdsa::DerSignature> for P256Signer<'_> {
+    fn try_sign(&self, msg: &[u8]) -> std::result::Result<p256::ecdsa::DerSignature, rsa::signature::Error> {
+        let signature = self
+            .0
+            .sign_digest(HashAlgorithm::Sha256, &Sha256::digest(msg))
+            .map_err(rsa::signature::Error::from_source)?;
+        Ok(p256::ecdsa::Signature::from_slice(&signature)?.to_der())
+    }
+}
+
+/// Ed25519 signs the message itself, which is what `sign_digest` signs.
+pub(crate) struct Ed25519Signer<'a>(&'a KmsSigner, &'a ed25519_dalek::VerifyingKey);
+
+impl Keypair for Ed25519Signer<'_> {
+    type VerifyingKey = ed25519_dalek::VerifyingKey;
+
+    fn verifying_key(&self) -> Self::VerifyingKey {
+        *self.1
+    }
+}
+
+impl Signer<ed25519_dalek::Signature> for Ed25519Signer<'_> {
+    fn try_sign(&self, msg: &[u8]) -> std::result::Result<ed25519_dalek::Signature, rsa::signature::Error> {
+        let signature = self
+            .0
+            .sign_digest(HashAlgorithm::Sha256, msg)
+            .map_err(rsa::signature::Error::from_source)?;
+        ed25519_dalek::Signature::from_slice(&signature)
+    }
+}
+
+/// A key in AWS KMS, reached through its JSON API with SigV4-signed requests.
+struct AwsSigner {
+    key_id: String,
+    region: String,
+    public_key: PublicKey,
+}
+
+struct AwsCredentials {
+    access_key_id: String,
+    secret_access_key: String,
+    session_token: Option<String>,
+}
+
+impl AwsSigner {
+    fn open(key_id: &str, region: &str) -> Result<AwsSigner> {
+        let response = aws_call(region, "GetPublicKey", &json!({ "KeyId": key_id }))?;
+        if string_field(&response, "KeyUsage", AWS)? != "SIGN_VERIFY" {
+            return Err("The AWS KMS key is not a signing key".into());
+        }
+        let key_spec = string_field(&response, "KeySpec", AWS)?;
+        if !key_spec.starts_with("RSA_") && key_spec != "ECC_NIST_P256" {
+            return Err(format!("Only RSA and P-256 keys in AWS KMS can sign, not {}", key_spec).into());
+        }
+        let der = decode_field(&response, "PublicKey", AWS, &BASE64)?;
+        Ok(AwsSigner {
+            key_id: key_id.to_string(),
+            region: region.to_string(),
+            public_key: PublicKey::from_public_key_der(&der)?,
+        })
+    }
+}
+
+/// Calls `TrentService.<action>`, the KMS JSON API, in `region` with `body`.
+fn aws_call(region: &str, action: &str, body: &Value) -> Result<Value> {
+    let credentials = aws_credentials()?;
+    let host = format!("kms.{}.amazonaws.com", region);
+    let body = body.to_string();
+    let now = Utc::now();
+    let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+    let date = now.format("%Y%m%d").to_string();
+    let target = format!("TrentService.{}", action);
+
+    // Signature Version 4: sign a canonical form of the request with a
+    // key derived from the secret for today, this region and KMS.
+    let mut headers = vec![
+        ("content-type", "application/x-amz-json-1.1".to_string()),
+        ("host", host.clone()),
+        ("x-amz-date", amz_date.clone()),
+    ];
+    if let Some(token) = &credentials.session_token {
+        headers.push(("x-amz-security-token", token.clone()));
+    }
+    headers.push(("x-amz-target", target));
+    let signed_headers = headers.iter().map(|(name, _)| *name).collect::<Vec<_>>().join(";");
+    let canonical_headers: String = headers.iter().map(|(name, value)| format!("{}:{}\n", name, value)).collect();
+    let canonical_request = format!(
+        "POST\n/\n\n{}\n{}\n{}",
+        canonical_headers,
+        signed_headers,
+        hex::encode(Sha256::digest(body.as_bytes()))
+    );
+    let scope = format!("{}/{}/kms/aws4_request", date, region);
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+        amz_date,
+        scope,
+        hex::encode(Sha256::digest(canonical_request.as_bytes()))
+    );
+    let mut key = hmac_sha256(format!("AWS4{}", credentials.secret_access_key).as_bytes(), date.as_bytes());
+    for part in [region, "kms", "aws4_request"] {
+        key = hmac_sha256(&key, part.as_bytes());
+    }
+    let authorization = format!(
+        "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+        credentials.access_key_id,
+        scope,
+        signed_headers,
+        hex::encode(hmac_sha256(&key, string_to_sign.as_bytes()))
+    );
+
+    let mut request = agent().post(&format!("https://{}/", host));
+    for (name, value) in &headers {
+        if *name != "host" {
+            request = request.set(name, value);
+        }
+    }
+    send(request.set("Authorization", &authorization), Some(&body), AWS)
+}
+
+impl RemoteSigner for AwsSigner {
+    fn public_key(&self) -> &PublicKey {
+        &self.public_key
+    }
+
+    fn sign_digest(&self, hash: HashAlgorithm, digest: &[u8]) -> Result<Vec<u8>> {
+        let algorithm = match (&self.public_key, hash) {
+            (PublicKey::Rsa(_), HashAlgorithm::Sha256) => "RSASSA_PKCS1_V1_5_SHA_256",
+            (PublicKey::Rsa(_), HashAlgorithm::Sha384) => "RSASSA_PKCS1_V1_5_SHA_384",
+            (PublicKey::Rsa(_), HashAlgorithm::Sha512) => "RSASSA_PKCS1_V1_5_SHA_512",
+            (PublicKey::P256(_), HashAlgorithm::Sha256) => "ECDSA_SHA_256",
+            _ => return Err(format!("This AWS KMS key cannot sign {} digests", hash.label()).into()),
+        };
+        let response = aws_call(
+            &self.region,
+            "Sign",
+            &json!({
+                "KeyId": self.key_id,
+                "Message": BASE64.encode(digest),
+                "MessageType": "DIGEST",
+                "SigningAlgorithm": algorithm,
+            }),
+        )?;
+        fixed_size(&self.public_key, decode_field(&response, "Signature", AWS, &BASE64)?)
+    }
+}
+
+/// A key version in Google Cloud KMS. Each version has a fixed algorithm, so
+/// it only signs digests of the hash that algorithm names.
+struct GcpSigner {
+    key_version: String,
+    hash: HashAlgorithm,
+    public_key: PublicKey,
+}
+
+impl GcpSigner {
+    fn open(key_version: &str) -> Result<GcpSigner> {
+        let token = access_token("GOOGLE_OAUTH_ACCESS_TOKEN", "gcloud", &["auth", "print-access-token"])?;
+        let request = agent()
+            .get(&format!("https://cloudkms.googleapis.com/v1/{}/publicKey", key_version))
+            .set("Authorization", &format!("Bearer {}", token));
+        let response = send(request, None, GCP)?;
+
+        // e.g. RSA_SIGN_PKCS1_2048_SHA256 or EC_SIGN_P256_SHA256
+        let algorithm = string_field(&response, "algorithm", GCP)?;
+        let hash = match algorithm {
+            _ if !algorithm.starts_with("RSA_SIGN_PKCS1_") && !algorithm.starts_with("EC_SIGN_P256_") => None,
+            _ if algorithm.ends_with("_SHA256") => Some(HashAlgorithm::Sha256),
+            _ if algorithm.ends_with("_SHA384") => Some(HashAlgorithm::Sha384),
+            _ if algorithm.ends_with("_SHA512") => Some(HashAlgorithm::Sha512),
+            _ => None,
+        };
+        let hash = hash.ok_or_else(|| {
+            format!("Only PKCS#1 v1.5 RSA and P-256 keys in Google Cloud KMS can sign, not {}", algorithm)
+        })?;
+        Ok(GcpSigner {
+            key_version: key_version.to_string(),
+            hash,
+            public_key: PublicKey::from_public_key_pem(string_field(&response, "pem", GCP)?)?,
+        })
+    }
+}
+
+impl RemoteSigner for GcpSigner {
+    fn public_key(&self) -> &PublicKey {
+        &self.public_key
+    }
+
+    fn sign_digest(&self, hash: HashAlgorithm, digest: &[u8]) -> Result<Vec<u8>> {
+        if hash != self.hash {
+            return Err(format!("This Google Cloud KMS key only signs {} digests", self.hash.label()).into());
+        }
+        let token = access_token("GOOGLE_OAUTH_ACCESS_TOKEN", "gcloud", &["auth", "print-access-token"])?;
+        let request = agent()
+            .post(&format!("https://cloudkms.googleapis.com/v1/{}:asymmetricSign", self.key_version))
+            .set("Authorization", &format!("Bearer {}", token))
+            .set("Content-Type", "application/json");
+        let body = json!({ "digest": { hash.as_str(): BASE64.encode(digest) } });
+        let response = send(request, Some(&body.to_string()), GCP)?;
+        fixed_size(&self.public_key, decode_field(&response, "signature", GCP, &BASE64)?)
+    }
+}
+
+/// A key in Azure Key Vault, pinned to the version current when opened.
+struct AzureSigner {
+    /// The key identifier with its version, as the vault reports it.
+    kid: String,
+    public_key: PublicKey,
+}
+
+impl AzureSigner {
+    fn open(key_id: &str) -> Result<AzureSigner> {
+        let request = agent()
+            .get(&format!("{}?api-version={}", key_id, AZURE_API_VERSION))
+            .set("Authorization", &format!("Bearer {}", azure_token()?));
+        let response = send(request, None, AZURE)?;
+        let jwk = response.get("key").ok_or("Azure Key Vault returned no key")?;
+        let field = |name| decode_field(jwk, name, AZURE, &BASE64_URL);
+        let public_key = match string_field(jwk, "kty", AZURE)? {
+            "RSA" | "RSA-HSM" => PublicKey::Rsa(
+                RsaPublicKey::new(BigUint::from_bytes_be(&field("n")?), BigUint::from_bytes_be(&field("e")?))
+                    .map_err(|e| format!("Invalid Azure Key Vault key: {}", e))?,
+            ),
+            "EC" | "EC-HSM" if string_field(jwk, "crv", AZURE)? == "P-256" => {
+                let (x, y) = (field("x")?, field("y")?);
+                if x.len() != 32 || y.len() != 32 {
+                    return Err("Invalid Azure Key Vault key: bad P-256 point".into());
+                }
+                let point = p256::EncodedPoint::from_affine_coordinates(x.as_slice().into(), y.as_slice().into(), false);
+                PublicKey::P256(
+                    p256::ecdsa::VerifyingKey::from_encoded_point(&point)
+                        .map_err(|e| format!("Invalid Azure Key Vault key: {}", e))?,
+                )
+            }
+            kty => return Err(format!("Only RSA and P-256 keys in Azure Key Vault can sign, not {}", kty).into()),
+        };
+        Ok(AzureSigner {
+            kid: string_field(jwk, "kid", AZURE)?.to_string(),
+            public_key,
+        })
+    }
+}
+
+impl RemoteSigner for AzureSigner {
+    fn public_key(&self) -> &PublicKey {
+        &self.public_key
+    }
+
+    fn sign_digest(&self, hash: HashAlgorithm, digest: &[u8]) -> Result<Vec<u8>> {
+        // The JWS names; ES256 signatures come back as r || s already.
+        let algorithm = match (&self.public_key, hash) {
+            (PublicKey::Rsa(_), HashAlgorithm::Sha256) => "RS256",
+            (PublicKey::Rsa(_), HashAlgorithm::Sha384) => "RS384",
+            (PublicKey::Rsa(_), HashAlgorithm::Sha512) => "RS512",
+            (PublicKey::P256(_), HashAlgorithm::Sha256) => "ES256",
+            _ => return Err(format!("This Azure Key Vault key cannot sign {} digests", hash.label()).into()),
+        };
+        let request = agent()
+            .post(&format!("{}/sign?api-version={}", self.kid, AZURE_API_VERSION))
+            .set("Authorization", &format!("Bearer {}", azure_token()?))
+            .set("Content-Type", "application/json");
+        let body = json!({ "alg": algorithm, "value": BASE64_URL.encode(digest) });
+        let response = send(request, Some(&body.to_string()), AZURE)?;
+        decode_field(&response, "value", AZURE, &BASE64_URL)
+    }
+}
+
+/// A key of Vault's transit engine. Vault adds the DigestInfo for RSA itself
+/// and signs the bytes given with Ed25519, as local keys do.
+struct VaultSigner {
+    address: String,
+    mount: String,
+    key_name: String,
+    token: String,
+    public_key: PublicKey,
+}
+
+impl VaultSigner {
+    fn open(address: &str, mount: &str, key_name: &str) -> Result<VaultSigner> {
+        let token = vault_token(address)?;
+        let request = vault_request(agent().get(&format!("{}/v1/{}/keys/{}", address, mount, key_name)), &token);
+        let response = send(request, None, VAULT)?;
+        let data = response.get("data").ok_or("Vault returned no key")?;
+        let key_type = string_field(data, "type", VAULT)?;
+        let latest = data
+            .get("latest_version")
+            .and_then(Value::as_u64)
+            .ok_or("Invalid reply from Vault: no latest_version")?;
+        let version = data
+            .get("keys")
+            .and_then(|keys| keys.get(latest.to_string()))
+            .ok_or("Invalid reply from Vault: no public key")?;
+        let public_key = match key_type {
+            "rsa-2048" | "rsa-3072" | "rsa-4096" | "ecdsa-p256" => {
+                PublicKey::from_public_key_pem(string_field(version, "public_key", VAULT)?)?
+            }
+            "ed25519" => {
+                let bytes = decode_field(version, "public_key", VAULT, &BASE64)?;
+                let bytes = <[u8; 32]>::try_from(bytes.as_slice()).map_err(|_| "Invalid Vault key: bad Ed25519 key")?;
+                PublicKey::Ed25519(
+                    ed25519_dalek::VerifyingKey::from_bytes(&bytes).map_err(|e| format!("Invalid Vault key: {}", e))?,
+                )
+            }
+            _ => return Err(format!("Only RSA, P-256 and Ed25519 keys in Vault can sign, not {}", key_type).into()),
+        };
+        Ok(VaultSigner {
+            address: address.to_string(),
+            mount: mount.to_string(),
+            key_name: key_name.to_string(),
+            token,
+            public_key,
+        })
+    }
+}
+
+impl RemoteSigner for VaultSigner {
+    fn public_key(&self) -> &PublicKey {
+        &self.public_key
+    }
+
+    fn sign_digest(&self, hash: HashAlgorithm, digest: &[u8]) -> Result<Vec<u8>> {
+        let hash_name = match hash {
+            HashAlgorithm::Sha256 => "sha2-256",
+            HashAlgorithm::Sha384 => "sha2-384",
+            HashAlgorithm::Sha512 => "sha2-512",
+            HashAlgorithm::Blake3 => "",
+        };
+        let body = match (&self.public_key, hash) {
+            (PublicKey::Ed25519(_), _) => json!({ "input": BASE64.encode(digest) }),
+            (PublicKey::Rsa(_), HashAlgorithm::Sha256 | HashAlgorithm::Sha384 | HashAlgorithm::Sha512) => json!({
+                "input": BASE64.encode(digest),
+                "prehashed": true,
+                "signature_algorithm": "pkcs1v15",
+            }),
+            (PublicKey::P256(_), HashAlgorithm::Sha256) => json!({
+                "input": BASE64.encode(digest),
+                "prehashed": true,
+                "marshaling_algorithm": "asn1",
+            }),
+            _ => return Err(format!("This Vault key cannot sign {} digests", hash.label()).into()),
+        };
+        let url = match &self.public_key {
+            PublicKey::Ed25519(_) => format!("{}/v1/{}/sign/{}", self.address, self.mount, self.key_name),
+            _ => format!("{}/v1/{}/sign/{}/{}", self.address, self.mount, self.key_name, hash_name),
+        };
+        let request = vault_request(agent().post(&url), &self.token).set("Content-Type", "application/json");
+        let response = send(request, Some(&body.to_string()), VAULT)?;
+        // "vault:v<version>:<base64>"
+        let signature = response
+            .get("data")
+            .and_then(|data| data.get("signature"))
+            .and_then(Value::as_str)
+            .and_then(|signature| signature.rsplit(':').next())
+            .ok_or("Invalid reply from Vault: no signature")?;
+        let signature = BASE64
+            .decode(signature)
+            .map_err(|e| format!("Invalid reply from Vault: bad signature: {}", e))?;
+        fixed_size(&self.public_key, signature)
+    }
+}
+
+/// A Vault token: `VAULT_TOKEN`, one from an AppRole login, or the one
+/// `vault login` saved.
+fn vault_token(address: &str) -> Result<String> {
+    if let Some(token) = env::var("VAULT_TOKEN").ok().filter(|token| !token.trim().is_empty()) {
+        return Ok(token.trim().to_string());
+    }
+    if let (Ok(role_id), Ok(secret_id)) = (env::var("VAULT_ROLE_ID"), env::var("VAULT_SECRET_ID")) {
+        let mount = env::var("VAULT_APPROLE_MOUNT").unwrap_or_else(|_| DEFAULT_APPROLE_MOUNT.to_string());
+        let mut request = agent()
+            .post(&format!("{}/v1/auth/{}/login", address, mount.trim_matches('/')))
+            .set("Content-Type", "application/json");
+        if let Ok(namespace) = env::var("VAULT_NAMESPACE") {
+            request = request.set("X-Vault-Namespace", &namespace);
+        }
+        let body = json!({ "role_id": role_id, "secret_id": secret_id });
+        let response = send(request, Some(&body.to_string()), VAULT)?;
+        let auth = response.get("auth").ok_or("Vault returned no token for the AppRole")?;
+        return Ok(string_field(auth, "client_token", VAULT)?.to_string());
+    }
+    Ok(home_dir()
+        .and_then(|home| fs::read_to_string(home.join(".vault-token")).ok())
+        .map(|token| token.trim().to_string())
+        .filter(|token| !token.is_empty())
+        .ok_or_else(|| "No Vault token; set VAULT_TOKEN, set VAULT_ROLE_ID and VAULT_SECRET_ID, or run vault login".to_string())?)
+}
+
+fn vault_request(request: ureq::Request, token: &str) -> ureq::Request {
+    let request = request.set("X-Vault-Token", token);
+    match env::var("VAULT_NAMESPACE") {
+        Ok(namespace) if !namespace.is_empty() => request.set("X-Vault-Namespace", &namespace),
+        _ => request,
+    }
+}
+
+fn agent() -> ureq::Agent {
+    ureq::AgentBuilder::new().timeout(REQUEST_TIMEOUT).build()
+}
+
+/// Sends `request` and reads the JSON reply, taking the service's own
+/// explanation when it refuses.
+fn send(request: ureq::Request, body: Option<&str>, service: &str) -> Result<Value> {
+    let response = match body {
+        Some(body) => request.send_string(body),
+        None => request.call(),
+    };
+    match response {
+        Ok(response) => read_json(response, service),
+        Err(ureq::Error::Status(status, response)) => {
+            // AWS says {"message": ...}; Google and Azure {"error": {"message": ...}};
+            // Vault {"errors": [...]}.
+            let message = read_json(response, service).ok().and_then(|reply| {
+                let message = reply.get("message").or_else(|| reply.get("Message"));
+                let message = message.or_else(|| reply.get("error")?.get("message"));
+                message.or_else(|| reply.get("errors")?.get(0))?.as_str().map(str::to_string)
+            });
+            Err(format!("{} refused the request: {}", service, message.unwrap_or_else(|| format!("HTTP {}", status))).into())
+        }
+        Err(e) => Err(format!("Failed to reach {}: {}", service, e).into()),
+    }
+}
+
+fn read_json(response: ureq::Response, service: &str) -> Result<Value> {
+    let mut text = String::new();
+    response
+        .into_reader()
+        .take(MAX_RESPONSE_SIZE)
+        .read_to_string(&mut text)
+        .map_err(|e| format!("Failed to read the reply from {}: {}", service, e))?;
+    Ok(serde_json::from_str(&text).map_err(|e| format!("Invalid reply from {}: {}", service, e))?)
+}
+
+fn string_field<'a>(value: &'a Value, name: &str, service: &str) -> Result<&'a str> {
+    Ok(value
+        .get(name)
+        .and_then(Value::as_str)
+        .ok_or_else(|| format!("Invalid reply from {}: no {}", service, name))?)
+}
+
+fn decode_field<E: Engine>(value: &Value, name: &str, service: &str, engine: &E) -> Result<Vec<u8>> {
+    Ok(engine
+        .decode(string_field(value, name, service)?)
+        .map_err(|e| format!("Invalid reply from {}: bad {}: {}", service, name, e))?)
+}
+
+/// AWS, Google and Vault give ECDSA signatures in DER; local P-256 keys sign as
+/// fixed-size r || s.
+fn fixed_size(public_key: &PublicKey, signature: Vec<u8>) -> Result<Vec<u8>> {
+    match public_key {
+        PublicKey::P256(_) => Ok(p256::ecdsa::Signature::from_der(&signature)
+            .map(|signature| signature.to_bytes().to_vec())
+            .map_err(|e| format!("Invalid signature from the key management service: {}", e))?),
+        _ => Ok(signature),
+    }
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC takes keys of any length");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// AWS credentials from the environment, or else from the shared
+/// credentials file the AWS CLI writes.
+fn aws_credentials() -> Result<AwsCredentials> {
+    if let (Ok(access_key_id), Ok(secret_access_key)) = (env::var("AWS_ACCESS_KEY_ID"), env::var("AWS_SECRET_ACCESS_KEY")) {
+        return Ok(AwsCredentials {
+            access_key_id,
+            secret_access_key,
+            session_token: env::var("AWS_SESSION_TOKEN").ok().filter(|token| !token.is_empty()),
+        });
+    }
+    let no_credentials = || "No AWS credentials; set AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY, or run aws configure".to_string();
+    let path = env::var_os("AWS_SHARED_CREDENTIALS_FILE")
+        .map(PathBuf::from)
+        .or_else(|| home_dir().map(|home| home.join(".aws").join("credentials")))
+        .ok_or_else(no_credentials)?;
+    let text = fs::read_to_string(path).map_err(|_| no_credentials())?;
+    let profile = env::var("AWS_PROFILE").unwrap_or_else(|_| "default".to_string());
+
+    // An INI file with one [section] per profile.
+    let mut section = None;
+    let mut values = HashMap::new();
+    for line in text.lines().map(str::trim) {
+        if let Some(header) = line.strip_prefix('[').and_then(|line| line.strip_suffix(']')) {
+            section = Some(header.trim());
+        } else if section == Some(profile.as_str()) {
+            if let Some((key, value)) = line.split_once('=') {
+                values.insert(key.trim(), value.trim().to_string());
+            }
+        }
+    }
+    Ok(AwsCredentials {
+        access_key_id: values.remove("aws_access_key_id").ok_or_else(no_credentials)?,
+        secret_access_key: values.remove("aws_secret_access_key").ok_or_else(no_credentials)?,
+        session_token: values.remove("aws_session_token"),
+    })
+}
+
+fn azure_token() -> Result<String> {
+    access_token(
+        "AZURE_ACCESS_TOKEN",
+        "az",
+        &["account", "get-access-token", "--resource", AZURE_RESOURCE, "--query", "accessToken", "--output", "tsv"],
+    )
+}
+
+/// An OAuth access token from `variable`, or else from the provider's CLI,
+/// which keeps it fresh for as long as the user is signed in.
+fn access_token(variable: &str, program: &str, args: &[&str]) -> Result<String> {
+    if let Some(token) = env::var(variable).ok().filter(|token| !token.trim().is_empty()) {
+        return Ok(token.trim().to_string());
+    }
+    let output = Command::new(program).args(args).output().map_err(|e| {
+        if e.kind() == std::io::ErrorKind::NotFound {
+            format!("No access token; set {} or install {} and sign in", variable, program)
+        } else {
+            format!("Failed to run {}: {}", program, e)
+        }
+    })?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let reason = stderr.lines().rev().find(|line| !line.trim().is_empty()).unwrap_or("it failed");
+        return Err(format!("{} could not give an access token: {}", program, reason.trim()).into());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+fn home_dir() -> Option<PathBuf> {
+    env::var_os("HOME")
+        .or_else(|| env::var_os("USERPROFILE"))
+        .filter(|home| !home.is_empty())
+        .map(PathBuf::from)
+}
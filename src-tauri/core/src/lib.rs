@@ -0,0 +1,67 @@
+//! Key management, PDF signing and verification, shared by the Sigillum app
+//! and CLI and usable from any Rust program.
+//!
+//! [`api`] holds the entry points: [`api::sign`] and [`api::verify`] for PDFs,
+//! [`api::sign_detached`] and [`api::verify_detached`] for other files.
+
+pub mod api;
+pub mod appearance;
+pub mod audit;
+pub mod backup;
+pub mod biometric;
+pub mod cert_store;
+pub mod certificate;
+pub mod diff;
+pub mod discovery;
+pub(crate) mod encryption;
+pub mod error;
+pub mod gnupg;
+pub mod hash;
+pub mod identity;
+pub(crate) mod key_encryption;
+pub mod key_store;
+pub(crate) mod keychain;
+pub mod keys;
+pub mod kms;
+pub(crate) mod lock;
+pub mod manifest;
+pub mod mnemonic;
+pub mod ntp;
+pub mod openpgp;
+pub(crate) mod pades;
+pub(crate) mod pdf_utils;
+pub(crate) mod pdfa;
+pub mod piv;
+pub mod pkcs11;
+pub mod pkcs12;
+pub(crate) mod policy;
+pub mod preview;
+pub mod progress;
+pub mod report;
+pub mod settings;
+pub mod shamir;
+pub mod share;
+pub mod ssh_agent;
+pub(crate) mod text_metrics;
+pub mod tpm;
+pub mod trust_store;
+pub(crate) mod truetype;
+pub mod tsa;
+pub mod watch;
+
+pub use api::{ExpiredKeyPolicy, KeyInfo, KeyPair, ResignPolicy, RetiredKey, SignOptions, SignatureInfo, SignatureStatus, SigningDefaults, VerifyOptions};
+pub use appearance::Appearance;
+pub use audit::{AuditEntry, AuditLog};
+pub use certificate::{CertificateInfo, CertificateSubject};
+pub use error::SigillumError;
+pub use hash::HashAlgorithm;
+pub use identity::Identity;
+pub use key_store::{KeyStorage, KeyStore};
+pub use keys::KeyAlgorithm;
+pub use piv::SigningDevice;
+pub use pkcs11::Pkcs11Config;
+pub use progress::{CancellationToken, Monitor, Progress};
+pub use report::{ReportFormat, VerificationReport};
+pub use settings::Settings;
+pub use shamir::{KeyShare, ShamirScheme};
+pub use trust_store::{Revocation, TrustStore, TrustedKey};
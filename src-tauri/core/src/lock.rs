@@ -0,0 +1,362 @@
+//! Locking a document when it is signed. Its form fields are flattened into
+//! the pages, and the signature forbids any further change: the first
+//! signature in a document certifies it with DocMDP permissions, a later one
+//! locks the whole form with a FieldMDP lock. Viewers that check these, such
+//! as Acrobat, then warn about anything changed afterwards.
+
+use crate::error::Result;
+use lopdf::{Dictionary, Document, Object, ObjectId};
+use std::collections::HashSet;
+
+/// The `/P` that allows no changes at all, not even another signature.
+const NO_CHANGES: i64 = 1;
+/// How deep the field tree and the page tree are followed.
+const MAX_DEPTH: usize = 32;
+/// Annotation flags that keep a widget off the page.
+const HIDDEN: i64 = 1 << 1;
+const NO_VIEW: i64 = 1 << 5;
+
+/// Draws the widgets of every form field other than signatures into their
+/// pages as they appear now and removes the fields, so what was filled in can
+/// no longer be edited. Signature fields stay, as removing one would drop its
+/// signature. Returns how many widgets were flattened.
+pub fn flatten_form_fields(doc: &mut Document) -> Result<usize> {
+    let root_id = root_id(doc)?;
+    let Some((form_id, mut form)) = acro_form(doc, root_id) else {
+        return Ok(0);
+    };
+    let fields = form
+        .get(b"Fields")
+        .and_then(|fields| doc.dereference(fields))
+        .and_then(|(_, fields)| fields.as_array())
+        .cloned()
+        .unwrap_or_default();
+
+    let mut widgets = HashSet::new();
+    let mut kept = Vec::new();
+    for field in fields {
+        let mut field_widgets = Vec::new();
+        let has_signature = collect_widgets(doc, &field, None, 0, &mut field_widgets);
+        widgets.extend(field_widgets);
+        if has_signature {
+            kept.push(field);
+        }
+    }
+
+    let mut flattened = 0;
+    for page_id in doc.get_pages().into_values() {
+        let Ok(page) = doc.get_dictionary(page_id) else {
+            continue;
+        };
+        let annots = page.get(b"Annots").ok().cloned();
+        let (annots_id, annots) = match annots {
+            Some(Object::Reference(id)) => match doc.get_object(id).and_then(Object::as_array) {
+                Ok(annots) => (Some(id), annots.clone()),
+                Err(_) => continue,
+            },
+            Some(Object::Array(annots)) => (None, annots),
+            _ => continue,
+        };
+        let (flatten, keep): (Vec<Object>, Vec<Object>) = annots.into_iter().partition(|annot| {
+            annot.as_reference().is_ok_and(|id| widgets.contains(&id))
+        });
+        if flatten.is_empty() {
+            continue;
+        }
+
+        let mut content = String::new();
+        let mut appearances = Vec::new();
+        for widget_id in flatten.iter().filter_map(|annot| annot.as_reference().ok()) {
+            if let Some((appearance_id, placement)) = widget_appearance(doc, widget_id) {
+                let name = format!("FFL{}", appearance_id.0);
+                content.push_str(&format!("q {} cm /{} Do Q\n", placement, name));
+                appearances.push((name, appearance_id));
+            }
+            flattened += 1;
+        }
+
+        let mut page = doc
+            .get_dictionary(page_id)
+            .map_err(|e| format!("Failed to get page: {}", e))?
+            .clone();
+        match annots_id {
+            Some(id) => {
+                doc.objects.insert(id, Object::Array(keep));
+            }
+            None if keep.is_empty() => {
+                page.remove(b"Annots");
+            }
+            None => page.set("Annots", Object::Array(keep)),
+        }
+        if !content.is_empty() {
+            let stream_id = doc.add_object(lopdf::Stream::new(Dictionary::new(), content.into_bytes()));
+            let contents = match page.get(b"Contents") {
+                Ok(Object::Array(contents)) => {
+                    let mut contents = contents.clone();
+                    contents.push(Object::Reference(stream_id));
+                    contents
+                }
+                Ok(Object::Reference(existing)) => vec![Object::Reference(*existing), Object::Reference(stream_id)],
+                _ => vec![Object::Reference(stream_id)],
+            };
+            page.set("Contents", Object::Array(contents));
+
+            let mut resources = page_resources(doc, &page);
+            let mut xobjects = match resources.get(b"XObject").map(|d| doc.dereference(d)) {
+                Ok(Ok((_, Object::Dictionary(d)))) => d.clone(),
+                _ => Dictionary::new(),
+            };
+            for (name, id) in appearances {
+                xobjects.set(name, Object::Reference(id));
+            }
+            resources.set("XObject", Object::Dictionary(xobjects));
+            page.set("Resources", Object::Dictionary(resources));
+        }
+        doc.objects.insert(page_id, Object::Dictionary(page));
+    }
+
+    form.set("Fields", Object::Array(kept));
+    // Nothing is left for a viewer to regenerate or for XFA to render.
+    form.remove(b"NeedAppearances");
+    form.remove(b"XFA");
+    match form_id {
+        Some(id) => {
+            doc.objects.insert(id, Object::Dictionary(form));
+        }
+        None => {
+            doc.get_object_mut(root_id)
+                .and_then(Object::as_dict_mut)
+                .map_err(|e| format!("Failed to get catalog: {}", e))?
+                .set("AcroForm", Object::Dictionary(form));
+        }
+    }
+    Ok(flattened)
+}
+
+/// Whether a signature in `doc` forbids any further change, so signing it
+/// again would break that signature.
+pub fn is_locked(doc: &Document) -> bool {
+    let Ok(root_id) = root_id(doc) else {
+        return false;
+    };
+    let certified = doc
+        .get_dictionary(root_id)
+        .and_then(|catalog| catalog.get(b"Perms"))
+        .and_then(|perms| doc.dereference(perms))
+        .and_then(|(_, perms)| perms.as_dict())
+        .and_then(|perms| perms.get(b"DocMDP"))
+        .and_then(|sig| doc.dereference(sig))
+        .and_then(|(_, sig)| sig.as_dict())
+        .is_ok_and(|sig| forbids_changes(doc, sig, b"DocMDP"));
+    if certified {
+        return true;
+    }
+
+    let fields = acro_form(doc, root_id)
+        .and_then(|(_, form)| form.get(b"Fields").ok().cloned())
+        .and_then(|fields| doc.dereference(&fields).ok().and_then(|(_, fields)| fields.as_array().ok().cloned()))
+        .unwrap_or_default();
+    fields.iter().any(|field| {
+        let Ok((_, Object::Dictionary(field))) = doc.dereference(field) else {
+            return false;
+        };
+        let Ok((_, Object::Dictionary(sig))) = field.get(b"V").and_then(|sig| doc.dereference(sig)) else {
+            return false;
+        };
+        forbids_changes(doc, sig, b"FieldMDP")
+    })
+}
+
+/// Whether `field` is a signature field that has been signed.
+pub(crate) fn is_signed_field(doc: &Document, field: &Object) -> bool {
+    doc.dereference(field)
+        .and_then(|(_, field)| field.as_dict())
+        .is_ok_and(|field| {
+            field.get(b"FT").and_then(Object::as_name).ok() == Some(b"Sig".as_slice()) && field.has(b"V")
+        })
+}
+
+/// The `/Reference` of a signature that locks the document: a DocMDP
+/// transform when it `certifies` the document, a FieldMDP one covering every
+/// field otherwise. `root_id` is the catalog the FieldMDP transform inspects.
+pub(crate) fn signature_reference(certifies: bool, root_id: ObjectId) -> Object {
+    let mut params = Dictionary::from_iter(vec![
+        ("Type", Object::Name(b"TransformParams".to_vec())),
+        ("P", Object::Integer(NO_CHANGES)),
+        ("V", Object::Name(b"1.2".to_vec())),
+    ]);
+    let mut reference = Dictionary::from_iter(vec![("Type", Object::Name(b"SigRef".to_vec()))]);
+    if certifies {
+        reference.set("TransformMethod", Object::Name(b"DocMDP".to_vec()));
+    } else {
+        params.set("Action", Object::Name(b"All".to_vec()));
+        reference.set("TransformMethod", Object::Name(b"FieldMDP".to_vec()));
+        reference.set("Data", Object::Reference(root_id));
+    }
+    reference.set("TransformParams", Object::Dictionary(params));
+    Object::Array(vec![Object::Dictionary(reference)])
+}
+
+/// The `/Lock` of a signature field that locks every field once signed.
+pub(crate) fn field_lock() -> Object {
+    Object::Dictionary(Dictionary::from_iter(vec![
+        ("Type", Object::Name(b"SigFieldLock".to_vec())),
+        ("Action", Object::Name(b"All".to_vec())),
+        ("P", Object::Integer(NO_CHANGES)),
+    ]))
+}
+
+/// Whether `sig` carries a `method` transform allowing no changes.
+fn forbids_changes(doc: &Document, sig: &Dictionary, method: &[u8]) -> bool {
+    let Ok((_, Object::Array(references))) = sig.get(b"Reference").and_then(|r| doc.dereference(r)) else {
+        return false;
+    };
+    references
+        .iter()
+        .filter_map(|reference| doc.dereference(reference).and_then(|(_, r)| r.as_dict()).ok())
+        .filter(|reference| reference.get(b"TransformMethod").and_then(Object::as_name).ok() == Some(method))
+        .any(|reference| {
+            // Without a /P, DocMDP allows form filling and signing.
+            reference
+                .get(b"TransformParams")
+                .and_then(|params| doc.dereference(params))
+                .and_then(|(_, params)| params.as_dict())
+                .and_then(|params| params.get(b"P"))
+                .and_then(Object::as_i64)
+                .is_ok_and(|p| p == NO_CHANGES)
+        })
+}
+
+fn root_id(doc: &Document) -> Result<ObjectId> {
+    Ok(doc.trailer
+        .get(b"Root")
+        .and_then(Object::as_reference)
+        .map_err(|e| format!("Failed to find catalog: {}", e))?)
+}
+
+/// The interactive form and the object it is stored in, `None` when it is
+/// inline in the catalog.
+fn acro_form(doc: &Document, root_id: ObjectId) -> Option<(Option<ObjectId>, Dictionary)> {
+    let form = doc.get_dictionary(root_id).ok()?.get(b"AcroForm").ok()?;
+    let (form_id, form) = doc.dereference(form).ok()?;
+    Some((form_id, form.as_dict().ok()?.clone()))
+}
+
+/// Adds the widgets of `field` and its descendants, other than those of
+/// signature fields, to `widgets`. `field_type` is the `/FT` inherited from
+/// its parent. Returns whether any of them is a signature field.
+fn collect_widgets(
+    doc: &Document,
+    field: &Object,
+    field_type: Option<&[u8]>,
+    depth: usize,
+    widgets: &mut Vec<ObjectId>,
+) -> bool {
+    let Ok((Some(id), Object::Dictionary(dict))) = doc.dereference(field) else {
+        return false;
+    };
+    let field_type = dict.get(b"FT").and_then(Object::as_name).ok().or(field_type);
+    match dict.get(b"Kids").and_then(|kids| doc.dereference(kids)) {
+        Ok((_, Object::Array(kids))) if depth < MAX_DEPTH => {
+            let mut has_signature = false;
+            for kid in kids {
+                has_signature |= collect_widgets(doc, kid, field_type, depth + 1, widgets);
+            }
+            has_signature
+        }
+        _ if field_type == Some(b"Sig".as_slice()) => true,
+        _ => {
+            widgets.push(id);
+            false
+        }
+    }
+}
+
+/// The normal appearance `widget` shows and the `cm` operands that draw it
+/// in the widget's rectangle; `None` when it has none or is hidden.
+fn widget_appearance(doc: &Document, widget_id: ObjectId) -> Option<(ObjectId, String)> {
+    let widget = doc.get_dictionary(widget_id).ok()?;
+    let flags = widget.get(b"F").and_then(Object::as_i64).unwrap_or(0);
+    if flags & (HIDDEN | NO_VIEW) != 0 {
+        return None;
+    }
+    let (_, appearances) = doc.dereference(widget.get(b"AP").ok()?).ok()?;
+    let normal = appearances.as_dict().ok()?.get(b"N").ok()?;
+    // Checkboxes and radio buttons have one appearance per state.
+    let appearance_id = match doc.dereference(normal).ok()? {
+        (Some(id), Object::Stream(_)) => id,
+        (_, Object::Dictionary(states)) => {
+            let state = widget.get(b"AS").and_then(Object::as_name).ok()?;
+            states.get(state).and_then(Object::as_reference).ok()?
+        }
+        _ => return None,
+    };
+    let appearance = doc.get_object(appearance_id).and_then(Object::as_stream).ok()?;
+
+    let rect = numbers(doc, widget.get(b"Rect").ok()?)?;
+    let bbox = numbers(doc, appearance.dict.get(b"BBox").ok()?)?;
+    let matrix = appearance
+        .dict
+        .get(b"Matrix")
+        .ok()
+        .and_then(|matrix| numbers(doc, matrix))
+        .filter(|matrix| matrix.len() == 6)
+        .unwrap_or_else(|| vec![1.0, 0.0, 0.0, 1.0, 0.0, 0.0]);
+    if rect.len() != 4 || bbox.len() != 4 {
+        return None;
+    }
+
+    // The form's bounding box, transformed by its matrix, is fitted to the
+    // rectangle (ISO 32000-1, 12.5.5).
+    let corners = [(bbox[0], bbox[1]), (bbox[0], bbox[3]), (bbox[2], bbox[1]), (bbox[2], bbox[3])]
+        .map(|(x, y)| (matrix[0] * x + matrix[2] * y + matrix[4], matrix[1] * x + matrix[3] * y + matrix[5]));
+    let (min_x, max_x) = bounds(corners.iter().map(|corner| corner.0));
+    let (min_y, max_y) = bounds(corners.iter().map(|corner| corner.1));
+    if max_x - min_x <= 0.0 || max_y - min_y <= 0.0 {
+        return None;
+    }
+    let (left, right) = (rect[0].min(rect[2]), rect[0].max(rect[2]));
+    let (bottom, top) = (rect[1].min(rect[3]), rect[1].max(rect[3]));
+    let scale_x = (right - left) / (max_x - min_x);
+    let scale_y = (top - bottom) / (max_y - min_y);
+    let placement = format!(
+        "{} 0 0 {} {} {}",
+        scale_x,
+        scale_y,
+        left - scale_x * min_x,
+        bottom - scale_y * min_y
+    );
+    Some((appearance_id, placement))
+}
+
+fn numbers(doc: &Document, array: &Object) -> Option<Vec<f32>> {
+    let (_, array) = doc.dereference(array).ok()?;
+    array.as_array().ok()?.iter().map(|n| n.as_float().ok()).collect()
+}
+
+fn bounds(values: impl Iterator<Item = f32>) -> (f32, f32) {
+    values.fold((f32::MAX, f32::MIN), |(min, max), value| (min.min(value), max.max(value)))
+}
+
+/// The page's own resources or the nearest inherited ones, to be stored
+/// inline on the page so shared dictionaries are left untouched.
+fn page_resources(doc: &Document, page: &Dictionary) -> Dictionary {
+    let mut node = Some(page);
+    let mut depth = 0;
+    while let Some(dict) = node {
+        if let Ok(found) = dict.get(b"Resources") {
+            return match doc.dereference(found) {
+                Ok((_, Object::Dictionary(resources))) => resources.clone(),
+                _ => Dictionary::new(),
+            };
+        }
+        depth += 1;
+        node = dict
+            .get(b"Parent")
+            .and_then(Object::as_reference)
+            .and_then(|id| doc.get_dictionary(id))
+            .ok()
+            .filter(|_| depth < MAX_DEPTH);
+    }
+    Dictionary::new()
+}
@@ -0,0 +1,171 @@
+//! Signing a submission of several files at once: a manifest lists each
+//! file's name, size and SHA-256, and a detached signature covers the
+//! manifest, so changing, swapping or dropping any file shows.
+
+use crate::api::{self, DetachedSignature, KeyPair, SignOptions, SignatureReport, SignatureStatus, VerifyOptions};
+use crate::error::Result;
+use crate::hash::{HashAlgorithm, Hasher};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+/// The list of files a manifest signature covers, saved as JSON.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Manifest {
+    /// When the manifest was made, e.g. "2024-01-31 12:00:00 UTC".
+    pub created: String,
+    pub files: Vec<ManifestEntry>,
+}
+
+/// One file of a manifest.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    /// The file's name, without its folder.
+    pub name: String,
+    /// Size in bytes.
+    pub size: u64,
+    /// SHA-256 of the contents, as hex.
+    pub sha256: String,
+}
+
+impl Manifest {
+    /// Lists the files at `paths`. They are listed by name alone, so no two
+    /// may share one.
+    pub fn build(paths: &[PathBuf]) -> Result<Manifest> {
+        if paths.is_empty() {
+            return Err("A manifest needs at least one file".into());
+        }
+        let mut names = HashSet::new();
+        let mut files = Vec::with_capacity(paths.len());
+        for path in paths {
+            let name = path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .ok_or_else(|| format!("{} is not a file", path.display()))?;
+            if !names.insert(name.clone()) {
+                return Err(format!("Two files are named {}; a manifest lists files by name", name).into());
+            }
+            let (size, sha256) = hash_file(path)?;
+            files.push(ManifestEntry { name, size, sha256 });
+        }
+        Ok(Manifest {
+            created: Utc::now().format("%Y-%m-%d %H:%M:%S UTC").to_string(),
+            files,
+        })
+    }
+
+    /// Where the signature of the manifest at `path` goes by default: the
+    /// same name with a `.sig` extension, as in `manifest.sig`.
+    pub fn signature_path_for(path: &Path) -> PathBuf {
+        path.with_extension("sig")
+    }
+
+    pub fn load(path: &Path) -> Result<Manifest> {
+        let json = fs::read_to_string(path).map_err(|e| format!("Failed to read manifest: {}", e))?;
+        Ok(serde_json::from_str(&json).map_err(|e| format!("Invalid manifest: {}", e))?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let json = serde_json::to_string_pretty(self).map_err(|e| format!("JSON error: {}", e))?;
+        Ok(fs::write(path, json).map_err(|e| format!("Failed to save manifest: {}", e))?)
+    }
+}
+
+/// Lists the files at `paths` in a manifest saved to `manifest_path`, then
+/// signs it into `signature_path`. Only the options `api::sign_detached`
+/// uses apply.
+pub fn sign_manifest(
+    paths: &[PathBuf],
+    manifest_path: &Path,
+    signature_path: &Path,
+    opts: &SignOptions,
+    key: &KeyPair,
+) -> Result<(Manifest, DetachedSignature)> {
+    if signature_path == manifest_path {
+        return Err("The manifest and its signature need different names".into());
+    }
+    let manifest = Manifest::build(paths)?;
+    manifest.save(manifest_path)?;
+    // The signature covers the manifest as saved, byte for byte.
+    let signature = api::sign_detached(manifest_path, opts, key)?;
+    signature.save(signature_path)?;
+    Ok((manifest, signature))
+}
+
+/// How a listed file compares with the manifest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FileState {
+    /// Same size and contents as when the manifest was signed.
+    Intact,
+    Modified,
+    Missing,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileCheck {
+    pub name: String,
+    pub state: FileState,
+}
+
+/// Outcome of checking a signed manifest and the files it lists.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManifestReport {
+    /// The check of the signature over the manifest itself.
+    pub signature: SignatureReport,
+    pub files: Vec<FileCheck>,
+}
+
+impl ManifestReport {
+    /// Whether the manifest is as it was signed and every file matches it.
+    /// A signature that could not be checked against a key still passes, as
+    /// for PDFs.
+    pub fn passed(&self) -> bool {
+        self.signature.status != SignatureStatus::Invalid
+            && self.files.iter().all(|file| file.state == FileState::Intact)
+    }
+}
+
+/// Checks the signature of the manifest at `manifest_path`, then re-hashes
+/// the files it lists, looking for them in `dir`.
+pub fn verify_manifest(
+    manifest_path: &Path,
+    signature: &DetachedSignature,
+    dir: &Path,
+    opts: VerifyOptions,
+) -> Result<ManifestReport> {
+    let signature = api::verify_detached(manifest_path, signature, opts)?;
+    let manifest = Manifest::load(manifest_path)?;
+    let files = manifest
+        .files
+        .iter()
+        .map(|entry| {
+            // A tampered manifest must not send the check outside `dir`.
+            let mut components = Path::new(&entry.name).components();
+            if !matches!((components.next(), components.next()), (Some(Component::Normal(_)), None)) {
+                return Err(format!("Invalid file name in manifest: {}", entry.name).into());
+            }
+            let path = dir.join(&entry.name);
+            let state = if !path.is_file() {
+                FileState::Missing
+            } else if hash_file(&path)? == (entry.size, entry.sha256.to_ascii_lowercase()) {
+                FileState::Intact
+            } else {
+                FileState::Modified
+            };
+            Ok(FileCheck { name: entry.name.clone(), state })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(ManifestReport { signature, files })
+}
+
+/// The size and SHA-256 of the file at `path`.
+fn hash_file(path: &Path) -> Result<(u64, String)> {
+    let read_error = |e: std::io::Error| format!("Failed to read {}: {}", path.display(), e);
+    let mut file = fs::File::open(path).map_err(read_error)?;
+    let mut hasher = Hasher::new(HashAlgorithm::Sha256);
+    let size = std::io::copy(&mut file, &mut hasher).map_err(read_error)?;
+    Ok((size, hex::encode(hasher.finalize())))
+}
@@ -0,0 +1,46 @@
+//! Recovery phrases: an Ed25519 or P-256 private key written as the 24 BIP39
+//! words that encode its 32-byte seed, for a backup on paper. The words do
+//! not say which kind of key they hold, so restoring needs to be told.
+
+use crate::error::Result;
+use crate::keys::{KeyAlgorithm, PrivateKey};
+use bip39::Mnemonic;
+use zeroize::Zeroizing;
+
+/// The 24 words for `private_key`, separated by spaces.
+pub fn to_phrase(private_key: &PrivateKey) -> Result<Zeroizing<String>> {
+    let seed = Zeroizing::new(match private_key {
+        PrivateKey::Ed25519(key) => key.to_bytes(),
+        PrivateKey::P256(key) => key.to_bytes().into(),
+        PrivateKey::Rsa(_) => {
+            return Err("RSA keys cannot be written as a recovery phrase; back them up with export or key shares".into())
+        }
+        _ => return Err("Only keys kept by Sigillum can be written as a recovery phrase".into()),
+    });
+    let phrase = Mnemonic::from_entropy(seed.as_ref()).map_err(|e| format!("Failed to make recovery phrase: {}", e))?;
+    Ok(Zeroizing::new(phrase.to_string()))
+}
+
+/// The `algorithm` key whose seed `phrase` holds. Case and spacing do not
+/// matter; a mistyped word usually fails the phrase's checksum.
+pub fn from_phrase(phrase: &str, algorithm: KeyAlgorithm) -> Result<PrivateKey> {
+    let normalized = Zeroizing::new(phrase.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase());
+    let entropy = Zeroizing::new(
+        Mnemonic::parse_normalized(&normalized)
+            .map_err(|e| format!("Invalid recovery phrase: {}", e))?
+            .to_entropy(),
+    );
+    let seed: Zeroizing<[u8; 32]> = Zeroizing::new(
+        entropy
+            .as_slice()
+            .try_into()
+            .map_err(|_| "Invalid recovery phrase: a key's phrase has 24 words".to_string())?,
+    );
+    match algorithm {
+        KeyAlgorithm::Ed25519 => Ok(PrivateKey::Ed25519(ed25519_dalek::SigningKey::from_bytes(&seed))),
+        KeyAlgorithm::P256 => Ok(p256::ecdsa::SigningKey::from_bytes(&(*seed).into())
+            .map(PrivateKey::P256)
+            .map_err(|_| "The recovery phrase does not hold a P-256 key".to_string())?),
+        _ => Err(format!("{} keys cannot be restored from a recovery phrase", algorithm).into()),
+    }
+}
@@ -0,0 +1,145 @@
+//! Checking the local clock against an NTP server at signing time, for when
+//! no timestamp authority is available to vouch for the signing time.
+
+use crate::error::Result;
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::{Deserialize, Serialize};
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::time::Duration;
+
+const NTP_PORT: u16 = 123;
+const QUERY_TIMEOUT: Duration = Duration::from_secs(5);
+const PACKET_SIZE: usize = 48;
+/// Seconds from the NTP epoch, 1900, to the Unix epoch.
+const UNIX_EPOCH_OFFSET: i64 = 2_208_988_800;
+
+/// How far the local clock may be off, in seconds, before signing warns about
+/// it when no other limit is given.
+pub const DEFAULT_MAX_SKEW_SECS: u64 = 5;
+
+/// What the local clock and an NTP server said when a document was signed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ClockCheck {
+    /// The server that answered.
+    pub server: String,
+    /// The local clock when the answer arrived, e.g.
+    /// "2024-01-31 12:00:00.250 UTC".
+    pub local_time: String,
+    /// The server's time at that moment, corrected for the round trip.
+    pub network_time: String,
+    /// How far the local clock was ahead of the network time, in
+    /// milliseconds; negative when it was behind.
+    pub skew_ms: i64,
+}
+
+impl ClockCheck {
+    /// Why the local clock is not to be trusted, when it is more than
+    /// `max_skew_secs` off.
+    pub fn warning(&self, max_skew_secs: u64) -> Option<String> {
+        if self.skew_ms.unsigned_abs() <= max_skew_secs.saturating_mul(1000) {
+            return None;
+        }
+        Some(format!(
+            "The local clock is {:.1} s {} {}, more than the {} s allowed, so the signing time may be wrong",
+            self.skew_ms.unsigned_abs() as f64 / 1000.0,
+            if self.skew_ms > 0 { "ahead of" } else { "behind" },
+            self.server,
+            max_skew_secs
+        ))
+    }
+}
+
+/// Asks each of `servers` in turn for the time until one answers. A server
+/// is a host name or address, optionally with a port.
+pub fn check_clock(servers: &[String]) -> Result<ClockCheck> {
+    let mut errors = Vec::new();
+    for server in servers {
+        match query(server) {
+            Ok(check) => return Ok(check),
+            Err(e) => {
+                log::warn!("NTP server {} did not answer: {}", server, e);
+                errors.push(format!("{}: {}", server, e));
+            }
+        }
+    }
+    if errors.is_empty() {
+        return Err("No NTP server given".into());
+    }
+    Err(format!("No NTP server answered ({})", errors.join("; ")).into())
+}
+
+fn query(server: &str) -> Result<ClockCheck> {
+    let address = resolve(server)?;
+    let local = match address {
+        SocketAddr::V4(_) => "0.0.0.0:0",
+        SocketAddr::V6(_) => "[::]:0",
+    };
+    let socket = UdpSocket::bind(local).map_err(|e| format!("Failed to open socket: {}", e))?;
+    socket.set_read_timeout(Some(QUERY_TIMEOUT)).map_err(|e| format!("Failed to open socket: {}", e))?;
+    socket.connect(address).map_err(|e| format!("Failed to connect: {}", e))?;
+
+    // Version 4, client mode. The transmit time comes back as the originate
+    // time, which ties the answer to this request.
+    let mut request = [0u8; PACKET_SIZE];
+    request[0] = 0x23;
+    let sent = Utc::now();
+    let sent_stamp = to_ntp(sent);
+    request[40..48].copy_from_slice(&sent_stamp.to_be_bytes());
+    socket.send(&request).map_err(|e| format!("Failed to send request: {}", e))?;
+
+    let mut response = [0u8; PACKET_SIZE];
+    let length = socket.recv(&mut response).map_err(|e| format!("No answer: {}", e))?;
+    let received = Utc::now();
+    if length < PACKET_SIZE || response[0] & 0x07 != 4 {
+        return Err("Not an NTP server answer".into());
+    }
+    if response[1] == 0 {
+        return Err("The server refused to answer".into());
+    }
+    if response[24..32] != sent_stamp.to_be_bytes() {
+        return Err("The answer is not for this request".into());
+    }
+
+    let stamp = |at: usize| u64::from_be_bytes(response[at..at + 8].try_into().unwrap());
+    let server_received = from_ntp(stamp(32));
+    let server_sent = from_ntp(stamp(40));
+    // The network is taken to be as slow one way as the other.
+    let offset = ((server_received - sent) + (server_sent - received)) / 2;
+    let network_time = received + offset;
+
+    Ok(ClockCheck {
+        server: server.to_string(),
+        local_time: format_time(received),
+        network_time: format_time(network_time),
+        skew_ms: -offset.num_milliseconds(),
+    })
+}
+
+/// `server` as an address, with the NTP port unless it names one.
+fn resolve(server: &str) -> Result<SocketAddr> {
+    let addresses = match server.to_socket_addrs() {
+        Ok(addresses) => addresses,
+        Err(_) => (server, NTP_PORT)
+            .to_socket_addrs()
+            .map_err(|e| format!("Failed to resolve: {}", e))?,
+    };
+    Ok(addresses.into_iter().next().ok_or_else(|| "Failed to resolve: no address".to_string())?)
+}
+
+/// 32.32 fixed-point seconds since 1900. Times past 2036 wrap, as in NTP
+/// itself.
+fn to_ntp(time: DateTime<Utc>) -> u64 {
+    let seconds = (time.timestamp() + UNIX_EPOCH_OFFSET) as u64;
+    let fraction = (u64::from(time.timestamp_subsec_nanos()) << 32) / 1_000_000_000;
+    (seconds << 32) | fraction
+}
+
+fn from_ntp(stamp: u64) -> DateTime<Utc> {
+    let seconds = (stamp >> 32) as i64 - UNIX_EPOCH_OFFSET;
+    let nanos = ((stamp & 0xffff_ffff) * 1_000_000_000) >> 32;
+    DateTime::from_timestamp(seconds, 0).unwrap_or_default() + TimeDelta::nanoseconds(nanos as i64)
+}
+
+fn format_time(time: DateTime<Utc>) -> String {
+    time.format("%Y-%m-%d %H:%M:%S%.3f UTC").to_string()
+}
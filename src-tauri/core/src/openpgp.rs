@@ -0,0 +1,504 @@
+//! OpenPGP detached signatures (RFC 4880), so recipients with a GnuPG
+//! workflow can check a signed document with `gpg --verify`. The stored key
+//! is presented as a version 4 OpenPGP key, created when the keypair was.
+
+use crate::api::KeyPair;
+use crate::error::Result;
+use crate::hash::{HashAlgorithm, Hasher};
+use crate::keys::{PrivateKey, PublicKey};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use chrono::{DateTime, Utc};
+use rsa::traits::PublicKeyParts;
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+const TAG_SIGNATURE: u8 = 2;
+const TAG_PUBLIC_KEY: u8 = 6;
+const TAG_USER_ID: u8 = 13;
+
+const SIG_BINARY_DOCUMENT: u8 = 0x00;
+const SIG_POSITIVE_CERTIFICATION: u8 = 0x13;
+
+const ALGO_RSA: u8 = 1;
+const ALGO_ECDSA: u8 = 19;
+const ALGO_EDDSA: u8 = 22;
+
+const SUBPACKET_CREATION_TIME: u8 = 2;
+const SUBPACKET_ISSUER: u8 = 16;
+const SUBPACKET_PREFERRED_HASHES: u8 = 21;
+const SUBPACKET_KEY_FLAGS: u8 = 27;
+const SUBPACKET_ISSUER_FINGERPRINT: u8 = 33;
+
+/// Certify and sign.
+const KEY_FLAGS: u8 = 0x03;
+
+const OID_ED25519: &[u8] = &[0x2B, 0x06, 0x01, 0x04, 0x01, 0xDA, 0x47, 0x0F, 0x01];
+const OID_P256: &[u8] = &[0x2A, 0x86, 0x48, 0xCE, 0x3D, 0x03, 0x01, 0x07];
+
+const ARMOR_SIGNATURE: &str = "PGP SIGNATURE";
+const ARMOR_PUBLIC_KEY: &str = "PGP PUBLIC KEY BLOCK";
+
+/// Where the OpenPGP signature of `path` goes by default: the same name with
+/// `.asc` added.
+pub fn signature_path_for(path: &Path) -> PathBuf {
+    let mut sig_path = path.as_os_str().to_owned();
+    sig_path.push(".asc");
+    PathBuf::from(sig_path)
+}
+
+/// Whether `data` is an ASCII-armored OpenPGP signature rather than one of
+/// Sigillum's own `.sig` files.
+pub fn is_armored_signature(data: &str) -> bool {
+    data.trim_start().starts_with(&format!("-----BEGIN {}-----", ARMOR_SIGNATURE))
+}
+
+/// The OpenPGP fingerprint of `key`, as 40 upper-case hex digits.
+pub fn fingerprint(key: &KeyPair) -> Result<String> {
+    let public_key = PublicKey::from_public_key_pem(&key.public_key)?;
+    let packet = public_key_packet(&public_key, creation_time(key));
+    Ok(hex::encode_upper(v4_fingerprint(&packet)))
+}
+
+/// The key as an armored OpenPGP public key, certified for `user_id` (such as
+/// "Jane Doe <jane@example.com>"), for recipients to `gpg --import`.
+pub fn public_key_block(key: &KeyPair, passphrase: Option<&str>, user_id: &str) -> Result<String> {
+    if user_id.trim().is_empty() {
+        return Err("An OpenPGP key needs a user ID".into());
+    }
+    let private_key = key.private_key(passphrase)?;
+    let public_key = private_key.public_key();
+    let key_packet = public_key_packet(&public_key, creation_time(key));
+    let fingerprint = v4_fingerprint(&key_packet);
+
+    let mut hashed = signed_subpackets(Utc::now(), &fingerprint);
+    hashed.extend(subpacket(SUBPACKET_KEY_FLAGS, &[KEY_FLAGS]));
+    hashed.extend(subpacket(SUBPACKET_PREFERRED_HASHES, &[8, 9, 10]));
+    let hash = HashAlgorithm::Sha256;
+    let mut hasher = Hasher::new(hash);
+    hasher.update(&key_hash_prefix(&key_packet));
+    hasher.update(&[0xB4]);
+    hasher.update(&(user_id.len() as u32).to_be_bytes());
+    hasher.update(user_id.as_bytes());
+    let certification = signature_packet(&private_key, hasher, hash, SIG_POSITIVE_CERTIFICATION, &hashed, &fingerprint)?;
+
+    let mut block = packet(TAG_PUBLIC_KEY, &key_packet);
+    block.extend(packet(TAG_USER_ID, user_id.as_bytes()));
+    block.extend(packet(TAG_SIGNATURE, &certification));
+    Ok(armor(ARMOR_PUBLIC_KEY, &block))
+}
+
+/// An armored OpenPGP signature over everything read from `data`.
+pub fn sign_detached<R: Read>(
+    mut data: R,
+    key: &KeyPair,
+    passphrase: Option<&str>,
+    hash: HashAlgorithm,
+) -> Result<String> {
+    let private_key = key.private_key(passphrase)?;
+    let key_packet = public_key_packet(&private_key.public_key(), creation_time(key));
+    let fingerprint = v4_fingerprint(&key_packet);
+
+    let mut hasher = Hasher::new(hash);
+    std::io::copy(&mut data, &mut hasher).map_err(|e| format!("Failed to read file: {}", e))?;
+    let hashed = signed_subpackets(Utc::now(), &fingerprint);
+    let signature = signature_packet(&private_key, hasher, hash, SIG_BINARY_DOCUMENT, &hashed, &fingerprint)?;
+    Ok(armor(ARMOR_SIGNATURE, &packet(TAG_SIGNATURE, &signature)))
+}
+
+/// Outcome of checking an OpenPGP signature.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PgpVerification {
+    /// Whether the signature matches the data and the key.
+    pub valid: bool,
+    /// When the signature says it was made.
+    pub created: Option<DateTime<Utc>>,
+    pub hash_algorithm: HashAlgorithm,
+    /// The fingerprint, or failing that the key ID, of the key the signature
+    /// names, as upper-case hex.
+    pub issuer: Option<String>,
+}
+
+/// Checks the armored OpenPGP signature `armored` over everything read from
+/// `data` against `public_key`.
+pub fn verify_detached<R: Read>(mut data: R, armored: &str, public_key: &PublicKey) -> Result<PgpVerification> {
+    let bytes = dearmor(armored, ARMOR_SIGNATURE)?;
+    let (tag, body) = read_packet(&bytes)?;
+    if tag != TAG_SIGNATURE {
+        return Err("Not an OpenPGP signature".into());
+    }
+    let signature = ParsedSignature::parse(body)?;
+    if signature.kind != SIG_BINARY_DOCUMENT {
+        return Err(format!("Unsupported OpenPGP signature type 0x{:02x}", signature.kind).into());
+    }
+
+    let mut hasher = Hasher::new(signature.hash);
+    std::io::copy(&mut data, &mut hasher).map_err(|e| format!("Failed to read file: {}", e))?;
+    hasher.update(signature.hashed_part);
+    hasher.update(&trailer(signature.hashed_part.len()));
+    let digest = hasher.finalize();
+
+    let valid = digest[..2] == signature.left16
+        && signature_value(public_key, &signature.mpis).is_some_and(|value| {
+            public_key.verify_digest(signature.hash, &digest, &value)
+        });
+    Ok(PgpVerification {
+        valid,
+        created: signature.created,
+        hash_algorithm: signature.hash,
+        issuer: signature.issuer,
+    })
+}
+
+/// When the key was made; keys saved before that was recorded count from the
+/// epoch, so their fingerprint stays the same.
+fn creation_time(key: &KeyPair) -> u32 {
+    key.created.map_or(0, |created| created.timestamp().clamp(0, u32::MAX as i64) as u32)
+}
+
+/// The body of a version 4 public key packet.
+fn public_key_packet(key: &PublicKey, created: u32) -> Vec<u8> {
+    let mut body = vec![4];
+    body.extend(created.to_be_bytes());
+    match key {
+        PublicKey::Rsa(key) => {
+            body.push(ALGO_RSA);
+            body.extend(mpi(&key.n().to_bytes_be()));
+            body.extend(mpi(&key.e().to_bytes_be()));
+        }
+        PublicKey::Ed25519(key) => {
+            body.push(ALGO_EDDSA);
+            body.push(OID_ED25519.len() as u8);
+            body.extend(OID_ED25519);
+            let mut point = vec![0x40];
+            point.extend(key.to_bytes());
+            body.extend(mpi(&point));
+        }
+        PublicKey::P256(key) => {
+            body.push(ALGO_ECDSA);
+            body.push(OID_P256.len() as u8);
+            body.extend(OID_P256);
+            body.extend(mpi(key.to_encoded_point(false).as_bytes()));
+        }
+    }
+    body
+}
+
+fn v4_fingerprint(key_packet: &[u8]) -> [u8; 20] {
+    Sha1::digest(key_hash_prefix(key_packet)).into()
+}
+
+/// A public key packet as it is hashed for fingerprints and certifications.
+fn key_hash_prefix(key_packet: &[u8]) -> Vec<u8> {
+    let mut prefix = vec![0x99];
+    prefix.extend((key_packet.len() as u16).to_be_bytes());
+    prefix.extend(key_packet);
+    prefix
+}
+
+/// The creation time and issuer fingerprint every signature carries.
+fn signed_subpackets(created: DateTime<Utc>, fingerprint: &[u8; 20]) -> Vec<u8> {
+    let mut hashed = subpacket(SUBPACKET_CREATION_TIME, &(created.timestamp() as u32).to_be_bytes());
+    let mut issuer = vec![4];
+    issuer.extend(fingerprint);
+    hashed.extend(subpacket(SUBPACKET_ISSUER_FINGERPRINT, &issuer));
+    hashed
+}
+
+/// Finishes `hasher`, which has the signed data in it, and signs it into the
+/// body of a version 4 signature packet.
+fn signature_packet(
+    key: &PrivateKey,
+    mut hasher: Hasher,
+    hash: HashAlgorithm,
+    kind: u8,
+    hashed_subpackets: &[u8],
+    fingerprint: &[u8; 20],
+) -> Result<Vec<u8>> {
+    let algorithm = match key.public_key() {
+        PublicKey::Rsa(_) => ALGO_RSA,
+        PublicKey::Ed25519(_) => ALGO_EDDSA,
+        PublicKey::P256(_) => ALGO_ECDSA,
+    };
+    let mut body = vec![4, kind, algorithm, hash_id(hash)?];
+    body.extend((hashed_subpackets.len() as u16).to_be_bytes());
+    body.extend(hashed_subpackets);
+    hasher.update(&body);
+    hasher.update(&trailer(body.len()));
+    let digest = hasher.finalize();
+
+    let unhashed = subpacket(SUBPACKET_ISSUER, &fingerprint[12..]);
+    body.extend((unhashed.len() as u16).to_be_bytes());
+    body.extend(unhashed);
+    body.extend(&digest[..2]);
+    let value = key.sign_digest(hash, &digest)?;
+    match algorithm {
+        ALGO_RSA => body.extend(mpi(&value)),
+        // EdDSA's R || S and ECDSA's r || s are two numbers of equal size.
+        _ => {
+            let (first, second) = value.split_at(value.len() / 2);
+            body.extend(mpi(first));
+            body.extend(mpi(second));
+        }
+    }
+    Ok(body)
+}
+
+/// What closes the hashed data of a version 4 signature.
+fn trailer(hashed_length: usize) -> Vec<u8> {
+    let mut trailer = vec![4, 0xFF];
+    trailer.extend((hashed_length as u32).to_be_bytes());
+    trailer
+}
+
+fn hash_id(hash: HashAlgorithm) -> Result<u8> {
+    match hash {
+        HashAlgorithm::Sha256 => Ok(8),
+        HashAlgorithm::Sha384 => Ok(9),
+        HashAlgorithm::Sha512 => Ok(10),
+        HashAlgorithm::Blake3 => Err("OpenPGP signatures cannot use BLAKE3; choose SHA-256, SHA-384 or SHA-512".into()),
+    }
+}
+
+/// Checks that OpenPGP signatures can be made over `hash`, before anything
+/// else is signed.
+pub fn check_hash(hash: HashAlgorithm) -> Result<()> {
+    hash_id(hash).map(|_| ())
+}
+
+fn hash_from_id(id: u8) -> Result<HashAlgorithm> {
+    match id {
+        8 => Ok(HashAlgorithm::Sha256),
+        9 => Ok(HashAlgorithm::Sha384),
+        10 => Ok(HashAlgorithm::Sha512),
+        _ => Err(format!("Unsupported OpenPGP hash algorithm {}", id).into()),
+    }
+}
+
+fn subpacket(kind: u8, data: &[u8]) -> Vec<u8> {
+    // Every subpacket written here is short enough for a one-byte length.
+    let mut subpacket = vec![data.len() as u8 + 1, kind];
+    subpacket.extend(data);
+    subpacket
+}
+
+/// A multiprecision integer: its length in bits, then its big-endian bytes.
+fn mpi(bytes: &[u8]) -> Vec<u8> {
+    let start = bytes.iter().position(|&b| b != 0).unwrap_or(bytes.len());
+    let bytes = &bytes[start..];
+    let bits = bytes.first().map_or(0, |&first| (bytes.len() - 1) * 8 + (8 - first.leading_zeros() as usize));
+    let mut mpi = (bits as u16).to_be_bytes().to_vec();
+    mpi.extend(bytes);
+    mpi
+}
+
+/// A new-format packet.
+fn packet(tag: u8, body: &[u8]) -> Vec<u8> {
+    let mut packet = vec![0xC0 | tag];
+    match body.len() {
+        length @ 0..=191 => packet.push(length as u8),
+        length @ 192..=8383 => {
+            let length = length - 192;
+            packet.push((length >> 8) as u8 + 192);
+            packet.push(length as u8);
+        }
+        length => {
+            packet.push(0xFF);
+            packet.extend((length as u32).to_be_bytes());
+        }
+    }
+    packet.extend(body);
+    packet
+}
+
+/// The tag and body of the first packet in `data`, in either format.
+fn read_packet(data: &[u8]) -> Result<(u8, &[u8])> {
+    let truncated = || "Truncated OpenPGP packet".to_string();
+    let header = *data.first().ok_or_else(truncated)?;
+    if header & 0x80 == 0 {
+        return Err("Not an OpenPGP packet".into());
+    }
+    let (tag, length, offset) = if header & 0x40 != 0 {
+        let first = *data.get(1).ok_or_else(truncated)? as usize;
+        match first {
+            0..=191 => (header & 0x3F, first, 2),
+            192..=223 => (header & 0x3F, ((first - 192) << 8) + *data.get(2).ok_or_else(truncated)? as usize + 192, 3),
+            255 => (header & 0x3F, be_u32(data.get(2..6).ok_or_else(truncated)?) as usize, 6),
+            _ => return Err("Partial-length OpenPGP packets are not supported".into()),
+        }
+    } else {
+        let tag = (header >> 2) & 0x0F;
+        match header & 0x03 {
+            0 => (tag, *data.get(1).ok_or_else(truncated)? as usize, 2),
+            1 => (tag, u16::from_be_bytes([data[1], *data.get(2).ok_or_else(truncated)?]) as usize, 3),
+            2 => (tag, be_u32(data.get(1..5).ok_or_else(truncated)?) as usize, 5),
+            _ => (tag, data.len() - 1, 1),
+        }
+    };
+    let body = data.get(offset..offset + length).ok_or_else(truncated)?;
+    Ok((tag, body))
+}
+
+fn be_u32(bytes: &[u8]) -> u32 {
+    u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+/// The parts of a version 4 signature packet needed to check it.
+struct ParsedSignature<'a> {
+    kind: u8,
+    hash: HashAlgorithm,
+    /// From the version byte to the end of the hashed subpackets.
+    hashed_part: &'a [u8],
+    created: Option<DateTime<Utc>>,
+    issuer: Option<String>,
+    left16: [u8; 2],
+    mpis: Vec<&'a [u8]>,
+}
+
+impl<'a> ParsedSignature<'a> {
+    fn parse(body: &'a [u8]) -> Result<ParsedSignature<'a>> {
+        let malformed = || "Malformed OpenPGP signature".to_string();
+        if body.first() != Some(&4) {
+            return Err("Only version 4 OpenPGP signatures are supported".into());
+        }
+        let header = body.get(..6).ok_or_else(malformed)?;
+        let (kind, hash) = (header[1], hash_from_id(header[3])?);
+        let hashed_length = u16::from_be_bytes([header[4], header[5]]) as usize;
+        let hashed_end = 6 + hashed_length;
+        let hashed = body.get(6..hashed_end).ok_or_else(malformed)?;
+        let unhashed_length =
+            u16::from_be_bytes([*body.get(hashed_end).ok_or_else(malformed)?, *body.get(hashed_end + 1).ok_or_else(malformed)?])
+                as usize;
+        let unhashed_end = hashed_end + 2 + unhashed_length;
+        let unhashed = body.get(hashed_end + 2..unhashed_end).ok_or_else(malformed)?;
+        let left16 = body.get(unhashed_end..unhashed_end + 2).ok_or_else(malformed)?;
+
+        let mut mpis = Vec::new();
+        let mut rest = &body[unhashed_end + 2..];
+        while rest.len() >= 2 {
+            let length = (u16::from_be_bytes([rest[0], rest[1]]) as usize).div_ceil(8);
+            mpis.push(rest.get(2..2 + length).ok_or_else(malformed)?);
+            rest = &rest[2 + length..];
+        }
+
+        let mut created = None;
+        let mut fingerprint = None;
+        let mut key_id = None;
+        for (kind, data) in subpackets(hashed)?.into_iter().chain(subpackets(unhashed)?) {
+            match kind & 0x7F {
+                SUBPACKET_CREATION_TIME if data.len() == 4 => {
+                    created = DateTime::from_timestamp(be_u32(data) as i64, 0);
+                }
+                SUBPACKET_ISSUER_FINGERPRINT if data.len() > 1 => fingerprint = Some(hex::encode_upper(&data[1..])),
+                SUBPACKET_ISSUER => key_id = Some(hex::encode_upper(data)),
+                _ => {}
+            }
+        }
+        Ok(ParsedSignature {
+            kind,
+            hash,
+            hashed_part: &body[..hashed_end],
+            created,
+            issuer: fingerprint.or(key_id),
+            left16: [left16[0], left16[1]],
+            mpis,
+        })
+    }
+}
+
+/// The type and data of each subpacket in `data`.
+fn subpackets(mut data: &[u8]) -> Result<Vec<(u8, &[u8])>> {
+    let malformed = || "Malformed OpenPGP signature subpacket".to_string();
+    let mut subpackets = Vec::new();
+    while let Some(&first) = data.first() {
+        let (length, offset) = match first {
+            0..=191 => (first as usize, 1),
+            192..=254 => (((first as usize - 192) << 8) + *data.get(1).ok_or_else(malformed)? as usize + 192, 2),
+            255 => (be_u32(data.get(1..5).ok_or_else(malformed)?) as usize, 5),
+        };
+        let subpacket = data.get(offset..offset + length).filter(|s| !s.is_empty()).ok_or_else(malformed)?;
+        subpackets.push((subpacket[0], &subpacket[1..]));
+        data = &data[offset + length..];
+    }
+    Ok(subpackets)
+}
+
+/// The signature's numbers in the form `PublicKey::verify_digest` takes, or
+/// `None` when they do not fit the key.
+fn signature_value(key: &PublicKey, mpis: &[&[u8]]) -> Option<Vec<u8>> {
+    match (key, mpis) {
+        (PublicKey::Rsa(key), [value]) => left_pad(value, key.size()),
+        (PublicKey::Ed25519(_) | PublicKey::P256(_), [first, second]) => {
+            let mut value = left_pad(first, 32)?;
+            value.extend(left_pad(second, 32)?);
+            Some(value)
+        }
+        _ => None,
+    }
+}
+
+fn left_pad(bytes: &[u8], size: usize) -> Option<Vec<u8>> {
+    let mut padded = vec![0; size.checked_sub(bytes.len())?];
+    padded.extend(bytes);
+    Some(padded)
+}
+
+/// ASCII armor around `data`, with its CRC-24 checksum.
+fn armor(label: &str, data: &[u8]) -> String {
+    let encoded = BASE64.encode(data);
+    let mut armored = format!("-----BEGIN {}-----\n\n", label);
+    for line in encoded.as_bytes().chunks(64) {
+        armored.push_str(std::str::from_utf8(line).unwrap_or_default());
+        armored.push('\n');
+    }
+    armored.push('=');
+    armored.push_str(&BASE64.encode(&crc24(data).to_be_bytes()[1..]));
+    armored.push_str(&format!("\n-----END {}-----\n", label));
+    armored
+}
+
+/// The data inside the `label` armor of `text`, checked against its checksum
+/// when it has one.
+fn dearmor(text: &str, label: &str) -> Result<Vec<u8>> {
+    let begin = format!("-----BEGIN {}-----", label);
+    let end = format!("-----END {}-----", label);
+    let lines: Vec<&str> = text
+        .lines()
+        .map(str::trim)
+        .skip_while(|line| *line != begin)
+        .skip(1)
+        .take_while(|line| *line != end)
+        .collect();
+    // Armor headers, such as "Version:", end with a blank line.
+    let data_lines = match lines.iter().position(|line| line.is_empty()) {
+        Some(blank) => &lines[blank + 1..],
+        None => &lines[..],
+    };
+    if data_lines.is_empty() {
+        return Err(format!("No {} found", label).into());
+    }
+    let armor_error = |e: base64::DecodeError| format!("Invalid OpenPGP armor: {}", e);
+    let (checksum, body): (Vec<&str>, Vec<&str>) = data_lines.iter().partition(|line| line.starts_with('='));
+    let data = BASE64.decode(body.concat()).map_err(armor_error)?;
+    if let Some(checksum) = checksum.first() {
+        if BASE64.decode(&checksum[1..]).map_err(armor_error)? != crc24(&data).to_be_bytes()[1..] {
+            return Err("The OpenPGP armor checksum does not match".into());
+        }
+    }
+    Ok(data)
+}
+
+fn crc24(data: &[u8]) -> u32 {
+    let mut crc: u32 = 0xB704CE;
+    for &byte in data {
+        crc ^= (byte as u32) << 16;
+        for _ in 0..8 {
+            crc <<= 1;
+            if crc & 0x1000000 != 0 {
+                crc ^= 0x1864CFB;
+            }
+        }
+    }
+    crc & 0xFFFFFF
+}
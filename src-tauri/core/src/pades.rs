@@ -0,0 +1,299 @@
+//! Standard PDF signatures: the detached CMS `SignedData` a signature
+//! dictionary's `/Contents` holds, built for PAdES signing and parsed and
+//! checked for the signatures other applications add.
+
+use crate::api::SignatureStatus;
+use crate::certificate;
+use crate::error::Result;
+use crate::hash::{HashAlgorithm, Hasher};
+use crate::keys::{PrivateKey, PublicKey, MIN_RSA_BITS};
+use crate::kms::CmsSigner;
+use crate::tsa;
+use cms::builder::{SignedDataBuilder, SignerInfoBuilder};
+use cms::cert::{CertificateChoices, IssuerAndSerialNumber};
+use cms::content_info::ContentInfo;
+use cms::signed_data::{EncapsulatedContentInfo, SignedData, SignerIdentifier, SignerInfo, SignerInfos};
+use rsa::pkcs1v15::{Signature, SigningKey};
+use rsa::signature::{Keypair, Signer};
+use sha2::{Digest, Sha256};
+use x509_cert::attr::Attribute;
+use x509_cert::der::asn1::{Any, OctetStringRef, SetOfVec};
+use x509_cert::der::oid::db::{rfc5911, rfc5912};
+use x509_cert::der::{Decode, Encode, SliceReader, Tag};
+use x509_cert::spki::{AlgorithmIdentifierOwned, DynSignatureAlgorithmIdentifier, EncodePublicKey, SignatureBitStringEncoding};
+use x509_cert::time::Time;
+use x509_cert::Certificate;
+
+const ED25519_CMS: &str = "Standard PDF signatures need an RSA or P-256 key";
+
+/// Builds a detached CMS `SignedData` (as used by `ETSI.CAdES.detached`) over
+/// `content_digest`, the SHA-256 of the byte ranges covered by a `/Sig`
+/// dictionary.
+///
+/// `certificate` is the signer's own or, failing that, `certificate::ad_hoc`;
+/// either way it must be for `private_key`.
+///
+/// With `tsa_url`, a timestamp over the signature value is added as an
+/// unsigned attribute, as PAdES-B-T expects.
+///
+/// Ed25519 is rejected: PDF viewers do not generally accept it in CMS yet.
+pub fn build_cms(
+    private_key: &PrivateKey,
+    certificate: &Certificate,
+    content_digest: &[u8],
+    tsa_url: Option<&str>,
+) -> Result<Vec<u8>> {
+    let cms = match private_key {
+        PrivateKey::Rsa(key) => {
+            let signing_key = SigningKey::<Sha256>::new(key.clone());
+            build_cms_with::<_, Signature>(&signing_key, certificate, content_digest)?
+        }
+        PrivateKey::P256(key) => build_cms_with::<_, p256::ecdsa::DerSignature>(key, certificate, content_digest)?,
+        PrivateKey::Pkcs11(key) => build_cms_with::<_, Signature>(&key.rsa_signer(), certificate, content_digest)?,
+        PrivateKey::Kms(key) => match key.cms_signer() {
+            CmsSigner::Rsa(signer) => build_cms_with::<_, Signature>(&signer, certificate, content_digest)?,
+            CmsSigner::P256(signer) => build_cms_with::<_, p256::ecdsa::DerSignature>(&signer, certificate, content_digest)?,
+            CmsSigner::Ed25519(_) => return Err(ED25519_CMS.to_string().into()),
+        },
+        PrivateKey::Ed25519(_) | PrivateKey::SshAgent(_) => return Err(ED25519_CMS.to_string().into()),
+    };
+    match tsa_url {
+        Some(url) => add_signature_timestamp(&cms, url),
+        None => Ok(cms),
+    }
+}
+
+/// Timestamps the signer's signature value and attaches the token to the
+/// `SignerInfo` as `id-aa-signatureTimeStampToken`.
+fn add_signature_timestamp(cms: &[u8], tsa_url: &str) -> Result<Vec<u8>> {
+    let cms_error = |e: x509_cert::der::Error| format!("Failed to build CMS signature: {}", e);
+
+    let mut content_info = ContentInfo::from_der(cms).map_err(cms_error)?;
+    let mut signed_data: SignedData = content_info.content.decode_as().map_err(cms_error)?;
+    let mut signer_infos = signed_data.signer_infos.0.into_vec();
+    let signer_info = signer_infos.first_mut().ok_or("CMS signature has no signer")?;
+
+    let token = tsa::request_timestamp(tsa_url, signer_info.signature.as_bytes())?;
+    let mut values = SetOfVec::new();
+    values.insert(tsa::token_attribute_value(&token)?).map_err(cms_error)?;
+    let mut unsigned_attrs = SetOfVec::new();
+    unsigned_attrs
+        .insert(Attribute {
+            oid: tsa::ID_AA_SIGNATURE_TIME_STAMP_TOKEN,
+            values,
+        })
+        .map_err(cms_error)?;
+    signer_info.unsigned_attrs = Some(unsigned_attrs);
+
+    signed_data.signer_infos = SignerInfos(SetOfVec::try_from(signer_infos).map_err(cms_error)?);
+    content_info.content = Any::encode_from(&signed_data).map_err(cms_error)?;
+    Ok(content_info.to_der().map_err(cms_error)?)
+}
+
+fn build_cms_with<S, Sig>(signing_key: &S, certificate: &Certificate, content_digest: &[u8]) -> Result<Vec<u8>>
+where
+    S: Keypair + DynSignatureAlgorithmIdentifier + Signer<Sig>,
+    S::VerifyingKey: EncodePublicKey,
+    Sig: SignatureBitStringEncoding,
+{
+    let certificate_der = certificate::to_der(certificate)?;
+
+    let content = EncapsulatedContentInfo {
+        econtent_type: rfc5911::ID_DATA,
+        econtent: None,
+    };
+    let digest_algorithm = AlgorithmIdentifierOwned {
+        oid: rfc5912::ID_SHA_256,
+        parameters: None,
+    };
+    let sid = SignerIdentifier::IssuerAndSerialNumber(IssuerAndSerialNumber {
+        issuer: certificate.tbs_certificate.issuer.clone(),
+        serial_number: certificate.tbs_certificate.serial_number.clone(),
+    });
+
+    let cms_error = |e: &dyn std::fmt::Display| format!("Failed to build CMS signature: {}", e);
+    let mut signer_info = SignerInfoBuilder::new(
+        signing_key,
+        sid,
+        digest_algorithm.clone(),
+        &content,
+        Some(content_digest),
+    )
+    .map_err(|e| cms_error(&e))?;
+    signer_info
+        .add_signed_attribute(signing_certificate_attribute(&certificate_der)?)
+        .map_err(|e| cms_error(&e))?;
+
+    let content_info = SignedDataBuilder::new(&content)
+        .add_digest_algorithm(digest_algorithm)
+        .map_err(|e| cms_error(&e))?
+        .add_certificate(CertificateChoices::Certificate(certificate.clone()))
+        .map_err(|e| cms_error(&e))?
+        .add_signer_info::<_, Sig>(signer_info)
+        .map_err(|e| cms_error(&e))?
+        .build()
+        .map_err(|e| cms_error(&e))?;
+
+    Ok(content_info.to_der().map_err(|e| cms_error(&e))?)
+}
+
+/// ESS `signing-certificate-v2` attribute, which CAdES requires to bind the
+/// signature to the certificate: a single `ESSCertIDv2` holding the SHA-256 of
+/// the certificate (the default hash algorithm, so it is omitted).
+fn signing_certificate_attribute(certificate_der: &[u8]) -> Result<Attribute> {
+    let attr_error = |e: x509_cert::der::Error| format!("Failed to encode signing certificate: {}", e);
+
+    let cert_hash = Sha256::digest(certificate_der);
+    let cert_hash = OctetStringRef::new(&cert_hash).map_err(attr_error)?;
+    let ess_cert_id = Any::new(Tag::Sequence, cert_hash.to_der().map_err(attr_error)?).map_err(attr_error)?;
+    let certs = Any::new(Tag::Sequence, ess_cert_id.to_der().map_err(attr_error)?).map_err(attr_error)?;
+    let signing_certificate = Any::new(Tag::Sequence, certs.to_der().map_err(attr_error)?).map_err(attr_error)?;
+
+    let mut values = SetOfVec::new();
+    values.insert(signing_certificate).map_err(attr_error)?;
+    Ok(Attribute {
+        oid: rfc5911::ID_AA_SIGNING_CERTIFICATE_V_2,
+        values,
+    })
+}
+
+/// A CMS signature read from the `/Contents` of a `/Sig` dictionary, with the
+/// certificate of whoever made it.
+pub struct CmsSignature {
+    signer: SignerInfo,
+    pub certificate: Certificate,
+    /// What the signed byte ranges were hashed with.
+    pub hash_algorithm: HashAlgorithm,
+}
+
+/// Why a CMS signature did not check out, and whether that makes it
+/// `Invalid` or only `Unverified`.
+pub type CmsFailure = (SignatureStatus, String);
+
+impl CmsSignature {
+    /// Reads `contents`, which may be zero-padded to the room reserved for it.
+    pub fn parse(contents: &[u8]) -> std::result::Result<CmsSignature, CmsFailure> {
+        let malformed = |e: &dyn std::fmt::Display| (SignatureStatus::Invalid, format!("Malformed CMS signature: {}", e));
+
+        // Decoding from a reader stops at the end of the DER, before the padding.
+        let mut reader = SliceReader::new(contents).map_err(|e| malformed(&e))?;
+        let content_info = ContentInfo::decode(&mut reader).map_err(|e| malformed(&e))?;
+        if content_info.content_type != rfc5911::ID_SIGNED_DATA {
+            return Err(malformed(&"not signed data"));
+        }
+        let signed_data: SignedData = content_info.content.decode_as().map_err(|e| malformed(&e))?;
+        let signer = signed_data.signer_infos.0.iter().next().cloned().ok_or_else(|| malformed(&"no signer"))?;
+        let certificate = tsa::signer_certificate(&signed_data, &signer)
+            .cloned()
+            .ok_or((SignatureStatus::Unverified, "The signature does not include the signer's certificate".to_string()))?;
+        let hash_algorithm = match signer.digest_alg.oid {
+            rfc5912::ID_SHA_256 => HashAlgorithm::Sha256,
+            rfc5912::ID_SHA_384 => HashAlgorithm::Sha384,
+            rfc5912::ID_SHA_512 => HashAlgorithm::Sha512,
+            oid => return Err((SignatureStatus::Unverified, format!("Unsupported digest algorithm {}", oid))),
+        };
+        Ok(CmsSignature {
+            signer,
+            certificate,
+            hash_algorithm,
+        })
+    }
+
+    /// The signing time the signer's clock gave, as "2024-01-31 12:00:00 UTC".
+    pub fn signing_time(&self) -> Option<String> {
+        let time = self
+            .signer
+            .signed_attrs
+            .as_ref()?
+            .iter()
+            .find(|attr| attr.oid == rfc5911::ID_SIGNING_TIME)?
+            .values
+            .iter()
+            .next()?;
+        // A CHOICE of UTCTime and GeneralizedTime, so it is decoded with its tag.
+        let time = Time::from_der(&time.to_der().ok()?).ok()?;
+        Some(certificate::format_time(time.to_date_time()))
+    }
+
+    /// The DER token of a timestamp over the signature value, if one was added.
+    pub fn timestamp_token(&self) -> Option<Vec<u8>> {
+        self.signer
+            .unsigned_attrs
+            .as_ref()?
+            .iter()
+            .find(|attr| attr.oid == tsa::ID_AA_SIGNATURE_TIME_STAMP_TOKEN)?
+            .values
+            .iter()
+            .next()?
+            .to_der()
+            .ok()
+    }
+
+    pub fn signature_value(&self) -> &[u8] {
+        self.signer.signature.as_bytes()
+    }
+
+    /// Whether the signed attributes hold `content_digest`, the signed byte
+    /// ranges hashed with `hash_algorithm`. Without signed attributes there
+    /// is only the signature itself to go by, so this is `None`.
+    pub fn covers(&self, content_digest: &[u8]) -> Option<bool> {
+        let signed_attrs = self.signer.signed_attrs.as_ref()?;
+        let covered = signed_attrs
+            .iter()
+            .find(|attr| attr.oid == rfc5911::ID_MESSAGE_DIGEST)
+            .and_then(|attr| attr.values.iter().next())
+            .and_then(|value| value.decode_as::<OctetStringRef>().ok())
+            .is_some_and(|message_digest| message_digest.as_bytes() == content_digest);
+        Some(covered)
+    }
+
+    /// Checks that the certificate's key signed `content_digest`. Without
+    /// signed attributes, as older `adbe.pkcs7.detached` signatures have it,
+    /// the signature is over the digest itself.
+    pub fn verify(&self, content_digest: &[u8]) -> std::result::Result<(), CmsFailure> {
+        let unsupported = || (SignatureStatus::Unverified, "The signature uses a key type Sigillum cannot check".to_string());
+
+        if self.covers(content_digest) == Some(false) {
+            return Err((SignatureStatus::Invalid, "The signed content has been altered".to_string()));
+        }
+        let public_key = certificate::public_key(&self.certificate).map_err(|_| unsupported())?;
+        // Other applications still sign with keys too short to trust.
+        if matches!(public_key, PublicKey::Rsa(_)) && public_key.bits() < MIN_RSA_BITS {
+            let reason = format!("The signer's RSA key is only {} bits, too weak to trust", public_key.bits());
+            return Err((SignatureStatus::Invalid, reason));
+        }
+        // What was signed, and its hash for the key types that sign one.
+        let (message, digest) = match &self.signer.signed_attrs {
+            Some(signed_attrs) => {
+                let message = signed_attrs
+                    .to_der()
+                    .map_err(|e| (SignatureStatus::Invalid, format!("Malformed CMS signature: {}", e)))?;
+                let mut hasher = Hasher::new(self.hash_algorithm);
+                hasher.update(&message);
+                (message, hasher.finalize())
+            }
+            None if matches!(public_key, PublicKey::Ed25519(_)) => return Err(unsupported()),
+            None => (content_digest.to_vec(), content_digest.to_vec()),
+        };
+
+        let signature = self.signature_value();
+        let valid = match &public_key {
+            PublicKey::Rsa(_) => {
+                if self.signer.signature_algorithm.oid == rfc5912::ID_RSASSA_PSS {
+                    return Err(unsupported());
+                }
+                public_key.verify_digest(self.hash_algorithm, &digest, signature)
+            }
+            PublicKey::P256(_) => p256::ecdsa::Signature::from_der(signature)
+                .is_ok_and(|signature| public_key.verify_digest(self.hash_algorithm, &digest, &signature.to_bytes())),
+            // Ed25519 signs the attributes themselves, which `verify_digest`
+            // passes through.
+            PublicKey::Ed25519(_) => public_key.verify_digest(self.hash_algorithm, &message, signature),
+        };
+        if valid {
+            Ok(())
+        } else {
+            Err((SignatureStatus::Invalid, "Signature does not match the signer's certificate".to_string()))
+        }
+    }
+}
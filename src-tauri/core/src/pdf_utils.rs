@@ -0,0 +1,2520 @@
+use crate::api::{SignatureInfo, DIGEST_FORMAT};
+use crate::appearance::{Appearance, Color, Position, SignaturePlacement, StampPlacement};
+use crate::encryption::DocumentKey;
+use crate::error::{Result, SigillumError};
+use crate::hash::{HashAlgorithm, Hasher};
+use crate::lock;
+use crate::ntp::ClockCheck;
+use crate::pdfa::{self, PdfA};
+use crate::progress::{Monitor, Progress};
+use crate::text_metrics::{self, StandardFont, TextWidth};
+use crate::truetype::TrueTypeFont;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use chrono::{DateTime, Utc};
+use lopdf::{Document, Dictionary, IncrementalDocument, Object, ObjectId, StringFormat};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+const SIGNATURE_MARKER: &[u8] = b"Digitally signed by ";
+
+/// `/Type` of the structured signature dictionary.
+const PAYLOAD_TYPE: &[u8] = b"SigillumSignature";
+const PAYLOAD_MARKER: &[u8] = b"/SigillumSignature";
+const EOF_MARKER: &[u8] = b"%%EOF";
+const ENCRYPT_MARKER: &[u8] = b"/Encrypt";
+const BYTE_RANGE_MARKER: &[u8] = b"/ByteRange";
+/// Key of the attachment file specification holding an OpenPGP signature,
+/// giving how many bytes of the document it covers.
+const PGP_SIGNED_LENGTH_KEY: &[u8] = b"SigillumSignedLength";
+const PGP_SIGNED_LENGTH_MARKER: &[u8] = b"/SigillumSignedLength";
+/// Attachment name of the first OpenPGP signature; later ones are numbered.
+const PGP_SIGNATURE_FILE: &str = "signature.asc";
+/// Most name tree nodes visited when looking for attachments.
+const MAX_NAME_TREE_NODES: usize = 1024;
+
+/// Most signatures read from one document; also stops a `/Previous` cycle.
+const MAX_SIGNATURES: usize = 64;
+
+const WATERMARK_FONT: StandardFont = StandardFont::Helvetica;
+/// Embedded (as a subset) when the watermark has characters outside
+/// WinAnsiEncoding. Covers Latin, Greek, Cyrillic and many other scripts.
+const UNICODE_FONT: &[u8] = include_bytes!("../fonts/DejaVuSans.ttf");
+const UNICODE_FONT_NAME: &str = "DejaVuSans";
+/// Distance from the page edges to the watermark block.
+const WATERMARK_MARGIN: f32 = 10.0;
+/// Line height as a multiple of the font size.
+const LINE_SPACING: f32 = 1.25;
+/// Room above and below a line's baseline, as fractions of the font size.
+const ASCENT: f32 = 0.625;
+const DESCENT: f32 = 0.25;
+/// Space between a stamp image and the watermark text, in points.
+const STAMP_GAP: f32 = 6.0;
+/// Resource name prefix of stamp images.
+const STAMP_PREFIX: &str = "SWM";
+/// Resource name prefix of QR code images.
+const QR_PREFIX: &str = "QWM";
+/// Side of one QR code module, in points. Fixed rather than fitting the code
+/// into a fixed box, so that longer payloads still scan once printed.
+const QR_MODULE_SIZE: f32 = 1.0;
+/// Blank modules around a QR code, as scanners expect.
+const QR_QUIET_ZONE: usize = 4;
+/// Namespace of the signature details in the XMP metadata.
+pub(crate) const XMP_NAMESPACE: &str = "https://sigillum.app/ns/signature/1.0/";
+/// How the signature details' `rdf:Description` starts, so a later signature
+/// can find and replace it.
+const XMP_DESCRIPTION_START: &str = "<rdf:Description rdf:about=\"\" xmlns:sigillum=";
+/// How every watermark content stream starts, so later signatures can find them.
+const WATERMARK_PREFIX: &str = "q\nBT\n/FWM";
+/// How the standard signature fields Sigillum adds are named, before their
+/// number.
+const SIGNATURE_FIELD_NAME: &str = "Sigillum Signature ";
+
+/// How many bytes from the signature marker onwards are handed to the line
+/// parser. The watermark block is only a handful of short lines, but the
+/// payload may hold a timestamp token with the TSA's certificates; this bounds
+/// the work on both the in-memory and the streaming path.
+const SIGNATURE_WINDOW: usize = 64 * 1024;
+
+const SCAN_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Bytes reserved for the CMS blob in a `/Sig` dictionary. A signature with a
+/// 2048-bit key and its self-signed certificate needs well under half of this.
+const PADES_CONTENTS_SIZE: usize = 8192;
+
+/// Wide enough that the real offsets always fit when patched in.
+const BYTE_RANGE_PLACEHOLDER: i64 = 9_999_999_999;
+
+/// Bytes reserved for the signature over a signing update. An RSA-4096
+/// signature, the largest a supported key makes, needs half of this.
+const UPDATE_SIGNATURE_SIZE: usize = 1024;
+
+/// Most parts `/UpdateRange` lists: one either side of the update signature
+/// and, with a standard signature, either side of its `/Contents` too.
+pub const UPDATE_RANGE_PARTS: usize = 3;
+
+/// Longest value, `<` and `>` included, that `/UpdateRange` may leave out.
+pub const MAX_UPDATE_GAP: u64 = 2 * PADES_CONTENTS_SIZE as u64 + 2;
+
+/// Result of a single pass over a document: the `canonical_hash` of the whole
+/// file and the signatures the byte scan could see.
+#[derive(Debug)]
+pub struct PdfScan {
+    pub canonical_hash: String,
+    /// Signatures read from their payloads, oldest first.
+    pub signatures: Vec<SignatureInfo>,
+    /// The first watermark's text, for documents signed before the payload
+    /// existed. Read from the raw bytes, so `signatures_from_document` is the
+    /// better source whenever the document can be parsed.
+    pub watermark: Option<SignatureInfo>,
+    /// See `may_be_encrypted`.
+    pub may_be_encrypted: bool,
+    /// See `may_have_signature_fields`.
+    pub may_have_signature_fields: bool,
+    /// See `may_have_pgp_signatures`.
+    pub may_have_pgp_signatures: bool,
+}
+
+/// Draws `text` on the selected pages, with a QR code of `qr_payload` to the
+/// right of it when there is one. With a `placement`, it is drawn only there,
+/// from the rectangle's top-left corner and wrapped to its width.
+///
+/// On a PDF/A document the font is always embedded and nothing is
+/// transparent: the opacity is drawn by fading the colours towards white.
+#[allow(clippy::too_many_arguments)]
+pub fn add_watermark_to_pdf(
+    doc: &mut Document,
+    text: &str,
+    qr_payload: Option<&str>,
+    max_line_width: Option<f32>,
+    appearance: &Appearance,
+    placement: Option<&SignaturePlacement>,
+    pdfa: Option<&PdfA>,
+    monitor: &Monitor,
+) -> Result<()> {
+    appearance.validate()?;
+    let pages = doc.get_pages();
+    let page_count = pages.len() as u32;
+    let page_ids: Vec<(u32, u16)> = match placement {
+        Some(placement) => vec![placement_page(&pages, placement)?],
+        None => {
+            if let Some(page) = appearance.pages.missing_page(page_count) {
+                return Err(format!("Page {} is past the end of the {}-page document", page, page_count).into());
+            }
+            pages
+                .iter()
+                .filter(|(&number, _)| appearance.pages.includes(number, page_count))
+                .map(|(_, &id)| id)
+                .collect()
+        }
+    };
+    if page_ids.is_empty() {
+        return Err(format!("No page matches the page selection '{}'", appearance.pages).into());
+    }
+    
+    let font = if pdfa.is_none() && text.chars().all(|c| c == '\n' || text_metrics::win_ansi_code(c).is_some()) {
+        WatermarkFont::Standard(WATERMARK_FONT)
+    } else {
+        WatermarkFont::Unicode(TrueTypeFont::parse(UNICODE_FONT)?)
+    };
+    let font_id = font.add_to(doc, text);
+    // Earlier watermarks on the page keep their own graphics state and image,
+    // so these are named after their objects rather than reusing one name.
+    let graphics_state = (appearance.opacity < 1.0 && pdfa.is_none()).then(|| {
+        let id = doc.add_object(Dictionary::from_iter(vec![
+            ("Type", Object::Name(b"ExtGState".to_vec())),
+            ("ca", Object::Real(appearance.opacity)),
+            ("CA", Object::Real(appearance.opacity)),
+        ]));
+        (format!("GSWM{}", id.0), id)
+    });
+    let stamp = match &appearance.stamp {
+        Some(stamp) => {
+            let flatten = pdfa.map(|_| appearance.opacity);
+            let (id, pixel_width, pixel_height) = add_stamp_image(doc, &stamp.png, flatten)?;
+            Some(StampImage {
+                name: format!("{}{}", STAMP_PREFIX, id.0),
+                id,
+                placement: stamp.placement,
+                width: stamp.width,
+                height: stamp.width * pixel_height as f32 / pixel_width as f32,
+            })
+        }
+        None => None,
+    };
+    let qr = match qr_payload {
+        Some(payload) => {
+            let (id, modules) = add_qr_image(doc, payload)?;
+            Some(QrImage {
+                name: format!("{}{}", QR_PREFIX, id.0),
+                id,
+                size: modules as f32 * QR_MODULE_SIZE,
+            })
+        }
+        None => None,
+    };
+    
+    let total = page_ids.len() as u64;
+    for (done, page_id) in page_ids.into_iter().enumerate() {
+        monitor.report(Progress::Watermarking { done: done as u64, total })?;
+        let page_obj = doc.get_object(page_id)
+            .map_err(|e| format!("Failed to get page: {}", e))?;
+        
+        let mut page_dict = match page_obj {
+            Object::Dictionary(ref d) => d.clone(),
+            _ => continue,
+        };
+        
+        // The block is laid out on the page as displayed, upright and with
+        // its origin at the displayed bottom-left corner, and then mapped into
+        // the page's own coordinates.
+        let frame = PageFrame::of(doc, &page_dict);
+        let (width, height) = frame.display_size();
+        
+        let font_size = appearance.font_size;
+        let line_height = font_size * LINE_SPACING;
+        let stamp_room = match &stamp {
+            Some(stamp) if matches!(stamp.placement, StampPlacement::Left | StampPlacement::Right) => {
+                stamp.width + STAMP_GAP
+            }
+            _ => 0.0,
+        };
+        let qr_room = qr.as_ref().map_or(0.0, |qr| qr.size + STAMP_GAP);
+        let available = match placement {
+            Some(placement) => placement.rect[2],
+            None => width - 2.0 * WATERMARK_MARGIN,
+        };
+        let max_width = max_line_width.unwrap_or(available - stamp_room - qr_room);
+        let lines: Vec<String> = text
+            .split('\n')
+            .flat_map(|line| text_metrics::wrap_text(line, &font, font_size, max_width))
+            .collect();
+        
+        // Lay the text and the stamp out with the block's top-left corner at
+        // the origin, then place the block on the page.
+        let text_width = lines
+            .iter()
+            .map(|line| font.text_width(line, font_size))
+            .fold(0.0, f32::max);
+        let text_height = (lines.len() as f32 - 1.0) * line_height + font_size * (ASCENT + DESCENT);
+        let (text_x, text_top, stamp_box, block_width, block_height) = match &stamp {
+            None => (0.0, 0.0, None, text_width, text_height),
+            Some(stamp) => {
+                let (w, h) = (stamp.width, stamp.height);
+                match stamp.placement {
+                    StampPlacement::Left => (w + STAMP_GAP, 0.0, Some((0.0, 0.0)), w + STAMP_GAP + text_width, text_height.max(h)),
+                    StampPlacement::Right => (0.0, 0.0, Some((text_width + STAMP_GAP, 0.0)), text_width + STAMP_GAP + w, text_height.max(h)),
+                    StampPlacement::Above => (0.0, h + STAMP_GAP, Some((0.0, 0.0)), text_width.max(w), h + STAMP_GAP + text_height),
+                    StampPlacement::Below => (0.0, 0.0, Some((0.0, text_height + STAMP_GAP)), text_width.max(w), text_height + STAMP_GAP + h),
+                }
+            }
+        };
+        // The QR code goes to the right of everything else, top-aligned.
+        let (qr_x, block_width, block_height) = match &qr {
+            Some(qr) => (block_width + STAMP_GAP, block_width + STAMP_GAP + qr.size, block_height.max(qr.size)),
+            None => (0.0, block_width, block_height),
+        };
+        
+        let (left, top) = match placement {
+            Some(placement) => {
+                let [rect_x, rect_y, rect_width, rect_height] = placement.rect;
+                if rect_x + rect_width > width + 0.5 || rect_y + rect_height > height + 0.5 {
+                    return Err(format!("The signature rectangle runs off page {}", placement.page).into());
+                }
+                (rect_x, height - rect_y)
+            }
+            None => {
+                // Co-signatures go next to the watermarks already on the page,
+                // away from the edge, with a blank line between them.
+                let earlier = watermark_extent(doc, &page_dict);
+                let shift = if earlier > 0.0 { earlier + line_height } else { 0.0 };
+                let left = match appearance.position {
+                    Position::TopLeft | Position::BottomLeft => WATERMARK_MARGIN,
+                    Position::TopRight | Position::BottomRight => width - WATERMARK_MARGIN - block_width,
+                    Position::Center => (width - block_width) / 2.0,
+                };
+                let top = match appearance.position {
+                    Position::TopLeft | Position::TopRight => height - WATERMARK_MARGIN - shift,
+                    Position::BottomLeft | Position::BottomRight => WATERMARK_MARGIN + block_height + shift,
+                    Position::Center => (height + block_height) / 2.0 - shift,
+                };
+                (left, top)
+            }
+        };
+        // Everything is drawn relative to the start of the first line, which
+        // is also what the block turns about. It turns with the page too, so
+        // that it reads upright as displayed.
+        let x = left + text_x;
+        let y = top - text_top - font_size * ASCENT;
+        let (page_x, page_y) = frame.to_user(x, height - y);
+        let rotation = appearance.rotation + frame.rotation as f32;
+        let (sin, cos) = rotation.to_radians().sin_cos();
+        // Quarter turns get an exact matrix; adding zero turns -0 into 0.
+        let (sin, cos) = if rotation % 90.0 == 0.0 { (sin.round() + 0.0, cos.round() + 0.0) } else { (sin, cos) };
+        
+        let mut content = String::new();
+        content.push_str(&format!("{} {} Tf\n", WATERMARK_PREFIX, font_size));
+        if let Some((name, _)) = &graphics_state {
+            content.push_str(&format!("/{} gs\n", name));
+        }
+        if pdfa.is_some() {
+            let [r, g, b] = appearance.color.to_unit().map(|c| fade(c, appearance.opacity));
+            // Grey is allowed whatever colours the output intent is for.
+            if r == g && g == b {
+                if r != 0.0 {
+                    content.push_str(&format!("{} g\n", r));
+                }
+            } else {
+                content.push_str(&format!("{} {} {} rg\n", r, g, b));
+            }
+        } else if appearance.color != Color::default() {
+            let [r, g, b] = appearance.color.to_unit();
+            content.push_str(&format!("{} {} {} rg\n", r, g, b));
+        }
+        
+        for (i, line) in lines.iter().enumerate() {
+            let line = font.encode(line);
+            if i > 0 {
+                content.push_str(&format!("0 {} Td {} Tj\n", -line_height, line));
+            } else if rotation != 0.0 {
+                content.push_str(&format!("{} {} {} {} {} {} Tm {} Tj\n", cos, sin, -sin, cos, page_x, page_y, line));
+            } else {
+                content.push_str(&format!("{} {} Td {} Tj\n", page_x, page_y, line));
+            }
+        }
+        
+        content.push_str("ET\n");
+        if let (Some(stamp), Some((stamp_x, stamp_top))) = (&stamp, stamp_box) {
+            // `watermark_extent` reads the height back from this line.
+            let (dx, dy) = (left + stamp_x - x, top - stamp_top - stamp.height - y);
+            content.push_str(&format!(
+                "q {} {} {} {} {} {} cm {} 0 0 {} {} {} cm /{} Do Q\n",
+                cos, sin, -sin, cos, page_x, page_y, stamp.width, stamp.height, dx, dy, stamp.name
+            ));
+        }
+        if let Some(qr) = &qr {
+            // Same form as the stamp, for `watermark_extent`.
+            let (dx, dy) = (left + qr_x - x, top - qr.size - y);
+            content.push_str(&format!(
+                "q {} {} {} {} {} {} cm {} 0 0 {} {} {} cm /{} Do Q\n",
+                cos, sin, -sin, cos, page_x, page_y, qr.size, qr.size, dx, dy, qr.name
+            ));
+        }
+        content.push('Q');
+        
+        let stream = lopdf::Stream::new(Dictionary::new(), content.into_bytes());
+        let stream_id = doc.add_object(Object::Stream(stream));
+        
+        let new_contents = match page_dict.get(b"Contents") {
+            Ok(Object::Array(arr)) => {
+                let mut arr = arr.clone();
+                arr.push(Object::Reference(stream_id));
+                Object::Array(arr)
+            }
+            Ok(Object::Reference(existing)) => {
+                Object::Array(vec![Object::Reference(*existing), Object::Reference(stream_id)])
+            }
+            _ => Object::Array(vec![Object::Reference(stream_id)]),
+        };
+        
+        page_dict.set("Contents", new_contents);
+        
+        let mut resources = watermark_resources(doc, &page_dict, font_id);
+        if let Some((name, id)) = &graphics_state {
+            add_resource(doc, &mut resources, b"ExtGState", name, *id);
+        }
+        if let Some(stamp) = &stamp {
+            add_resource(doc, &mut resources, b"XObject", &stamp.name, stamp.id);
+        }
+        if let Some(qr) = &qr {
+            add_resource(doc, &mut resources, b"XObject", &qr.name, qr.id);
+        }
+        page_dict.set("Resources", Object::Dictionary(resources));
+        
+        doc.objects.insert(page_id, Object::Dictionary(page_dict));
+    }
+    
+    monitor.report(Progress::Watermarking { done: total, total })
+}
+
+/// The id of the page `placement` is on.
+fn placement_page(pages: &BTreeMap<u32, ObjectId>, placement: &SignaturePlacement) -> Result<ObjectId> {
+    placement.validate()?;
+    Ok(pages
+        .get(&placement.page)
+        .copied()
+        .ok_or_else(|| format!("Page {} is past the end of the {}-page document", placement.page, pages.len()))?)
+}
+
+/// Width and height in points of each page as a viewer displays it, that is
+/// of its crop box with its rotation applied.
+pub fn page_sizes(doc: &Document) -> Vec<[f32; 2]> {
+    doc.get_pages()
+        .into_values()
+        .map(|id| match doc.get_dictionary(id) {
+            Ok(page) => {
+                let (width, height) = PageFrame::of(doc, page).display_size();
+                [width, height]
+            }
+            Err(_) => [612.0, 792.0],
+        })
+        .collect()
+}
+
+/// The part of a page a viewer shows, in default user space, and how far
+/// the page is turned clockwise for display.
+struct PageFrame {
+    left: f32,
+    bottom: f32,
+    right: f32,
+    top: f32,
+    /// 0, 90, 180 or 270.
+    rotation: i64,
+}
+
+impl PageFrame {
+    /// The page's crop box, or its media box when it has none, and its
+    /// rotation, either of which it may inherit from the page tree.
+    fn of(doc: &Document, page_dict: &Dictionary) -> PageFrame {
+        let page_box = |key: &[u8]| {
+            let values = inherited(doc, page_dict, key)
+                .and_then(|found| doc.dereference(found).ok())
+                .and_then(|(_, found)| found.as_array().ok())
+                .and_then(|values| values.iter().map(|n| n.as_float().ok()).collect::<Option<Vec<f32>>>())
+                .filter(|values| values.len() == 4)?;
+            Some([
+                values[0].min(values[2]),
+                values[1].min(values[3]),
+                values[0].max(values[2]),
+                values[1].max(values[3]),
+            ])
+        };
+        let media = page_box(b"MediaBox").unwrap_or([0.0, 0.0, 612.0, 792.0]);
+        // A crop box is clipped to the media box.
+        let [left, bottom, right, top] = match page_box(b"CropBox") {
+            Some(crop) => [
+                crop[0].max(media[0]),
+                crop[1].max(media[1]),
+                crop[2].min(media[2]),
+                crop[3].min(media[3]),
+            ],
+            None => media,
+        };
+        let rotation = inherited(doc, page_dict, b"Rotate")
+            .and_then(|rotate| rotate.as_i64().ok())
+            .map_or(0, |rotate| rotate.rem_euclid(360) / 90 * 90);
+        PageFrame { left, bottom, right, top, rotation }
+    }
+
+    /// Width and height as displayed.
+    fn display_size(&self) -> (f32, f32) {
+        let (width, height) = (self.right - self.left, self.top - self.bottom);
+        if self.rotation % 180 == 0 {
+            (width, height)
+        } else {
+            (height, width)
+        }
+    }
+
+    /// The point `x` across and `y` down from the displayed top-left corner.
+    fn to_user(&self, x: f32, y: f32) -> (f32, f32) {
+        match self.rotation {
+            90 => (self.left + y, self.bottom + x),
+            180 => (self.right - x, self.bottom + y),
+            270 => (self.right - y, self.top - x),
+            _ => (self.left + x, self.top - y),
+        }
+    }
+}
+
+/// `key` from the page or the nearest of its ancestors that has it.
+fn inherited<'a>(doc: &'a Document, page_dict: &'a Dictionary, key: &[u8]) -> Option<&'a Object> {
+    let mut node = Some(page_dict);
+    let mut depth = 0;
+    while let Some(dict) = node {
+        if let Ok(found) = dict.get(key) {
+            return Some(found);
+        }
+        depth += 1;
+        node = dict
+            .get(b"Parent")
+            .and_then(Object::as_reference)
+            .and_then(|id| doc.get_dictionary(id))
+            .ok()
+            .filter(|_| depth < 32);
+    }
+    None
+}
+
+/// A colour component drawn at `opacity` over white paper.
+fn fade(component: f32, opacity: f32) -> f32 {
+    component * opacity + (1.0 - opacity)
+}
+
+/// A stamp image added to the document, at the size it is drawn.
+struct StampImage {
+    name: String,
+    id: (u32, u16),
+    placement: StampPlacement,
+    width: f32,
+    height: f32,
+}
+
+/// A QR code image added to the document; it is drawn square.
+struct QrImage {
+    name: String,
+    id: (u32, u16),
+    size: f32,
+}
+
+/// The font watermark text is set in: a standard font if WinAnsiEncoding has
+/// every character, otherwise the embedded Unicode font.
+enum WatermarkFont<'a> {
+    Standard(StandardFont),
+    Unicode(TrueTypeFont<'a>),
+}
+
+impl WatermarkFont<'_> {
+    /// Adds the font dictionary, with a subset of the font program covering
+    /// `text` when it is embedded.
+    fn add_to(&self, doc: &mut Document, text: &str) -> (u32, u16) {
+        let font = match self {
+            WatermarkFont::Standard(font) => font,
+            WatermarkFont::Unicode(font) => return add_unicode_font(doc, font, text),
+        };
+        doc.add_object(Dictionary::from_iter(vec![
+            ("Type", Object::Name(b"Font".to_vec())),
+            ("Subtype", Object::Name(b"Type1".to_vec())),
+            ("BaseFont", Object::Name(font.base_font().as_bytes().to_vec())),
+            ("Encoding", Object::Name(b"WinAnsiEncoding".to_vec())),
+            ("Name", Object::Name(b"FWM".to_vec())),
+        ]))
+    }
+
+    /// `line` as a string operand for `Tj`: an escaped WinAnsi literal, or the
+    /// glyph ids as hex for the embedded font.
+    fn encode(&self, line: &str) -> String {
+        match self {
+            WatermarkFont::Standard(_) => {
+                let mut literal = String::from("(");
+                for byte in line.chars().filter_map(text_metrics::win_ansi_code) {
+                    match byte {
+                        b'(' | b')' | b'\\' => {
+                            literal.push('\\');
+                            literal.push(byte as char);
+                        }
+                        b' '..=b'~' => literal.push(byte as char),
+                        _ => literal.push_str(&format!("\\{:03o}", byte)),
+                    }
+                }
+                literal.push(')');
+                literal
+            }
+            WatermarkFont::Unicode(font) => {
+                let glyphs: String = line.chars().map(|c| format!("{:04X}", font.glyph_id(c))).collect();
+                format!("<{}>", glyphs)
+            }
+        }
+    }
+}
+
+impl TextWidth for WatermarkFont<'_> {
+    fn text_width(&self, text: &str, font_size: f32) -> f32 {
+        match self {
+            WatermarkFont::Standard(font) => font.text_width(text, font_size),
+            WatermarkFont::Unicode(font) => font.text_width(text, font_size),
+        }
+    }
+}
+
+/// Embeds the subset of `font` that `text` needs as a Type0 font with
+/// Identity-H encoding, so two-byte glyph ids are drawn directly. The
+/// `/ToUnicode` map keeps the text searchable and copyable.
+fn add_unicode_font(doc: &mut Document, font: &TrueTypeFont, text: &str) -> (u32, u16) {
+    let mut glyphs = BTreeSet::new();
+    let mut to_unicode = std::collections::BTreeMap::new();
+    for c in text.chars().filter(|&c| c != '\n') {
+        let glyph = font.glyph_id(c);
+        glyphs.insert(glyph);
+        // Glyph 0 is the "missing" glyph every unsupported character shares.
+        if glyph != 0 {
+            to_unicode.entry(glyph).or_insert(c);
+        }
+    }
+    
+    // Subset fonts are named with a tag unique to the glyphs they hold.
+    let digest = Sha256::digest(glyphs.iter().flat_map(|g| g.to_be_bytes()).collect::<Vec<u8>>());
+    let tag: String = digest.iter().take(6).map(|b| (b'A' + b % 26) as char).collect();
+    let base_font = format!("{}+{}", tag, UNICODE_FONT_NAME).into_bytes();
+    
+    let program = font.subset(&glyphs);
+    // PDF/A-1 wants subsets to list the glyphs they have, one bit each.
+    let kept = font.subset_glyphs(&glyphs);
+    let mut cid_set = vec![0u8; kept.last().map_or(0, |&last| usize::from(last) / 8 + 1)];
+    for glyph in kept {
+        cid_set[usize::from(glyph) / 8] |= 0x80 >> (glyph % 8);
+    }
+    let mut cid_set = lopdf::Stream::new(Dictionary::new(), cid_set);
+    let _ = cid_set.compress();
+    let cid_set_id = doc.add_object(cid_set);
+    let mut font_file = lopdf::Stream::new(
+        Dictionary::from_iter(vec![("Length1", Object::Integer(program.len() as i64))]),
+        program,
+    );
+    let _ = font_file.compress();
+    let font_file_id = doc.add_object(font_file);
+    
+    let metrics = font.metrics();
+    let descriptor_id = doc.add_object(Dictionary::from_iter(vec![
+        ("Type", Object::Name(b"FontDescriptor".to_vec())),
+        ("FontName", Object::Name(base_font.clone())),
+        // Nonsymbolic.
+        ("Flags", Object::Integer(32)),
+        ("FontBBox", Object::Array(metrics.bbox.iter().map(|&v| Object::Integer(v.into())).collect())),
+        ("ItalicAngle", Object::Integer(0)),
+        ("Ascent", Object::Integer(metrics.ascent.into())),
+        ("Descent", Object::Integer(metrics.descent.into())),
+        ("CapHeight", Object::Integer(metrics.cap_height.into())),
+        ("StemV", Object::Integer(80)),
+        ("FontFile2", Object::Reference(font_file_id)),
+        ("CIDSet", Object::Reference(cid_set_id)),
+    ]));
+    
+    let widths: Vec<Object> = glyphs
+        .iter()
+        .flat_map(|&glyph| {
+            [
+                Object::Integer(glyph.into()),
+                Object::Array(vec![Object::Integer(font.glyph_width(glyph).into())]),
+            ]
+        })
+        .collect();
+    let cid_font_id = doc.add_object(Dictionary::from_iter(vec![
+        ("Type", Object::Name(b"Font".to_vec())),
+        ("Subtype", Object::Name(b"CIDFontType2".to_vec())),
+        ("BaseFont", Object::Name(base_font.clone())),
+        ("CIDSystemInfo", Object::Dictionary(Dictionary::from_iter(vec![
+            ("Registry", Object::string_literal("Adobe")),
+            ("Ordering", Object::string_literal("Identity")),
+            ("Supplement", Object::Integer(0)),
+        ]))),
+        ("FontDescriptor", Object::Reference(descriptor_id)),
+        ("W", Object::Array(widths)),
+        ("CIDToGIDMap", Object::Name(b"Identity".to_vec())),
+    ]));
+    
+    let mut cmap = String::from(concat!(
+        "/CIDInit /ProcSet findresource begin\n12 dict begin\nbegincmap\n",
+        "/CIDSystemInfo << /Registry (Adobe) /Ordering (UCS) /Supplement 0 >> def\n",
+        "/CMapName /Adobe-Identity-UCS def\n/CMapType 2 def\n",
+        "1 begincodespacerange\n<0000> <FFFF>\nendcodespacerange\n",
+    ));
+    let entries: Vec<(u16, char)> = to_unicode.into_iter().collect();
+    // At most 100 entries per block.
+    for block in entries.chunks(100) {
+        cmap.push_str(&format!("{} beginbfchar\n", block.len()));
+        for (glyph, c) in block {
+            let unicode: String = c.encode_utf16(&mut [0; 2]).iter().map(|unit| format!("{:04X}", unit)).collect();
+            cmap.push_str(&format!("<{:04X}> <{}>\n", glyph, unicode));
+        }
+        cmap.push_str("endbfchar\n");
+    }
+    cmap.push_str("endcmap\nCMapName currentdict /CMap defineresource pop\nend\nend\n");
+    let to_unicode_id = doc.add_object(lopdf::Stream::new(Dictionary::new(), cmap.into_bytes()));
+    
+    doc.add_object(Dictionary::from_iter(vec![
+        ("Type", Object::Name(b"Font".to_vec())),
+        ("Subtype", Object::Name(b"Type0".to_vec())),
+        ("BaseFont", Object::Name(base_font)),
+        ("Encoding", Object::Name(b"Identity-H".to_vec())),
+        ("DescendantFonts", Object::Array(vec![Object::Reference(cid_font_id)])),
+        ("ToUnicode", Object::Reference(to_unicode_id)),
+    ]))
+}
+
+/// Height in points taken up by the watermarks earlier signatures drew on the
+/// page. Text counts a full line height per line, stamps their drawn height.
+fn watermark_extent(doc: &Document, page_dict: &Dictionary) -> f32 {
+    let contents = match page_dict.get(b"Contents") {
+        Ok(Object::Array(contents)) => contents.clone(),
+        Ok(contents) => vec![contents.clone()],
+        Err(_) => return 0.0,
+    };
+    contents
+        .iter()
+        .filter_map(|content| match doc.dereference(content) {
+            Ok((_, Object::Stream(stream))) => Some(stream.decompressed_content().unwrap_or_else(|_| stream.content.clone())),
+            _ => None,
+        })
+        .filter(|content| content.starts_with(WATERMARK_PREFIX.as_bytes()))
+        .map(|content| {
+            let content = String::from_utf8_lossy(&content);
+            // Watermarks from before the size was configurable are all 8pt.
+            let font_size = content[WATERMARK_PREFIX.len()..]
+                .split_whitespace()
+                .next()
+                .and_then(|size| size.parse::<f32>().ok())
+                .unwrap_or(8.0);
+            let text = content.lines().filter(|line| line.ends_with(" Tj")).count() as f32 * font_size * LINE_SPACING;
+            let stamps: f32 = content
+                .lines()
+                .filter(|line| line.contains(&format!("/{}", STAMP_PREFIX)) || line.contains(&format!("/{}", QR_PREFIX)))
+                .filter_map(|line| line.split_whitespace().nth(11)?.parse::<f32>().ok())
+                .sum();
+            text + stamps
+        })
+        .sum()
+}
+
+/// Encodes `payload` as a QR code in a 1-bit image XObject, quiet zone
+/// included. Returns the object and the image size in modules.
+fn add_qr_image(doc: &mut Document, payload: &str) -> Result<((u32, u16), usize)> {
+    let code = qrcode::QrCode::with_error_correction_level(payload, qrcode::EcLevel::L)
+        .map_err(|e| format!("Failed to make QR code: {}", e))?;
+    let colors = code.to_colors();
+    let width = code.width();
+    let size = width + 2 * QR_QUIET_ZONE;
+    
+    // One bit per module, rows padded to whole bytes; 1 is white.
+    let row_bytes = size.div_ceil(8);
+    let mut data = vec![0xFF; row_bytes * size];
+    for (index, color) in colors.iter().enumerate() {
+        if *color == qrcode::Color::Dark {
+            let (x, y) = (index % width + QR_QUIET_ZONE, index / width + QR_QUIET_ZONE);
+            data[y * row_bytes + x / 8] &= !(0x80 >> (x % 8));
+        }
+    }
+    
+    let mut stream = lopdf::Stream::new(
+        Dictionary::from_iter(vec![
+            ("Type", Object::Name(b"XObject".to_vec())),
+            ("Subtype", Object::Name(b"Image".to_vec())),
+            ("Width", Object::Integer(size as i64)),
+            ("Height", Object::Integer(size as i64)),
+            ("ColorSpace", Object::Name(b"DeviceGray".to_vec())),
+            ("BitsPerComponent", Object::Integer(1)),
+            ("Interpolate", Object::Boolean(false)),
+        ]),
+        data,
+    );
+    let _ = stream.compress();
+    Ok((doc.add_object(stream), size))
+}
+
+/// Whether `content`, a decoded content stream, is a watermark drawn by
+/// `add_watermark_to_pdf`.
+pub fn is_watermark_content(content: &[u8]) -> bool {
+    content.starts_with(WATERMARK_PREFIX.as_bytes())
+}
+
+/// Whether `name` is a page resource that `add_watermark_to_pdf` adds.
+pub fn is_watermark_resource(name: &[u8]) -> bool {
+    name == b"FWM" || [STAMP_PREFIX, QR_PREFIX, "GSWM"].iter().any(|prefix| name.starts_with(prefix.as_bytes()))
+}
+
+/// Decodes `png` into an image XObject, with its transparency as a soft mask.
+/// With `flatten`, an opacity, it is instead drawn at that opacity onto white
+/// so that nothing is transparent. Returns the object and the image size in
+/// pixels.
+fn add_stamp_image(doc: &mut Document, png: &[u8], flatten: Option<f32>) -> Result<((u32, u16), u32, u32)> {
+    let image_error = |e: png::DecodingError| format!("Invalid stamp image: {}", e);
+    let mut decoder = png::Decoder::new(std::io::Cursor::new(png));
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info().map_err(image_error)?;
+    let mut pixels = vec![0; reader.output_buffer_size()];
+    let frame = reader.next_frame(&mut pixels).map_err(image_error)?;
+    pixels.truncate(frame.buffer_size());
+    
+    let (color_space, channels): (&[u8], usize) = match frame.color_type {
+        png::ColorType::Grayscale | png::ColorType::GrayscaleAlpha => (b"DeviceGray", 1),
+        _ => (b"DeviceRGB", 3),
+    };
+    let has_alpha = matches!(frame.color_type, png::ColorType::GrayscaleAlpha | png::ColorType::Rgba);
+    let (color, alpha) = if let Some(opacity) = flatten {
+        let stride = if has_alpha { channels + 1 } else { channels };
+        let mut color = Vec::with_capacity(pixels.len());
+        for pixel in pixels.chunks_exact(stride) {
+            let alpha = if has_alpha { f32::from(pixel[channels]) / 255.0 } else { 1.0 };
+            color.extend(
+                pixel[..channels]
+                    .iter()
+                    .map(|&c| (fade(f32::from(c) / 255.0, alpha * opacity) * 255.0).round() as u8),
+            );
+        }
+        (color, None)
+    } else if has_alpha {
+        let mut color = Vec::with_capacity(pixels.len());
+        let mut alpha = Vec::with_capacity(pixels.len() / (channels + 1));
+        for pixel in pixels.chunks_exact(channels + 1) {
+            color.extend_from_slice(&pixel[..channels]);
+            alpha.push(pixel[channels]);
+        }
+        (color, Some(alpha))
+    } else {
+        (pixels, None)
+    };
+    
+    let image_stream = |color_space: &[u8], data: Vec<u8>| {
+        let mut stream = lopdf::Stream::new(
+            Dictionary::from_iter(vec![
+                ("Type", Object::Name(b"XObject".to_vec())),
+                ("Subtype", Object::Name(b"Image".to_vec())),
+                ("Width", Object::Integer(frame.width.into())),
+                ("Height", Object::Integer(frame.height.into())),
+                ("ColorSpace", Object::Name(color_space.to_vec())),
+                ("BitsPerComponent", Object::Integer(8)),
+            ]),
+            data,
+        );
+        // Raw pixels compress well; if it fails the stream is just bigger.
+        let _ = stream.compress();
+        stream
+    };
+    let mut image = image_stream(color_space, color);
+    if let Some(alpha) = alpha {
+        let mask_id = doc.add_object(image_stream(b"DeviceGray", alpha));
+        image.dict.set("SMask", Object::Reference(mask_id));
+    }
+    Ok((doc.add_object(image), frame.width, frame.height))
+}
+
+/// The page's effective resources (its own or the nearest inherited ones) with
+/// the watermark font added. The result is stored inline on the page so shared
+/// resource dictionaries are left untouched.
+fn watermark_resources(doc: &Document, page_dict: &Dictionary, font_id: (u32, u16)) -> Dictionary {
+    let mut node = Some(page_dict);
+    let mut resources = Dictionary::new();
+    let mut depth = 0;
+    while let Some(dict) = node {
+        if let Ok(found) = dict.get(b"Resources") {
+            if let Ok(Object::Dictionary(d)) = doc.dereference(found).map(|(_, o)| o) {
+                resources = d.clone();
+            }
+            break;
+        }
+        depth += 1;
+        node = dict
+            .get(b"Parent")
+            .and_then(Object::as_reference)
+            .and_then(|id| doc.get_dictionary(id))
+            .ok()
+            .filter(|_| depth < 32);
+    }
+    
+    add_resource(doc, &mut resources, b"Font", "FWM", font_id);
+    resources
+}
+
+/// Adds `name` to the `category` subdictionary of `resources`, copying that
+/// subdictionary inline if it was a reference.
+fn add_resource(doc: &Document, resources: &mut Dictionary, category: &[u8], name: &str, id: (u32, u16)) {
+    let mut entries = match resources.get(category).map(|d| doc.dereference(d)) {
+        Ok(Ok((_, Object::Dictionary(d)))) => d.clone(),
+        _ => Dictionary::new(),
+    };
+    entries.set(name, Object::Reference(id));
+    resources.set(category.to_vec(), Object::Dictionary(entries));
+}
+
+/// Records the signature details in a dedicated `/SigillumSignature`
+/// dictionary referenced from the catalog, so verification does not depend on
+/// how the visible watermark happens to be laid out.
+///
+/// `info.signed_length` is the number of leading file bytes the signature covers,
+/// i.e. the document as it was before this incremental update.
+pub fn add_signature_payload(doc: &mut Document, info: &SignatureInfo) -> Result<()> {
+    let signature_value = BASE64
+        .decode(&info.signature_value)
+        .map_err(|e| format!("Invalid signature value: {}", e))?;
+    let signed_length = info.signed_length.ok_or("Missing signed length")?;
+    let mut payload = Dictionary::from_iter(vec![
+        ("Type", Object::Name(PAYLOAD_TYPE.to_vec())),
+        ("Signer", hex_string(&info.signer_name)),
+        ("Timestamp", hex_string(&info.timestamp)),
+        ("Extra", hex_string(&info.extra)),
+        ("Signature", hex_string(&info.signature)),
+        ("SignatureValue", Object::String(signature_value, StringFormat::Hexadecimal)),
+        ("SignedLength", Object::Integer(signed_length as i64)),
+        ("DigestFormat", Object::Integer(info.digest_format.into())),
+    ]);
+    if let Some(algorithm) = info.algorithm {
+        payload.set("Algorithm", hex_string(algorithm.as_str()));
+    }
+    payload.set("HashAlgorithm", hex_string(info.hash_algorithm.as_str()));
+    if let Some(token) = &info.timestamp_token {
+        let token = BASE64
+            .decode(token)
+            .map_err(|e| format!("Invalid timestamp token: {}", e))?;
+        payload.set("TimestampToken", Object::String(token, StringFormat::Hexadecimal));
+    }
+    if let Some(public_key) = &info.public_key {
+        payload.set("PublicKey", hex_string(public_key));
+    }
+    if let Some(fingerprint) = &info.key_fingerprint {
+        payload.set("KeyFingerprint", hex_string(fingerprint));
+    }
+    if let Some(fingerprint) = &info.ssh_fingerprint {
+        payload.set("SshFingerprint", hex_string(fingerprint));
+    }
+    if let Some(certificate) = &info.certificate {
+        let certificate = BASE64
+            .decode(certificate)
+            .map_err(|e| format!("Invalid certificate: {}", e))?;
+        payload.set("Certificate", Object::String(certificate, StringFormat::Hexadecimal));
+    }
+    if let Some(url) = &info.key_url {
+        payload.set("KeyUrl", hex_string(url));
+    }
+    if let Some(identity) = &info.key_domain {
+        payload.set("KeyDomain", hex_string(identity));
+    }
+    if let Some(check) = &info.clock_check {
+        payload.set("NtpServer", hex_string(&check.server));
+        payload.set("LocalTime", hex_string(&check.local_time));
+        payload.set("NetworkTime", hex_string(&check.network_time));
+        payload.set("ClockSkew", hex_string(&check.skew_ms.to_string()));
+    }
+    // Placeholders `embed_update_signature` fills in once the update is saved.
+    payload.set("UpdateRange", Object::Array(vec![Object::Integer(BYTE_RANGE_PLACEHOLDER); 2 * UPDATE_RANGE_PARTS]));
+    payload.set("UpdateSignature", Object::String(vec![0; UPDATE_SIGNATURE_SIZE], StringFormat::Hexadecimal));
+    
+    let root_id = doc
+        .trailer
+        .get(b"Root")
+        .and_then(Object::as_reference)
+        .map_err(|e| format!("Failed to find catalog: {}", e))?;
+    let mut catalog = doc
+        .get_dictionary(root_id)
+        .map_err(|e| format!("Failed to get catalog: {}", e))?
+        .clone();
+    // Co-signing: keep earlier signatures reachable from the catalog.
+    if let Ok(Object::Reference(previous)) = catalog.get(b"SigillumInfo") {
+        payload.set("Previous", Object::Reference(*previous));
+    }
+    let payload_id = doc.add_object(Object::Dictionary(payload));
+    catalog.set("SigillumInfo", Object::Reference(payload_id));
+    doc.objects.insert(root_id, Object::Dictionary(catalog));
+    
+    Ok(())
+}
+
+/// Writes who signed the document and when into the document information
+/// dictionary and the XMP metadata, for document management systems that
+/// index those rather than the page contents. Goes after
+/// `add_signature_payload`, as it lists every signature in the catalog.
+///
+/// Existing metadata is kept; only the Sigillum entries and the modification
+/// date are replaced. A PDF/A document also gets the extension schema that
+/// describes the Sigillum entries, and keeps its old modification date if its
+/// XMP metadata cannot be edited, as both must agree.
+pub fn add_signature_metadata(doc: &mut Document, signed_at: DateTime<Utc>, pdfa: Option<&PdfA>) -> Result<()> {
+    let signatures = signatures_from_catalog(doc);
+    let Some(latest) = signatures.last() else {
+        return Err("The document has no signature to describe".into());
+    };
+    let signers: Vec<&str> = signatures.iter().map(|info| info.signer_name.as_str()).collect();
+    
+    let root_id = doc
+        .trailer
+        .get(b"Root")
+        .and_then(Object::as_reference)
+        .map_err(|e| format!("Failed to find catalog: {}", e))?;
+    let mut catalog = doc
+        .get_dictionary(root_id)
+        .map_err(|e| format!("Failed to get catalog: {}", e))?
+        .clone();
+    let existing = catalog
+        .get(b"Metadata")
+        .and_then(Object::as_reference)
+        .ok()
+        .and_then(|id| doc.get_object(id).ok())
+        .and_then(|object| object.as_stream().ok())
+        .map(|stream| stream.decompressed_content().unwrap_or_else(|_| stream.content.clone()));
+    let description = xmp_description(&signatures);
+    let packet = match existing {
+        // Not a packet this knows how to edit if `None`; it is left alone.
+        Some(packet) => merge_xmp(&String::from_utf8_lossy(&packet), &description),
+        None => Some(format!(
+            "<?xpacket begin=\"\u{feff}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>\n\
+             <x:xmpmeta xmlns:x=\"adobe:ns:meta/\">\n\
+             <rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">\n\
+             {}</rdf:RDF>\n\
+             </x:xmpmeta>\n\
+             <?xpacket end=\"w\"?>",
+            description
+        )),
+    };
+    let packet = match pdfa {
+        Some(_) => packet.and_then(|packet| pdfa::add_extension_schema(&packet)),
+        None => packet,
+    };
+    let modify_date = signed_at.format("%Y-%m-%dT%H:%M:%SZ").to_string();
+    let dated = packet.as_deref().and_then(|packet| pdfa::set_modify_date(packet, &modify_date));
+    let (packet, date_in_sync) = match dated {
+        Some(dated) => (Some(dated), true),
+        None => (packet, false),
+    };
+    if let Some(packet) = packet {
+        // Left uncompressed, so tools that scan files for XMP can find it.
+        let metadata = lopdf::Stream::new(
+            Dictionary::from_iter(vec![
+                ("Type", Object::Name(b"Metadata".to_vec())),
+                ("Subtype", Object::Name(b"XML".to_vec())),
+            ]),
+            packet.into_bytes(),
+        );
+        let metadata_id = doc.add_object(Object::Stream(metadata));
+        catalog.set("Metadata", Object::Reference(metadata_id));
+        doc.objects.insert(root_id, Object::Dictionary(catalog));
+    }
+    
+    let signing_time = signed_at.format("D:%Y%m%d%H%M%S+00'00'").to_string();
+    let info_id = doc.trailer.get(b"Info").and_then(Object::as_reference).ok();
+    let mut info = info_id
+        .and_then(|id| doc.get_dictionary(id).ok())
+        .cloned()
+        .unwrap_or_default();
+    info.set("SigillumSigners", text_string(&signers.join("; ")));
+    info.set("SigillumSigningTime", Object::string_literal(signing_time.as_str()));
+    if let Some(fingerprint) = &latest.key_fingerprint {
+        info.set("SigillumKeyFingerprint", Object::string_literal(fingerprint.as_str()));
+    }
+    if date_in_sync || pdfa.is_none() {
+        info.set("ModDate", Object::string_literal(signing_time));
+    }
+    match info_id {
+        Some(id) => {
+            doc.objects.insert(id, Object::Dictionary(info));
+        }
+        None => {
+            let id = doc.add_object(Object::Dictionary(info));
+            doc.trailer.set("Info", Object::Reference(id));
+        }
+    }
+    
+    Ok(())
+}
+
+/// The `rdf:Description` listing every signature, oldest first.
+fn xmp_description(signatures: &[SignatureInfo]) -> String {
+    let mut description = format!("{}\"{}\">\n<sigillum:Signatures>\n<rdf:Seq>\n", XMP_DESCRIPTION_START, XMP_NAMESPACE);
+    for info in signatures {
+        // Timestamps are recorded as `2024-01-31 12:00:00 UTC`; XMP dates are ISO 8601.
+        let time = chrono::NaiveDateTime::parse_from_str(&info.timestamp, "%Y-%m-%d %H:%M:%S UTC")
+            .map(|time| time.format("%Y-%m-%dT%H:%M:%SZ").to_string())
+            .unwrap_or_else(|_| info.timestamp.clone());
+        description.push_str("<rdf:li rdf:parseType=\"Resource\">\n");
+        description.push_str(&format!("<sigillum:Signer>{}</sigillum:Signer>\n", xml_escape(&info.signer_name)));
+        description.push_str(&format!("<sigillum:SigningTime>{}</sigillum:SigningTime>\n", xml_escape(&time)));
+        if let Some(fingerprint) = &info.key_fingerprint {
+            description.push_str(&format!("<sigillum:KeyFingerprint>{}</sigillum:KeyFingerprint>\n", xml_escape(fingerprint)));
+        }
+        description.push_str("</rdf:li>\n");
+    }
+    description.push_str("</rdf:Seq>\n</sigillum:Signatures>\n</rdf:Description>\n");
+    description
+}
+
+/// `packet` with its Sigillum description, if any, replaced by
+/// `description`. `None` when it has no `rdf:RDF` element to put it in.
+fn merge_xmp(packet: &str, description: &str) -> Option<String> {
+    let mut packet = packet.to_string();
+    if let Some(start) = packet.find(XMP_DESCRIPTION_START) {
+        let end_tag = "</rdf:Description>";
+        let end = start + packet[start..].find(end_tag)? + end_tag.len();
+        let end = if packet[end..].starts_with('\n') { end + 1 } else { end };
+        packet.replace_range(start..end, "");
+    }
+    let insert_at = packet.rfind("</rdf:RDF>")?;
+    packet.insert_str(insert_at, description);
+    Some(packet)
+}
+
+fn xml_escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Adds a signature field whose `/Sig` value carries placeholders for
+/// `/ByteRange` and `/Contents`. Once the document has been saved,
+/// `embed_pades_signature` fills them in. The field covers `placement`, or is
+/// invisible on the first page without one. With `lock`, the signature
+/// forbids any further change to the document.
+pub fn add_signature_field(
+    doc: &mut Document,
+    info: &SignatureInfo,
+    signing_time: &str,
+    placement: Option<&SignaturePlacement>,
+    lock: bool,
+) -> Result<()> {
+    let placeholder_range = vec![Object::Integer(0), Object::Integer(BYTE_RANGE_PLACEHOLDER), Object::Integer(BYTE_RANGE_PLACEHOLDER), Object::Integer(BYTE_RANGE_PLACEHOLDER)];
+    let mut sig = Dictionary::from_iter(vec![
+        ("Type", Object::Name(b"Sig".to_vec())),
+        ("Filter", Object::Name(b"Adobe.PPKLite".to_vec())),
+        ("SubFilter", Object::Name(b"ETSI.CAdES.detached".to_vec())),
+        ("ByteRange", Object::Array(placeholder_range)),
+        ("Contents", Object::String(vec![0; PADES_CONTENTS_SIZE], StringFormat::Hexadecimal)),
+        ("Name", text_string(&info.signer_name)),
+        ("M", Object::string_literal(signing_time)),
+    ]);
+    if !info.extra.is_empty() {
+        sig.set("Reason", text_string(&info.extra));
+    }
+    let sig_id = doc.add_object(Object::Dictionary(sig));
+    
+    let (page_id, rect) = match placement {
+        Some(placement) => {
+            let page_id = placement_page(&doc.get_pages(), placement)?;
+            let frame = PageFrame::of(doc, doc.get_dictionary(page_id).map_err(|e| format!("Failed to get page: {}", e))?);
+            let [x, y, width, height] = placement.rect;
+            let (x0, y0) = frame.to_user(x, y);
+            let (x1, y1) = frame.to_user(x + width, y + height);
+            (page_id, [x0.min(x1), y0.min(y1), x0.max(x1), y0.max(y1)])
+        }
+        None => (*doc.get_pages().values().next().ok_or("PDF has no pages")?, [0.0; 4]),
+    };
+    
+    let root_id = doc
+        .trailer
+        .get(b"Root")
+        .and_then(Object::as_reference)
+        .map_err(|e| format!("Failed to find catalog: {}", e))?;
+    let mut catalog = doc
+        .get_dictionary(root_id)
+        .map_err(|e| format!("Failed to get catalog: {}", e))?
+        .clone();
+    let mut acro_form = match catalog.get(b"AcroForm").map(|f| doc.dereference(f)) {
+        Ok(Ok((_, Object::Dictionary(d)))) => d.clone(),
+        _ => Dictionary::new(),
+    };
+    let mut fields = match acro_form.get(b"Fields").map(|f| doc.dereference(f)) {
+        Ok(Ok((_, Object::Array(a)))) => a.clone(),
+        _ => Vec::new(),
+    };
+    
+    // Only the first signature can certify the document; later ones lock
+    // every field instead.
+    let certifies = lock && !fields.iter().any(|field| lock::is_signed_field(doc, field));
+    if lock {
+        doc.get_object_mut(sig_id)
+            .and_then(Object::as_dict_mut)
+            .map_err(|e| format!("Failed to get signature: {}", e))?
+            .set("Reference", lock::signature_reference(certifies, root_id));
+    }
+    if certifies {
+        catalog.set("Perms", Dictionary::from_iter(vec![("DocMDP", Object::Reference(sig_id))]));
+    }
+
+    let mut field = Dictionary::from_iter(vec![
+        ("Type", Object::Name(b"Annot".to_vec())),
+        ("Subtype", Object::Name(b"Widget".to_vec())),
+        ("FT", Object::Name(b"Sig".to_vec())),
+        ("T", text_string(&format!("{}{}", SIGNATURE_FIELD_NAME, fields.len() + 1))),
+        ("V", Object::Reference(sig_id)),
+        ("Rect", Object::Array(rect.iter().map(|&n| Object::Real(n)).collect())),
+        // Print + Locked
+        ("F", Object::Integer(132)),
+        ("P", Object::Reference(page_id)),
+    ]);
+    if lock && !certifies {
+        field.set("Lock", lock::field_lock());
+    }
+    if placement.is_some() {
+        // The watermark under the field shows the signature; the field only
+        // needs an appearance, which visible widgets must have.
+        let appearance = doc.add_object(lopdf::Stream::new(
+            Dictionary::from_iter(vec![
+                ("Type", Object::Name(b"XObject".to_vec())),
+                ("Subtype", Object::Name(b"Form".to_vec())),
+                (
+                    "BBox",
+                    Object::Array(vec![
+                        0.into(),
+                        0.into(),
+                        Object::Real(rect[2] - rect[0]),
+                        Object::Real(rect[3] - rect[1]),
+                    ]),
+                ),
+            ]),
+            Vec::new(),
+        ));
+        field.set("AP", Dictionary::from_iter(vec![("N", Object::Reference(appearance))]));
+    }
+    let field_id = doc.add_object(Object::Dictionary(field));
+    
+    fields.push(Object::Reference(field_id));
+    acro_form.set("Fields", Object::Array(fields));
+    // SignaturesExist + AppendOnly
+    acro_form.set("SigFlags", Object::Integer(3));
+    catalog.set("AcroForm", Object::Dictionary(acro_form));
+    doc.objects.insert(root_id, Object::Dictionary(catalog));
+    
+    let page = doc
+        .get_object_mut(page_id)
+        .and_then(Object::as_dict_mut)
+        .map_err(|e| format!("Failed to get page: {}", e))?;
+    let annots = page.get(b"Annots").ok().cloned();
+    match annots {
+        Some(Object::Array(mut annots)) => {
+            annots.push(Object::Reference(field_id));
+            page.set("Annots", Object::Array(annots));
+        }
+        Some(Object::Reference(annots_id)) => {
+            doc.get_object_mut(annots_id)
+                .and_then(Object::as_array_mut)
+                .map_err(|e| format!("Failed to get page annotations: {}", e))?
+                .push(Object::Reference(field_id));
+        }
+        _ => page.set("Annots", Object::Array(vec![Object::Reference(field_id)])),
+    }
+    
+    Ok(())
+}
+
+/// Attaches the ASCII-armored OpenPGP signature `armored` of the document's
+/// first `signed_length` bytes as an embedded file, so PDF viewers list it
+/// among the attachments and `gpg --verify` can check it once saved.
+pub fn add_pgp_signature(doc: &mut Document, armored: &str, signed_length: u64) -> Result<()> {
+    let file_name = match pgp_signatures(doc).len() {
+        0 => PGP_SIGNATURE_FILE.to_string(),
+        count => format!("signature-{}.asc", count + 1),
+    };
+    let file_id = doc.add_object(lopdf::Stream::new(
+        Dictionary::from_iter(vec![
+            ("Type", Object::Name(b"EmbeddedFile".to_vec())),
+            ("Subtype", Object::Name(b"application/pgp-signature".to_vec())),
+            ("Params", Object::Dictionary(Dictionary::from_iter(vec![("Size", Object::Integer(armored.len() as i64))]))),
+        ]),
+        armored.as_bytes().to_vec(),
+    ));
+    let mut spec = Dictionary::from_iter(vec![
+        ("Type", Object::Name(b"Filespec".to_vec())),
+        ("F", text_string(&file_name)),
+        ("UF", text_string(&file_name)),
+        ("Desc", text_string("OpenPGP signature of the document")),
+        ("AFRelationship", Object::Name(b"Supplement".to_vec())),
+        ("EF", Object::Dictionary(Dictionary::from_iter(vec![("F", Object::Reference(file_id))]))),
+    ]);
+    spec.set(PGP_SIGNED_LENGTH_KEY, Object::Integer(signed_length as i64));
+    let spec_id = doc.add_object(spec);
+
+    let root_id = doc
+        .trailer
+        .get(b"Root")
+        .and_then(Object::as_reference)
+        .map_err(|e| format!("Failed to find catalog: {}", e))?;
+    let mut catalog = doc
+        .get_dictionary(root_id)
+        .map_err(|e| format!("Failed to get catalog: {}", e))?
+        .clone();
+    // The name dictionary and its embedded files tree may each be an object
+    // of their own, which is then updated where it is.
+    let (names_id, mut names) = shared_dictionary(doc, catalog.get(b"Names").ok());
+    let (tree_id, mut tree) = shared_dictionary(doc, names.get(b"EmbeddedFiles").ok());
+    let entry = vec![text_string(&file_name), Object::Reference(spec_id)];
+    match tree.get(b"Kids").and_then(|kids| doc.dereference(kids)).map(|(_, kids)| kids.clone()) {
+        Ok(Object::Array(mut kids)) => {
+            // A leaf of its own keeps the existing nodes as they are.
+            let leaf = doc.add_object(Dictionary::from_iter(vec![
+                ("Limits", Object::Array(vec![text_string(&file_name), text_string(&file_name)])),
+                ("Names", Object::Array(entry)),
+            ]));
+            kids.push(Object::Reference(leaf));
+            tree.set("Kids", Object::Array(kids));
+        }
+        _ => {
+            let mut entries = match tree.get(b"Names").and_then(|entries| doc.dereference(entries)) {
+                Ok((_, Object::Array(entries))) => entries.clone(),
+                _ => Vec::new(),
+            };
+            // Names are kept in order, as readers may search them.
+            let position = entries
+                .chunks(2)
+                .take_while(|pair| pair[0].as_str().is_ok_and(|name| name <= file_name.as_bytes()))
+                .count();
+            entries.splice(position * 2..position * 2, entry);
+            tree.set("Names", Object::Array(entries));
+        }
+    }
+
+    match tree_id {
+        Some(id) => {
+            doc.objects.insert(id, Object::Dictionary(tree));
+        }
+        None => names.set("EmbeddedFiles", Object::Dictionary(tree)),
+    }
+    match names_id {
+        Some(id) => {
+            doc.objects.insert(id, Object::Dictionary(names));
+        }
+        None => catalog.set("Names", Object::Dictionary(names)),
+    }
+    doc.objects.insert(root_id, Object::Dictionary(catalog));
+    Ok(())
+}
+
+/// The dictionary `value` is or refers to, with its id when it is an object
+/// of its own; an empty one when there is none.
+fn shared_dictionary(doc: &Document, value: Option<&Object>) -> (Option<ObjectId>, Dictionary) {
+    match value {
+        Some(Object::Reference(id)) => (Some(*id), doc.get_dictionary(*id).cloned().unwrap_or_default()),
+        Some(Object::Dictionary(dict)) => (None, dict.clone()),
+        _ => (None, Dictionary::new()),
+    }
+}
+
+/// Fills in the `/ByteRange` placeholder `add_signature_field` left in the
+/// saved file, whose update starts at `update_start`, and returns where the
+/// `/Contents` hex string it leaves out starts and ends.
+///
+/// `/ByteRange` is set to everything except that string. It is patched in
+/// place, so no offsets move; only the update is read into memory.
+pub fn prepare_pades_signature<P: Read + Write + Seek>(pdf: &mut P, update_start: u64) -> Result<(u64, u64)> {
+    let io_error = |e: io::Error| SigillumError::Io(format!("Failed to write signature: {}", e));
+    let len = pdf.seek(SeekFrom::End(0)).map_err(io_error)?;
+    let update = read_update(pdf, update_start)?;
+
+    let byte_range_start = rfind_bytes(&update, b"/ByteRange").ok_or("Signature placeholder not found")?;
+    let (array_start, array_end) = delimited_after(&update, byte_range_start, b'[', b']').ok_or("Malformed /ByteRange")?;
+    let contents_key = byte_range_start + find_bytes(&update[byte_range_start..], b"/Contents").ok_or("Signature placeholder not found")?;
+    let (contents_start, contents_end) = delimited_after(&update, contents_key, b'<', b'>').ok_or("Malformed /Contents")?;
+
+    let (hole_start, hole_end) = (update_start + contents_start as u64, update_start + contents_end as u64);
+    let byte_range = format!("[0 {} {} {}]", hole_start, hole_end, len - hole_end);
+    patch(pdf, update_start + array_start as u64, array_end - array_start, &byte_range)?;
+    Ok((hole_start, hole_end))
+}
+
+/// Fills in the `/Contents` hex string from `hole_start` to `hole_end` that
+/// `prepare_pades_signature` left out. `sign` receives the SHA-256 of the
+/// rest of the file and returns the DER-encoded CMS to store there, which is
+/// patched in place; the file is hashed straight from `pdf`.
+pub fn embed_pades_signature<P, F>(pdf: &mut P, (hole_start, hole_end): (u64, u64), sign: F) -> Result<()>
+where
+    P: Read + Write + Seek,
+    F: FnOnce(&[u8]) -> Result<Vec<u8>>,
+{
+    let io_error = |e: io::Error| SigillumError::Io(format!("Failed to write signature: {}", e));
+    let len = pdf.seek(SeekFrom::End(0)).map_err(io_error)?;
+    let mut hasher = Sha256::new();
+    pdf.seek(SeekFrom::Start(0)).map_err(io_error)?;
+    io::copy(&mut (&mut *pdf).take(hole_start), &mut hasher).map_err(io_error)?;
+    pdf.seek(SeekFrom::Start(hole_end)).map_err(io_error)?;
+    io::copy(&mut (&mut *pdf).take(len - hole_end), &mut hasher).map_err(io_error)?;
+    let cms = sign(&hasher.finalize())?;
+    
+    let contents_hex = hex::encode_upper(&cms);
+    let capacity = (hole_end - hole_start - 2) as usize;
+    if contents_hex.len() > capacity {
+        return Err(format!(
+            "CMS signature is {} bytes, but only {} were reserved",
+            cms.len(),
+            capacity / 2
+        )
+        .into());
+    }
+    pdf.seek(SeekFrom::Start(hole_start + 1)).map_err(io_error)?;
+    pdf.write_all(contents_hex.as_bytes()).map_err(io_error)?;
+    pdf.flush().map_err(io_error)
+}
+
+/// The range an update signature covers, as offset and length pairs, and
+/// the signature itself.
+pub type UpdateSignature = (Vec<(u64, u64)>, Vec<u8>);
+
+/// Fills in the `/UpdateRange` and `/UpdateSignature` placeholders
+/// `add_signature_payload` left in the saved file, whose update starts at
+/// `update_start`, so that nothing in the update can change unnoticed.
+///
+/// The range is the whole file but the update signature's hex string and,
+/// when `pades_hole` gives one, the standard signature's `/Contents`, whose
+/// `/ByteRange` must already be in place. `sign` receives a `hash_algorithm`
+/// hasher fed those parts, along with the range, and returns the signature,
+/// which is patched in place. Returns the range and the signature.
+pub fn embed_update_signature<P, F>(
+    pdf: &mut P,
+    update_start: u64,
+    pades_hole: Option<(u64, u64)>,
+    hash_algorithm: HashAlgorithm,
+    sign: F,
+) -> Result<UpdateSignature>
+where
+    P: Read + Write + Seek,
+    F: FnOnce(Hasher, &[(u64, u64)]) -> Result<Vec<u8>>,
+{
+    let io_error = |e: io::Error| SigillumError::Io(format!("Failed to write signature: {}", e));
+    let len = pdf.seek(SeekFrom::End(0)).map_err(io_error)?;
+    let update = read_update(pdf, update_start)?;
+
+    let range_key = rfind_bytes(&update, b"/UpdateRange").ok_or("Signature placeholder not found")?;
+    let (array_start, array_end) = delimited_after(&update, range_key, b'[', b']').ok_or("Malformed /UpdateRange")?;
+    let signature_key = rfind_bytes(&update, b"/UpdateSignature").ok_or("Signature placeholder not found")?;
+    let (value_start, value_end) = delimited_after(&update, signature_key, b'<', b'>').ok_or("Malformed /UpdateSignature")?;
+
+    let mut holes = vec![(update_start + value_start as u64, update_start + value_end as u64)];
+    holes.extend(pades_hole);
+    holes.sort_unstable();
+    let mut range = Vec::with_capacity(holes.len() + 1);
+    let mut offset = 0;
+    for (hole_start, hole_end) in holes {
+        range.push((offset, hole_start - offset));
+        offset = hole_end;
+    }
+    range.push((offset, len - offset));
+    let numbers: Vec<String> = range.iter().flat_map(|&(offset, length)| [offset.to_string(), length.to_string()]).collect();
+    patch(pdf, update_start + array_start as u64, array_end - array_start, &format!("[{}]", numbers.join(" ")))?;
+
+    let mut hasher = Hasher::new(hash_algorithm);
+    for &(offset, length) in &range {
+        pdf.seek(SeekFrom::Start(offset)).map_err(io_error)?;
+        io::copy(&mut (&mut *pdf).take(length), &mut hasher).map_err(io_error)?;
+    }
+    let signature = sign(hasher, &range)?;
+
+    let signature_hex = hex::encode_upper(&signature);
+    let capacity = value_end - value_start - 2;
+    if signature_hex.len() > capacity {
+        return Err(format!("Signature is {} bytes, but only {} were reserved", signature.len(), capacity / 2).into());
+    }
+    // Whitespace in a hex string is ignored, so the rest is blanked out.
+    patch(pdf, update_start + value_start as u64 + 1, capacity, &signature_hex)?;
+    pdf.flush().map_err(io_error)?;
+    Ok((range, signature))
+}
+
+/// The bytes of the update starting at `update_start`, through to the end.
+fn read_update<P: Read + Seek>(pdf: &mut P, update_start: u64) -> Result<Vec<u8>> {
+    let mut update = Vec::new();
+    pdf.seek(SeekFrom::Start(update_start))
+        .and_then(|_| pdf.read_to_end(&mut update))
+        .map_err(|e| SigillumError::Io(format!("Failed to write signature: {}", e)))?;
+    Ok(update)
+}
+
+/// Where the first `open` ... `close` after `from` starts and ends, the
+/// delimiters included.
+fn delimited_after(data: &[u8], from: usize, open: u8, close: u8) -> Option<(usize, usize)> {
+    let start = from + data[from..].iter().position(|&b| b == open)?;
+    let end = start + data[start..].iter().position(|&b| b == close)? + 1;
+    Some((start, end))
+}
+
+/// Writes `value` at `offset`, padded with spaces to `width` bytes.
+fn patch<P: Write + Seek>(pdf: &mut P, offset: u64, width: usize, value: &str) -> Result<()> {
+    if value.len() > width {
+        return Err("Signature placeholder is too small for this document".into());
+    }
+    pdf.seek(SeekFrom::Start(offset))
+        .and_then(|_| pdf.write_all(format!("{:<width$}", value, width = width).as_bytes()))
+        .map_err(|e| SigillumError::Io(format!("Failed to write signature: {}", e)))
+}
+
+/// Whether `value` is a hex string and nothing else, as the parts a signing
+/// update's `/UpdateRange` leaves out must be: a signature value in them
+/// then cannot hide anything else.
+pub fn is_update_gap(value: &[u8]) -> bool {
+    value.len() as u64 <= MAX_UPDATE_GAP
+        && value.len() >= 2
+        && value[0] == b'<'
+        && value[value.len() - 1] == b'>'
+        && value[1..value.len() - 1].iter().all(|b| b.is_ascii_hexdigit() || b.is_ascii_whitespace())
+}
+
+/// PDF text string: PDFDocEncoding-compatible ASCII as is, anything else as
+/// UTF-16BE with a byte order mark.
+fn text_string(value: &str) -> Object {
+    if value.is_ascii() {
+        Object::string_literal(value)
+    } else {
+        let mut bytes = vec![0xFE, 0xFF];
+        bytes.extend(value.encode_utf16().flat_map(|unit| unit.to_be_bytes()));
+        Object::String(bytes, StringFormat::Hexadecimal)
+    }
+}
+
+fn hex_string(value: &str) -> Object {
+    Object::String(value.as_bytes().to_vec(), StringFormat::Hexadecimal)
+}
+
+/// Whether the file mentions an `/Encrypt` dictionary. Only the parsed
+/// trailer can say for sure, but a file without the name is not encrypted.
+pub fn may_be_encrypted(pdf_data: &[u8]) -> bool {
+    find_bytes(pdf_data, ENCRYPT_MARKER).is_some()
+}
+
+/// Whether the file mentions a `/ByteRange`, which every standard signature
+/// dictionary has; a signature's `/Contents` has to be patched in place, so
+/// the dictionary is never compressed.
+pub fn may_have_signature_fields(pdf_data: &[u8]) -> bool {
+    find_bytes(pdf_data, BYTE_RANGE_MARKER).is_some()
+}
+
+/// Whether the file may have an OpenPGP signature attached: the file
+/// specification holding one is never compressed by `add_pgp_signature`.
+pub fn may_have_pgp_signatures(pdf_data: &[u8]) -> bool {
+    find_bytes(pdf_data, PGP_SIGNED_LENGTH_MARKER).is_some()
+}
+
+/// Hybrid-reference files pair a classic xref table with an `/XRefStm` stream
+/// listing the objects that live in object streams. lopdf does not follow that
+/// stream for single-section files, so those objects are silently dropped when
+/// the document is re-serialised.
+pub fn is_hybrid_reference(pdf_data: &[u8]) -> bool {
+    find_bytes(pdf_data, HYBRID_MARKER).is_some()
+}
+
+const HYBRID_MARKER: &[u8] = b"/XRefStm";
+
+/// Like `is_hybrid_reference`, for a file fed in a chunk at a time.
+#[derive(Default)]
+pub struct HybridDetector {
+    // Tail of the previous chunk, so a marker split across two chunks is still found.
+    carry: Vec<u8>,
+    found: bool,
+}
+
+impl HybridDetector {
+    pub fn feed(&mut self, data: &[u8]) {
+        if self.found {
+            return;
+        }
+        let keep = HYBRID_MARKER.len() - 1;
+        let head = &data[..data.len().min(keep)];
+        self.carry.extend_from_slice(head);
+        self.found = is_hybrid_reference(&self.carry) || is_hybrid_reference(data);
+        let tail = if data.len() > keep { &data[data.len() - keep..] } else { &self.carry[..] };
+        self.carry = tail[tail.len().saturating_sub(keep)..].to_vec();
+    }
+
+    pub fn found(&self) -> bool {
+        self.found
+    }
+}
+
+/// Makes sure newly added objects are numbered past everything the file
+/// declares. lopdf sizes its id space from the xref entries it could read, which
+/// for hybrid files misses the objects that only the `/XRefStm` stream lists.
+pub fn reserve_declared_ids(doc: &mut Document) {
+    if let Ok(size) = doc.trailer.get(b"Size").and_then(Object::as_i64) {
+        if size > 0 {
+            doc.max_id = doc.max_id.max(size as u32 - 1);
+        }
+    }
+}
+
+/// Loads a hybrid-reference file together with the objects only its
+/// `/XRefStm` stream lists. lopdf reads that stream from the newest trailer
+/// when the file has an earlier section, so an empty update naming it is
+/// appended to a copy first. The result is for rewriting: the copy's offsets
+/// are not `pdf_data`'s.
+pub fn load_hybrid(pdf_data: &[u8]) -> Result<Document> {
+    let load = |data: &[u8]| {
+        Document::load_mem(data).map_err(|e| SigillumError::InvalidPdf(format!("Failed to load PDF: {}", e)))
+    };
+    let at = find_bytes(pdf_data, b"/XRefStm").ok_or("The PDF has no cross-reference stream")? + b"/XRefStm".len();
+    let offset: String = pdf_data[at..]
+        .iter()
+        .skip_while(|b| b.is_ascii_whitespace())
+        .take_while(|b| b.is_ascii_digit())
+        .map(|&b| b as char)
+        .collect();
+    let offset: i64 = offset.parse().map_err(|_| "Malformed /XRefStm offset".to_string())?;
+
+    let mut update = IncrementalDocument::create_from(pdf_data.to_vec(), load(pdf_data)?);
+    update.new_document.trailer.set("XRefStm", offset);
+    let mut with_update = Vec::new();
+    update.save_to(&mut with_update).map_err(|e| format!("Save error: {}", e))?;
+    load(&with_update)
+}
+
+/// Serialises `doc` from scratch, as changing its encryption requires.
+pub fn save_rewrite(doc: &mut Document) -> Result<Vec<u8>> {
+    // Offsets from the source file mean nothing in the rewritten one.
+    doc.trailer.remove(b"Prev");
+    doc.trailer.remove(b"XRefStm");
+    let mut bytes = Vec::new();
+    doc.save_to(&mut bytes).map_err(|e| format!("Save error: {}", e))?;
+    Ok(bytes)
+}
+
+/// A document as it was loaded, to tell what signing changed. Stream
+/// contents are kept only as digests, so a large document is not held in
+/// memory twice.
+pub struct Snapshot {
+    /// The trailer and cross-reference details, without any objects.
+    header: Document,
+    objects: BTreeMap<ObjectId, SnapshotObject>,
+}
+
+enum SnapshotObject {
+    Plain(Object),
+    Stream(Dictionary, Vec<u8>),
+}
+
+impl Snapshot {
+    pub fn of(doc: &Document) -> Snapshot {
+        let mut header = Document::new();
+        header.version = doc.version.clone();
+        header.trailer = doc.trailer.clone();
+        header.reference_table = lopdf::xref::Xref::new(0, doc.reference_table.cross_reference_type);
+        header.max_id = doc.max_id;
+        header.max_bookmark_id = doc.max_bookmark_id;
+        header.xref_start = doc.xref_start;
+        let objects = doc
+            .objects
+            .iter()
+            .map(|(id, object)| {
+                let snapshot = match object {
+                    Object::Stream(stream) => SnapshotObject::Stream(stream.dict.clone(), Sha256::digest(&stream.content).to_vec()),
+                    object => SnapshotObject::Plain(object.clone()),
+                };
+                (*id, snapshot)
+            })
+            .collect();
+        Snapshot { header, objects }
+    }
+
+    /// Whether object `id` is still `object`.
+    fn unchanged(&self, id: &ObjectId, object: &Object) -> bool {
+        match (self.objects.get(id), object) {
+            (Some(SnapshotObject::Stream(dict, digest)), Object::Stream(stream)) => {
+                *dict == stream.dict && *digest == Sha256::digest(&stream.content).as_slice()
+            }
+            (Some(SnapshotObject::Plain(original)), object) => original == object,
+            _ => false,
+        }
+    }
+}
+
+/// Appends to `target`, which already holds the `original_length` bytes
+/// `original` was loaded from, every object that differs between `original`
+/// and `modified`, as an incremental update. For an encrypted document the
+/// objects are encrypted with its `key`.
+pub fn save_incremental<W: Write>(
+    original_length: u64,
+    ends_with_newline: bool,
+    original: Snapshot,
+    modified: &Document,
+    key: Option<&DocumentKey>,
+    target: &mut W,
+) -> Result<()> {
+    let changed: Vec<_> = modified
+        .objects
+        .iter()
+        .filter(|(id, object)| !original.unchanged(id, object))
+        .map(|(id, object)| (*id, object.clone()))
+        .collect();
+    // lopdf counts the update's offsets from the earlier bytes it writes
+    // first, so it gets a zeroed stand-in of the same length, which is
+    // skipped on the way out: a large zeroed allocation is left untouched
+    // pages, so the document is not held in memory a second time.
+    let original_length = usize::try_from(original_length).map_err(|_| "The PDF is too large to sign".to_string())?;
+    let mut stand_in = vec![0u8; original_length];
+    if let Some(last) = stand_in.last_mut().filter(|_| ends_with_newline) {
+        *last = b'\n';
+    }
+    let mut update = IncrementalDocument::create_from(stand_in, original.header);
+    for (id, mut object) in changed {
+        if let Some(key) = key {
+            key.apply(id, &mut object);
+        }
+        update.new_document.set_object(id, object);
+    }
+    
+    update.new_document.max_id = modified.max_id;
+    if let Ok(info) = modified.trailer.get(b"Info") {
+        update.new_document.trailer.set("Info", info.clone());
+    }
+    // The previous section keeps its own /XRefStm; repeating it here would
+    // point the new section at a stream it does not own.
+    update.new_document.trailer.remove(b"XRefStm");
+    
+    let mut target = Skip {
+        inner: target,
+        remaining: original_length,
+    };
+    update.save_to(&mut target).map_err(|e| SigillumError::Io(format!("Save error: {}", e)))
+}
+
+/// Drops the first `remaining` bytes written to it.
+struct Skip<'a, W: Write> {
+    inner: &'a mut W,
+    remaining: usize,
+}
+
+impl<W: Write> Write for Skip<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if self.remaining > 0 {
+            let skipped = self.remaining.min(buf.len());
+            self.remaining -= skipped;
+            return Ok(skipped);
+        }
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Plain text of every page, as used for content policy checks.
+pub fn extract_document_text(doc: &Document, monitor: &Monitor) -> Result<String> {
+    let page_numbers: Vec<u32> = doc.get_pages().keys().cloned().collect();
+    let total = page_numbers.len() as u64;
+    let mut text = String::new();
+    for (done, &page_number) in page_numbers.iter().enumerate() {
+        monitor.report(Progress::CheckingText { done: done as u64, total })?;
+        let page_text = doc.extract_text(&[page_number])
+            .map_err(|e| format!("Failed to extract text: {}", e))?;
+        text.push_str(&page_text);
+    }
+    monitor.report(Progress::CheckingText { done: total, total })?;
+    Ok(text)
+}
+
+/// SHA-256 of the whole file, signatures and all. This is the canonical hash
+/// only of a document nobody has signed yet; `api::verify` hashes a signed one
+/// up to where its first signature starts.
+pub fn canonical_hash(pdf_data: &[u8]) -> String {
+    hex::encode(Sha256::digest(pdf_data))
+}
+
+/// Compares a user-supplied hash against a computed one, tolerating case,
+/// surrounding whitespace and a leading `SHA256:` (or other algorithm) label.
+pub fn hash_matches(expected: &str, actual: &str) -> bool {
+    let expected = expected.trim();
+    let expected = match expected.split_once(':') {
+        Some((label, hash)) if label.parse::<HashAlgorithm>().is_ok() => hash.trim(),
+        _ => expected,
+    };
+    expected.eq_ignore_ascii_case(actual)
+}
+
+/// Every signature in the document, oldest first.
+pub fn extract_signatures(pdf_data: &[u8]) -> Vec<SignatureInfo> {
+    let window_at = |start: usize| {
+        let end = (start + SIGNATURE_WINDOW).min(pdf_data.len());
+        &pdf_data[start..end]
+    };
+    let signatures: Vec<SignatureInfo> = find_all_bytes(pdf_data, PAYLOAD_MARKER)
+        .filter_map(|start| parse_payload_window(window_at(start)))
+        .collect();
+    if !signatures.is_empty() {
+        return signatures;
+    }
+    match Document::load_mem(pdf_data) {
+        Ok(doc) => signatures_from_document(&doc),
+        Err(_) => find_bytes(pdf_data, SIGNATURE_MARKER)
+            .map(window_at)
+            .and_then(parse_signature_window)
+            .into_iter()
+            .collect(),
+    }
+}
+
+/// Whether a signature field Sigillum did not add covers bytes past `offset`,
+/// i.e. was signed after the document was that long.
+pub fn foreign_signature_after(doc: &Document, offset: u64) -> bool {
+    foreign_signatures(doc).into_iter().any(|field| {
+        let covered_end = field
+            .get(b"V")
+            .and_then(|sig| doc.dereference(sig))
+            .and_then(|(_, sig)| sig.as_dict())
+            .and_then(|sig| sig.get(b"ByteRange"))
+            .and_then(Object::as_array)
+            .ok()
+            .and_then(|range| Some(range.get(2)?.as_i64().ok()? + range.get(3)?.as_i64().ok()?));
+        covered_end.is_some_and(|end| end > offset as i64)
+    })
+}
+
+/// A signed `/Sig` field another application added, such as Acrobat or
+/// LibreOffice, with what it takes to check it.
+#[derive(Debug, Clone)]
+pub struct ForeignSignature {
+    pub field_name: String,
+    /// How the signature is encoded, e.g. `adbe.pkcs7.detached`.
+    pub sub_filter: Option<String>,
+    /// The parts of the file the signature covers, as offset and length.
+    pub byte_range: Vec<(u64, u64)>,
+    /// The CMS signature, zero-padded to the room reserved for it.
+    pub contents: Vec<u8>,
+    pub signer_name: Option<String>,
+    /// `/M`, as "2024-01-31 12:00:00 UTC".
+    pub signing_time: Option<String>,
+    pub reason: Option<String>,
+    pub location: Option<String>,
+}
+
+impl ForeignSignature {
+    /// Where the covered bytes end: the length of the file when it was signed.
+    pub fn covered_end(&self) -> u64 {
+        self.byte_range.iter().map(|&(offset, length)| offset + length).max().unwrap_or(0)
+    }
+}
+
+/// The signature fields `foreign_signatures` finds, read out. Fields whose
+/// `/ByteRange` or `/Contents` is missing or malformed are left out, since
+/// there is nothing to check.
+pub fn foreign_signature_details(doc: &Document) -> Vec<ForeignSignature> {
+    let text = |dict: &Dictionary, key: &[u8]| dict.get(key).ok().and_then(|value| lopdf::decode_text_string(value).ok());
+    foreign_signatures(doc)
+        .into_iter()
+        .filter_map(|field| {
+            let (_, value) = doc.dereference(field.get(b"V").ok()?).ok()?;
+            let sig = value.as_dict().ok()?;
+            let range = sig.get(b"ByteRange").and_then(|range| doc.dereference(range)).ok()?.1.as_array().ok()?;
+            let numbers = range
+                .iter()
+                .map(|n| n.as_i64().ok().and_then(|n| u64::try_from(n).ok()))
+                .collect::<Option<Vec<u64>>>()?;
+            if numbers.is_empty() || numbers.len() % 2 != 0 {
+                return None;
+            }
+            let contents = match sig.get(b"Contents").and_then(|contents| doc.dereference(contents)).ok()?.1 {
+                Object::String(bytes, _) => bytes.clone(),
+                _ => return None,
+            };
+            Some(ForeignSignature {
+                field_name: text(field, b"T").unwrap_or_default(),
+                sub_filter: sig.get(b"SubFilter").and_then(Object::as_name).ok().map(|name| String::from_utf8_lossy(name).into_owned()),
+                byte_range: numbers.chunks(2).map(|pair| (pair[0], pair[1])).collect(),
+                contents,
+                signer_name: text(sig, b"Name"),
+                signing_time: text(sig, b"M").as_deref().and_then(parse_pdf_date),
+                reason: text(sig, b"Reason"),
+                location: text(sig, b"Location"),
+            })
+        })
+        .collect()
+}
+
+/// A PDF date such as `D:20240131120000+01'00'` as "2024-01-31 11:00:00 UTC".
+fn parse_pdf_date(date: &str) -> Option<String> {
+    let date = date.strip_prefix("D:").unwrap_or(date);
+    let digits: String = date.chars().take_while(char::is_ascii_digit).collect();
+    if digits.len() < 4 || digits.len() > 14 || !digits.len().is_multiple_of(2) {
+        return None;
+    }
+    // Parts left off the end default to January 1st, midnight.
+    let padded = format!("{}{}", digits, &"0101000000"[digits.len() - 4..]);
+    let local = chrono::NaiveDateTime::parse_from_str(&padded, "%Y%m%d%H%M%S").ok()?;
+    let zone = &date[digits.len()..];
+    let offset_minutes = match zone.chars().next() {
+        Some(sign @ ('+' | '-')) => {
+            let mut parts = zone[1..].split('\'').filter(|part| !part.is_empty());
+            let hours: i64 = parts.next()?.parse().ok()?;
+            let minutes: i64 = parts.next().map_or(Some(0), |minutes| minutes.parse().ok())?;
+            if sign == '-' {
+                -(hours * 60 + minutes)
+            } else {
+                hours * 60 + minutes
+            }
+        }
+        // `Z`, or no zone at all, which is taken as UTC too.
+        _ => 0,
+    };
+    let utc = local - chrono::Duration::minutes(offset_minutes);
+    Some(utc.format("%Y-%m-%d %H:%M:%S UTC").to_string())
+}
+
+/// An OpenPGP signature attached with `add_pgp_signature`.
+#[derive(Debug, Clone)]
+pub struct PgpSignature {
+    /// Name of the attachment, e.g. "signature.asc".
+    pub file_name: String,
+    /// How many bytes of the file the signature covers: its length when it
+    /// was signed.
+    pub signed_length: u64,
+    pub armored: String,
+}
+
+/// The OpenPGP signatures attached to the document, oldest first.
+pub fn pgp_signatures(doc: &Document) -> Vec<PgpSignature> {
+    let mut signatures: Vec<PgpSignature> = embedded_files(doc)
+        .into_iter()
+        .filter_map(|spec| {
+            let signed_length = spec.get(PGP_SIGNED_LENGTH_KEY).and_then(Object::as_i64).ok()?;
+            let file = spec.get(b"EF").and_then(|files| doc.dereference(files)).ok()?.1.as_dict().ok()?.get(b"F").ok()?;
+            let Object::Stream(stream) = doc.dereference(file).ok()?.1 else {
+                return None;
+            };
+            let name = spec.get(b"UF").or_else(|_| spec.get(b"F")).ok();
+            Some(PgpSignature {
+                file_name: name.and_then(|name| lopdf::decode_text_string(name).ok()).unwrap_or_default(),
+                signed_length: u64::try_from(signed_length).ok()?,
+                armored: String::from_utf8(stream.get_plain_content().ok()?).ok()?,
+            })
+        })
+        .collect();
+    signatures.sort_by_key(|signature| signature.signed_length);
+    signatures
+}
+
+/// The file specifications in the document's embedded files name tree.
+fn embedded_files(doc: &Document) -> Vec<&Dictionary> {
+    fn dictionary<'a>(doc: &'a Document, object: &'a Object) -> Option<&'a Dictionary> {
+        doc.dereference(object).ok().and_then(|(_, object)| object.as_dict().ok())
+    }
+    let root = doc
+        .catalog()
+        .ok()
+        .and_then(|catalog| dictionary(doc, catalog.get(b"Names").ok()?))
+        .and_then(|names| dictionary(doc, names.get(b"EmbeddedFiles").ok()?));
+    let mut files = Vec::new();
+    let mut pending: Vec<&Dictionary> = root.into_iter().collect();
+    // Bounded so a cycle among the kids cannot loop forever.
+    let mut visited = 0;
+    while let Some(node) = pending.pop().filter(|_| visited < MAX_NAME_TREE_NODES) {
+        visited += 1;
+        if let Ok((_, Object::Array(entries))) = node.get(b"Names").and_then(|entries| doc.dereference(entries)) {
+            files.extend(entries.chunks(2).filter_map(|pair| dictionary(doc, pair.get(1)?)));
+        }
+        if let Ok((_, Object::Array(kids))) = node.get(b"Kids").and_then(|kids| doc.dereference(kids)) {
+            pending.extend(kids.iter().filter_map(|kid| dictionary(doc, kid)));
+        }
+    }
+    files
+}
+
+/// The signed signature fields of the document that Sigillum did not add.
+pub fn foreign_signatures(doc: &Document) -> Vec<&Dictionary> {
+    let Ok(Object::Dictionary(form)) = doc
+        .catalog()
+        .and_then(|catalog| catalog.get(b"AcroForm"))
+        .and_then(|form| doc.dereference(form))
+        .map(|(_, form)| form)
+    else {
+        return Vec::new();
+    };
+    let Ok(fields) = form.get(b"Fields").and_then(|fields| doc.dereference(fields)).and_then(|(_, f)| f.as_array()) else {
+        return Vec::new();
+    };
+    fields
+        .iter()
+        .filter(|field| lock::is_signed_field(doc, field))
+        .filter_map(|field| doc.dereference(field).and_then(|(_, field)| field.as_dict()).ok())
+        .filter(|field| {
+            !field
+                .get(b"T")
+                .and_then(Object::as_str)
+                .is_ok_and(|name| name.starts_with(SIGNATURE_FIELD_NAME.as_bytes()))
+        })
+        .collect()
+}
+
+/// Signatures found by parsing the document rather than scanning its bytes,
+/// for files where another tool re-saved everything into compressed object
+/// and content streams. Reads the payloads through the catalog, or failing
+/// that the watermark text in the decoded page contents.
+pub fn signatures_from_document(doc: &Document) -> Vec<SignatureInfo> {
+    let signatures = signatures_from_catalog(doc);
+    if !signatures.is_empty() {
+        return signatures;
+    }
+    doc.get_pages()
+        .into_values()
+        .filter_map(|page_id| doc.get_page_content(page_id).ok())
+        .find_map(|content| {
+            let start = find_bytes(&content, SIGNATURE_MARKER)?;
+            let end = (start + SIGNATURE_WINDOW).min(content.len());
+            parse_signature_window(&content[start..end])
+        })
+        .into_iter()
+        .collect()
+}
+
+/// Reads the `/SigillumInfo` payloads through the document catalog, following
+/// each one's `/Previous` link to the signature before it. Oldest first.
+pub fn signatures_from_catalog(doc: &Document) -> Vec<SignatureInfo> {
+    let mut signatures = Vec::new();
+    let mut next = doc.catalog().ok().and_then(|catalog| catalog.get(b"SigillumInfo").ok());
+    // Bounded so a reference cycle cannot loop forever.
+    while let Some(reference) = next.filter(|_| signatures.len() < MAX_SIGNATURES) {
+        let Ok((_, Object::Dictionary(payload))) = doc.dereference(reference) else {
+            break;
+        };
+        let Some(info) = signature_from_payload(payload) else {
+            break;
+        };
+        signatures.push(info);
+        next = payload.get(b"Previous").ok();
+    }
+    signatures.reverse();
+    signatures
+}
+
+fn signature_from_payload(payload: &Dictionary) -> Option<SignatureInfo> {
+    if payload.get(b"Type").and_then(Object::as_name).ok()? != PAYLOAD_TYPE {
+        return None;
+    }
+    
+    let bytes = |name: &[u8]| payload.get(name).and_then(Object::as_str).ok().map(<[u8]>::to_vec);
+    let text = |name: &[u8]| bytes(name).map(|value| String::from_utf8_lossy(&value).into_owned());
+    Some(SignatureInfo {
+        signer_name: text(b"Signer")?,
+        timestamp: text(b"Timestamp")?,
+        extra: text(b"Extra").unwrap_or_default(),
+        signature: text(b"Signature")?,
+        signature_value: bytes(b"SignatureValue").map(|value| BASE64.encode(value)).unwrap_or_default(),
+        signed_length: payload.get(b"SignedLength").and_then(Object::as_i64).ok().and_then(|n| u64::try_from(n).ok()),
+        algorithm: text(b"Algorithm").and_then(|a| a.parse().ok()),
+        hash_algorithm: text(b"HashAlgorithm").and_then(|a| a.parse().ok()).unwrap_or_default(),
+        timestamp_token: bytes(b"TimestampToken").map(|token| BASE64.encode(token)),
+        public_key: text(b"PublicKey"),
+        key_fingerprint: text(b"KeyFingerprint"),
+        ssh_fingerprint: text(b"SshFingerprint"),
+        certificate: bytes(b"Certificate").map(|certificate| BASE64.encode(certificate)),
+        clock_check: clock_check_from(text),
+        key_url: text(b"KeyUrl"),
+        key_domain: text(b"KeyDomain"),
+        update_range: payload
+            .get(b"UpdateRange")
+            .and_then(Object::as_array)
+            .ok()
+            .and_then(|range| range.iter().map(|n| n.as_i64().ok().and_then(|n| u64::try_from(n).ok())).collect::<Option<Vec<u64>>>())
+            .map(|numbers| range_pairs(&numbers))
+            .unwrap_or_default(),
+        update_signature: bytes(b"UpdateSignature").map(|value| BASE64.encode(value)).unwrap_or_default(),
+        digest_format: payload
+            .get(b"DigestFormat")
+            .and_then(Object::as_i64)
+            .ok()
+            .and_then(|n| u32::try_from(n).ok())?,
+    })
+}
+
+/// Streaming counterpart of `canonical_hash` + `extract_signatures`.
+///
+/// Reads the document in fixed-size chunks so memory use stays bounded no matter
+/// how large the file is. Signatures that are not in plain payloads (those of
+/// documents signed before the payload existed, or moved into compressed
+/// object streams) need `signatures_from_document` instead.
+pub fn scan_pdf<R: Read>(mut reader: R) -> std::io::Result<PdfScan> {
+    let mut hasher = Sha256::new();
+    let mut chunk = vec![0u8; SCAN_CHUNK_SIZE];
+    let mut payloads = WindowCapture::new(PAYLOAD_MARKER, MAX_SIGNATURES);
+    let mut watermark = WindowCapture::new(SIGNATURE_MARKER, 1);
+    let mut encrypt = WindowCapture::new(ENCRYPT_MARKER, 1);
+    let mut byte_range = WindowCapture::new(BYTE_RANGE_MARKER, 1);
+    let mut pgp = WindowCapture::new(PGP_SIGNED_LENGTH_MARKER, 1);
+    
+    loop {
+        let read = reader.read(&mut chunk)?;
+        if read == 0 {
+            break;
+        }
+        let data = &chunk[..read];
+        hasher.update(data);
+        payloads.feed(data);
+        watermark.feed(data);
+        encrypt.feed(data);
+        byte_range.feed(data);
+        pgp.feed(data);
+    }
+    
+    Ok(PdfScan {
+        canonical_hash: hex::encode(hasher.finalize()),
+        signatures: payloads.windows.iter().filter_map(|window| parse_payload_window(window)).collect(),
+        watermark: watermark.windows.first().and_then(|window| parse_signature_window(window)),
+        may_be_encrypted: !encrypt.windows.is_empty(),
+        may_have_signature_fields: !byte_range.windows.is_empty(),
+        may_have_pgp_signatures: !pgp.windows.is_empty(),
+    })
+}
+
+/// Collects the `SIGNATURE_WINDOW` bytes starting at each of the first `limit`
+/// occurrences of `marker` in data that arrives in chunks.
+struct WindowCapture {
+    marker: &'static [u8],
+    limit: usize,
+    // Tail of the previous chunk, so a marker split across two reads is still found.
+    carry: Vec<u8>,
+    windows: Vec<Vec<u8>>,
+}
+
+impl WindowCapture {
+    fn new(marker: &'static [u8], limit: usize) -> Self {
+        WindowCapture {
+            marker,
+            limit,
+            carry: Vec::new(),
+            windows: Vec::new(),
+        }
+    }
+    
+    fn feed(&mut self, data: &[u8]) {
+        for window in &mut self.windows {
+            let wanted = SIGNATURE_WINDOW - window.len();
+            window.extend_from_slice(&data[..wanted.min(data.len())]);
+        }
+        if self.windows.len() >= self.limit {
+            return;
+        }
+        
+        let mut buffer = std::mem::take(&mut self.carry);
+        buffer.extend_from_slice(data);
+        for start in find_all_bytes(&buffer, self.marker) {
+            if self.windows.len() >= self.limit {
+                break;
+            }
+            let end = (start + SIGNATURE_WINDOW).min(buffer.len());
+            self.windows.push(buffer[start..end].to_vec());
+        }
+        let keep = self.marker.len() - 1;
+        self.carry = buffer[buffer.len().saturating_sub(keep)..].to_vec();
+    }
+}
+
+/// Watches the bytes after the signed part of a document for anything added
+/// once it was signed. Signing appends one incremental update, which ends at
+/// the first `%%EOF`; only whitespace may follow it.
+pub struct AppendDetector {
+    // Tail of the previous chunk, so a marker split across two writes is still found.
+    carry: Vec<u8>,
+    update_ended: bool,
+    appended: bool,
+}
+
+impl AppendDetector {
+    pub fn new() -> Self {
+        AppendDetector {
+            carry: Vec::new(),
+            update_ended: false,
+            appended: false,
+        }
+    }
+
+    /// A detector for what follows an update already known to have ended.
+    pub fn after_update() -> Self {
+        AppendDetector {
+            update_ended: true,
+            ..Self::new()
+        }
+    }
+
+    pub fn feed(&mut self, data: &[u8]) {
+        if self.update_ended {
+            self.appended |= data.iter().any(|b| !b.is_ascii_whitespace());
+            return;
+        }
+        self.carry.extend_from_slice(data);
+        if let Some(pos) = find_bytes(&self.carry, EOF_MARKER) {
+            self.update_ended = true;
+            let rest = self.carry.split_off(pos + EOF_MARKER.len());
+            self.carry.clear();
+            self.feed(&rest);
+        } else {
+            let keep = EOF_MARKER.len() - 1;
+            if self.carry.len() > keep {
+                self.carry.drain(..self.carry.len() - keep);
+            }
+        }
+    }
+
+    /// Whether anything follows the signing update. A missing `%%EOF` means
+    /// the update itself was cut short, which counts as a change too.
+    pub fn appended(&self) -> bool {
+        self.appended || !self.update_ended
+    }
+}
+
+impl Default for AppendDetector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl std::io::Write for AppendDetector {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.feed(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+fn find_bytes(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+fn find_all_bytes<'a>(haystack: &'a [u8], needle: &'a [u8]) -> impl Iterator<Item = usize> + 'a {
+    haystack
+        .windows(needle.len())
+        .enumerate()
+        .filter(move |(_, w)| *w == needle)
+        .map(|(i, _)| i)
+}
+
+fn rfind_bytes(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).rposition(|w| w == needle)
+}
+
+/// Reads the `/Key value` pairs of the payload dictionary that follow its
+/// `/Type` entry. Only the hex strings and integers we write are understood.
+fn parse_payload_window(window: &[u8]) -> Option<SignatureInfo> {
+    let is_delimiter = |b: u8| b.is_ascii_whitespace() || b"/<>[]()".contains(&b);
+    let mut strings: Vec<(&[u8], Vec<u8>)> = Vec::new();
+    let mut integers: Vec<(&[u8], u64)> = Vec::new();
+    let mut arrays: Vec<(&[u8], Vec<u64>)> = Vec::new();
+    let mut rest = &window[PAYLOAD_MARKER.len()..];
+    
+    loop {
+        rest = rest.trim_ascii_start();
+        if rest.is_empty() || rest.starts_with(b">>") || rest[0] != b'/' {
+            break;
+        }
+        let key_end = rest[1..].iter().position(|&b| is_delimiter(b)).map_or(rest.len(), |p| p + 1);
+        let key = &rest[1..key_end];
+        rest = rest[key_end..].trim_ascii_start();
+        
+        if rest.starts_with(b"<") && !rest.starts_with(b"<<") {
+            let close = rest.iter().position(|&b| b == b'>')?;
+            let mut digits: Vec<u8> = rest[1..close].iter().cloned().filter(|b| !b.is_ascii_whitespace()).collect();
+            if digits.len() % 2 == 1 {
+                digits.push(b'0');
+            }
+            strings.push((key, hex::decode(&digits).ok()?));
+            rest = &rest[close + 1..];
+        } else if rest.starts_with(b"[") {
+            let close = rest.iter().position(|&b| b == b']')?;
+            let numbers = String::from_utf8_lossy(&rest[1..close]);
+            if let Ok(numbers) = numbers.split_ascii_whitespace().map(str::parse).collect::<std::result::Result<Vec<u64>, _>>() {
+                arrays.push((key, numbers));
+            }
+            rest = &rest[close + 1..];
+        } else if rest.first().is_some_and(u8::is_ascii_digit) {
+            let end = rest.iter().position(|b| !b.is_ascii_digit()).unwrap_or(rest.len());
+            let value = std::str::from_utf8(&rest[..end]).ok()?.parse().ok()?;
+            rest = &rest[end..];
+            // `n g R` is a reference (`/Previous`), not an integer.
+            let after = rest.trim_ascii_start();
+            let generation = after.iter().take_while(|b| b.is_ascii_digit()).count();
+            if generation > 0 && after[generation..].trim_ascii_start().starts_with(b"R") {
+                rest = &after[generation..].trim_ascii_start()[1..];
+            } else {
+                integers.push((key, value));
+            }
+        } else {
+            // Not a value we write; skip to the next key.
+            let skip = rest.iter().skip(1).position(|&b| b == b'/' || b == b'>').map_or(rest.len(), |p| p + 1);
+            rest = &rest[skip..];
+        }
+    }
+    
+    let bytes = |name: &[u8]| strings.iter().find(|(key, _)| *key == name).map(|(_, value)| value.clone());
+    let text = |name: &[u8]| bytes(name).map(|value| String::from_utf8_lossy(&value).into_owned());
+    Some(SignatureInfo {
+        signer_name: text(b"Signer")?,
+        timestamp: text(b"Timestamp")?,
+        extra: text(b"Extra").unwrap_or_default(),
+        signature: text(b"Signature")?,
+        signature_value: bytes(b"SignatureValue").map(|value| BASE64.encode(value)).unwrap_or_default(),
+        signed_length: integers.iter().find(|(key, _)| *key == b"SignedLength").map(|(_, value)| *value),
+        algorithm: text(b"Algorithm").and_then(|a| a.parse().ok()),
+        hash_algorithm: text(b"HashAlgorithm").and_then(|a| a.parse().ok()).unwrap_or_default(),
+        timestamp_token: bytes(b"TimestampToken").map(|token| BASE64.encode(token)),
+        public_key: text(b"PublicKey"),
+        key_fingerprint: text(b"KeyFingerprint"),
+        ssh_fingerprint: text(b"SshFingerprint"),
+        certificate: bytes(b"Certificate").map(|certificate| BASE64.encode(certificate)),
+        clock_check: clock_check_from(text),
+        key_url: text(b"KeyUrl"),
+        key_domain: text(b"KeyDomain"),
+        update_range: arrays
+            .iter()
+            .find(|(key, _)| *key == b"UpdateRange")
+            .map(|(_, numbers)| range_pairs(numbers))
+            .unwrap_or_default(),
+        update_signature: bytes(b"UpdateSignature").map(|value| BASE64.encode(value)).unwrap_or_default(),
+        digest_format: integers
+            .iter()
+            .find(|(key, _)| *key == b"DigestFormat")
+            .and_then(|(_, value)| u32::try_from(*value).ok())?,
+    })
+}
+
+fn parse_signature_window(window: &[u8]) -> Option<SignatureInfo> {
+    let after_marker = String::from_utf8_lossy(window);
+    
+    let clean_lines = parse_signature_lines(&after_marker)?;
+    
+    let (signer_name, timestamp, extra, signature) = match clean_lines.len() {
+        len if len >= 4 => {
+            let sig = if clean_lines[2].starts_with("Hash:") {
+                clean_lines[2].trim_start_matches("Hash:").trim().to_string()
+            } else {
+                clean_lines[3].trim_start_matches("Hash:").trim().to_string()
+            };
+            let ext = if clean_lines[2].starts_with("Hash:") {
+                "(none)".to_string()
+            } else {
+                clean_lines[2].clone()
+            };
+            (clean_lines[0].clone(), clean_lines[1].clone(), ext, sig)
+        }
+        len if len >= 3 => {
+            let ext = if clean_lines[2].starts_with("Hash:") {
+                "(none)".to_string()
+            } else {
+                clean_lines[2].clone()
+            };
+            let sig = if clean_lines[2].starts_with("Hash:") {
+                clean_lines[2].trim_start_matches("Hash:").trim().to_string()
+            } else {
+                "SHA256: (hash not found)".to_string()
+            };
+            (clean_lines[0].clone(), clean_lines[1].clone(), ext, sig)
+        }
+        len if len >= 2 => {
+            (clean_lines[0].clone(), clean_lines.get(1).cloned().unwrap_or_default(), "(none)".to_string(), "SHA256: (hash not found)".to_string())
+        }
+        _ => return None,
+    };
+    
+    Some(SignatureInfo {
+        hash_algorithm: HashAlgorithm::from_label(&signature),
+        signer_name,
+        timestamp,
+        extra,
+        signature,
+        signature_value: String::new(),
+        signed_length: None,
+        algorithm: None,
+        timestamp_token: None,
+        public_key: None,
+        key_fingerprint: None,
+        ssh_fingerprint: None,
+        certificate: None,
+        clock_check: None,
+        key_url: None,
+        key_domain: None,
+        update_range: Vec::new(),
+        update_signature: String::new(),
+        digest_format: DIGEST_FORMAT,
+    })
+}
+
+/// Offset and length pairs from a flat `/UpdateRange` list; empty when the
+/// list cannot be one.
+fn range_pairs(numbers: &[u64]) -> Vec<(u64, u64)> {
+    if !numbers.len().is_multiple_of(2) {
+        return Vec::new();
+    }
+    numbers.chunks(2).map(|pair| (pair[0], pair[1])).collect()
+}
+
+/// The clock check of a payload whose text entries `text` reads. The skew is
+/// a string because the byte scan only understands unsigned integers.
+fn clock_check_from(text: impl Fn(&[u8]) -> Option<String>) -> Option<ClockCheck> {
+    Some(ClockCheck {
+        server: text(b"NtpServer")?,
+        local_time: text(b"LocalTime")?,
+        network_time: text(b"NetworkTime")?,
+        skew_ms: text(b"ClockSkew")?.parse().ok()?,
+    })
+}
+
+fn parse_signature_lines(after_marker: &str) -> Option<Vec<String>> {
+    let mut clean_lines: Vec<String> = Vec::new();
+    
+    if let Some(ds_pos) = after_marker.find("Digitally signed by ") {
+        let after_ds = &after_marker[ds_pos + "Digitally signed by ".len()..];
+        let mut remaining = after_ds.to_string();
+        
+        while clean_lines.len() < 4 {
+            if let Some(td_pos) = remaining.find("0 ") {
+                if let Some(td_end) = remaining[td_pos..].find(" Td (") {
+                    remaining = remaining[td_pos + td_end + " Td (".len()..].to_string();
+                } else {
+                    break;
+                }
+            }
+            
+            if let Some(open_paren) = remaining.find('(') {
+                if let Some(close_paren) = remaining[open_paren..].find(") Tj") {
+                    let text = &remaining[open_paren + 1..open_paren + close_paren];
+                    let trimmed = text.trim().to_string();
+                    if !trimmed.is_empty() {
+                        clean_lines.push(trimmed);
+                    }
+                    remaining = remaining[open_paren + close_paren + 4..].to_string();
+                } else {
+                    break;
+                }
+            } else {
+                break;
+            }
+        }
+    }
+    
+    if clean_lines.len() < 2 {
+        clean_lines.clear();
+        if let Some(ds_pos) = after_marker.find("Digitally signed by ") {
+            let after_ds = &after_marker[ds_pos + "Digitally signed by ".len()..];
+            if let Some(newline_pos) = after_ds.find('\n') {
+                let name = after_ds[..newline_pos].trim().to_string();
+                if !name.is_empty() && name != ") Tj" {
+                    clean_lines.push(name);
+                }
+                let rest = &after_ds[newline_pos + 1..];
+                for line in rest.lines().take(4) {
+                    let cleaned = line.replace(") Tj", "")
+                                     .replace("0 -10 Td (", "")
+                                     .trim()
+                                     .to_string();
+                    if !cleaned.is_empty() {
+                        clean_lines.push(cleaned);
+                    }
+                }
+            }
+        }
+    }
+    
+    let clean_lines: Vec<String> = clean_lines.into_iter()
+        .map(|line| {
+            line.replace(") Tj", "")
+                .replace("0 -10 Td (", "")
+                .replace("0 500 Td (", "")
+                .replace("BT", "")
+                .replace("ET", "")
+                .trim()
+                .to_string()
+        })
+        .filter(|line| !line.is_empty())
+        .collect();
+    
+    if clean_lines.is_empty() {
+        None
+    } else {
+        Some(clean_lines)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // Hybrid-reference PDFs keep the objects only their `/XRefStm` stream lists.
+    //
+    // In the fixture, the catalog's `/ViewerPreferences` (object 7) lives in an
+    // object stream that the classic cross-reference table marks as free.
+
+    use super::*;
+    use crate::{api, KeyAlgorithm, KeyPair, SignOptions, SignatureStatus, VerifyOptions};
+
+    const HYBRID: &[u8] = include_bytes!("../tests/fixtures/hybrid-reference.pdf");
+
+    fn viewer_preferences(doc: &Document) -> Option<&Dictionary> {
+        let reference = doc.catalog().ok()?.get(b"ViewerPreferences").ok()?;
+        doc.dereference(reference).ok()?.1.as_dict().ok()
+    }
+
+    fn displays_title(doc: &Document) -> bool {
+        viewer_preferences(doc).and_then(|prefs| prefs.get(b"DisplayDocTitle").and_then(Object::as_bool).ok()) == Some(true)
+    }
+
+    #[test]
+    fn the_fixture_hides_objects_from_a_plain_load() {
+        assert!(is_hybrid_reference(HYBRID));
+        assert!(!displays_title(&Document::load_mem(HYBRID).unwrap()));
+        assert!(displays_title(&load_hybrid(HYBRID).unwrap()));
+    }
+
+    #[test]
+    fn signing_appends_to_the_original_bytes() {
+        let key = KeyPair::generate(KeyAlgorithm::Ed25519).unwrap();
+        let signed = api::sign(HYBRID, SignOptions::default(), &key).unwrap();
+        assert!(signed.pdf.starts_with(HYBRID));
+        // New objects are numbered past the ones the cross-reference stream lists.
+        assert!(displays_title(&load_hybrid(&signed.pdf).unwrap()));
+
+        let opts = VerifyOptions {
+            public_key: Some(key.public_key.clone()),
+            ..Default::default()
+        };
+        assert_eq!(api::verify(&signed.pdf, opts).unwrap().status(), SignatureStatus::Valid);
+    }
+
+    #[test]
+    fn signing_a_file_in_place_appends_to_its_bytes() {
+        let key = KeyPair::generate(KeyAlgorithm::Ed25519).unwrap();
+        let path = std::env::temp_dir().join(format!("sigillum-{}-hybrid.pdf", std::process::id()));
+        std::fs::write(&path, HYBRID).unwrap();
+        let signed = api::sign_to_file(&path, &path, SignOptions::default(), &key);
+        let pdf = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(signed.unwrap().signature_info.signed_length, Some(HYBRID.len() as u64));
+        assert!(pdf.starts_with(HYBRID));
+        assert!(displays_title(&load_hybrid(&pdf).unwrap()));
+        let opts = VerifyOptions {
+            public_key: Some(key.public_key.clone()),
+            ..Default::default()
+        };
+        assert_eq!(api::verify(&pdf, opts).unwrap().status(), SignatureStatus::Valid);
+    }
+
+    #[test]
+    fn encrypting_while_signing_keeps_the_hidden_objects() {
+        let key = KeyPair::generate(KeyAlgorithm::Ed25519).unwrap();
+        let opts = SignOptions {
+            output_password: Some("secret".to_string().into()),
+            ..Default::default()
+        };
+        let signed = api::sign(HYBRID, opts, &key).unwrap();
+        let doc = Document::load_mem(&signed.pdf).unwrap();
+        assert!(doc.trailer.get(b"XRefStm").is_err());
+        assert!(displays_title(&doc));
+
+        let opts = VerifyOptions {
+            public_key: Some(key.public_key.clone()),
+            password: Some("secret".to_string().into()),
+            ..Default::default()
+        };
+        assert_eq!(api::verify(&signed.pdf, opts).unwrap().status(), SignatureStatus::Valid);
+    }
+}
@@ -0,0 +1,259 @@
+//! Keeping PDF/A documents conforming when they are signed.
+//!
+//! A PDF/A document names its part and conformance level in its XMP metadata
+//! and carries an output intent saying which device colours it may use. When
+//! one is signed, the watermark embeds its font, draws without transparency
+//! and keeps to those colours, and the XMP metadata describes the Sigillum
+//! properties it adds.
+
+use crate::api::SignOptions;
+use crate::appearance::Appearance;
+use crate::error::Result;
+use crate::pdf_utils::XMP_NAMESPACE;
+use lopdf::{Document, Object};
+
+/// The `/S` of a PDF/A output intent.
+const PDFA_OUTPUT_INTENT: &[u8] = b"GTS_PDFA1";
+
+/// The PDF/A version a document claims to conform to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PdfA {
+    /// 1, 2 or 3; `None` when only an output intent marks the document.
+    pub part: Option<u8>,
+    /// The conformance level, such as `B`.
+    pub conformance: Option<String>,
+    /// Colour components of the output intent's profile: 1 for grey, 3 for
+    /// RGB, 4 for CMYK.
+    pub output_components: Option<i64>,
+}
+
+impl PdfA {
+    /// The PDF/A version `doc` claims, read from the `pdfaid` properties of
+    /// its XMP metadata or, failing that, its PDF/A output intent.
+    pub fn detect(doc: &Document) -> Option<PdfA> {
+        let catalog = doc.catalog().ok()?;
+        let packet = catalog
+            .get(b"Metadata")
+            .and_then(Object::as_reference)
+            .and_then(|id| doc.get_object(id))
+            .and_then(Object::as_stream)
+            .ok()
+            .map(|stream| stream.decompressed_content().unwrap_or_else(|_| stream.content.clone()))
+            .map(|packet| String::from_utf8_lossy(&packet).into_owned());
+        let part = packet
+            .as_deref()
+            .and_then(|packet| xmp_value(packet, "pdfaid:part"))
+            .and_then(|part| part.parse().ok());
+
+        let intent = catalog
+            .get(b"OutputIntents")
+            .and_then(|intents| doc.dereference(intents))
+            .and_then(|(_, intents)| intents.as_array())
+            .ok()
+            .into_iter()
+            .flatten()
+            .filter_map(|intent| doc.dereference(intent).and_then(|(_, intent)| intent.as_dict()).ok())
+            .find(|intent| intent.get(b"S").and_then(Object::as_name).ok() == Some(PDFA_OUTPUT_INTENT));
+        if part.is_none() && intent.is_none() {
+            return None;
+        }
+        let output_components = intent
+            .and_then(|intent| intent.get(b"DestOutputProfile").ok())
+            .and_then(|profile| doc.dereference(profile).ok())
+            .and_then(|(_, profile)| profile.as_stream().ok())
+            .and_then(|profile| profile.dict.get(b"N").and_then(Object::as_i64).ok());
+
+        Some(PdfA {
+            part,
+            conformance: packet
+                .as_deref()
+                .and_then(|packet| xmp_value(packet, "pdfaid:conformance"))
+                .map(|level| level.to_ascii_uppercase()),
+            output_components,
+        })
+    }
+
+    /// Such as `PDF/A-2B`.
+    pub fn label(&self) -> String {
+        match self.part {
+            Some(part) => format!("PDF/A-{}{}", part, self.conformance.as_deref().unwrap_or_default()),
+            None => "PDF/A".to_string(),
+        }
+    }
+
+    /// Whether the output intent allows DeviceRGB colours.
+    pub fn allows_rgb(&self) -> bool {
+        self.output_components == Some(3)
+    }
+
+    /// Checks that signing with `opts` leaves the document conforming,
+    /// reporting everything that would not at once.
+    pub fn check(&self, opts: &SignOptions) -> Result<()> {
+        let mut problems = Vec::new();
+        if !self.allows_rgb() {
+            if !is_grey(&opts.appearance) {
+                problems.push("the watermark colour needs an RGB output intent");
+            }
+            if opts.appearance.stamp.as_ref().is_some_and(|stamp| is_colour_png(&stamp.png)) {
+                problems.push("the colour stamp image needs an RGB output intent");
+            }
+        }
+        if opts.output_password.is_some() {
+            problems.push("PDF/A does not allow encryption");
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(format!(
+                "Signing would break the document's {} conformance: {}",
+                self.label(),
+                problems.join("; ")
+            ).into())
+        }
+    }
+}
+
+/// Whether the watermark text is drawn in a shade of grey.
+fn is_grey(appearance: &Appearance) -> bool {
+    let [r, g, b] = appearance.color.0;
+    r == g && g == b
+}
+
+/// Whether `png` holds colour rather than greyscale pixels; an image that
+/// cannot be read is left for the watermark to report.
+fn is_colour_png(png: &[u8]) -> bool {
+    png::Decoder::new(std::io::Cursor::new(png))
+        .read_info()
+        .is_ok_and(|reader| {
+            !matches!(
+                reader.info().color_type,
+                png::ColorType::Grayscale | png::ColorType::GrayscaleAlpha
+            )
+        })
+}
+
+/// The value of the simple XMP property `name`, written either as an
+/// attribute (`pdfaid:part="2"`) or as an element (`<pdfaid:part>2</...>`).
+pub(crate) fn xmp_value(packet: &str, name: &str) -> Option<String> {
+    let start = packet.find(name)? + name.len();
+    let rest = &packet[start..];
+    let value = if let Some(rest) = rest.strip_prefix('>') {
+        &rest[..rest.find('<')?]
+    } else {
+        let rest = rest.trim_start().strip_prefix('=')?.trim_start();
+        let quote = rest.chars().next().filter(|&c| c == '"' || c == '\'')?;
+        let rest = &rest[1..];
+        &rest[..rest.find(quote)?]
+    };
+    Some(value.trim().to_string())
+}
+
+/// `packet` with its `xmp:ModifyDate` set to `date`, added to `description`
+/// when there is none; `None` if it is written in a form this cannot edit.
+pub(crate) fn set_modify_date(packet: &str, date: &str) -> Option<String> {
+    const ELEMENT: &str = "<xmp:ModifyDate>";
+    const ATTRIBUTE: &str = "xmp:ModifyDate=";
+    let mut packet = packet.to_string();
+    if let Some(start) = packet.find(ELEMENT).map(|start| start + ELEMENT.len()) {
+        let end = start + packet[start..].find('<')?;
+        packet.replace_range(start..end, date);
+    } else if let Some(start) = packet.find(ATTRIBUTE).map(|start| start + ATTRIBUTE.len()) {
+        let quote = packet[start..].chars().next().filter(|&c| c == '"' || c == '\'')?;
+        let end = start + 1 + packet[start + 1..].find(quote)?;
+        packet.replace_range(start + 1..end, date);
+    } else if packet.contains("ModifyDate") {
+        // Under a prefix other than `xmp`.
+        return None;
+    } else {
+        let insert_at = packet.rfind("</rdf:RDF>")?;
+        packet.insert_str(
+            insert_at,
+            &format!(
+                "<rdf:Description rdf:about=\"\" xmlns:xmp=\"http://ns.adobe.com/xap/1.0/\">\n\
+                 <xmp:ModifyDate>{}</xmp:ModifyDate>\n\
+                 </rdf:Description>\n",
+                date
+            ),
+        );
+    }
+    Some(packet)
+}
+
+/// `packet` with the PDF/A extension schema that describes the Sigillum
+/// properties, which PDF/A requires of every property outside the standard
+/// schemas. Added to the document's own list of extension schemas when it has
+/// one; `None` when that list is written in a form this cannot edit.
+pub(crate) fn add_extension_schema(packet: &str) -> Option<String> {
+    let mut packet = packet.to_string();
+    if packet.contains(&format!("<pdfaSchema:namespaceURI>{}</pdfaSchema:namespaceURI>", XMP_NAMESPACE)) {
+        return Some(packet);
+    }
+    let schema = extension_schema();
+    if let Some(schemas) = packet.find("<pdfaExtension:schemas>") {
+        let bag = schemas + packet[schemas..].find("<rdf:Bag>")? + "<rdf:Bag>".len();
+        packet.insert_str(bag, &format!("\n{}", schema));
+    } else if packet.contains("pdfaExtension:schemas") {
+        // An empty element, or one under another prefix.
+        return None;
+    } else {
+        let insert_at = packet.rfind("</rdf:RDF>")?;
+        packet.insert_str(
+            insert_at,
+            &format!(
+                "<rdf:Description rdf:about=\"\" xmlns:pdfaExtension=\"http://www.aiim.org/pdfa/ns/extension/\">\n\
+                 <pdfaExtension:schemas>\n<rdf:Bag>\n{}</rdf:Bag>\n</pdfaExtension:schemas>\n\
+                 </rdf:Description>\n",
+                schema
+            ),
+        );
+    }
+    Some(packet)
+}
+
+/// The schema's `rdf:li`, declaring the namespaces it uses itself so it can
+/// go into a list that declares them differently.
+fn extension_schema() -> String {
+    let field = |name: &str, value_type: &str, description: &str| {
+        format!(
+            "<rdf:li rdf:parseType=\"Resource\">\n\
+             <pdfaField:name>{}</pdfaField:name>\n\
+             <pdfaField:valueType>{}</pdfaField:valueType>\n\
+             <pdfaField:description>{}</pdfaField:description>\n\
+             </rdf:li>\n",
+            name, value_type, description
+        )
+    };
+    format!(
+        "<rdf:li rdf:parseType=\"Resource\" \
+         xmlns:pdfaSchema=\"http://www.aiim.org/pdfa/ns/schema#\" \
+         xmlns:pdfaProperty=\"http://www.aiim.org/pdfa/ns/property#\" \
+         xmlns:pdfaType=\"http://www.aiim.org/pdfa/ns/type#\" \
+         xmlns:pdfaField=\"http://www.aiim.org/pdfa/ns/field#\">\n\
+         <pdfaSchema:schema>Sigillum signatures</pdfaSchema:schema>\n\
+         <pdfaSchema:namespaceURI>{namespace}</pdfaSchema:namespaceURI>\n\
+         <pdfaSchema:prefix>sigillum</pdfaSchema:prefix>\n\
+         <pdfaSchema:property>\n<rdf:Seq>\n\
+         <rdf:li rdf:parseType=\"Resource\">\n\
+         <pdfaProperty:name>Signatures</pdfaProperty:name>\n\
+         <pdfaProperty:valueType>Seq Signature</pdfaProperty:valueType>\n\
+         <pdfaProperty:category>external</pdfaProperty:category>\n\
+         <pdfaProperty:description>Who signed the document and when, oldest first</pdfaProperty:description>\n\
+         </rdf:li>\n\
+         </rdf:Seq>\n</pdfaSchema:property>\n\
+         <pdfaSchema:valueType>\n<rdf:Seq>\n\
+         <rdf:li rdf:parseType=\"Resource\">\n\
+         <pdfaType:type>Signature</pdfaType:type>\n\
+         <pdfaType:namespaceURI>{namespace}</pdfaType:namespaceURI>\n\
+         <pdfaType:prefix>sigillum</pdfaType:prefix>\n\
+         <pdfaType:description>One signature made with Sigillum</pdfaType:description>\n\
+         <pdfaType:field>\n<rdf:Seq>\n{signer}{time}{fingerprint}</rdf:Seq>\n</pdfaType:field>\n\
+         </rdf:li>\n\
+         </rdf:Seq>\n</pdfaSchema:valueType>\n\
+         </rdf:li>\n",
+        namespace = XMP_NAMESPACE,
+        signer = field("Signer", "Text", "The signer's name"),
+        time = field("SigningTime", "Date", "When the document was signed"),
+        fingerprint = field("KeyFingerprint", "Text", "SHA-256 fingerprint of the signing key"),
+    )
+}
@@ -0,0 +1,107 @@
+//! YubiKeys and other PIV smart cards. A card is reached through a PKCS#11
+//! module for PIV, Yubico's ykcs11 or OpenSC's, and signs with the key in its
+//! digital signature slot (9c), whose certificate is read off the card.
+
+use crate::certificate::{self, CertificateInfo};
+use crate::error::Result;
+use crate::pkcs11::{self, Pkcs11Config};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::Path;
+use x509_cert::Certificate;
+
+/// The CKA_ID PIV modules give the key and certificate in slot 9c.
+const SIGNATURE_SLOT_ID: &[u8] = &[0x02];
+
+/// Where the PIV modules are installed, Yubico's first since it knows more
+/// about YubiKeys.
+#[cfg(target_os = "linux")]
+const MODULES: &[&str] = &[
+    "/usr/lib/x86_64-linux-gnu/libykcs11.so",
+    "/usr/lib/aarch64-linux-gnu/libykcs11.so",
+    "/usr/lib64/libykcs11.so",
+    "/usr/lib/libykcs11.so",
+    "/usr/local/lib/libykcs11.so",
+    "/usr/lib/x86_64-linux-gnu/opensc-pkcs11.so",
+    "/usr/lib/aarch64-linux-gnu/opensc-pkcs11.so",
+    "/usr/lib64/opensc-pkcs11.so",
+    "/usr/lib/opensc-pkcs11.so",
+];
+#[cfg(target_os = "macos")]
+const MODULES: &[&str] = &[
+    "/opt/homebrew/lib/libykcs11.dylib",
+    "/usr/local/lib/libykcs11.dylib",
+    "/Library/OpenSC/lib/opensc-pkcs11.so",
+];
+#[cfg(windows)]
+const MODULES: &[&str] = &[
+    r"C:\Program Files\Yubico\Yubico PIV Tool\bin\libykcs11.dll",
+    r"C:\Program Files\OpenSC Project\OpenSC\pkcs11\opensc-pkcs11.dll",
+];
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+const MODULES: &[&str] = &[];
+
+/// A plugged-in card that can sign.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SigningDevice {
+    /// What the card calls itself, such as "YubiKey PIV #12345678".
+    pub name: String,
+    pub manufacturer: String,
+    pub serial: String,
+    /// The module and slot the card is reached through.
+    pub token: Pkcs11Config,
+    /// The certificate in the card's digital signature slot.
+    pub certificate: CertificateInfo,
+}
+
+/// The PIV cards plugged in now that have a certificate in their digital
+/// signature slot; ones without cannot sign and are left out. A card that
+/// two installed modules both see is listed once.
+pub fn devices() -> Vec<SigningDevice> {
+    let mut serials = HashSet::new();
+    let mut devices = Vec::new();
+    for module in MODULES.iter().map(Path::new).filter(|module| module.is_file()) {
+        // A module that fails to load or list its slots just finds nothing.
+        let Ok(tokens) = pkcs11::tokens(module) else {
+            continue;
+        };
+        for token in tokens {
+            let Ok(Some(der)) = pkcs11::certificate(module, token.slot, SIGNATURE_SLOT_ID) else {
+                continue;
+            };
+            let Ok(info) = certificate::from_der(&der).and_then(|c| certificate::info(&c)) else {
+                continue;
+            };
+            if !token.serial.is_empty() && !serials.insert(token.serial.clone()) {
+                continue;
+            }
+            devices.push(SigningDevice {
+                name: token.label,
+                manufacturer: token.manufacturer,
+                serial: token.serial,
+                token: Pkcs11Config {
+                    module: module.to_path_buf(),
+                    slot: token.slot,
+                    key_label: None,
+                },
+                certificate: info,
+            });
+        }
+    }
+    devices
+}
+
+/// The token settings for the digital signature key of the card `device`
+/// reaches, and the certificate beside it. `pin` may be needed to see the key.
+pub fn signing_key(device: &Pkcs11Config, pin: Option<&str>) -> Result<(Pkcs11Config, Certificate)> {
+    let der = pkcs11::certificate(&device.module, device.slot, SIGNATURE_SLOT_ID)?
+        .ok_or("The card has no certificate in its digital signature slot (9c)")?;
+    let label = pkcs11::private_key_label(&device.module, device.slot, pin, SIGNATURE_SLOT_ID)?
+        .ok_or("The card has no key in its digital signature slot (9c)")?;
+    let config = Pkcs11Config {
+        module: device.module.clone(),
+        slot: device.slot,
+        key_label: Some(label),
+    };
+    Ok((config, certificate::from_der(&der)?))
+}
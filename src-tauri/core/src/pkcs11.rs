@@ -0,0 +1,763 @@
+//! RSA keys that stay on a PKCS#11 token, such as a smart card, an HSM or
+//! SoftHSM: the token's module is loaded at run time and does the private key
+//! operation, so the key itself never leaves it.
+
+use crate::error::Result;
+use crate::hash::HashAlgorithm;
+use libloading::Library;
+use rsa::pkcs1v15::{Signature, VerifyingKey};
+use rsa::signature::{Keypair, Signer};
+use rsa::traits::PublicKeyParts;
+use rsa::{BigUint, RsaPublicKey};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::ffi::c_void;
+use std::mem;
+use std::os::raw::c_ulong;
+use std::path::{Path, PathBuf};
+use std::ptr;
+use std::sync::{Arc, Mutex, OnceLock};
+use x509_cert::der::asn1::Any;
+use x509_cert::der::oid::db::rfc5912;
+use x509_cert::spki::{AlgorithmIdentifierOwned, DynSignatureAlgorithmIdentifier};
+use zeroize::Zeroizing;
+
+/// Which key on which token to sign with, as stored in a key profile.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Pkcs11Config {
+    /// The token's PKCS#11 module, such as `/usr/lib/softhsm/libsofthsm2.so`.
+    pub module: PathBuf,
+    pub slot: u64,
+    /// Label of the private key; the first one on the token when `None`.
+    #[serde(default)]
+    pub key_label: Option<String>,
+}
+
+type CkUlong = c_ulong;
+type CkRv = CkUlong;
+
+const CKR_OK: CkRv = 0;
+const CKR_SLOT_ID_INVALID: CkRv = 0x03;
+const CKR_PIN_INCORRECT: CkRv = 0xa0;
+const CKR_PIN_LOCKED: CkRv = 0xa4;
+const CKR_TOKEN_NOT_PRESENT: CkRv = 0xe0;
+const CKR_USER_ALREADY_LOGGED_IN: CkRv = 0x100;
+const CKR_USER_NOT_LOGGED_IN: CkRv = 0x101;
+const CKR_CRYPTOKI_ALREADY_INITIALIZED: CkRv = 0x191;
+
+const CK_TRUE: u8 = 1;
+const CK_FALSE: u8 = 0;
+const CKF_OS_LOCKING_OK: CkUlong = 0x02;
+const CKF_RW_SESSION: CkUlong = 0x02;
+const CKF_SERIAL_SESSION: CkUlong = 0x04;
+const CKU_USER: CkUlong = 1;
+const CKU_CONTEXT_SPECIFIC: CkUlong = 2;
+const CKO_CERTIFICATE: CkUlong = 1;
+const CKO_PRIVATE_KEY: CkUlong = 3;
+const CKK_RSA: CkUlong = 0;
+const CKK_EC: CkUlong = 3;
+const CKA_CLASS: CkUlong = 0x000;
+const CKA_TOKEN: CkUlong = 0x001;
+const CKA_PRIVATE: CkUlong = 0x002;
+const CKA_LABEL: CkUlong = 0x003;
+const CKA_VALUE: CkUlong = 0x011;
+const CKA_KEY_TYPE: CkUlong = 0x100;
+const CKA_ID: CkUlong = 0x102;
+const CKA_SENSITIVE: CkUlong = 0x103;
+const CKA_SIGN: CkUlong = 0x108;
+const CKA_VERIFY: CkUlong = 0x10a;
+const CKA_MODULUS: CkUlong = 0x120;
+const CKA_MODULUS_BITS: CkUlong = 0x121;
+const CKA_PUBLIC_EXPONENT: CkUlong = 0x122;
+const CKA_EXTRACTABLE: CkUlong = 0x162;
+const CKA_ALWAYS_AUTHENTICATE: CkUlong = 0x202;
+/// Raw PKCS#1 v1.5 signing over a `DigestInfo` built by the caller, which
+/// works with every digest the token does not need to know about.
+const CKM_RSA_PKCS: CkUlong = 0x001;
+const CKM_RSA_PKCS_KEY_PAIR_GEN: CkUlong = 0x000;
+/// 65537, big-endian, the public exponent of generated keys.
+const PUBLIC_EXPONENT: [u8; 3] = [0x01, 0x00, 0x01];
+
+/// DER `DigestInfo` headers that go in front of a digest for PKCS#1 v1.5.
+const SHA256_DIGEST_INFO: &[u8] = &[
+    0x30, 0x31, 0x30, 0x0d, 0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x01, 0x05, 0x00, 0x04, 0x20,
+];
+const SHA384_DIGEST_INFO: &[u8] = &[
+    0x30, 0x41, 0x30, 0x0d, 0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x02, 0x05, 0x00, 0x04, 0x30,
+];
+const SHA512_DIGEST_INFO: &[u8] = &[
+    0x30, 0x51, 0x30, 0x0d, 0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x03, 0x05, 0x00, 0x04, 0x40,
+];
+
+// The structures of the PKCS#11 headers. Windows builds of the headers pack
+// them to single bytes.
+#[derive(Clone, Copy)]
+#[cfg_attr(windows, repr(C, packed))]
+#[cfg_attr(not(windows), repr(C))]
+struct CkAttribute {
+    kind: CkUlong,
+    value: *mut c_void,
+    value_len: CkUlong,
+}
+
+#[cfg_attr(windows, repr(C, packed))]
+#[cfg_attr(not(windows), repr(C))]
+struct CkMechanism {
+    mechanism: CkUlong,
+    parameter: *mut c_void,
+    parameter_len: CkUlong,
+}
+
+#[cfg_attr(windows, repr(C, packed))]
+#[cfg_attr(not(windows), repr(C))]
+struct CkInitializeArgs {
+    create_mutex: *const c_void,
+    destroy_mutex: *const c_void,
+    lock_mutex: *const c_void,
+    unlock_mutex: *const c_void,
+    flags: CkUlong,
+    reserved: *mut c_void,
+}
+
+#[cfg_attr(windows, repr(C, packed))]
+#[cfg_attr(not(windows), repr(C))]
+struct CkTokenInfo {
+    label: [u8; 32],
+    manufacturer_id: [u8; 32],
+    model: [u8; 16],
+    serial_number: [u8; 16],
+    // Flags, session and PIN limits and memory sizes.
+    _flags_and_counts: [CkUlong; 11],
+    _versions: [u8; 4],
+    _utc_time: [u8; 16],
+}
+
+type Unused = Option<unsafe extern "C" fn()>;
+
+/// `CK_FUNCTION_LIST` up to `C_GenerateKeyPair`, the last function used
+/// here; the entries in between keep the offsets right.
+#[cfg_attr(windows, repr(C, packed))]
+#[cfg_attr(not(windows), repr(C))]
+struct FunctionList {
+    version: [u8; 2],
+    initialize: Option<unsafe extern "C" fn(args: *mut c_void) -> CkRv>,
+    _finalize_to_get_function_list: [Unused; 3],
+    get_slot_list: Option<unsafe extern "C" fn(token_present: u8, slots: *mut CkUlong, count: *mut CkUlong) -> CkRv>,
+    _get_slot_info: Unused,
+    get_token_info: Option<unsafe extern "C" fn(slot: CkUlong, info: *mut CkTokenInfo) -> CkRv>,
+    _get_mechanism_list_to_set_pin: [Unused; 5],
+    open_session: Option<
+        unsafe extern "C" fn(
+            slot: CkUlong,
+            flags: CkUlong,
+            application: *mut c_void,
+            notify: *const c_void,
+            session: *mut CkUlong,
+        ) -> CkRv,
+    >,
+    close_session: Option<unsafe extern "C" fn(session: CkUlong) -> CkRv>,
+    _close_all_sessions_to_set_operation_state: [Unused; 4],
+    login: Option<unsafe extern "C" fn(session: CkUlong, user: CkUlong, pin: *const u8, pin_len: CkUlong) -> CkRv>,
+    _logout_to_get_object_size: [Unused; 5],
+    get_attribute_value: Option<
+        unsafe extern "C" fn(session: CkUlong, object: CkUlong, template: *mut CkAttribute, count: CkUlong) -> CkRv,
+    >,
+    _set_attribute_value: Unused,
+    find_objects_init: Option<unsafe extern "C" fn(session: CkUlong, template: *const CkAttribute, count: CkUlong) -> CkRv>,
+    find_objects: Option<
+        unsafe extern "C" fn(session: CkUlong, objects: *mut CkUlong, max: CkUlong, count: *mut CkUlong) -> CkRv,
+    >,
+    find_objects_final: Option<unsafe extern "C" fn(session: CkUlong) -> CkRv>,
+    _encrypt_init_to_digest_final: [Unused; 13],
+    sign_init: Option<unsafe extern "C" fn(session: CkUlong, mechanism: *const CkMechanism, key: CkUlong) -> CkRv>,
+    sign: Option<
+        unsafe extern "C" fn(
+            session: CkUlong,
+            data: *const u8,
+            data_len: CkUlong,
+            signature: *mut u8,
+            signature_len: *mut CkUlong,
+        ) -> CkRv,
+    >,
+    _sign_update_to_generate_key: [Unused; 15],
+    generate_key_pair: Option<
+        unsafe extern "C" fn(
+            session: CkUlong,
+            mechanism: *const CkMechanism,
+            public_template: *const CkAttribute,
+            public_count: CkUlong,
+            private_template: *const CkAttribute,
+            private_count: CkUlong,
+            public_key: *mut CkUlong,
+            private_key: *mut CkUlong,
+        ) -> CkRv,
+    >,
+}
+
+/// A loaded and initialized module. Modules are kept for the life of the
+/// process, since finalizing one would end the sessions of every other key
+/// opened through it.
+struct Module {
+    _library: Library,
+    functions: *const FunctionList,
+}
+
+// The module is initialized with CKF_OS_LOCKING_OK, so it may be called from
+// any thread; each session is only used behind a lock.
+unsafe impl Send for Module {}
+unsafe impl Sync for Module {}
+
+impl Module {
+    fn load(path: &Path) -> Result<Arc<Module>> {
+        static MODULES: OnceLock<Mutex<HashMap<PathBuf, Arc<Module>>>> = OnceLock::new();
+        let mut modules = MODULES.get_or_init(Default::default).lock().unwrap();
+        if let Some(module) = modules.get(path) {
+            return Ok(module.clone());
+        }
+
+        let load_error = |e: &dyn std::fmt::Display| format!("Failed to load PKCS#11 module {}: {}", path.display(), e);
+        // SAFETY: loading a library runs its initializers; the path is one the
+        // user named as their token's module.
+        let library = unsafe { Library::new(path) }.map_err(|e| load_error(&e))?;
+        let mut functions: *const FunctionList = ptr::null();
+        // SAFETY: `C_GetFunctionList` has this signature in every PKCS#11
+        // version and fills in a pointer that stays valid while the library
+        // is loaded.
+        let rv = unsafe {
+            let get_function_list = library
+                .get::<unsafe extern "C" fn(*mut *const FunctionList) -> CkRv>(b"C_GetFunctionList\0")
+                .map_err(|e| load_error(&e))?;
+            get_function_list(&mut functions)
+        };
+        if rv != CKR_OK || functions.is_null() {
+            return Err(load_error(&"it has no function list").into());
+        }
+        let module = Module {
+            _library: library,
+            functions,
+        };
+
+        let mut args = CkInitializeArgs {
+            create_mutex: ptr::null(),
+            destroy_mutex: ptr::null(),
+            lock_mutex: ptr::null(),
+            unlock_mutex: ptr::null(),
+            flags: CKF_OS_LOCKING_OK,
+            reserved: ptr::null_mut(),
+        };
+        let initialize = module.function(|f| f.initialize, "C_Initialize")?;
+        // SAFETY: `args` outlives the call.
+        let rv = unsafe { initialize(&mut args as *mut CkInitializeArgs as *mut c_void) };
+        if rv != CKR_CRYPTOKI_ALREADY_INITIALIZED {
+            check(rv, "C_Initialize")?;
+        }
+
+        let module = Arc::new(module);
+        modules.insert(path.to_path_buf(), module.clone());
+        Ok(module)
+    }
+
+    fn function<F>(&self, get: impl FnOnce(&FunctionList) -> Option<F>, name: &str) -> Result<F> {
+        // SAFETY: `functions` was checked to be non-null and lives as long
+        // as the library.
+        Ok(get(unsafe { &*self.functions }).ok_or_else(|| format!("The PKCS#11 module does not provide {}", name))?)
+    }
+}
+
+fn check(rv: CkRv, function: &str) -> Result<()> {
+    match rv {
+        CKR_OK => Ok(()),
+        CKR_SLOT_ID_INVALID => Err("There is no such slot on the PKCS#11 module".into()),
+        CKR_TOKEN_NOT_PRESENT => Err("No token is present in the slot".into()),
+        CKR_PIN_INCORRECT => Err("Wrong token PIN".into()),
+        CKR_PIN_LOCKED => Err("The token PIN is locked".into()),
+        CKR_USER_NOT_LOGGED_IN => Err("The token needs a PIN".into()),
+        _ => Err(format!("PKCS#11 error 0x{:08x} from {}", rv, function).into()),
+    }
+}
+
+/// An open session on a token, closed when dropped. Sessions must not be used
+/// from two threads at once, hence the lock.
+struct Session {
+    module: Arc<Module>,
+    handle: Mutex<CkUlong>,
+}
+
+impl Session {
+    fn open(module: Arc<Module>, slot: u64) -> Result<Session> {
+        Self::open_with(module, slot, CKF_SERIAL_SESSION)
+    }
+
+    /// A session that may also create objects on the token.
+    fn open_rw(module: Arc<Module>, slot: u64) -> Result<Session> {
+        Self::open_with(module, slot, CKF_SERIAL_SESSION | CKF_RW_SESSION)
+    }
+
+    fn open_with(module: Arc<Module>, slot: u64, flags: CkUlong) -> Result<Session> {
+        let open_session = module.function(|f| f.open_session, "C_OpenSession")?;
+        let mut handle = 0;
+        // SAFETY: no callback is passed, and `handle` outlives the call.
+        let rv = unsafe {
+            open_session(
+                slot as CkUlong,
+                flags,
+                ptr::null_mut(),
+                ptr::null(),
+                &mut handle,
+            )
+        };
+        check(rv, "C_OpenSession")?;
+        Ok(Session {
+            module,
+            handle: Mutex::new(handle),
+        })
+    }
+
+    fn login(&self, pin: &str) -> Result<()> {
+        login(&self.module, *self.handle.lock().unwrap(), CKU_USER, pin)
+    }
+
+    fn find_private_key(&self, label: Option<&str>) -> Result<CkUlong> {
+        Ok(self.find_object(CKO_PRIVATE_KEY, label, None)?.ok_or_else(|| match label {
+            Some(label) => format!("The token has no private key labelled '{}'", label),
+            None => "The token has no private key; is the PIN right?".to_string(),
+        })?)
+    }
+
+    /// The first object of `class`, with the given label and ID if any.
+    fn find_object(&self, class: CkUlong, label: Option<&str>, id: Option<&[u8]>) -> Result<Option<CkUlong>> {
+        Ok(self.find_objects(class, label, id, 1)?.first().copied())
+    }
+
+    /// Up to `max` objects of `class`, with the given label and ID if any.
+    fn find_objects(
+        &self,
+        class: CkUlong,
+        label: Option<&str>,
+        id: Option<&[u8]>,
+        max: usize,
+    ) -> Result<Vec<CkUlong>> {
+        let find_objects_init = self.module.function(|f| f.find_objects_init, "C_FindObjectsInit")?;
+        let find_objects = self.module.function(|f| f.find_objects, "C_FindObjects")?;
+        let find_objects_final = self.module.function(|f| f.find_objects_final, "C_FindObjectsFinal")?;
+
+        let mut class = class;
+        let mut template = vec![CkAttribute {
+            kind: CKA_CLASS,
+            value: &mut class as *mut CkUlong as *mut c_void,
+            value_len: mem::size_of::<CkUlong>() as CkUlong,
+        }];
+        if let Some(label) = label {
+            template.push(CkAttribute {
+                kind: CKA_LABEL,
+                value: label.as_ptr() as *mut c_void,
+                value_len: label.len() as CkUlong,
+            });
+        }
+        if let Some(id) = id {
+            template.push(CkAttribute {
+                kind: CKA_ID,
+                value: id.as_ptr() as *mut c_void,
+                value_len: id.len() as CkUlong,
+            });
+        }
+
+        let handle = self.handle.lock().unwrap();
+        let mut objects = vec![0; max];
+        let mut count = 0;
+        // SAFETY: the template and what it points to outlive the calls, and
+        // the module only reads it; `objects` holds `max` handles.
+        unsafe {
+            check(
+                find_objects_init(*handle, template.as_ptr(), template.len() as CkUlong),
+                "C_FindObjectsInit",
+            )?;
+            let found = check(
+                find_objects(*handle, objects.as_mut_ptr(), max as CkUlong, &mut count),
+                "C_FindObjects",
+            );
+            find_objects_final(*handle);
+            found?;
+        }
+        objects.truncate(count as usize);
+        Ok(objects)
+    }
+
+    fn attribute(&self, object: CkUlong, kind: CkUlong) -> Result<Vec<u8>> {
+        let get_attribute_value = self.module.function(|f| f.get_attribute_value, "C_GetAttributeValue")?;
+        let handle = self.handle.lock().unwrap();
+        let mut template = CkAttribute {
+            kind,
+            value: ptr::null_mut(),
+            value_len: 0,
+        };
+        // SAFETY: the first call only asks for the length; the second passes
+        // a buffer of that length, which outlives the call.
+        unsafe {
+            check(get_attribute_value(*handle, object, &mut template, 1), "C_GetAttributeValue")?;
+            let mut value = vec![0u8; template.value_len as usize];
+            template.value = value.as_mut_ptr() as *mut c_void;
+            check(get_attribute_value(*handle, object, &mut template, 1), "C_GetAttributeValue")?;
+            value.truncate(template.value_len as usize);
+            Ok(value)
+        }
+    }
+}
+
+/// Logs in to `session`, which the caller holds the lock of.
+fn login(module: &Module, session: CkUlong, user: CkUlong, pin: &str) -> Result<()> {
+    let login = module.function(|f| f.login, "C_Login")?;
+    // SAFETY: the PIN is passed with its length and outlives the call.
+    let rv = unsafe { login(session, user, pin.as_ptr(), pin.len() as CkUlong) };
+    if rv == CKR_USER_ALREADY_LOGGED_IN {
+        return Ok(());
+    }
+    check(rv, "C_Login")
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        if let Ok(close_session) = self.module.function(|f| f.close_session, "C_CloseSession") {
+            // SAFETY: the session is ours and is not used after this.
+            unsafe { close_session(*self.handle.get_mut().unwrap()) };
+        }
+    }
+}
+
+/// An RSA private key on a token, with the session it was found in, logged
+/// in when a PIN was given.
+pub struct Pkcs11Key {
+    session: Session,
+    key: CkUlong,
+    public_key: RsaPublicKey,
+    /// Kept for keys that need the PIN again for every signature, such as
+    /// the one in a PIV card's digital signature slot. Wiped from memory
+    /// when the key is dropped.
+    pin: Option<Zeroizing<String>>,
+    always_authenticate: bool,
+}
+
+impl Pkcs11Key {
+    pub fn open(config: &Pkcs11Config, pin: Option<&str>) -> Result<Pkcs11Key> {
+        let session = Session::open(Module::load(&config.module)?, config.slot)?;
+        if let Some(pin) = pin {
+            session.login(pin)?;
+        }
+        let key = session.find_private_key(config.key_label.as_deref())?;
+
+        let key_type = session.attribute(key, CKA_KEY_TYPE)?;
+        let key_type = <[u8; mem::size_of::<CkUlong>()]>::try_from(key_type.as_slice())
+            .map(CkUlong::from_ne_bytes)
+            .map_err(|_| "Invalid token key: bad key type".to_string())?;
+        if key_type != CKK_RSA {
+            return Err("Only RSA keys on a PKCS#11 token can sign".into());
+        }
+        let modulus = BigUint::from_bytes_be(&session.attribute(key, CKA_MODULUS)?);
+        let exponent = BigUint::from_bytes_be(&session.attribute(key, CKA_PUBLIC_EXPONENT)?);
+        let public_key = RsaPublicKey::new(modulus, exponent).map_err(|e| format!("Invalid token key: {}", e))?;
+        // Not every module knows the attribute; those that don't never ask.
+        let always_authenticate = session
+            .attribute(key, CKA_ALWAYS_AUTHENTICATE)
+            .is_ok_and(|value| value.first() == Some(&CK_TRUE));
+
+        Ok(Pkcs11Key {
+            session,
+            key,
+            public_key,
+            pin: pin.map(|pin| Zeroizing::new(pin.to_string())),
+            always_authenticate,
+        })
+    }
+
+    pub fn public_key(&self) -> &RsaPublicKey {
+        &self.public_key
+    }
+
+    /// PKCS#1 v1.5 signature of a `digest` made with `hash`, as
+    /// `PrivateKey::sign_digest` makes for file-based RSA keys.
+    pub fn sign_digest(&self, hash: HashAlgorithm, digest: &[u8]) -> Result<Vec<u8>> {
+        let prefix = match hash {
+            HashAlgorithm::Sha256 => SHA256_DIGEST_INFO,
+            HashAlgorithm::Sha384 => SHA384_DIGEST_INFO,
+            HashAlgorithm::Sha512 => SHA512_DIGEST_INFO,
+            HashAlgorithm::Blake3 => &[],
+        };
+        let digest_info = [prefix, digest].concat();
+        let mechanism = CkMechanism {
+            mechanism: CKM_RSA_PKCS,
+            parameter: ptr::null_mut(),
+            parameter_len: 0,
+        };
+        let sign_init = self.session.module.function(|f| f.sign_init, "C_SignInit")?;
+        let sign = self.session.module.function(|f| f.sign, "C_Sign")?;
+
+        let handle = self.session.handle.lock().unwrap();
+        let mut signature = vec![0u8; self.public_key.size()];
+        let mut signature_len = signature.len() as CkUlong;
+        // SAFETY: every buffer is passed with its length and outlives the
+        // calls.
+        unsafe {
+            check(sign_init(*handle, &mechanism, self.key), "C_SignInit")?;
+            if self.always_authenticate {
+                let pin = self.pin.as_deref().ok_or("The token needs a PIN")?;
+                login(&self.session.module, *handle, CKU_CONTEXT_SPECIFIC, pin)?;
+            }
+            check(
+                sign(
+                    *handle,
+                    digest_info.as_ptr(),
+                    digest_info.len() as CkUlong,
+                    signature.as_mut_ptr(),
+                    &mut signature_len,
+                ),
+                "C_Sign",
+            )?;
+        }
+        signature.truncate(signature_len as usize);
+        Ok(signature)
+    }
+
+    /// Signs with SHA-256 through the `signature` traits, for building
+    /// certificates and CMS signatures.
+    pub(crate) fn rsa_signer(&self) -> RsaSigner<'_> {
+        RsaSigner(self)
+    }
+}
+
+/// A token in one of a module's slots, as it describes itself.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenInfo {
+    pub slot: u64,
+    pub label: String,
+    pub manufacturer: String,
+    pub model: String,
+    pub serial: String,
+}
+
+/// The tokens present in the slots of `module`.
+pub fn tokens(module: &Path) -> Result<Vec<TokenInfo>> {
+    let module = Module::load(module)?;
+    let get_slot_list = module.function(|f| f.get_slot_list, "C_GetSlotList")?;
+    let get_token_info = module.function(|f| f.get_token_info, "C_GetTokenInfo")?;
+
+    let mut count = 0;
+    // SAFETY: the first call only asks for the count; the second passes a
+    // buffer of that length, which outlives the call.
+    let slots = unsafe {
+        check(get_slot_list(CK_TRUE, ptr::null_mut(), &mut count), "C_GetSlotList")?;
+        let mut slots = vec![0; count as usize];
+        check(get_slot_list(CK_TRUE, slots.as_mut_ptr(), &mut count), "C_GetSlotList")?;
+        slots.truncate(count as usize);
+        slots
+    };
+
+    let mut tokens = Vec::new();
+    for slot in slots {
+        // SAFETY: the structure is plain data, which the module fills in.
+        let mut info: CkTokenInfo = unsafe { mem::zeroed() };
+        // A token pulled out since the slots were listed is skipped.
+        // SAFETY: `info` outlives the call.
+        if unsafe { get_token_info(slot, &mut info) } != CKR_OK {
+            continue;
+        }
+        // CK_ULONG is only 32 bits on Windows.
+        #[allow(clippy::useless_conversion)]
+        tokens.push(TokenInfo {
+            slot: u64::from(slot),
+            label: padded_text(&info.label),
+            manufacturer: padded_text(&info.manufacturer_id),
+            model: padded_text(&info.model),
+            serial: padded_text(&info.serial_number),
+        });
+    }
+    Ok(tokens)
+}
+
+/// An attribute pointing at `value`, which must outlive every use of it.
+fn attribute<T>(kind: CkUlong, value: &T) -> CkAttribute {
+    CkAttribute {
+        kind,
+        value: value as *const T as *mut c_void,
+        value_len: mem::size_of::<T>() as CkUlong,
+    }
+}
+
+/// Generates an RSA key of `bits` on the token `config` names, labelled
+/// `config.key_label`, logging in with `pin`. The private key is made
+/// sensitive and not extractable, so it can only ever sign on the token.
+pub fn generate_rsa_key(config: &Pkcs11Config, pin: &str, bits: usize) -> Result<()> {
+    let label = config.key_label.as_deref().ok_or("A key generated on a token needs a label")?;
+    let session = Session::open_rw(Module::load(&config.module)?, config.slot)?;
+    session.login(pin)?;
+    if session.find_object(CKO_PRIVATE_KEY, Some(label), None)?.is_some() {
+        return Err(format!("The token already has a key labelled '{}'", label).into());
+    }
+    let generate_key_pair = session.module.function(|f| f.generate_key_pair, "C_GenerateKeyPair")?;
+
+    // The ID pairs the two halves, and later a certificate, on the token.
+    let id: [u8; 16] = rand::random();
+    let label_attribute = CkAttribute {
+        kind: CKA_LABEL,
+        value: label.as_ptr() as *mut c_void,
+        value_len: label.len() as CkUlong,
+    };
+    let id_attribute = attribute(CKA_ID, &id);
+    let modulus_bits = bits as CkUlong;
+    let public_template = [
+        attribute(CKA_TOKEN, &CK_TRUE),
+        attribute(CKA_VERIFY, &CK_TRUE),
+        attribute(CKA_MODULUS_BITS, &modulus_bits),
+        attribute(CKA_PUBLIC_EXPONENT, &PUBLIC_EXPONENT),
+        label_attribute,
+        id_attribute,
+    ];
+    let private_template = [
+        attribute(CKA_TOKEN, &CK_TRUE),
+        attribute(CKA_PRIVATE, &CK_TRUE),
+        attribute(CKA_SENSITIVE, &CK_TRUE),
+        attribute(CKA_EXTRACTABLE, &CK_FALSE),
+        attribute(CKA_SIGN, &CK_TRUE),
+        label_attribute,
+        id_attribute,
+    ];
+    let mechanism = CkMechanism {
+        mechanism: CKM_RSA_PKCS_KEY_PAIR_GEN,
+        parameter: ptr::null_mut(),
+        parameter_len: 0,
+    };
+
+    let handle = session.handle.lock().unwrap();
+    let mut public_key = 0;
+    let mut private_key = 0;
+    // SAFETY: the templates and what they point to outlive the call, and the
+    // module only reads them.
+    let rv = unsafe {
+        generate_key_pair(
+            *handle,
+            &mechanism,
+            public_template.as_ptr(),
+            public_template.len() as CkUlong,
+            private_template.as_ptr(),
+            private_template.len() as CkUlong,
+            &mut public_key,
+            &mut private_key,
+        )
+    };
+    check(rv, "C_GenerateKeyPair")
+}
+
+/// A private key on a token, as `keys` lists it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenKey {
+    /// What goes in `Pkcs11Config::key_label` to sign with the key.
+    pub label: String,
+    /// CKA_ID in hex, which pairs the key with its certificate.
+    pub id: String,
+    /// "RSA", "EC" or "other"; only RSA keys can sign here.
+    pub key_type: String,
+    /// Size of an RSA key in bits.
+    pub bits: Option<usize>,
+}
+
+/// Most keys `keys` lists from one token.
+const MAX_LISTED_KEYS: usize = 64;
+
+/// The private keys on the token in `slot`, logging in with `pin` first when
+/// given; most tokens hide their keys until then.
+pub fn keys(module: &Path, slot: u64, pin: Option<&str>) -> Result<Vec<TokenKey>> {
+    let session = Session::open(Module::load(module)?, slot)?;
+    if let Some(pin) = pin {
+        session.login(pin)?;
+    }
+    let mut keys = Vec::new();
+    for key in session.find_objects(CKO_PRIVATE_KEY, None, None, MAX_LISTED_KEYS)? {
+        // Keys without a label cannot be picked by one, so are left out.
+        let Some(label) = session.attribute(key, CKA_LABEL).ok().and_then(|label| String::from_utf8(label).ok()) else {
+            continue;
+        };
+        let id = session.attribute(key, CKA_ID).unwrap_or_default();
+        let key_type = session
+            .attribute(key, CKA_KEY_TYPE)
+            .ok()
+            .and_then(|value| <[u8; mem::size_of::<CkUlong>()]>::try_from(value.as_slice()).ok())
+            .map(CkUlong::from_ne_bytes);
+        let bits = match key_type {
+            Some(CKK_RSA) => session.attribute(key, CKA_MODULUS).ok().map(|modulus| BigUint::from_bytes_be(&modulus).bits()),
+            _ => None,
+        };
+        keys.push(TokenKey {
+            label,
+            id: hex::encode(id),
+            key_type: match key_type {
+                Some(CKK_RSA) => "RSA",
+                Some(CKK_EC) => "EC",
+                _ => "other",
+            }
+            .to_string(),
+            bits,
+        });
+    }
+    Ok(keys)
+}
+
+/// The DER certificate with CKA_ID `id` on the token in `slot`, if it has one.
+/// Certificates are public, so no PIN is needed.
+pub fn certificate(module: &Path, slot: u64, id: &[u8]) -> Result<Option<Vec<u8>>> {
+    let session = Session::open(Module::load(module)?, slot)?;
+    session
+        .find_object(CKO_CERTIFICATE, None, Some(id))?
+        .map(|certificate| session.attribute(certificate, CKA_VALUE))
+        .transpose()
+}
+
+/// The label of the private key with CKA_ID `id` on the token in `slot`, if it
+/// has one, logging in with `pin` first when given.
+pub fn private_key_label(module: &Path, slot: u64, pin: Option<&str>, id: &[u8]) -> Result<Option<String>> {
+    let session = Session::open(Module::load(module)?, slot)?;
+    if let Some(pin) = pin {
+        session.login(pin)?;
+    }
+    session
+        .find_object(CKO_PRIVATE_KEY, None, Some(id))?
+        .map(|key| {
+            let label = session.attribute(key, CKA_LABEL)?;
+            Ok(String::from_utf8(label).map_err(|_| "Invalid token key: bad label")?)
+        })
+        .transpose()
+}
+
+/// Text from a token information field, which is padded with spaces.
+fn padded_text(field: &[u8]) -> String {
+    String::from_utf8_lossy(field).trim_end_matches([' ', '\0']).to_string()
+}
+
+/// A token key as a SHA-256 RSA signer.
+pub(crate) struct RsaSigner<'a>(&'a Pkcs11Key);
+
+impl Keypair for RsaSigner<'_> {
+    type VerifyingKey = VerifyingKey<Sha256>;
+
+    fn verifying_key(&self) -> Self::VerifyingKey {
+        VerifyingKey::new(self.0.public_key.clone())
+    }
+}
+
+impl DynSignatureAlgorithmIdentifier for RsaSigner<'_> {
+    fn signature_algorithm_identifier(&self) -> x509_cert::spki::Result<AlgorithmIdentifierOwned> {
+        Ok(AlgorithmIdentifierOwned {
+            oid: rfc5912::SHA_256_WITH_RSA_ENCRYPTION,
+            parameters: Some(Any::null()),
+        })
+    }
+}
+
+impl Signer<Signature> for RsaSigner<'_> {
+    fn try_sign(&self, msg: &[u8]) -> std::result::Result<Signature, rsa::signature::Error> {
+        let signature = self
+            .0
+            .sign_digest(HashAlgorithm::Sha256, &Sha256::digest(msg))
+            .map_err(rsa::signature::Error::from_source)?;
+        Signature::try_from(signature.as_slice())
+    }
+}
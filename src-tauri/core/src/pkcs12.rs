@@ -0,0 +1,88 @@
+//! PKCS#12 (.p12/.pfx) files, the form employers and CAs usually hand out
+//! signing credentials in: a private key and its certificate chain under one
+//! password.
+
+use crate::certificate;
+use crate::error::{Result, SigillumError};
+use crate::keys::PrivateKey;
+use p12_keystore::error::Error;
+use p12_keystore::{KeyStore, KeyStoreEntry, PrivateKeyChain};
+use sha1::{Digest, Sha1};
+use x509_cert::der::asn1::Any;
+use x509_cert::der::{Decode, SliceReader, Tag, Tagged};
+use x509_cert::Certificate;
+
+/// Name the key is stored under when no certificate gives it one.
+const DEFAULT_FRIENDLY_NAME: &str = "Sigillum";
+
+/// Whether `data` looks like a PKCS#12 file: a version 3 `PFX` structure.
+/// Does not check the password or the contents.
+pub fn is_pkcs12(data: &[u8]) -> bool {
+    let Ok(pfx) = Any::from_der(data) else {
+        return false;
+    };
+    if pfx.tag() != Tag::Sequence {
+        return false;
+    }
+    let Ok(mut reader) = SliceReader::new(pfx.value()) else {
+        return false;
+    };
+    u8::decode(&mut reader).is_ok_and(|version| version == 3)
+        && cms::content_info::ContentInfo::decode(&mut reader).is_ok()
+}
+
+/// Reads the first private key in `data` and its certificate. Without a
+/// `password` the empty one is tried, which some tools write.
+pub fn read(data: &[u8], password: Option<&str>) -> Result<(PrivateKey, Option<Certificate>)> {
+    let store = KeyStore::from_pkcs12(data, password.unwrap_or_default()).map_err(|e| match e {
+        Error::MacError(_) if password.is_none_or(str::is_empty) => {
+            SigillumError::PassphraseRequired("The PKCS#12 file is protected; its password is required".to_string())
+        }
+        Error::MacError(_) | Error::Pkcs5Error(_) | Error::UnpadError => {
+            SigillumError::WrongPassphrase("Wrong password for the PKCS#12 file".to_string())
+        }
+        e => SigillumError::Other(format!("Invalid PKCS#12 file: {}", e)),
+    })?;
+    let (_, chain) = store
+        .private_key_chain()
+        .ok_or("The PKCS#12 file holds no private key")?;
+
+    let private_key = PrivateKey::import(chain.key(), None)?;
+    // The chain starts with the key's own certificate, then its issuers;
+    // only the first is kept.
+    let certificate = match chain.chain().first() {
+        Some(certificate) => {
+            let certificate = certificate::from_der(certificate.as_der())?;
+            if certificate::public_key(&certificate)?.fingerprint()? != private_key.public_key().fingerprint()? {
+                return Err("The certificate in the PKCS#12 file is not for its private key".into());
+            }
+            Some(certificate)
+        }
+        None => None,
+    };
+    Ok((private_key, certificate))
+}
+
+/// Writes `private_key` and its `certificate` as a PKCS#12 file protected by
+/// `password`, with PBES2 (AES-256) encryption and an HMAC-SHA256 MAC as
+/// current OpenSSL and Windows read them.
+pub fn write(private_key: &PrivateKey, certificate: &Certificate, password: &str) -> Result<Vec<u8>> {
+    if password.is_empty() {
+        return Err("The PKCS#12 file needs a password".into());
+    }
+    let pkcs12_error = |e: Error| format!("Failed to write PKCS#12 file: {}", e);
+
+    let certificate_der = certificate::to_der(certificate)?;
+    let chain = PrivateKeyChain::new(
+        private_key.to_pkcs8_der()?,
+        // Matching the key to its certificate by the certificate's SHA-1, as
+        // OpenSSL does.
+        Sha1::digest(&certificate_der),
+        vec![p12_keystore::Certificate::from_der(&certificate_der).map_err(pkcs12_error)?],
+    );
+    let friendly_name = certificate::common_name(certificate).unwrap_or_else(|| DEFAULT_FRIENDLY_NAME.to_string());
+
+    let mut store = KeyStore::new();
+    store.add_entry(&friendly_name, KeyStoreEntry::PrivateKeyChain(chain));
+    Ok(store.writer(password).write().map_err(pkcs12_error)?)
+}
@@ -0,0 +1,58 @@
+use crate::error::Result;
+use regex::Regex;
+
+/// Content rules a document must satisfy before it may be signed.
+///
+/// Every pattern is a regular expression, so plain words work as-is and
+/// `(?i)DRAFT` can be used for a case-insensitive match.
+pub struct ContentPolicy {
+    required: Vec<Regex>,
+    forbidden: Vec<Regex>,
+}
+
+impl ContentPolicy {
+    pub fn new(required: &[String], forbidden: &[String]) -> Result<Self> {
+        Ok(ContentPolicy {
+            required: compile_patterns(required)?,
+            forbidden: compile_patterns(forbidden)?,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.required.is_empty() && self.forbidden.is_empty()
+    }
+
+    /// Checks the extracted document text, reporting every violated rule at once.
+    pub fn check(&self, text: &str) -> Result<()> {
+        let mut violations = Vec::new();
+
+        for pattern in &self.required {
+            if !pattern.is_match(text) {
+                violations.push(format!("required text '{}' is missing", pattern.as_str()));
+            }
+        }
+
+        for pattern in &self.forbidden {
+            if let Some(found) = pattern.find(text) {
+                violations.push(format!(
+                    "forbidden text '{}' is present (found \"{}\")",
+                    pattern.as_str(),
+                    found.as_str()
+                ));
+            }
+        }
+
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(format!("Document does not meet the content policy: {}", violations.join("; ")).into())
+        }
+    }
+}
+
+fn compile_patterns(patterns: &[String]) -> Result<Vec<Regex>> {
+    patterns
+        .iter()
+        .map(|p| Regex::new(p).map_err(|e| format!("Invalid text pattern '{}': {}", p, e).into()))
+        .collect()
+}
@@ -0,0 +1,72 @@
+//! Page previews for choosing where the stamp goes: a page rendered to PNG by
+//! a PDFium library loaded at run time, so builds without one still sign and
+//! verify.
+
+use crate::error::Result;
+use pdfium_render::prelude::*;
+use std::path::PathBuf;
+
+/// The resolutions a preview may be rendered at, in dots per inch.
+pub const MIN_DPI: f32 = 10.0;
+pub const MAX_DPI: f32 = 300.0;
+
+/// Renders page `page`, counting from 1, of `pdf` at `dpi` and returns it as
+/// a PNG.
+pub fn render_page(pdf: &[u8], password: Option<&str>, page: u32, dpi: f32) -> Result<Vec<u8>> {
+    if page == 0 {
+        return Err("Page numbers start at 1".into());
+    }
+    if !(MIN_DPI..=MAX_DPI).contains(&dpi) {
+        return Err(format!("The resolution must be between {} and {} dpi", MIN_DPI, MAX_DPI).into());
+    }
+
+    let pdfium = Pdfium::new(bind()?);
+    let document = pdfium
+        .load_pdf_from_byte_slice(pdf, password)
+        .map_err(|e| format!("Failed to load PDF: {}", e))?;
+    let pages = document.pages();
+    let page_count = u32::from(pages.len());
+    if page > page_count {
+        return Err(format!("Page {} is past the end of the {}-page document", page, page_count).into());
+    }
+    let pdf_page = pages
+        .get((page - 1) as u16)
+        .map_err(|e| format!("Failed to read page {}: {}", page, e))?;
+
+    let config = PdfRenderConfig::new().scale_page_by_factor(dpi / 72.0);
+    let bitmap = pdf_page
+        .render_with_config(&config)
+        .map_err(|e| format!("Failed to render page {}: {}", page, e))?;
+    encode_png(bitmap.width() as u32, bitmap.height() as u32, &bitmap.as_rgba_bytes())
+}
+
+/// PDFium from next to the executable, where the app bundles it, or else from
+/// the system's library path.
+fn bind() -> Result<Box<dyn PdfiumLibraryBindings>> {
+    let bundled = std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(PathBuf::from))
+        .map(|dir| Pdfium::pdfium_platform_library_name_at_path(&dir));
+    if let Some(bindings) = bundled.and_then(|path| Pdfium::bind_to_library(path).ok()) {
+        return Ok(bindings);
+    }
+    Ok(Pdfium::bind_to_system_library().map_err(|e| {
+        log::warn!("Failed to load PDFium: {}", e);
+        format!(
+            "Page previews need the PDFium library ({}), which was not found",
+            Pdfium::pdfium_platform_library_name().to_string_lossy()
+        )
+    })?)
+}
+
+fn encode_png(width: u32, height: u32, rgba: &[u8]) -> Result<Vec<u8>> {
+    let png_error = |e: png::EncodingError| format!("Failed to encode preview: {}", e);
+    let mut png = Vec::new();
+    let mut encoder = png::Encoder::new(&mut png, width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().map_err(png_error)?;
+    writer.write_image_data(rgba).map_err(png_error)?;
+    writer.finish().map_err(png_error)?;
+    Ok(png)
+}
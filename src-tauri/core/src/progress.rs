@@ -0,0 +1,87 @@
+//! Progress reports and cancellation for signing and verifying large
+//! documents, so a front-end can show how far along it is and stop it.
+
+use crate::error::{Result, SigillumError};
+use serde::Serialize;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Error an operation fails with once its token has been cancelled.
+pub const CANCELLED: &str = "Operation cancelled";
+
+/// What an operation is busy with: `done` of `total` pages or bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(tag = "stage", rename_all = "snake_case")]
+pub enum Progress {
+    /// Checking the document text against the content policy, in pages.
+    CheckingText { done: u64, total: u64 },
+    /// Drawing the watermark, in pages.
+    Watermarking { done: u64, total: u64 },
+    /// Hashing the part of the document a signature covers, in bytes.
+    Hashing { done: u64, total: u64 },
+    /// Waiting for a key on a token to sign, which may need a touch or PIN
+    /// on the device first.
+    AwaitingToken,
+}
+
+/// Stops an operation from another thread. Clones share the same flag.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Where an operation reports its progress and learns whether to stop. The
+/// default reports nowhere and is never cancelled.
+#[derive(Clone, Default)]
+pub struct Monitor {
+    on_progress: Option<Arc<dyn Fn(Progress) + Send + Sync>>,
+    token: CancellationToken,
+}
+
+impl Monitor {
+    pub fn new(on_progress: impl Fn(Progress) + Send + Sync + 'static, token: CancellationToken) -> Self {
+        Monitor {
+            on_progress: Some(Arc::new(on_progress)),
+            token,
+        }
+    }
+
+    /// Fails with [`CANCELLED`] once the token has been cancelled.
+    pub fn check(&self) -> Result<()> {
+        if self.token.is_cancelled() {
+            return Err(SigillumError::Cancelled(CANCELLED.to_string()));
+        }
+        Ok(())
+    }
+
+    /// Passes `progress` on, unless the operation has been cancelled.
+    pub fn report(&self, progress: Progress) -> Result<()> {
+        self.check()?;
+        if let Some(on_progress) = &self.on_progress {
+            on_progress(progress);
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Monitor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Monitor")
+            .field("reports", &self.on_progress.is_some())
+            .field("token", &self.token)
+            .finish()
+    }
+}
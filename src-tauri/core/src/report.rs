@@ -0,0 +1,364 @@
+//! Verification reports to keep with a case file: what was checked, when, and
+//! what came out, as JSON or as a printable PDF.
+
+use crate::api::{ForeignSignatureReport, Integrity, PgpSignatureReport, SignatureReport, SignatureStatus, VerifyReport};
+use crate::error::Result;
+use crate::text_metrics::{self, StandardFont};
+use crate::tsa::TimestampInfo;
+use chrono::Utc;
+use lopdf::{Dictionary, Document, Object, Stream};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+
+const REPORT_FONT: StandardFont = StandardFont::Helvetica;
+const PAGE_WIDTH: f32 = 612.0;
+const PAGE_HEIGHT: f32 = 792.0;
+const PAGE_MARGIN: f32 = 56.0;
+const TITLE_SIZE: f32 = 16.0;
+const HEADING_SIZE: f32 = 12.0;
+const BODY_SIZE: f32 = 9.0;
+/// Line height as a multiple of the font size.
+const LINE_SPACING: f32 = 1.4;
+
+/// What a report is written as.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReportFormat {
+    #[default]
+    Json,
+    Pdf,
+}
+
+impl ReportFormat {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ReportFormat::Json => "json",
+            ReportFormat::Pdf => "pdf",
+        }
+    }
+
+    /// PDF for a `.pdf` file, JSON for anything else.
+    pub fn for_path(path: &Path) -> ReportFormat {
+        match path.extension() {
+            Some(extension) if extension.eq_ignore_ascii_case("pdf") => ReportFormat::Pdf,
+            _ => ReportFormat::Json,
+        }
+    }
+}
+
+impl fmt::Display for ReportFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for ReportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "json" => Ok(ReportFormat::Json),
+            "pdf" => Ok(ReportFormat::Pdf),
+            _ => Err(format!("Unknown report format '{}' (expected json or pdf)", s)),
+        }
+    }
+}
+
+/// The outcome of verifying one document, with enough about the document to
+/// tell later which file it was.
+#[derive(Debug, Clone, Serialize)]
+pub struct VerificationReport {
+    /// Name of the checked file, without its directory.
+    pub file_name: String,
+    /// SHA-256 of the file as it was checked, as hex.
+    pub file_sha256: String,
+    /// See `VerifyReport::canonical_hash`.
+    pub canonical_hash: String,
+    pub hash_matches: Option<bool>,
+    pub status: SignatureStatus,
+    pub verified_at: String,
+    pub signatures: Vec<SignatureReport>,
+    pub foreign_signatures: Vec<ForeignSignatureReport>,
+    pub pgp_signatures: Vec<PgpSignatureReport>,
+}
+
+impl VerificationReport {
+    /// Reports on the document `pdf`; `file_name` may be a full path.
+    pub fn new(file_name: &str, pdf: &[u8], report: VerifyReport) -> VerificationReport {
+        VerificationReport::with_hash(file_name, hex::encode(Sha256::digest(pdf)), report)
+    }
+
+    /// Like `new`, hashing the document at `path` on disk.
+    pub fn from_file(path: &Path, report: VerifyReport) -> Result<VerificationReport> {
+        let mut file = fs::File::open(path).map_err(|e| format!("Read error: {}", e))?;
+        let mut hasher = Sha256::new();
+        io::copy(&mut file, &mut hasher).map_err(|e| format!("Read error: {}", e))?;
+        Ok(VerificationReport::with_hash(
+            &path.to_string_lossy(),
+            hex::encode(hasher.finalize()),
+            report,
+        ))
+    }
+
+    fn with_hash(file_name: &str, file_sha256: String, report: VerifyReport) -> VerificationReport {
+        VerificationReport {
+            file_name: Path::new(file_name)
+                .file_name()
+                .map_or_else(|| file_name.to_string(), |name| name.to_string_lossy().into_owned()),
+            file_sha256,
+            status: report.status(),
+            canonical_hash: report.canonical_hash,
+            hash_matches: report.hash_matches,
+            verified_at: Utc::now().format("%Y-%m-%d %H:%M:%S UTC").to_string(),
+            signatures: report.signatures,
+            foreign_signatures: report.foreign_signatures,
+            pgp_signatures: report.pgp_signatures,
+        }
+    }
+
+    pub fn render(&self, format: ReportFormat) -> Result<Vec<u8>> {
+        match format {
+            ReportFormat::Json => Ok(serde_json::to_vec_pretty(self).map_err(|e| format!("JSON error: {}", e))?),
+            ReportFormat::Pdf => self.to_pdf(),
+        }
+    }
+
+    /// The report as text, one `(font size, line)` pair per line.
+    fn lines(&self) -> Vec<(f32, String)> {
+        let mut lines = vec![
+            (TITLE_SIZE, "Sigillum verification report".to_string()),
+            (BODY_SIZE, String::new()),
+            (BODY_SIZE, format!("Result: {}", status_text(self.status))),
+            (BODY_SIZE, format!("File: {}", self.file_name)),
+            (BODY_SIZE, format!("File SHA-256: {}", self.file_sha256)),
+            (BODY_SIZE, format!("Canonical hash: {}", self.canonical_hash)),
+        ];
+        match self.hash_matches {
+            Some(true) => lines.push((BODY_SIZE, "Canonical hash matches the expected hash".to_string())),
+            Some(false) => lines.push((BODY_SIZE, "Canonical hash does NOT match the expected hash".to_string())),
+            None => {}
+        }
+        lines.push((BODY_SIZE, format!("Verified at: {}", self.verified_at)));
+
+        let count = self.signatures.len();
+        for (index, report) in self.signatures.iter().enumerate() {
+            let info = &report.signature_info;
+            lines.push((BODY_SIZE, String::new()));
+            lines.push((HEADING_SIZE, format!("Signature {} of {}", index + 1, count)));
+            lines.push((BODY_SIZE, format!("Status: {}", status_text(report.status))));
+            if let Some(reason) = &report.status_reason {
+                lines.push((BODY_SIZE, format!("Reason: {}", reason)));
+            }
+            let integrity = match report.integrity {
+                Some(Integrity::Intact) => "Document is intact",
+                Some(Integrity::ModifiedAfterSigning) => "Document was modified after signing",
+                None => "Cannot be checked (signed by an older version)",
+            };
+            lines.push((BODY_SIZE, format!("Integrity: {}", integrity)));
+            lines.push((BODY_SIZE, format!("Signer: {}", info.signer_name)));
+            lines.push((BODY_SIZE, format!("Signed at: {}", info.timestamp)));
+            if let Some(check) = &info.clock_check {
+                let network = format!("Network time: {} from {} (clock off by {} ms)", check.network_time, check.server, check.skew_ms);
+                lines.push((BODY_SIZE, network));
+            }
+            if !info.extra.is_empty() {
+                lines.push((BODY_SIZE, format!("Extra: {}", info.extra)));
+            }
+            lines.push((BODY_SIZE, format!("Digest: {}", info.signature)));
+            if let Some(algorithm) = info.algorithm {
+                lines.push((BODY_SIZE, format!("Algorithm: {}", algorithm)));
+            }
+            if let Some(fingerprint) = &report.signer_fingerprint {
+                let trust = if report.key_trusted { "trusted" } else { "not trusted" };
+                lines.push((BODY_SIZE, format!("Signer key ({}): {}", trust, fingerprint)));
+            }
+            if let Some(trusted) = &report.trusted_signer {
+                lines.push((BODY_SIZE, format!("Signed with the trusted key '{}'", trusted)));
+            }
+            if let Some(timestamp) = &report.timestamp {
+                lines.push((BODY_SIZE, format!("Timestamped {} by {}{}", timestamp.time, timestamp.authority, untrusted(timestamp))));
+            }
+            if let Some(certificate) = &report.certificate {
+                lines.push((BODY_SIZE, format!("Certificate: {}", certificate.subject)));
+            }
+        }
+
+        for report in &self.foreign_signatures {
+            lines.push((BODY_SIZE, String::new()));
+            lines.push((HEADING_SIZE, format!("Signature field '{}'", report.field_name)));
+            lines.push((BODY_SIZE, format!("Status: {}", status_text(report.status))));
+            if let Some(reason) = &report.status_reason {
+                lines.push((BODY_SIZE, format!("Reason: {}", reason)));
+            }
+            let integrity = match report.integrity {
+                Some(Integrity::Intact) => "Document is intact",
+                Some(Integrity::ModifiedAfterSigning) => "Document was modified after signing",
+                None => "Cannot be checked",
+            };
+            lines.push((BODY_SIZE, format!("Integrity: {}", integrity)));
+            let coverage = if report.covers_whole_document { "whole document" } else { "part of the document" };
+            lines.push((BODY_SIZE, format!("Covers: {} {:?}", coverage, report.byte_range)));
+            if let Some(signer) = &report.signer_name {
+                lines.push((BODY_SIZE, format!("Signer: {}", signer)));
+            }
+            if let Some(time) = &report.signing_time {
+                lines.push((BODY_SIZE, format!("Signed at: {}", time)));
+            }
+            if let Some(reason) = &report.reason {
+                lines.push((BODY_SIZE, format!("Signing reason: {}", reason)));
+            }
+            if let Some(location) = &report.location {
+                lines.push((BODY_SIZE, format!("Location: {}", location)));
+            }
+            if let Some(sub_filter) = &report.sub_filter {
+                lines.push((BODY_SIZE, format!("Format: {}", sub_filter)));
+            }
+            if let Some(bits) = report.key_bits {
+                lines.push((BODY_SIZE, format!("Key size: {} bits", bits)));
+            }
+            if let Some(trusted) = &report.trusted_signer {
+                lines.push((BODY_SIZE, format!("Signed with the trusted key '{}'", trusted)));
+            }
+            if let Some(timestamp) = &report.timestamp {
+                lines.push((BODY_SIZE, format!("Timestamped {} by {}{}", timestamp.time, timestamp.authority, untrusted(timestamp))));
+            }
+            if let Some(certificate) = &report.certificate {
+                lines.push((BODY_SIZE, format!("Certificate: {} (issued by {})", certificate.subject, certificate.issuer)));
+            }
+        }
+
+        for report in &self.pgp_signatures {
+            lines.push((BODY_SIZE, String::new()));
+            lines.push((HEADING_SIZE, format!("OpenPGP signature '{}'", report.file_name)));
+            lines.push((BODY_SIZE, format!("Status: {}", status_text(report.status))));
+            if let Some(reason) = &report.status_reason {
+                lines.push((BODY_SIZE, format!("Reason: {}", reason)));
+            }
+            let integrity = match report.integrity {
+                Some(Integrity::Intact) => "Document is intact",
+                Some(Integrity::ModifiedAfterSigning) => "Document was modified after signing",
+                None => "Cannot be checked",
+            };
+            lines.push((BODY_SIZE, format!("Integrity: {}", integrity)));
+            lines.push((BODY_SIZE, format!("Covers: the first {} bytes", report.signed_length)));
+            if let Some(signer) = &report.signer {
+                lines.push((BODY_SIZE, format!("Signer: {}", signer)));
+            }
+            if let Some(time) = &report.signing_time {
+                lines.push((BODY_SIZE, format!("Signed at: {}", time)));
+            }
+            if let Some(fingerprint) = &report.fingerprint {
+                lines.push((BODY_SIZE, format!("Signer key: {}", fingerprint)));
+            }
+        }
+        lines
+    }
+
+    /// Lays the report out on letter-size pages, continuing on a new page
+    /// when one is full.
+    fn to_pdf(&self) -> Result<Vec<u8>> {
+        let max_width = PAGE_WIDTH - 2.0 * PAGE_MARGIN;
+        let mut pages = vec![String::new()];
+        let mut y = PAGE_HEIGHT - PAGE_MARGIN;
+        for (size, line) in self.lines() {
+            for line in text_metrics::wrap_text(&line, &REPORT_FONT, size, max_width) {
+                y -= size * LINE_SPACING;
+                if y < PAGE_MARGIN {
+                    pages.push(String::new());
+                    y = PAGE_HEIGHT - PAGE_MARGIN - size * LINE_SPACING;
+                }
+                if !line.is_empty() {
+                    let content = pages.last_mut().expect("there is always a page");
+                    content.push_str(&format!("BT /F1 {} Tf {} {:.2} Td {} Tj ET\n", size, PAGE_MARGIN, y, literal(&line)));
+                }
+            }
+        }
+
+        let mut doc = Document::with_version("1.7");
+        let pages_id = doc.new_object_id();
+        let font_id = doc.add_object(Dictionary::from_iter(vec![
+            ("Type", Object::Name(b"Font".to_vec())),
+            ("Subtype", Object::Name(b"Type1".to_vec())),
+            ("BaseFont", Object::Name(REPORT_FONT.base_font().as_bytes().to_vec())),
+            ("Encoding", Object::Name(b"WinAnsiEncoding".to_vec())),
+        ]));
+        let resources = Dictionary::from_iter(vec![(
+            "Font",
+            Object::Dictionary(Dictionary::from_iter(vec![("F1", Object::Reference(font_id))])),
+        )]);
+        let mut kids = Vec::with_capacity(pages.len());
+        for content in pages {
+            let content_id = doc.add_object(Stream::new(Dictionary::new(), content.into_bytes()));
+            kids.push(Object::Reference(doc.add_object(Dictionary::from_iter(vec![
+                ("Type", Object::Name(b"Page".to_vec())),
+                ("Parent", Object::Reference(pages_id)),
+                ("Contents", Object::Reference(content_id)),
+            ]))));
+        }
+        let page_count = kids.len() as i64;
+        doc.objects.insert(
+            pages_id,
+            Object::Dictionary(Dictionary::from_iter(vec![
+                ("Type", Object::Name(b"Pages".to_vec())),
+                ("Kids", Object::Array(kids)),
+                ("Count", Object::Integer(page_count)),
+                ("Resources", Object::Dictionary(resources)),
+                (
+                    "MediaBox",
+                    Object::Array(vec![0.into(), 0.into(), PAGE_WIDTH.into(), PAGE_HEIGHT.into()]),
+                ),
+            ])),
+        );
+        let catalog_id = doc.add_object(Dictionary::from_iter(vec![
+            ("Type", Object::Name(b"Catalog".to_vec())),
+            ("Pages", Object::Reference(pages_id)),
+        ]));
+        doc.trailer.set("Root", Object::Reference(catalog_id));
+        doc.compress();
+
+        let mut pdf = Vec::new();
+        doc.save_to(&mut pdf).map_err(|e| format!("Save error: {}", e))?;
+        Ok(pdf)
+    }
+}
+
+fn status_text(status: SignatureStatus) -> &'static str {
+    match status {
+        SignatureStatus::Valid => "Valid",
+        SignatureStatus::Invalid => "INVALID",
+        SignatureStatus::Unverified => "Could not be verified",
+        SignatureStatus::Unsigned => "Not signed",
+    }
+}
+
+/// What follows a timestamp whose authority no trusted root vouches for.
+fn untrusted(timestamp: &TimestampInfo) -> &'static str {
+    if timestamp.trusted {
+        ""
+    } else {
+        " (authority not trusted)"
+    }
+}
+
+/// `line` as an escaped WinAnsi string literal; characters the standard fonts
+/// cannot show become `?`.
+fn literal(line: &str) -> String {
+    let mut literal = String::from("(");
+    for byte in line.chars().map(|c| text_metrics::win_ansi_code(c).unwrap_or(b'?')) {
+        match byte {
+            b'(' | b')' | b'\\' => {
+                literal.push('\\');
+                literal.push(byte as char);
+            }
+            b' '..=b'~' => literal.push(byte as char),
+            _ => literal.push_str(&format!("\\{:03o}", byte)),
+        }
+    }
+    literal.push(')');
+    literal
+}
@@ -0,0 +1,97 @@
+use crate::api::ExpiredKeyPolicy;
+use crate::appearance::StampTimeZone;
+use crate::error::Result;
+use crate::key_store::{KeyStorage, DEFAULT_PROFILE};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+const SETTINGS_FILE: &str = "settings.json";
+/// Minutes a key unlocked in the app stays unlocked unless set otherwise.
+pub const DEFAULT_KEY_UNLOCK_MINUTES: u32 = 15;
+
+/// `settings.json` in the app data directory, shared by the GUI and the CLI.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Settings {
+    #[serde(default)]
+    pub(crate) key_storage: KeyStorage,
+    #[serde(default = "default_profile")]
+    pub(crate) active_profile: String,
+    /// Profile names, kept here because the keychain cannot be listed.
+    #[serde(default)]
+    pub(crate) profiles: Vec<String>,
+    /// RFC 3161 timestamp authority used when signing, if any.
+    #[serde(default)]
+    pub tsa_url: Option<String>,
+    /// PEM root certificates a timestamp authority must chain to for its
+    /// timestamps to be trusted when verifying.
+    #[serde(default)]
+    pub tsa_roots: Vec<String>,
+    /// NTP servers the local clock is checked against when signing.
+    #[serde(default)]
+    pub ntp_servers: Vec<String>,
+    /// Seconds the local clock may be off before signing warns about it.
+    #[serde(default)]
+    pub max_clock_skew: Option<u64>,
+    /// strftime format of the signing time in the watermark.
+    #[serde(default)]
+    pub timestamp_format: Option<String>,
+    /// Time zone of the signing time in the watermark.
+    #[serde(default)]
+    pub time_zone: Option<StampTimeZone>,
+    /// Whether signing with an expired key fails or only warns.
+    #[serde(default)]
+    pub expired_key_policy: ExpiredKeyPolicy,
+    /// Minutes a key unlocked in the app stays unlocked; `None` for
+    /// `DEFAULT_KEY_UNLOCK_MINUTES`.
+    #[serde(default)]
+    pub key_unlock_minutes: Option<u32>,
+    /// Whether signing with a passphrase-protected key asks for Touch ID or
+    /// Windows Hello, each time, instead of the passphrase.
+    #[serde(default)]
+    pub biometric_unlock: bool,
+    /// Whether verifying fetches the key a signature says its signer
+    /// publishes, to check it is the one that signed.
+    #[serde(default)]
+    pub fetch_signer_keys: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            key_storage: KeyStorage::default(),
+            active_profile: default_profile(),
+            profiles: Vec::new(),
+            tsa_url: None,
+            tsa_roots: Vec::new(),
+            ntp_servers: Vec::new(),
+            max_clock_skew: None,
+            timestamp_format: None,
+            time_zone: None,
+            expired_key_policy: ExpiredKeyPolicy::default(),
+            key_unlock_minutes: None,
+            biometric_unlock: false,
+            fetch_signer_keys: false,
+        }
+    }
+}
+
+pub(crate) fn default_profile() -> String {
+    DEFAULT_PROFILE.to_string()
+}
+
+impl Settings {
+    pub fn load(dir: &Path) -> Result<Settings> {
+        let path = dir.join(SETTINGS_FILE);
+        if !path.exists() {
+            return Ok(Settings::default());
+        }
+        let json = fs::read_to_string(&path).map_err(|e| format!("Read error: {}", e))?;
+        Ok(serde_json::from_str(&json).map_err(|e| format!("Invalid settings: {}", e))?)
+    }
+
+    pub fn save(&self, dir: &Path) -> Result<()> {
+        let json = serde_json::to_string_pretty(self).map_err(|e| format!("JSON error: {}", e))?;
+        Ok(fs::write(dir.join(SETTINGS_FILE), json).map_err(|e| format!("Write error: {}", e))?)
+    }
+}
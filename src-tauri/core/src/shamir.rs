@@ -0,0 +1,215 @@
+//! Shamir secret sharing of a private key, for backing it up without trusting
+//! any one place with it: the key is split into share files, any `threshold`
+//! of which rebuild it, while fewer say nothing about it at all.
+//!
+//! Each byte of the PKCS#8 DER key is the constant term of its own random
+//! polynomial over GF(2^8) of degree `threshold - 1`; share `i` holds every
+//! polynomial's value at `x = i`.
+
+use crate::error::Result;
+use crate::keys::PrivateKey;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+use zeroize::Zeroizing;
+
+const SHARE_FORMAT: &str = "sigillum-key-share";
+const SHARE_VERSION: u32 = 1;
+
+/// How a key is split: `threshold` of the `shares` rebuild it. Written as
+/// "3of5".
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ShamirScheme {
+    pub threshold: u8,
+    pub shares: u8,
+}
+
+impl FromStr for ShamirScheme {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let invalid = || format!("Invalid share scheme '{}': expected e.g. 3of5", s);
+        let lower = s.trim().to_ascii_lowercase();
+        let (threshold, shares) = lower.split_once("of").ok_or_else(invalid)?;
+        let threshold: u8 = threshold.trim().parse().map_err(|_| invalid())?;
+        let shares: u8 = shares.trim().parse().map_err(|_| invalid())?;
+        if threshold < 2 {
+            return Err("At least two shares must be needed to rebuild the key".to_string());
+        }
+        if threshold > shares {
+            return Err(format!("Cannot need {} shares when only {} are made", threshold, shares));
+        }
+        Ok(ShamirScheme { threshold, shares })
+    }
+}
+
+impl fmt::Display for ShamirScheme {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}of{}", self.threshold, self.shares)
+    }
+}
+
+/// One share of a private key, as saved to its own file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeyShare {
+    format: String,
+    version: u32,
+    /// Random ID common to the shares of one split, so shares of different
+    /// splits of the same key are not mixed up.
+    pub split_id: String,
+    /// How many shares rebuild the key.
+    pub threshold: u8,
+    /// How many shares were made.
+    pub shares: u8,
+    /// Which share this is, from 1 to `shares`.
+    pub index: u8,
+    /// Fingerprint of the public key, to tell whose key the share belongs to
+    /// and to check the rebuilt key.
+    pub fingerprint: String,
+    /// Base64 share of the PKCS#8 DER private key.
+    pub data: String,
+}
+
+impl KeyShare {
+    pub fn from_json(data: &[u8]) -> Result<KeyShare> {
+        let share: KeyShare = serde_json::from_slice(data).map_err(|_| "Not a Sigillum key share".to_string())?;
+        if share.format != SHARE_FORMAT {
+            return Err("Not a Sigillum key share".into());
+        }
+        if share.version > SHARE_VERSION {
+            return Err(format!("Key share version {} is newer than this version of Sigillum supports", share.version).into());
+        }
+        if share.index == 0 || share.index > share.shares || share.threshold < 2 || share.threshold > share.shares {
+            return Err(format!("Corrupt key share: share {} of {}, {} needed", share.index, share.shares, share.threshold).into());
+        }
+        Ok(share)
+    }
+
+    pub fn to_json(&self) -> Result<Vec<u8>> {
+        Ok(serde_json::to_vec_pretty(self).map_err(|e| format!("JSON error: {}", e))?)
+    }
+
+    /// File name for the share, e.g. "key-share-2-of-5.json".
+    pub fn file_name(&self) -> String {
+        format!("key-share-{}-of-{}.json", self.index, self.shares)
+    }
+}
+
+/// Splits `private_key` into `scheme.shares` shares, any `scheme.threshold`
+/// of which rebuild it.
+pub fn split(private_key: &PrivateKey, scheme: ShamirScheme) -> Result<Vec<KeyShare>> {
+    let secret = private_key.to_pkcs8_der()?;
+    let fingerprint = private_key.public_key().fingerprint()?;
+    let split_id = hex::encode(rand::random::<[u8; 8]>());
+
+    let mut values = vec![Vec::with_capacity(secret.len()); scheme.shares as usize];
+    let mut coefficients = Zeroizing::new(vec![0u8; scheme.threshold as usize]);
+    for &byte in secret.iter() {
+        coefficients[0] = byte;
+        rand::thread_rng().fill_bytes(&mut coefficients[1..]);
+        for (x, value) in (1..=scheme.shares).zip(values.iter_mut()) {
+            // Horner's rule, from the highest coefficient down.
+            value.push(coefficients.iter().rev().fold(0, |y, &c| gf_mul(y, x) ^ c));
+        }
+    }
+
+    Ok((1..=scheme.shares)
+        .zip(values)
+        .map(|(index, value)| KeyShare {
+            format: SHARE_FORMAT.to_string(),
+            version: SHARE_VERSION,
+            split_id: split_id.clone(),
+            threshold: scheme.threshold,
+            shares: scheme.shares,
+            index,
+            fingerprint: fingerprint.clone(),
+            data: BASE64.encode(value),
+        })
+        .collect())
+}
+
+/// Rebuilds the private key from at least `threshold` shares of one split.
+pub fn combine(shares: &[KeyShare]) -> Result<PrivateKey> {
+    let first = shares.first().ok_or("No key shares given")?;
+    if shares.iter().any(|share| share.split_id != first.split_id || share.fingerprint != first.fingerprint) {
+        return Err("The key shares do not all come from the same split".into());
+    }
+    let mut points: Vec<(u8, Vec<u8>)> = Vec::new();
+    for share in shares {
+        let value = BASE64.decode(&share.data).map_err(|e| format!("Corrupt key share {}: {}", share.index, e))?;
+        match points.iter().find(|(x, _)| *x == share.index) {
+            // The same file picked twice.
+            Some((_, known)) if *known == value => continue,
+            Some(_) => return Err(format!("Two different key shares claim to be share {}", share.index).into()),
+            None => points.push((share.index, value)),
+        }
+    }
+    if points.len() < first.threshold as usize {
+        return Err(format!(
+            "{} different key shares are needed to rebuild the key, but only {} were given",
+            first.threshold,
+            points.len()
+        ).into());
+    }
+    points.truncate(first.threshold as usize);
+    let length = points[0].1.len();
+    if points.iter().any(|(_, value)| value.len() != length) {
+        return Err("Corrupt key share: the shares differ in length".into());
+    }
+
+    // Lagrange interpolation at x = 0; subtraction in GF(2^8) is XOR.
+    let weights: Vec<u8> = points
+        .iter()
+        .map(|(xj, _)| {
+            points
+                .iter()
+                .filter(|(xm, _)| xm != xj)
+                .fold(1, |weight, (xm, _)| gf_mul(weight, gf_mul(*xm, gf_inverse(xm ^ xj))))
+        })
+        .collect();
+    let secret: Zeroizing<Vec<u8>> = Zeroizing::new(
+        (0..length)
+            .map(|i| points.iter().zip(&weights).fold(0, |byte, ((_, value), &weight)| byte ^ gf_mul(value[i], weight)))
+            .collect(),
+    );
+
+    let private_key = PrivateKey::from_pkcs8_der(&secret).map_err(|_| "The key shares do not rebuild a valid key".to_string())?;
+    if private_key.public_key().fingerprint()? != first.fingerprint {
+        return Err("The key shares do not rebuild the key they were made from".into());
+    }
+    Ok(private_key)
+}
+
+/// Multiplication in GF(2^8) modulo the AES polynomial x^8 + x^4 + x^3 + x + 1.
+fn gf_mul(mut a: u8, mut b: u8) -> u8 {
+    let mut product = 0;
+    while b != 0 {
+        if b & 1 != 0 {
+            product ^= a;
+        }
+        let carry = a & 0x80 != 0;
+        a <<= 1;
+        if carry {
+            a ^= 0x1b;
+        }
+        b >>= 1;
+    }
+    product
+}
+
+/// Multiplicative inverse in GF(2^8): a^254, since a^255 = 1.
+fn gf_inverse(a: u8) -> u8 {
+    let mut result = 1;
+    let mut power = a;
+    let mut exponent = 254u8;
+    while exponent != 0 {
+        if exponent & 1 != 0 {
+            result = gf_mul(result, power);
+        }
+        power = gf_mul(power, power);
+        exponent >>= 1;
+    }
+    result
+}
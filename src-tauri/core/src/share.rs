@@ -0,0 +1,139 @@
+//! Handing the public key to the people who verify: as a PEM or DER file, or
+//! as a QR code to scan from the screen.
+
+use crate::error::Result;
+use crate::keys::PublicKey;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Blank modules around the code, as the QR spec asks for.
+const QUIET_ZONE: usize = 4;
+/// Pixels per module, enough for a phone to read it off a monitor.
+const MODULE_PIXELS: usize = 8;
+
+/// How a public key file is encoded.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PublicKeyFormat {
+    #[default]
+    Pem,
+    /// Binary `SubjectPublicKeyInfo`.
+    Der,
+}
+
+impl PublicKeyFormat {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            PublicKeyFormat::Pem => "pem",
+            PublicKeyFormat::Der => "der",
+        }
+    }
+
+    /// DER for a `.der` file, PEM for anything else.
+    pub fn for_path(path: &Path) -> PublicKeyFormat {
+        match path.extension() {
+            Some(extension) if extension.eq_ignore_ascii_case("der") => PublicKeyFormat::Der,
+            _ => PublicKeyFormat::Pem,
+        }
+    }
+}
+
+impl fmt::Display for PublicKeyFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for PublicKeyFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "pem" => Ok(PublicKeyFormat::Pem),
+            "der" => Ok(PublicKeyFormat::Der),
+            _ => Err(format!("Unknown public key format '{}' (expected pem or der)", s)),
+        }
+    }
+}
+
+/// What a key's QR code holds.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QrContent {
+    /// The whole key as PEM, so it can be used straight from the scan.
+    #[default]
+    PublicKey,
+    /// Only its SHA-256 fingerprint, for checking a key received some other
+    /// way.
+    Fingerprint,
+}
+
+/// `public_key_pem` encoded as `format`.
+pub fn encode_public_key(public_key_pem: &str, format: PublicKeyFormat) -> Result<Vec<u8>> {
+    let key = PublicKey::from_public_key_pem(public_key_pem)?;
+    match format {
+        PublicKeyFormat::Pem => Ok(key.to_public_key_pem()?.into_bytes()),
+        PublicKeyFormat::Der => key.to_public_key_der(),
+    }
+}
+
+pub fn save_public_key(public_key_pem: &str, path: &Path, format: PublicKeyFormat) -> Result<()> {
+    let encoded = encode_public_key(public_key_pem, format)?;
+    Ok(fs::write(path, encoded).map_err(|e| format!("Failed to save public key: {}", e))?)
+}
+
+/// A QR code of `public_key_pem` or its fingerprint, as a PNG. With
+/// `fetch_url`, where the key can be downloaded, the fingerprint code holds
+/// that URL with the fingerprint as its fragment ("...#sha256=<hex>"), so a
+/// phone camera opens the key and the fingerprint to check it against.
+pub fn public_key_qr(public_key_pem: &str, content: QrContent, fetch_url: Option<&str>) -> Result<Vec<u8>> {
+    let key = PublicKey::from_public_key_pem(public_key_pem)?;
+    let fetch_url = fetch_url.map(str::trim).filter(|url| !url.is_empty());
+    let payload = match (content, fetch_url) {
+        (QrContent::PublicKey, None) => key.to_public_key_pem()?,
+        (QrContent::PublicKey, Some(_)) => return Err("A fetch URL goes with the fingerprint, not the whole key".into()),
+        (QrContent::Fingerprint, None) => format!("SHA256:{}", key.fingerprint()?),
+        (QrContent::Fingerprint, Some(url)) => {
+            if !url.starts_with("https://") && !url.starts_with("http://") {
+                return Err("The fetch URL must start with https:// or http://".into());
+            }
+            if url.contains('#') {
+                return Err("The fetch URL must not have a fragment (#...)".into());
+            }
+            format!("{}#sha256={}", url, key.fingerprint()?.replace(':', "").to_ascii_lowercase())
+        }
+    };
+    let code = qrcode::QrCode::with_error_correction_level(payload.as_bytes(), qrcode::EcLevel::L)
+        .map_err(|e| format!("Failed to make QR code: {}", e))?;
+    let colors = code.to_colors();
+    let width = code.width();
+    let size = (width + 2 * QUIET_ZONE) * MODULE_PIXELS;
+
+    // 8-bit grayscale, white background.
+    let mut pixels = vec![0xFF; size * size];
+    for (index, color) in colors.iter().enumerate() {
+        if *color != qrcode::Color::Dark {
+            continue;
+        }
+        let (x, y) = ((index % width + QUIET_ZONE) * MODULE_PIXELS, (index / width + QUIET_ZONE) * MODULE_PIXELS);
+        for row in y..y + MODULE_PIXELS {
+            pixels[row * size + x..row * size + x + MODULE_PIXELS].fill(0);
+        }
+    }
+    encode_png(size as u32, &pixels)
+}
+
+fn encode_png(size: u32, gray: &[u8]) -> Result<Vec<u8>> {
+    let png_error = |e: png::EncodingError| format!("Failed to encode QR code: {}", e);
+    let mut png = Vec::new();
+    let mut encoder = png::Encoder::new(&mut png, size, size);
+    encoder.set_color(png::ColorType::Grayscale);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().map_err(png_error)?;
+    writer.write_image_data(gray).map_err(png_error)?;
+    writer.finish().map_err(png_error)?;
+    Ok(png)
+}
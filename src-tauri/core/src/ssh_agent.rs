@@ -0,0 +1,214 @@
+//! Keys held by ssh-agent, where many developers keep their identity. The
+//! agent makes each signature, so the key never leaves it; a key profile only
+//! records the key's fingerprint.
+//!
+//! Only Ed25519 keys can sign here. For RSA and ECDSA keys the agent hashes
+//! what it is given itself, while Sigillum signs a digest it has already made.
+
+use crate::error::Result;
+use serde::{Deserialize, Serialize};
+use ssh_key::public::KeyData;
+use ssh_key::HashAlg;
+use std::io::{Read, Write};
+
+const SSH_AGENT_FAILURE: u8 = 5;
+const SSH_AGENTC_REQUEST_IDENTITIES: u8 = 11;
+const SSH_AGENT_IDENTITIES_ANSWER: u8 = 12;
+const SSH_AGENTC_SIGN_REQUEST: u8 = 13;
+const SSH_AGENT_SIGN_RESPONSE: u8 = 14;
+/// Largest reply read from the agent; a list of a few hundred keys fits.
+const MAX_REPLY: usize = 256 * 1024;
+
+/// A key the agent holds, as `identities` lists it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentIdentity {
+    /// SHA-256 fingerprint as `ssh-keygen -l` prints it, e.g. "SHA256:...".
+    pub fingerprint: String,
+    pub comment: String,
+    /// The key type, such as "ssh-ed25519".
+    pub key_type: String,
+    /// Whether the key can sign here, which only Ed25519 keys can.
+    pub usable: bool,
+}
+
+/// An Ed25519 key in the agent, found by its fingerprint.
+pub struct SshAgentKey {
+    blob: Vec<u8>,
+    public_key: ed25519_dalek::VerifyingKey,
+    fingerprint: String,
+}
+
+/// The keys the agent holds now.
+pub fn identities() -> Result<Vec<AgentIdentity>> {
+    Ok(agent_keys()?
+        .into_iter()
+        .map(|(_, key)| AgentIdentity {
+            fingerprint: key.fingerprint(HashAlg::Sha256).to_string(),
+            comment: key.comment().to_string(),
+            key_type: key.algorithm().to_string(),
+            usable: matches!(key.key_data(), KeyData::Ed25519(_)),
+        })
+        .collect())
+}
+
+impl SshAgentKey {
+    /// The key with `fingerprint` ("SHA256:..."), which the agent must hold.
+    pub fn open(fingerprint: &str) -> Result<SshAgentKey> {
+        let (blob, key) = agent_keys()?
+            .into_iter()
+            .find(|(_, key)| key.fingerprint(HashAlg::Sha256).to_string() == fingerprint)
+            .ok_or_else(|| format!("ssh-agent does not hold the key {}; add it with ssh-add", fingerprint))?;
+        let KeyData::Ed25519(public_key) = key.key_data() else {
+            return Err(format!("Only Ed25519 keys in ssh-agent can sign, not {}", key.algorithm()).into());
+        };
+        let public_key =
+            ed25519_dalek::VerifyingKey::from_bytes(&public_key.0).map_err(|e| format!("Invalid agent key: {}", e))?;
+        Ok(SshAgentKey {
+            blob,
+            public_key,
+            fingerprint: fingerprint.to_string(),
+        })
+    }
+
+    pub fn public_key(&self) -> &ed25519_dalek::VerifyingKey {
+        &self.public_key
+    }
+
+    /// SHA-256 fingerprint of the key as ssh shows it.
+    pub fn fingerprint(&self) -> &str {
+        &self.fingerprint
+    }
+
+    /// Has the agent sign `message`, as an Ed25519 key of our own would.
+    pub fn sign(&self, message: &[u8]) -> Result<ed25519_dalek::Signature> {
+        let mut request = vec![SSH_AGENTC_SIGN_REQUEST];
+        put_string(&mut request, &self.blob);
+        put_string(&mut request, message);
+        request.extend_from_slice(&0u32.to_be_bytes());
+
+        let reply = exchange(&request)?;
+        let mut reader = Reader(&reply);
+        match reader.byte()? {
+            SSH_AGENT_SIGN_RESPONSE => {}
+            SSH_AGENT_FAILURE => return Err("ssh-agent refused to sign; was the key confirmed?".into()),
+            other => return Err(format!("Unexpected reply {} from ssh-agent", other).into()),
+        }
+        let mut signature = Reader(reader.string()?);
+        if signature.string()? != b"ssh-ed25519" {
+            return Err("ssh-agent signed with an unexpected algorithm".into());
+        }
+        let signature = ed25519_dalek::Signature::from_slice(signature.string()?)
+            .map_err(|e| format!("Invalid signature from ssh-agent: {}", e))?;
+        // An agent that signed with some other key must not go unnoticed.
+        self.public_key
+            .verify_strict(message, &signature)
+            .map_err(|_| "ssh-agent signed with a different key".to_string())?;
+        Ok(signature)
+    }
+}
+
+impl rsa::signature::Keypair for SshAgentKey {
+    type VerifyingKey = ed25519_dalek::VerifyingKey;
+
+    fn verifying_key(&self) -> Self::VerifyingKey {
+        self.public_key
+    }
+}
+
+impl rsa::signature::Signer<ed25519_dalek::Signature> for SshAgentKey {
+    fn try_sign(&self, msg: &[u8]) -> std::result::Result<ed25519_dalek::Signature, rsa::signature::Error> {
+        self.sign(msg).map_err(rsa::signature::Error::from_source)
+    }
+}
+
+/// Each key the agent holds, as its wire-format blob and parsed. Keys of
+/// types ssh-key does not know are left out.
+fn agent_keys() -> Result<Vec<(Vec<u8>, ssh_key::PublicKey)>> {
+    let reply = exchange(&[SSH_AGENTC_REQUEST_IDENTITIES])?;
+    let mut reader = Reader(&reply);
+    if reader.byte()? != SSH_AGENT_IDENTITIES_ANSWER {
+        return Err("ssh-agent would not list its keys".into());
+    }
+    let count = reader.u32()?;
+    let mut keys = Vec::new();
+    for _ in 0..count {
+        let blob = reader.string()?;
+        let comment = String::from_utf8_lossy(reader.string()?).into_owned();
+        if let Ok(mut key) = ssh_key::PublicKey::from_bytes(blob) {
+            key.set_comment(comment);
+            keys.push((blob.to_vec(), key));
+        }
+    }
+    Ok(keys)
+}
+
+/// Sends one request to the agent and returns its reply, both without the
+/// length prefix.
+fn exchange(request: &[u8]) -> Result<Vec<u8>> {
+    let mut agent = connect()?;
+    let agent_error = |e: std::io::Error| format!("Failed to talk to ssh-agent: {}", e);
+    let mut message = (request.len() as u32).to_be_bytes().to_vec();
+    message.extend_from_slice(request);
+    agent.write_all(&message).map_err(agent_error)?;
+
+    let mut length = [0u8; 4];
+    agent.read_exact(&mut length).map_err(agent_error)?;
+    let length = u32::from_be_bytes(length) as usize;
+    if length == 0 || length > MAX_REPLY {
+        return Err("Invalid reply from ssh-agent".into());
+    }
+    let mut reply = vec![0u8; length];
+    agent.read_exact(&mut reply).map_err(agent_error)?;
+    Ok(reply)
+}
+
+#[cfg(unix)]
+fn connect() -> Result<std::os::unix::net::UnixStream> {
+    let socket = std::env::var_os("SSH_AUTH_SOCK").ok_or("ssh-agent is not running (SSH_AUTH_SOCK is not set)")?;
+    Ok(std::os::unix::net::UnixStream::connect(&socket).map_err(|e| format!("Failed to reach ssh-agent: {}", e))?)
+}
+
+/// Windows' OpenSSH agent listens on a named pipe, unless SSH_AUTH_SOCK
+/// names another.
+#[cfg(windows)]
+fn connect() -> Result<std::fs::File> {
+    let pipe = std::env::var_os("SSH_AUTH_SOCK").unwrap_or_else(|| r"\\.\pipe\openssh-ssh-agent".into());
+    Ok(std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(&pipe)
+        .map_err(|e| format!("Failed to reach ssh-agent: {}", e))?)
+}
+
+fn put_string(buffer: &mut Vec<u8>, value: &[u8]) {
+    buffer.extend_from_slice(&(value.len() as u32).to_be_bytes());
+    buffer.extend_from_slice(value);
+}
+
+/// Reads the agent protocol's integers and length-prefixed strings.
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8]> {
+        if self.0.len() < n {
+            return Err("Truncated reply from ssh-agent".into());
+        }
+        let (head, rest) = self.0.split_at(n);
+        self.0 = rest;
+        Ok(head)
+    }
+
+    fn byte(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn string(&mut self) -> Result<&'a [u8]> {
+        let length = self.u32()? as usize;
+        self.take(length)
+    }
+}
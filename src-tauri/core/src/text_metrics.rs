@@ -0,0 +1,181 @@
+/// Glyph widths for the standard-14 fonts used in watermarks, so text can be
+/// measured and wrapped without embedding a font program.
+///
+/// Widths are in 1/1000 text space units, taken from the Adobe AFM files for
+/// the printable ASCII range (0x20..=0x7E). Anything outside that range is
+/// measured with a conservative average width.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+// Watermarks and reports are set in Helvetica for now.
+#[allow(dead_code)]
+pub enum StandardFont {
+    Helvetica,
+    TimesRoman,
+    Courier,
+}
+
+const HELVETICA_WIDTHS: [u16; 95] = [
+    278, 278, 355, 556, 556, 889, 667, 191, 333, 333, 389, 584, 278, 333, 278, 278, // ' ' .. '/'
+    556, 556, 556, 556, 556, 556, 556, 556, 556, 556, 278, 278, 584, 584, 584, 556, // '0' .. '?'
+    1015, 667, 667, 722, 722, 667, 611, 778, 722, 278, 500, 667, 556, 833, 722, 778, // '@' .. 'O'
+    667, 778, 722, 667, 611, 722, 667, 944, 667, 667, 611, 278, 278, 278, 469, 556, // 'P' .. '_'
+    333, 556, 556, 500, 556, 556, 278, 556, 556, 222, 222, 500, 222, 833, 556, 556, // '`' .. 'o'
+    556, 556, 333, 500, 278, 556, 500, 722, 500, 500, 500, 334, 260, 334, 584, // 'p' .. '~'
+];
+
+const TIMES_ROMAN_WIDTHS: [u16; 95] = [
+    250, 333, 408, 500, 500, 833, 778, 180, 333, 333, 500, 564, 250, 333, 250, 278, // ' ' .. '/'
+    500, 500, 500, 500, 500, 500, 500, 500, 500, 500, 278, 278, 564, 564, 564, 444, // '0' .. '?'
+    921, 722, 667, 667, 722, 611, 556, 722, 722, 333, 389, 722, 611, 889, 722, 722, // '@' .. 'O'
+    556, 722, 667, 556, 611, 722, 722, 944, 722, 722, 611, 333, 278, 333, 469, 500, // 'P' .. '_'
+    333, 444, 500, 444, 500, 444, 333, 500, 500, 278, 278, 500, 278, 778, 500, 500, // '`' .. 'o'
+    500, 500, 333, 389, 278, 500, 500, 722, 500, 500, 444, 480, 200, 480, 541, // 'p' .. '~'
+];
+
+const COURIER_WIDTH: u16 = 600;
+
+impl StandardFont {
+    /// The `/BaseFont` name for the font dictionary.
+    pub fn base_font(self) -> &'static str {
+        match self {
+            StandardFont::Helvetica => "Helvetica",
+            StandardFont::TimesRoman => "Times-Roman",
+            StandardFont::Courier => "Courier",
+        }
+    }
+
+    /// Width of a single character in 1/1000 text space units.
+    pub fn char_width(self, c: char) -> u16 {
+        let index = match c {
+            ' '..='~' => c as usize - ' ' as usize,
+            _ => return self.fallback_width(),
+        };
+        match self {
+            StandardFont::Helvetica => HELVETICA_WIDTHS[index],
+            StandardFont::TimesRoman => TIMES_ROMAN_WIDTHS[index],
+            StandardFont::Courier => COURIER_WIDTH,
+        }
+    }
+
+    fn fallback_width(self) -> u16 {
+        match self {
+            StandardFont::Helvetica => 556,
+            StandardFont::TimesRoman => 500,
+            StandardFont::Courier => COURIER_WIDTH,
+        }
+    }
+}
+
+impl TextWidth for StandardFont {
+    fn text_width(&self, text: &str, font_size: f32) -> f32 {
+        let units: u32 = text.chars().map(|c| self.char_width(c) as u32).sum();
+        units as f32 * font_size / 1000.0
+    }
+}
+
+/// A font that text can be measured in.
+pub trait TextWidth {
+    /// Width of `text` in points when set at `font_size`.
+    fn text_width(&self, text: &str, font_size: f32) -> f32;
+}
+
+/// The characters WinAnsiEncoding has at 0x80..=0x9F, where it differs from
+/// Latin-1. Unassigned codes are `None`.
+const WIN_ANSI_HIGH: [Option<char>; 32] = [
+    Some('€'), None, Some('‚'), Some('ƒ'), Some('„'), Some('…'), Some('†'), Some('‡'),
+    Some('ˆ'), Some('‰'), Some('Š'), Some('‹'), Some('Œ'), None, Some('Ž'), None,
+    None, Some('‘'), Some('’'), Some('“'), Some('”'), Some('•'), Some('–'), Some('—'),
+    Some('˜'), Some('™'), Some('š'), Some('›'), Some('œ'), None, Some('ž'), Some('Ÿ'),
+];
+
+/// The WinAnsiEncoding byte for `c`, if the standard fonts can show it.
+pub fn win_ansi_code(c: char) -> Option<u8> {
+    match c {
+        ' '..='~' | '\u{A0}'..='\u{FF}' => Some(c as u8),
+        _ => WIN_ANSI_HIGH
+            .iter()
+            .position(|&high| high == Some(c))
+            .map(|i| 0x80 + i as u8),
+    }
+}
+
+/// Word-wraps `text` so that no line is wider than `max_width` points.
+///
+/// Breaks at spaces where possible; a single word that is too long on its own
+/// (such as a hex digest) is split between characters instead.
+pub fn wrap_text(text: &str, font: &impl TextWidth, font_size: f32, max_width: f32) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut current = String::new();
+
+    for word in text.split(' ') {
+        let candidate = if current.is_empty() {
+            word.to_string()
+        } else {
+            format!("{} {}", current, word)
+        };
+
+        if font.text_width(&candidate, font_size) <= max_width {
+            current = candidate;
+            continue;
+        }
+
+        if !current.is_empty() {
+            lines.push(std::mem::take(&mut current));
+        }
+
+        for c in word.chars() {
+            current.push(c);
+            if current.chars().count() > 1 && font.text_width(&current, font_size) > max_width {
+                current.pop();
+                lines.push(std::mem::take(&mut current));
+                current.push(c);
+            }
+        }
+    }
+
+    if !current.is_empty() || lines.is_empty() {
+        lines.push(current);
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FONT_SIZE: f32 = 10.0;
+
+    const LONG_EXTRA: &str = "Approved by the finance committee at its quarterly meeting, \
+        subject to the conditions set out in the attached schedule of amendments";
+
+    fn assert_fits(lines: &[String], font: StandardFont, max_width: f32) {
+        for line in lines {
+            let width = font.text_width(line, FONT_SIZE);
+            assert!(width <= max_width, "'{}' is {} points wide", line, width);
+        }
+    }
+
+    #[test]
+    fn words_wrap_at_spaces_and_join_back() {
+        for font in [StandardFont::Helvetica, StandardFont::TimesRoman, StandardFont::Courier] {
+            let lines = wrap_text(LONG_EXTRA, &font, FONT_SIZE, 150.0);
+            assert!(lines.len() > 1);
+            assert_fits(&lines, font, 150.0);
+            assert_eq!(lines.join(" "), LONG_EXTRA);
+        }
+    }
+
+    #[test]
+    fn an_overlong_word_is_split_between_characters() {
+        let digest = "SHA256:".to_string() + &"0123456789abcdef".repeat(4);
+        let lines = wrap_text(&digest, &StandardFont::Courier, FONT_SIZE, 120.0);
+        assert!(lines.len() > 1);
+        assert_fits(&lines, StandardFont::Courier, 120.0);
+        assert_eq!(lines.concat(), digest);
+    }
+
+    #[test]
+    fn short_text_stays_on_one_line() {
+        let lines = wrap_text("Alice Jones", &StandardFont::Helvetica, FONT_SIZE, 500.0);
+        assert_eq!(lines, ["Alice Jones"]);
+    }
+}
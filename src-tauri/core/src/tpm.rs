@@ -0,0 +1,55 @@
+//! Keys generated in the computer's TPM 2.0 chip, reached through the
+//! tpm2-pkcs11 module. The key is created inside the TPM and cannot be read
+//! out of it, so copying the app data directory does not copy the key; the
+//! key profile only records where the key is, as for any PKCS#11 token.
+//!
+//! The TPM needs a token set up with `tpm2_ptool` first, which gives it the
+//! user PIN that unlocks its keys.
+
+use crate::error::Result;
+use crate::keys::KeyAlgorithm;
+use crate::pkcs11::{self, Pkcs11Config};
+use chrono::Utc;
+use std::path::Path;
+
+/// Where tpm2-pkcs11 is installed.
+#[cfg(target_os = "linux")]
+const MODULES: &[&str] = &[
+    "/usr/lib/x86_64-linux-gnu/pkcs11/libtpm2_pkcs11.so",
+    "/usr/lib/x86_64-linux-gnu/libtpm2_pkcs11.so",
+    "/usr/lib/aarch64-linux-gnu/pkcs11/libtpm2_pkcs11.so",
+    "/usr/lib/aarch64-linux-gnu/libtpm2_pkcs11.so",
+    "/usr/lib64/pkcs11/libtpm2_pkcs11.so",
+    "/usr/lib/pkcs11/libtpm2_pkcs11.so",
+    "/usr/local/lib/libtpm2_pkcs11.so",
+];
+#[cfg(not(target_os = "linux"))]
+const MODULES: &[&str] = &[];
+
+/// The installed tpm2-pkcs11 module, if any.
+pub fn module() -> Option<&'static Path> {
+    MODULES.iter().map(Path::new).find(|module| module.is_file())
+}
+
+/// Generates a key of type `algorithm` in the TPM, logging in to its token
+/// with `pin`, and returns where it is. Only RSA keys are made, as only those
+/// sign from a token.
+pub fn generate(algorithm: KeyAlgorithm, pin: &str) -> Result<Pkcs11Config> {
+    if !matches!(algorithm, KeyAlgorithm::Rsa2048 | KeyAlgorithm::Rsa3072 | KeyAlgorithm::Rsa4096) {
+        return Err(format!("Only RSA keys can be generated in the TPM, not {}", algorithm).into());
+    }
+    let module = module().ok_or("No TPM support found; install tpm2-pkcs11 and set up a token with tpm2_ptool")?;
+    // A slot without a label holds the token not yet set up, which
+    // tpm2-pkcs11 always offers.
+    let token = pkcs11::tokens(module)?
+        .into_iter()
+        .find(|token| !token.label.is_empty())
+        .ok_or("The TPM has no token yet; set one up with tpm2_ptool")?;
+    let config = Pkcs11Config {
+        module: module.to_path_buf(),
+        slot: token.slot,
+        key_label: Some(format!("Sigillum {}", Utc::now().format("%Y-%m-%d %H:%M:%S"))),
+    };
+    pkcs11::generate_rsa_key(&config, pin, algorithm.bits() as usize)?;
+    Ok(config)
+}
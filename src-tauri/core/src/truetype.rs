@@ -0,0 +1,323 @@
+//! Just enough TrueType to embed a font in a PDF: character to glyph lookup,
+//! advance widths, and subsetting down to the glyphs a watermark uses.
+
+use crate::error::Result;
+use std::collections::BTreeSet;
+
+/// Tables a PDF viewer needs to render glyphs from an embedded `/FontFile2`.
+/// Layout tables and names are dropped from subsets.
+const SUBSET_TABLES: [&[u8; 4]; 9] = [
+    b"cvt ", b"fpgm", b"glyf", b"head", b"hhea", b"hmtx", b"loca", b"maxp", b"prep",
+];
+
+// Composite glyph component flags.
+const ARG_1_AND_2_ARE_WORDS: u16 = 0x0001;
+const WE_HAVE_A_SCALE: u16 = 0x0008;
+const MORE_COMPONENTS: u16 = 0x0020;
+const WE_HAVE_AN_X_AND_Y_SCALE: u16 = 0x0040;
+const WE_HAVE_A_TWO_BY_TWO: u16 = 0x0080;
+
+pub struct TrueTypeFont<'a> {
+    data: &'a [u8],
+    tables: Vec<([u8; 4], &'a [u8])>,
+    cmap: &'a [u8],
+    cmap_format: u16,
+    units_per_em: u16,
+    number_of_h_metrics: u16,
+    num_glyphs: u16,
+    long_loca: bool,
+}
+
+/// Font-wide metrics for the PDF font descriptor, in 1/1000 text space units.
+#[derive(Debug, Clone, Copy)]
+pub struct FontMetrics {
+    pub bbox: [i32; 4],
+    pub ascent: i32,
+    pub descent: i32,
+    pub cap_height: i32,
+}
+
+impl<'a> TrueTypeFont<'a> {
+    pub fn parse(data: &'a [u8]) -> Result<Self> {
+        let invalid = || "Invalid TrueType font".to_string();
+        let num_tables = read_u16(data, 4).ok_or_else(invalid)?;
+        let mut tables = Vec::with_capacity(num_tables as usize);
+        for i in 0..num_tables as usize {
+            let record = 12 + 16 * i;
+            let tag: [u8; 4] = data.get(record..record + 4).and_then(|t| t.try_into().ok()).ok_or_else(invalid)?;
+            let offset = read_u32(data, record + 8).ok_or_else(invalid)? as usize;
+            let length = read_u32(data, record + 12).ok_or_else(invalid)? as usize;
+            let table = data.get(offset..offset.saturating_add(length)).ok_or_else(invalid)?;
+            tables.push((tag, table));
+        }
+        let table = |tag: &[u8; 4]| {
+            tables
+                .iter()
+                .find(|(t, _)| t == tag)
+                .map(|(_, table)| *table)
+                .ok_or_else(|| format!("TrueType font has no '{}' table", String::from_utf8_lossy(tag)))
+        };
+
+        let head = table(b"head")?;
+        let hhea = table(b"hhea")?;
+        let maxp = table(b"maxp")?;
+        table(b"hmtx")?;
+        table(b"loca")?;
+        table(b"glyf")?;
+        let (cmap, cmap_format) = unicode_cmap(table(b"cmap")?).ok_or_else(|| "TrueType font has no Unicode cmap".to_string())?;
+
+        Ok(TrueTypeFont {
+            data,
+            cmap,
+            cmap_format,
+            units_per_em: read_u16(head, 18).filter(|&units| units > 0).ok_or_else(invalid)?,
+            long_loca: read_u16(head, 50).ok_or_else(invalid)? == 1,
+            number_of_h_metrics: read_u16(hhea, 34).filter(|&n| n > 0).ok_or_else(invalid)?,
+            num_glyphs: read_u16(maxp, 4).ok_or_else(invalid)?,
+            tables,
+        })
+    }
+
+    fn table(&self, tag: &[u8; 4]) -> &'a [u8] {
+        self.tables.iter().find(|(t, _)| t == tag).map(|(_, table)| *table).unwrap_or(&[])
+    }
+
+    /// Glyph for `c`, or 0 (the font's "missing" glyph) if it has none.
+    pub fn glyph_id(&self, c: char) -> u16 {
+        let c = c as u32;
+        let cmap = self.cmap;
+        match self.cmap_format {
+            4 => {
+                if c > 0xFFFF {
+                    return 0;
+                }
+                let seg_count = read_u16(cmap, 6).unwrap_or(0) as usize / 2;
+                let end_codes = 14;
+                let start_codes = end_codes + 2 * seg_count + 2;
+                let deltas = start_codes + 2 * seg_count;
+                let range_offsets = deltas + 2 * seg_count;
+                for segment in 0..seg_count {
+                    let end = read_u16(cmap, end_codes + 2 * segment).unwrap_or(0) as u32;
+                    if c > end {
+                        continue;
+                    }
+                    let start = read_u16(cmap, start_codes + 2 * segment).unwrap_or(0) as u32;
+                    if c < start {
+                        return 0;
+                    }
+                    let delta = read_u16(cmap, deltas + 2 * segment).unwrap_or(0);
+                    let range_offset = read_u16(cmap, range_offsets + 2 * segment).unwrap_or(0) as usize;
+                    if range_offset == 0 {
+                        return (c as u16).wrapping_add(delta);
+                    }
+                    let at = range_offsets + 2 * segment + range_offset + 2 * (c - start) as usize;
+                    return match read_u16(cmap, at).unwrap_or(0) {
+                        0 => 0,
+                        glyph => glyph.wrapping_add(delta),
+                    };
+                }
+                0
+            }
+            _ => {
+                let groups = read_u32(cmap, 12).unwrap_or(0) as usize;
+                (0..groups)
+                    .map(|group| 16 + 12 * group)
+                    .find(|&at| {
+                        let start = read_u32(cmap, at).unwrap_or(u32::MAX);
+                        let end = read_u32(cmap, at + 4).unwrap_or(0);
+                        (start..=end).contains(&c)
+                    })
+                    .and_then(|at| read_u32(cmap, at + 8).map(|glyph| glyph + c - read_u32(cmap, at).unwrap_or(c)))
+                    .and_then(|glyph| u16::try_from(glyph).ok())
+                    .unwrap_or(0)
+            }
+        }
+    }
+
+    /// Advance width of `glyph` in 1/1000 text space units.
+    pub fn glyph_width(&self, glyph: u16) -> u16 {
+        let metric = glyph.min(self.number_of_h_metrics - 1) as usize;
+        let advance = read_u16(self.table(b"hmtx"), 4 * metric).unwrap_or(0) as u32;
+        (advance * 1000 / self.units_per_em as u32) as u16
+    }
+
+    /// Width of `text` in points when set at `font_size`.
+    pub fn text_width(&self, text: &str, font_size: f32) -> f32 {
+        let units: u32 = text.chars().map(|c| self.glyph_width(self.glyph_id(c)) as u32).sum();
+        units as f32 * font_size / 1000.0
+    }
+
+    pub fn metrics(&self) -> FontMetrics {
+        let head = self.table(b"head");
+        let hhea = self.table(b"hhea");
+        let os2 = self.table(b"OS/2");
+        let scale = |value: Option<u16>| value.map_or(0, |v| v as i16 as i32 * 1000 / self.units_per_em as i32);
+        let ascent = scale(read_u16(hhea, 4));
+        FontMetrics {
+            bbox: [scale(read_u16(head, 36)), scale(read_u16(head, 38)), scale(read_u16(head, 40)), scale(read_u16(head, 42))],
+            ascent,
+            descent: scale(read_u16(hhea, 6)),
+            // Only version 2 and later of the OS/2 table record it.
+            cap_height: match read_u16(os2, 0) {
+                Some(version) if version >= 2 => scale(read_u16(os2, 88)),
+                _ => ascent,
+            },
+        }
+    }
+
+    fn glyph_data(&self, glyph: u16) -> &'a [u8] {
+        let (loca, glyf) = (self.table(b"loca"), self.table(b"glyf"));
+        let offset = |index: usize| {
+            if self.long_loca {
+                read_u32(loca, 4 * index).map(|o| o as usize)
+            } else {
+                read_u16(loca, 2 * index).map(|o| 2 * o as usize)
+            }
+        };
+        match (offset(glyph as usize), offset(glyph as usize + 1)) {
+            (Some(start), Some(end)) if start <= end => glyf.get(start..end).unwrap_or(&[]),
+            _ => &[],
+        }
+    }
+
+    /// Glyphs that the composite `glyph` is built from.
+    fn components(&self, glyph: u16) -> Vec<u16> {
+        let data = self.glyph_data(glyph);
+        let mut components = Vec::new();
+        if read_u16(data, 0).is_none_or(|contours| contours as i16 >= 0) {
+            return components;
+        }
+        let mut at = 10;
+        while let (Some(flags), Some(component)) = (read_u16(data, at), read_u16(data, at + 2)) {
+            components.push(component);
+            at += 4 + if flags & ARG_1_AND_2_ARE_WORDS != 0 { 4 } else { 2 };
+            at += if flags & WE_HAVE_A_SCALE != 0 {
+                2
+            } else if flags & WE_HAVE_AN_X_AND_Y_SCALE != 0 {
+                4
+            } else if flags & WE_HAVE_A_TWO_BY_TWO != 0 {
+                8
+            } else {
+                0
+            };
+            if flags & MORE_COMPONENTS == 0 {
+                break;
+            }
+        }
+        components
+    }
+
+    /// The glyphs `subset` keeps the outlines of: `glyphs`, the glyphs they
+    /// are composed of and the missing glyph.
+    pub fn subset_glyphs(&self, glyphs: &BTreeSet<u16>) -> BTreeSet<u16> {
+        let mut keep = BTreeSet::new();
+        let mut pending: Vec<u16> = glyphs.iter().copied().chain([0]).collect();
+        while let Some(glyph) = pending.pop() {
+            if glyph < self.num_glyphs && keep.insert(glyph) {
+                pending.extend(self.components(glyph));
+            }
+        }
+        keep
+    }
+
+    /// A copy of the font that only has outlines for `glyphs` (and the glyphs
+    /// they are composed of). Glyph ids are unchanged, so text drawn with the
+    /// full font draws the same with the subset.
+    pub fn subset(&self, glyphs: &BTreeSet<u16>) -> Vec<u8> {
+        let keep = self.subset_glyphs(glyphs);
+
+        let mut glyf = Vec::new();
+        let mut loca = Vec::with_capacity(4 * (self.num_glyphs as usize + 1));
+        for glyph in 0..self.num_glyphs {
+            loca.extend_from_slice(&(glyf.len() as u32).to_be_bytes());
+            if keep.contains(&glyph) {
+                glyf.extend_from_slice(self.glyph_data(glyph));
+                glyf.resize(glyf.len().next_multiple_of(4), 0);
+            }
+        }
+        loca.extend_from_slice(&(glyf.len() as u32).to_be_bytes());
+
+        let mut head = self.table(b"head").to_vec();
+        // The checksum adjustment is recomputed below; loca is written long.
+        head[8..12].fill(0);
+        head[50..52].copy_from_slice(&1u16.to_be_bytes());
+
+        let tables: Vec<(&[u8; 4], Vec<u8>)> = SUBSET_TABLES
+            .iter()
+            .filter_map(|&tag| match tag {
+                b"glyf" => Some((tag, std::mem::take(&mut glyf))),
+                b"loca" => Some((tag, std::mem::take(&mut loca))),
+                b"head" => Some((tag, std::mem::take(&mut head))),
+                _ => self.tables.iter().find(|(t, _)| t == tag).map(|(_, table)| (tag, table.to_vec())),
+            })
+            .collect();
+
+        let num_tables = tables.len() as u16;
+        let entry_selector = 15 - num_tables.leading_zeros() as u16;
+        let search_range = 16 << entry_selector;
+        let mut font = Vec::new();
+        font.extend_from_slice(&self.data[0..4]);
+        for value in [num_tables, search_range, entry_selector, num_tables * 16 - search_range] {
+            font.extend_from_slice(&value.to_be_bytes());
+        }
+        let mut offset = 12 + 16 * tables.len();
+        let mut head_offset = 0;
+        for (tag, table) in &tables {
+            if *tag == b"head" {
+                head_offset = offset;
+            }
+            font.extend_from_slice(*tag);
+            font.extend_from_slice(&checksum(table).to_be_bytes());
+            font.extend_from_slice(&(offset as u32).to_be_bytes());
+            font.extend_from_slice(&(table.len() as u32).to_be_bytes());
+            offset += table.len().next_multiple_of(4);
+        }
+        for (_, table) in &tables {
+            font.extend_from_slice(table);
+            font.resize(font.len().next_multiple_of(4), 0);
+        }
+        let adjustment = 0xB1B0_AFBAu32.wrapping_sub(checksum(&font));
+        font[head_offset + 8..head_offset + 12].copy_from_slice(&adjustment.to_be_bytes());
+        font
+    }
+}
+
+/// The best Unicode subtable of `cmap`: full repertoire (format 12) if there
+/// is one, otherwise the Basic Multilingual Plane (format 4).
+fn unicode_cmap(cmap: &[u8]) -> Option<(&[u8], u16)> {
+    let count = read_u16(cmap, 2)? as usize;
+    let subtables: Vec<(u16, u16, &[u8])> = (0..count)
+        .filter_map(|i| {
+            let record = 4 + 8 * i;
+            let platform = read_u16(cmap, record)?;
+            let encoding = read_u16(cmap, record + 2)?;
+            let subtable = cmap.get(read_u32(cmap, record + 4)? as usize..)?;
+            Some((platform, encoding, subtable))
+        })
+        .collect();
+    let find = |format: u16, encodings: &[(u16, u16)]| {
+        subtables
+            .iter()
+            .find(|(platform, encoding, subtable)| {
+                encodings.contains(&(*platform, *encoding)) && read_u16(subtable, 0) == Some(format)
+            })
+            .map(|(_, _, subtable)| (*subtable, format))
+    };
+    find(12, &[(3, 10), (0, 4), (0, 6)]).or_else(|| find(4, &[(3, 1), (0, 3), (0, 1), (0, 0)]))
+}
+
+fn checksum(data: &[u8]) -> u32 {
+    data.chunks(4).fold(0u32, |sum, chunk| {
+        let mut word = [0u8; 4];
+        word[..chunk.len()].copy_from_slice(chunk);
+        sum.wrapping_add(u32::from_be_bytes(word))
+    })
+}
+
+fn read_u16(data: &[u8], at: usize) -> Option<u16> {
+    data.get(at..at + 2).map(|b| u16::from_be_bytes([b[0], b[1]]))
+}
+
+fn read_u32(data: &[u8], at: usize) -> Option<u32> {
+    data.get(at..at + 4).map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+}
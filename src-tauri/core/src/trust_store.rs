@@ -0,0 +1,290 @@
+//! Other signers' public keys, trusted by name so that their documents
+//! verify and are attributed to them, until a key is revoked.
+
+use crate::error::Result;
+use crate::keys::{KeyAlgorithm, PublicKey};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+
+const TRUSTED_KEYS_FILE: &str = "trusted_keys.json";
+
+/// Someone else's public key, kept so their documents can be verified and
+/// attributed to them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrustedKey {
+    pub name: String,
+    pub public_key: String,
+    pub algorithm: KeyAlgorithm,
+    /// Set once the key is known to be compromised or out of use.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub revoked: Option<Revocation>,
+}
+
+/// Why and from when a trusted key is no longer relied on.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Revocation {
+    /// Signatures made from this time on are refused.
+    pub date: DateTime<Utc>,
+    pub reason: String,
+}
+
+impl TrustedKey {
+    /// Why a signature the key made at `signed_at` is not to be relied on,
+    /// when the key had been revoked by then. A signature of unknown time
+    /// counts as made after.
+    pub fn revocation_problem(&self, signed_at: Option<DateTime<Utc>>) -> Option<String> {
+        let revocation = self.revoked.as_ref()?;
+        if signed_at.is_some_and(|time| time < revocation.date) {
+            return None;
+        }
+        Some(format!("The signer's key '{}' was {}", self.name, revocation))
+    }
+}
+
+/// As the CLI lists it, e.g. "revoked on 2024-01-31 00:00:00 UTC: key
+/// compromised".
+impl fmt::Display for Revocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "revoked on {}: {}", self.date.format("%Y-%m-%d %H:%M:%S UTC"), self.reason)
+    }
+}
+
+/// Named public keys of other signers, stored as `trusted_keys.json` in the
+/// app data directory.
+pub struct TrustStore {
+    path: PathBuf,
+    keys: Vec<TrustedKey>,
+}
+
+impl TrustStore {
+    pub fn open(dir: PathBuf) -> Result<TrustStore> {
+        let path = dir.join(TRUSTED_KEYS_FILE);
+        let keys = if path.exists() {
+            let json = fs::read_to_string(&path).map_err(|e| format!("Read error: {}", e))?;
+            serde_json::from_str(&json).map_err(|e| format!("Invalid trust store: {}", e))?
+        } else {
+            Vec::new()
+        };
+        Ok(TrustStore { path, keys })
+    }
+
+    pub fn keys(&self) -> &[TrustedKey] {
+        &self.keys
+    }
+
+    /// Trusts `public_key_pem` under `name`. Names and keys must both be unique.
+    pub fn add(&mut self, name: &str, public_key_pem: &str) -> Result<TrustedKey> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err("Trusted key name must not be empty".into());
+        }
+        if self.keys.iter().any(|k| k.name == name) {
+            return Err(format!("A trusted key named '{}' already exists", name).into());
+        }
+
+        // Re-encode so the same key pasted with different line wrapping is
+        // still recognised as a duplicate.
+        let public_key = PublicKey::from_public_key_pem(public_key_pem.trim())?;
+        let pem = public_key.to_public_key_pem()?;
+        if let Some(existing) = self.keys.iter().find(|k| k.public_key == pem) {
+            return Err(format!("This key is already trusted as '{}'", existing.name).into());
+        }
+
+        let key = TrustedKey {
+            name: name.to_string(),
+            public_key: pem,
+            algorithm: public_key.algorithm()?,
+            revoked: None,
+        };
+        self.keys.push(key.clone());
+        self.save()?;
+        Ok(key)
+    }
+
+    pub fn remove(&mut self, name: &str) -> Result<()> {
+        let count = self.keys.len();
+        self.keys.retain(|k| k.name != name);
+        if self.keys.len() == count {
+            return Err(format!("No trusted key named '{}'", name).into());
+        }
+        self.save()
+    }
+
+    /// Revokes the key `name` from `date`, or from now: documents it signed
+    /// from then on no longer verify.
+    pub fn revoke(&mut self, name: &str, reason: &str, date: Option<DateTime<Utc>>) -> Result<TrustedKey> {
+        let reason = reason.trim();
+        if reason.is_empty() {
+            return Err("Give a reason for revoking the key".into());
+        }
+        let key = self
+            .keys
+            .iter_mut()
+            .find(|k| k.name == name)
+            .ok_or_else(|| format!("No trusted key named '{}'", name))?;
+        key.revoked = Some(Revocation {
+            date: date.unwrap_or_else(Utc::now),
+            reason: reason.to_string(),
+        });
+        let key = key.clone();
+        self.save()?;
+        Ok(key)
+    }
+
+    fn save(&self) -> Result<()> {
+        let json = serde_json::to_string_pretty(&self.keys).map_err(|e| format!("JSON error: {}", e))?;
+        Ok(fs::write(&self.path, json).map_err(|e| format!("Write error: {}", e))?)
+    }
+}
+
+/// A revocation date as typed: "2024-01-31" for the start of that day, or
+/// "2024-01-31 12:00:00", both in UTC, or RFC 3339.
+pub fn parse_revocation_date(date: &str) -> Result<DateTime<Utc>> {
+    let date = date.trim();
+    if let Ok(time) = DateTime::parse_from_rfc3339(date) {
+        return Ok(time.with_timezone(&Utc));
+    }
+    if let Ok(time) = NaiveDateTime::parse_from_str(date, "%Y-%m-%d %H:%M:%S") {
+        return Ok(time.and_utc());
+    }
+    Ok(NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .map(|day| day.and_hms_opt(0, 0, 0).unwrap_or_default().and_utc())
+        .map_err(|_| format!("Invalid date '{}': expected e.g. 2024-01-31", date))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keys::PrivateKey;
+    use chrono::{Duration, TimeZone};
+
+    /// An empty store in a fresh directory.
+    fn store(name: &str) -> (PathBuf, TrustStore) {
+        let dir = std::env::temp_dir().join(format!("sigillum-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let store = TrustStore::open(dir.clone()).unwrap();
+        (dir, store)
+    }
+
+    fn public_key() -> String {
+        PrivateKey::generate(KeyAlgorithm::Ed25519).unwrap().public_key().to_public_key_pem().unwrap()
+    }
+
+    fn revoked_key() -> TrustedKey {
+        TrustedKey {
+            name: "alice".to_string(),
+            public_key: String::new(),
+            algorithm: KeyAlgorithm::Ed25519,
+            revoked: Some(Revocation {
+                date: Utc.with_ymd_and_hms(2024, 1, 31, 12, 0, 0).unwrap(),
+                reason: "key compromised".to_string(),
+            }),
+        }
+    }
+
+    #[test]
+    fn signatures_from_the_revocation_on_are_refused() {
+        let key = revoked_key();
+        let date = key.revoked.as_ref().unwrap().date;
+        assert_eq!(key.revocation_problem(Some(date - Duration::seconds(1))), None);
+        assert_eq!(
+            key.revocation_problem(Some(date)).as_deref(),
+            Some("The signer's key 'alice' was revoked on 2024-01-31 12:00:00 UTC: key compromised")
+        );
+        assert!(key.revocation_problem(Some(date + Duration::days(1))).is_some());
+    }
+
+    #[test]
+    fn a_signature_of_unknown_time_is_refused() {
+        assert!(revoked_key().revocation_problem(None).is_some());
+    }
+
+    #[test]
+    fn keys_not_revoked_are_relied_on() {
+        let mut key = revoked_key();
+        key.revoked = None;
+        assert_eq!(key.revocation_problem(None), None);
+    }
+
+    #[test]
+    fn revocations_show_their_utc_time_and_reason() {
+        let revocation = revoked_key().revoked.unwrap();
+        assert_eq!(revocation.to_string(), "revoked on 2024-01-31 12:00:00 UTC: key compromised");
+    }
+
+    #[test]
+    fn names_and_keys_are_unique() {
+        let (dir, mut store) = store("trust-unique");
+        let key = public_key();
+        store.add("alice", &key).unwrap();
+
+        let error = store.add(" alice ", &public_key()).unwrap_err().to_string();
+        assert_eq!(error, "A trusted key named 'alice' already exists");
+        // The same key wrapped differently is still the same key.
+        let rewrapped = key.replace('\n', "\r\n");
+        let error = store.add("bob", &rewrapped).unwrap_err().to_string();
+        assert_eq!(error, "This key is already trusted as 'alice'");
+        assert_eq!(store.keys().len(), 1);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn names_must_not_be_empty() {
+        let (dir, mut store) = store("trust-empty");
+        assert!(store.add("  ", &public_key()).is_err());
+        assert!(store.add("alice", "not a key").is_err());
+        assert!(store.keys().is_empty());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn unknown_names_cannot_be_removed_or_revoked() {
+        let (dir, mut store) = store("trust-unknown");
+        store.add("alice", &public_key()).unwrap();
+        assert_eq!(store.remove("bob").unwrap_err().to_string(), "No trusted key named 'bob'");
+        assert!(store.revoke("bob", "key compromised", None).is_err());
+        assert!(store.revoke("alice", " ", None).is_err());
+        assert_eq!(store.keys().len(), 1);
+        store.remove("alice").unwrap();
+        assert!(store.keys().is_empty());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn the_store_is_saved_and_opened_again() {
+        let (dir, mut store) = store("trust-saved");
+        let (alice, bob) = (public_key(), public_key());
+        store.add("alice", &alice).unwrap();
+        store.add("bob", &bob).unwrap();
+        let date = Utc.with_ymd_and_hms(2024, 1, 31, 0, 0, 0).unwrap();
+        store.revoke("bob", "key compromised", Some(date)).unwrap();
+
+        let reopened = TrustStore::open(dir.clone()).unwrap();
+        let keys = reopened.keys();
+        assert_eq!(keys.len(), 2);
+        assert_eq!((keys[0].name.as_str(), keys[0].public_key.as_str()), ("alice", alice.as_str()));
+        assert!(keys[0].revoked.is_none());
+        assert_eq!(keys[1].algorithm, KeyAlgorithm::Ed25519);
+        let revocation = keys[1].revoked.as_ref().unwrap();
+        assert_eq!((revocation.date, revocation.reason.as_str()), (date, "key compromised"));
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn revocation_dates_are_read_in_every_form() {
+        let noon = Utc.with_ymd_and_hms(2024, 1, 31, 12, 0, 0).unwrap();
+        assert_eq!(parse_revocation_date("2024-01-31T12:00:00Z").unwrap(), noon);
+        assert_eq!(parse_revocation_date("2024-01-31T14:00:00+02:00").unwrap(), noon);
+        assert_eq!(parse_revocation_date(" 2024-01-31 12:00:00 ").unwrap(), noon);
+        assert_eq!(parse_revocation_date("2024-01-31").unwrap(), noon - Duration::hours(12));
+
+        for bad in ["", "yesterday", "2024-02-30", "31/01/2024", "2024-01-31 25:00:00"] {
+            let error = parse_revocation_date(bad).unwrap_err().to_string();
+            assert!(error.starts_with("Invalid date"), "{:?}: {}", bad, error);
+        }
+    }
+}
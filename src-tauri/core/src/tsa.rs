@@ -0,0 +1,380 @@
+//! RFC 3161 trusted timestamps: asking a timestamp authority (TSA) to vouch
+//! for when a signature existed, and checking such a token later.
+
+use crate::certificate;
+use crate::error::Result;
+use cms::cert::CertificateChoices;
+use cms::content_info::ContentInfo;
+use cms::signed_data::{SignedData, SignerIdentifier, SignerInfo};
+use p256::ecdsa::signature::hazmat::PrehashVerifier;
+use pkcs8::DecodePublicKey;
+use rand::RngCore;
+use rsa::{Pkcs1v15Sign, RsaPublicKey};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256, Sha384, Sha512};
+use std::io::Read;
+use std::time::Duration;
+use x509_cert::ext::pkix::{BasicConstraints, ExtendedKeyUsage};
+use x509_cert::der::asn1::{Int, OctetString};
+use x509_cert::der::oid::db::{rfc5280, rfc5911, rfc5912};
+use x509_cert::der::oid::ObjectIdentifier;
+use x509_cert::der::{Any, Decode, Encode};
+use x509_cert::spki::{AlgorithmIdentifier, SubjectPublicKeyInfoOwned};
+use x509_cert::Certificate;
+use x509_tsp::{MessageImprint, TimeStampReq, TimeStampResp, TspVersion, TstInfo};
+
+/// `id-ct-TSTInfo`, the content type of a timestamp token.
+const ID_CT_TST_INFO: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.113549.1.9.16.1.4");
+
+/// `id-aa-signatureTimeStampToken`, the CMS unsigned attribute that carries a
+/// timestamp over a signature value.
+pub const ID_AA_SIGNATURE_TIME_STAMP_TOKEN: ObjectIdentifier =
+    ObjectIdentifier::new_unwrap("1.2.840.113549.1.9.16.2.14");
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+const MAX_RESPONSE_SIZE: u64 = 1024 * 1024;
+/// Most certificates followed from the TSA's up to a trusted root.
+const MAX_CHAIN_LENGTH: usize = 8;
+
+/// What a valid timestamp token says.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TimestampInfo {
+    /// Time the TSA vouches for, e.g. "2024-01-31 12:00:00 UTC".
+    pub time: String,
+    /// Subject of the TSA certificate that signed the token.
+    pub authority: String,
+    /// Whether that certificate may sign timestamps and chains to a trusted
+    /// root. Anyone can make a token that says any time, so one that does
+    /// not vouches for nothing.
+    #[serde(default)]
+    pub trusted: bool,
+}
+
+/// Asks the TSA at `url` to timestamp `data` and returns the DER token.
+pub fn request_timestamp(url: &str, data: &[u8]) -> Result<Vec<u8>> {
+    let tsa_error = |e: &dyn std::fmt::Display| format!("Timestamp request failed: {}", e);
+
+    let mut nonce = [0u8; 8];
+    rand::rngs::OsRng.fill_bytes(&mut nonce);
+    nonce[0] &= 0x7f;
+    let request = TimeStampReq {
+        version: TspVersion::V1,
+        message_imprint: MessageImprint {
+            hash_algorithm: AlgorithmIdentifier {
+                oid: rfc5912::ID_SHA_256,
+                parameters: None,
+            },
+            hashed_message: OctetString::new(Sha256::digest(data).to_vec()).map_err(|e| tsa_error(&e))?,
+        },
+        req_policy: None,
+        nonce: Some(Int::new(&nonce).map_err(|e| tsa_error(&e))?),
+        cert_req: true,
+        extensions: None,
+    };
+    let request_der = request.to_der().map_err(|e| tsa_error(&e))?;
+
+    let agent = ureq::AgentBuilder::new().timeout(REQUEST_TIMEOUT).build();
+    let response = agent
+        .post(url)
+        .set("Content-Type", "application/timestamp-query")
+        .send_bytes(&request_der)
+        .map_err(|e| tsa_error(&e))?;
+    let mut response_der = Vec::new();
+    response
+        .into_reader()
+        .take(MAX_RESPONSE_SIZE)
+        .read_to_end(&mut response_der)
+        .map_err(|e| tsa_error(&e))?;
+
+    let response = TimeStampResp::from_der(&response_der).map_err(|e| tsa_error(&e))?;
+    // 0 = granted, 1 = granted with modifications
+    if response.status.status as u8 > 1 {
+        let reason = response
+            .status
+            .status_string
+            .iter()
+            .flatten()
+            .map(|s| s.as_str())
+            .collect::<Vec<_>>()
+            .join("; ");
+        return Err(format!("Timestamp authority refused the request: {}", reason).into());
+    }
+    let token = response
+        .time_stamp_token
+        .ok_or("Timestamp authority returned no token")?;
+
+    let tst_info = token_tst_info(&signed_data(&token)?)?;
+    if tst_info.message_imprint != request.message_imprint {
+        return Err("Timestamp token covers different data than requested".into());
+    }
+    if tst_info.nonce != request.nonce {
+        return Err("Timestamp token does not answer this request".into());
+    }
+    Ok(token.to_der().map_err(|e| tsa_error(&e))?)
+}
+
+/// Checks that `token` timestamps `data` and is signed by the certificate it
+/// carries. The token is trusted when that certificate is for timestamping
+/// and chains, through the others the token carries, to one of `roots`.
+pub fn verify_timestamp(token: &[u8], data: &[u8], roots: &[Certificate]) -> Result<TimestampInfo> {
+    let token_error = |e: &dyn std::fmt::Display| format!("Invalid timestamp token: {}", e);
+
+    let token = ContentInfo::from_der(token).map_err(|e| token_error(&e))?;
+    let signed_data = signed_data(&token)?;
+    let tst_info = token_tst_info(&signed_data)?;
+
+    let imprint = &tst_info.message_imprint;
+    let expected = hash(&imprint.hash_algorithm.oid, data)?;
+    if imprint.hashed_message.as_bytes() != expected.as_slice() {
+        return Err("Timestamp token does not cover this signature".into());
+    }
+
+    let signer = signed_data
+        .signer_infos
+        .0
+        .iter()
+        .next()
+        .ok_or_else(|| token_error(&"no signer"))?;
+    let certificate =
+        signer_certificate(&signed_data, signer).ok_or("Timestamp token does not include the TSA certificate")?;
+    verify_token_signature(&signed_data, signer, certificate)?;
+
+    let carried: Vec<&Certificate> = signed_data
+        .certificates
+        .iter()
+        .flat_map(|set| set.0.iter())
+        .filter_map(|choice| match choice {
+            CertificateChoices::Certificate(certificate) => Some(certificate),
+            _ => None,
+        })
+        .collect();
+    let trusted = check_chain(certificate, &carried, roots, tst_info.gen_time.to_unix_duration()).is_ok();
+
+    Ok(TimestampInfo {
+        time: certificate::format_time(tst_info.gen_time.to_date_time()),
+        authority: certificate.tbs_certificate.subject.to_string(),
+        trusted,
+    })
+}
+
+/// Checks that the TSA's `certificate` may sign timestamps and that it
+/// chains, through `carried`, to one of `roots`, every certificate on the
+/// way valid at `time`.
+fn check_chain(certificate: &Certificate, carried: &[&Certificate], roots: &[Certificate], time: Duration) -> Result<()> {
+    if !extension::<ExtendedKeyUsage>(certificate, rfc5280::ID_CE_EXT_KEY_USAGE)
+        .is_some_and(|usage| usage.0.contains(&rfc5280::ID_KP_TIME_STAMPING))
+    {
+        return Err("TSA certificate is not for timestamping".into());
+    }
+    let mut current = certificate;
+    for _ in 0..MAX_CHAIN_LENGTH {
+        check_validity(current, time)?;
+        if roots.contains(current) {
+            return Ok(());
+        }
+        if let Some(root) = roots.iter().find(|root| issued_by(current, root)) {
+            return check_validity(root, time);
+        }
+        current = carried
+            .iter()
+            .copied()
+            .find(|issuer| is_ca(issuer) && issued_by(current, issuer))
+            .ok_or("TSA certificate does not chain to a trusted root")?;
+    }
+    Err("TSA certificate chain is too long".into())
+}
+
+fn check_validity(certificate: &Certificate, time: Duration) -> Result<()> {
+    let validity = &certificate.tbs_certificate.validity;
+    if validity.not_before.to_unix_duration() <= time && time <= validity.not_after.to_unix_duration() {
+        Ok(())
+    } else {
+        Err("TSA certificate was not valid at the time it vouches for".into())
+    }
+}
+
+fn is_ca(certificate: &Certificate) -> bool {
+    extension::<BasicConstraints>(certificate, rfc5280::ID_CE_BASIC_CONSTRAINTS).is_some_and(|constraints| constraints.ca)
+}
+
+/// The extension `id` of `certificate`, if it has one that decodes.
+fn extension<'a, T: Decode<'a>>(certificate: &'a Certificate, id: ObjectIdentifier) -> Option<T> {
+    let extension = certificate
+        .tbs_certificate
+        .extensions
+        .iter()
+        .flatten()
+        .find(|ext| ext.extn_id == id)?;
+    T::from_der(extension.extn_value.as_bytes()).ok()
+}
+
+/// Whether `issuer` signed `certificate`.
+fn issued_by(certificate: &Certificate, issuer: &Certificate) -> bool {
+    if certificate.tbs_certificate.issuer != issuer.tbs_certificate.subject {
+        return false;
+    }
+    let digest_algorithm = match certificate.signature_algorithm.oid {
+        rfc5912::SHA_256_WITH_RSA_ENCRYPTION | rfc5912::ECDSA_WITH_SHA_256 => rfc5912::ID_SHA_256,
+        rfc5912::SHA_384_WITH_RSA_ENCRYPTION | rfc5912::ECDSA_WITH_SHA_384 => rfc5912::ID_SHA_384,
+        rfc5912::SHA_512_WITH_RSA_ENCRYPTION | rfc5912::ECDSA_WITH_SHA_512 => rfc5912::ID_SHA_512,
+        _ => return false,
+    };
+    let (Ok(tbs_der), Some(signature)) = (certificate.tbs_certificate.to_der(), certificate.signature.as_bytes()) else {
+        return false;
+    };
+    hash(&digest_algorithm, &tbs_der).is_ok_and(|digest| {
+        verify_digest(&issuer.tbs_certificate.subject_public_key_info, &digest_algorithm, &digest, signature).unwrap_or(false)
+    })
+}
+
+fn signed_data(token: &ContentInfo) -> Result<SignedData> {
+    if token.content_type != rfc5911::ID_SIGNED_DATA {
+        return Err("Invalid timestamp token: not CMS signed data".into());
+    }
+    Ok(token
+        .content
+        .decode_as()
+        .map_err(|e| format!("Invalid timestamp token: {}", e))?)
+}
+
+fn token_tst_info(signed_data: &SignedData) -> Result<TstInfo> {
+    let token_error = |e: &dyn std::fmt::Display| format!("Invalid timestamp token: {}", e);
+
+    let content = &signed_data.encap_content_info;
+    if content.econtent_type != ID_CT_TST_INFO {
+        return Err(token_error(&"not a TSTInfo").into());
+    }
+    let econtent = content.econtent.as_ref().ok_or_else(|| token_error(&"no content"))?;
+    let tst_info = econtent.decode_as::<OctetString>().map_err(|e| token_error(&e))?;
+    Ok(TstInfo::from_der(tst_info.as_bytes()).map_err(|e| token_error(&e))?)
+}
+
+/// The certificate among those `signed_data` carries that `signer` names.
+pub(crate) fn signer_certificate<'a>(signed_data: &'a SignedData, signer: &SignerInfo) -> Option<&'a Certificate> {
+    let certificates = signed_data.certificates.iter().flat_map(|set| set.0.iter());
+    for choice in certificates {
+        let CertificateChoices::Certificate(certificate) = choice else {
+            continue;
+        };
+        let tbs = &certificate.tbs_certificate;
+        let matches = match &signer.sid {
+            SignerIdentifier::IssuerAndSerialNumber(id) => {
+                tbs.issuer == id.issuer && tbs.serial_number == id.serial_number
+            }
+            SignerIdentifier::SubjectKeyIdentifier(id) => tbs
+                .extensions
+                .iter()
+                .flatten()
+                .find(|ext| ext.extn_id == rfc5280::ID_CE_SUBJECT_KEY_IDENTIFIER)
+                .and_then(|ext| OctetString::from_der(ext.extn_value.as_bytes()).ok())
+                .is_some_and(|key_id| key_id == id.0),
+        };
+        if matches {
+            return Some(certificate);
+        }
+    }
+    None
+}
+
+/// Checks the TSA's signature: the signed attributes must hold the digest of
+/// the TSTInfo, and the signature must be over those attributes.
+fn verify_token_signature(signed_data: &SignedData, signer: &SignerInfo, certificate: &Certificate) -> Result<()> {
+    let token_error = |e: &dyn std::fmt::Display| format!("Invalid timestamp token: {}", e);
+
+    let signed_attrs = signer
+        .signed_attrs
+        .as_ref()
+        .ok_or_else(|| token_error(&"no signed attributes"))?;
+    let econtent = signed_data
+        .encap_content_info
+        .econtent
+        .as_ref()
+        .ok_or_else(|| token_error(&"no content"))?;
+    let content = econtent.decode_as::<OctetString>().map_err(|e| token_error(&e))?;
+    let content_digest = hash(&signer.digest_alg.oid, content.as_bytes())?;
+    let message_digest = signed_attrs
+        .iter()
+        .find(|attr| attr.oid == rfc5911::ID_MESSAGE_DIGEST)
+        .and_then(|attr| attr.values.iter().next())
+        .and_then(|value| value.decode_as::<OctetString>().ok())
+        .ok_or_else(|| token_error(&"no message digest"))?;
+    if message_digest.as_bytes() != content_digest.as_slice() {
+        return Err("Timestamp token content has been altered".into());
+    }
+
+    let signed_attrs_der = signed_attrs.to_der().map_err(|e| token_error(&e))?;
+    let digest = hash(&signer.digest_alg.oid, &signed_attrs_der)?;
+    let valid = verify_digest(
+        &certificate.tbs_certificate.subject_public_key_info,
+        &signer.digest_alg.oid,
+        &digest,
+        signer.signature.as_bytes(),
+    )?;
+    if valid {
+        Ok(())
+    } else {
+        Err("Timestamp token signature is invalid".into())
+    }
+}
+
+/// Whether `signature` is `public_key`'s over `digest`, which was made with
+/// `digest_algorithm`. RSA keys sign with PKCS#1 v1.5, EC keys on P-256 or
+/// P-384 with ECDSA.
+fn verify_digest(
+    public_key: &SubjectPublicKeyInfoOwned,
+    digest_algorithm: &ObjectIdentifier,
+    digest: &[u8],
+    signature: &[u8],
+) -> Result<bool> {
+    let unsupported = || "Timestamp token is signed with an unsupported key type".to_string();
+    let public_key_der = public_key
+        .to_der()
+        .map_err(|e| format!("Invalid timestamp token: {}", e))?;
+
+    match public_key.algorithm.oid {
+        rfc5912::RSA_ENCRYPTION => {
+            let key = RsaPublicKey::from_public_key_der(&public_key_der).map_err(|e| format!("Invalid timestamp token: {}", e))?;
+            let scheme = match *digest_algorithm {
+                rfc5912::ID_SHA_256 => Pkcs1v15Sign::new::<Sha256>(),
+                rfc5912::ID_SHA_384 => Pkcs1v15Sign::new::<Sha384>(),
+                _ => Pkcs1v15Sign::new::<Sha512>(),
+            };
+            Ok(key.verify(scheme, digest, signature).is_ok())
+        }
+        rfc5912::ID_EC_PUBLIC_KEY => {
+            let curve = public_key
+                .algorithm
+                .parameters
+                .as_ref()
+                .and_then(|parameters| parameters.decode_as::<ObjectIdentifier>().ok());
+            match curve {
+                Some(rfc5912::SECP_256_R_1) => {
+                    let key = p256::ecdsa::VerifyingKey::from_public_key_der(&public_key_der).map_err(|_| unsupported())?;
+                    Ok(p256::ecdsa::Signature::from_der(signature)
+                        .is_ok_and(|signature| key.verify_prehash(digest, &signature).is_ok()))
+                }
+                Some(rfc5912::SECP_384_R_1) => {
+                    let key = p384::ecdsa::VerifyingKey::from_public_key_der(&public_key_der).map_err(|_| unsupported())?;
+                    Ok(p384::ecdsa::Signature::from_der(signature)
+                        .is_ok_and(|signature| key.verify_prehash(digest, &signature).is_ok()))
+                }
+                _ => Err(unsupported().into()),
+            }
+        }
+        _ => Err(unsupported().into()),
+    }
+}
+
+fn hash(algorithm: &ObjectIdentifier, data: &[u8]) -> Result<Vec<u8>> {
+    match *algorithm {
+        rfc5912::ID_SHA_256 => Ok(Sha256::digest(data).to_vec()),
+        rfc5912::ID_SHA_384 => Ok(Sha384::digest(data).to_vec()),
+        rfc5912::ID_SHA_512 => Ok(Sha512::digest(data).to_vec()),
+        _ => Err(format!("Unsupported timestamp hash algorithm {}", algorithm).into()),
+    }
+}
+
+/// Wraps a DER timestamp token as the value of a CMS attribute.
+pub fn token_attribute_value(token: &[u8]) -> Result<Any> {
+    Ok(Any::from_der(token).map_err(|e| format!("Invalid timestamp token: {}", e))?)
+}
@@ -0,0 +1,113 @@
+//! Signing PDFs as they are dropped into a folder.
+
+use crate::api::{self, BatchJob, BatchResult, KeyPair, SignOptions};
+use crate::error::Result;
+use crate::progress::CancellationToken;
+use notify::event::EventKind;
+use notify::{RecursiveMode, Watcher};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+/// How often the cancellation token is checked while nothing happens.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+/// How long a file has to go unchanged before it is signed, so a PDF still
+/// being copied in is not read half-written.
+const SETTLE_TIME: Duration = Duration::from_secs(1);
+
+/// Watches `input_dir` and signs every PDF that appears in it into
+/// `output_dir` under the same name, until `token` is cancelled.
+///
+/// Only files created or changed after the watch starts are signed; one that
+/// changes again is signed again. The key is unlocked once up front. A file
+/// that fails is reported through `on_done` like any other and the watch
+/// carries on.
+pub fn watch<F>(
+    input_dir: &Path,
+    output_dir: &Path,
+    opts: &SignOptions,
+    key: &KeyPair,
+    token: &CancellationToken,
+    mut on_done: F,
+) -> Result<()>
+where
+    F: FnMut(&BatchResult),
+{
+    let input_dir = fs::canonicalize(input_dir).map_err(|e| format!("Failed to read directory: {}", e))?;
+    fs::create_dir_all(output_dir).map_err(|e| format!("Failed to create dir: {}", e))?;
+    // Signed copies keep their names, so they would overwrite the originals.
+    if fs::canonicalize(output_dir).ok().as_deref() == Some(input_dir.as_path()) {
+        return Err("The output directory must be different from the watched one".into());
+    }
+    let signing_key = api::SigningKey::new(key, opts);
+    signing_key.unlock()?;
+    let certificate = key.certificate()?;
+
+    let (sender, events) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(sender).map_err(|e| format!("Failed to watch directory: {}", e))?;
+    watcher
+        .watch(&input_dir, RecursiveMode::NonRecursive)
+        .map_err(|e| format!("Failed to watch directory: {}", e))?;
+
+    // When each file waiting to be signed last changed.
+    let mut pending: HashMap<PathBuf, Instant> = HashMap::new();
+    while !token.is_cancelled() {
+        match events.recv_timeout(POLL_INTERVAL) {
+            Ok(Ok(event)) => match event.kind {
+                EventKind::Create(_) | EventKind::Modify(_) => {
+                    for path in event.paths.into_iter().filter(|path| is_pdf(path)) {
+                        pending.insert(path, Instant::now());
+                    }
+                }
+                EventKind::Remove(_) => {
+                    for path in &event.paths {
+                        pending.remove(path);
+                    }
+                }
+                _ => {}
+            },
+            Ok(Err(e)) => return Err(format!("Failed to watch directory: {}", e).into()),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return Err("Stopped watching the directory".into()),
+        }
+
+        let settled: Vec<PathBuf> = pending
+            .iter()
+            .filter(|(_, changed)| changed.elapsed() >= SETTLE_TIME)
+            .map(|(path, _)| path.clone())
+            .collect();
+        for input in settled {
+            pending.remove(&input);
+            // Renamed away or deleted before it settled.
+            if !input.is_file() {
+                continue;
+            }
+            let job = BatchJob {
+                output: output_dir.join(input.file_name().unwrap_or_default()),
+                input,
+            };
+            let outcome = api::sign_file(&job, opts.clone(), &signing_key, certificate.as_ref());
+            on_done(&BatchResult {
+                input: job.input,
+                output: job.output,
+                signature_info: outcome.as_ref().ok().cloned(),
+                error: outcome.err().map(|e| e.to_string()),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// A `.pdf` file that is not hidden, which skips the temporary files some
+/// programs write before renaming them into place.
+fn is_pdf(path: &Path) -> bool {
+    let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+        return false;
+    };
+    !name.starts_with('.')
+        && path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("pdf"))
+}
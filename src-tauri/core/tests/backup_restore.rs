@@ -0,0 +1,129 @@
+//! A backup brings every profile, trusted key and time stamp server back, and
+//! only for the passphrase it was made with.
+
+mod common;
+
+use sigillum_core::{backup, KeyStore, Settings, TrustStore};
+use std::path::PathBuf;
+
+const PASSPHRASE: &str = "correct horse battery staple";
+
+/// An empty app data directory.
+fn data_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("sigillum-{}-{}", std::process::id(), name));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// A data directory with two profiles, "home" active, a revoked trusted key
+/// and a time stamp server, backed up.
+fn backed_up(name: &str) -> (PathBuf, Vec<u8>) {
+    let dir = data_dir(name);
+    let mut store = KeyStore::open(dir.clone()).unwrap();
+    store.create_profile("work", &common::test_key()).unwrap();
+    store.create_profile("home", &common::test_key()).unwrap();
+    store.set_active_profile("home").unwrap();
+
+    let mut trust_store = TrustStore::open(dir.clone()).unwrap();
+    trust_store.add("alice", &common::test_key().public_key).unwrap();
+    trust_store.revoke("alice", "Laptop stolen", None).unwrap();
+
+    let mut settings = Settings::load(&dir).unwrap();
+    settings.tsa_url = Some("https://tsa.example.com".to_string());
+    settings.save(&dir).unwrap();
+
+    let data = backup::backup(&dir, PASSPHRASE).unwrap();
+    (dir, data)
+}
+
+fn restore_error(data: &[u8], passphrase: &str, name: &str) -> String {
+    let dir = data_dir(name);
+    let error = match backup::restore(&dir, data, passphrase) {
+        Ok(_) => panic!("the backup was restored"),
+        Err(e) => e.to_string(),
+    };
+    assert!(KeyStore::open(dir.clone()).unwrap().profiles().is_empty(), "{}", error);
+    assert!(TrustStore::open(dir.clone()).unwrap().keys().is_empty(), "{}", error);
+    let _ = std::fs::remove_dir_all(&dir);
+    error
+}
+
+#[test]
+fn restoring_brings_everything_back() {
+    let (source, data) = backed_up("backup-source");
+    let target = data_dir("backup-target");
+    let summary = backup::restore(&target, &data, PASSPHRASE).unwrap();
+    assert_eq!(summary.profiles.len(), 2);
+    assert!(summary.skipped_profiles.is_empty());
+    assert_eq!(summary.trusted_keys, ["alice"]);
+
+    let mut original = KeyStore::open(source.clone()).unwrap();
+    let mut restored = KeyStore::open(target.clone()).unwrap();
+    assert_eq!(restored.active_profile(), "home");
+    for profile in ["work", "home"] {
+        original.select(profile).unwrap();
+        restored.select(profile).unwrap();
+        let (before, after) = (original.load().unwrap(), restored.load().unwrap());
+        assert_eq!(after.public_key, before.public_key, "{}", profile);
+        assert_eq!(after.private_key, before.private_key, "{}", profile);
+    }
+
+    let trusted = TrustStore::open(target.clone()).unwrap().keys().to_vec();
+    let original_trusted = TrustStore::open(source.clone()).unwrap().keys().to_vec();
+    assert_eq!(trusted[0].public_key, original_trusted[0].public_key);
+    assert_eq!(trusted[0].revoked.as_ref().unwrap().reason, "Laptop stolen");
+    assert_eq!(Settings::load(&target).unwrap().tsa_url.as_deref(), Some("https://tsa.example.com"));
+
+    let _ = std::fs::remove_dir_all(&source);
+    let _ = std::fs::remove_dir_all(&target);
+}
+
+#[test]
+fn restoring_leaves_existing_profiles_alone() {
+    let (source, data) = backed_up("backup-existing-source");
+    let target = data_dir("backup-existing-target");
+    let existing = common::test_key();
+    KeyStore::open(target.clone()).unwrap().create_profile("work", &existing).unwrap();
+
+    let summary = backup::restore(&target, &data, PASSPHRASE).unwrap();
+    assert_eq!(summary.profiles, ["home"]);
+    assert_eq!(summary.skipped_profiles, ["work"]);
+    let mut store = KeyStore::open(target.clone()).unwrap();
+    assert_eq!(store.active_profile(), "work");
+    store.select("work").unwrap();
+    assert_eq!(store.load().unwrap().public_key, existing.public_key);
+
+    let _ = std::fs::remove_dir_all(&source);
+    let _ = std::fs::remove_dir_all(&target);
+}
+
+#[test]
+fn a_wrong_passphrase_restores_nothing() {
+    let (source, data) = backed_up("backup-passphrase");
+    let error = restore_error(&data, "correct horse", "backup-passphrase-target");
+    assert!(error.contains("Wrong passphrase"), "{}", error);
+    let _ = std::fs::remove_dir_all(&source);
+}
+
+#[test]
+fn a_damaged_backup_restores_nothing() {
+    let (source, data) = backed_up("backup-damaged");
+    let mut file: serde_json::Value = serde_json::from_slice(&data).unwrap();
+
+    let ciphertext = file["ciphertext"].as_str().unwrap().to_string();
+    let flipped = if ciphertext.starts_with('A') { "B" } else { "A" };
+    let mut tampered = file.clone();
+    tampered["ciphertext"] = format!("{}{}", flipped, &ciphertext[1..]).into();
+    let error = restore_error(&serde_json::to_vec(&tampered).unwrap(), PASSPHRASE, "backup-damaged-ciphertext");
+    assert!(error.contains("damaged"), "{}", error);
+
+    let error = restore_error(&data[..data.len() / 2], PASSPHRASE, "backup-damaged-truncated");
+    assert!(error.contains("Not a Sigillum backup"), "{}", error);
+
+    file["scrypt_log_n"] = 30.into();
+    let error = restore_error(&serde_json::to_vec(&file).unwrap(), PASSPHRASE, "backup-damaged-cost");
+    assert!(error.contains("cost is too high"), "{}", error);
+
+    let _ = std::fs::remove_dir_all(&source);
+}
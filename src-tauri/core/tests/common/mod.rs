@@ -0,0 +1,64 @@
+//! Documents the integration tests sign and verify.
+
+#![allow(dead_code)]
+
+use lopdf::{dictionary, Document, Object, Stream};
+use sigillum_core::{KeyAlgorithm, KeyPair};
+
+/// A plain PDF with `pages` pages of Courier text.
+pub fn sample_pdf(pages: usize) -> Vec<u8> {
+    let mut doc = Document::with_version("1.5");
+    let pages_id = doc.new_object_id();
+    let font_id = doc.add_object(dictionary! {
+        "Type" => "Font",
+        "Subtype" => "Type1",
+        "BaseFont" => "Courier",
+    });
+    let resources_id = doc.add_object(dictionary! { "Font" => dictionary! { "F1" => font_id } });
+    let mut kids = Vec::new();
+    for page in 1..=pages {
+        let content = format!("BT /F1 24 Tf 100 600 Td (Hello page {}) Tj ET", page);
+        let content_id = doc.add_object(Stream::new(dictionary! {}, content.into_bytes()));
+        let page_id = doc.add_object(dictionary! {
+            "Type" => "Page",
+            "Parent" => pages_id,
+            "Contents" => content_id,
+            "Resources" => resources_id,
+            "MediaBox" => vec![0.into(), 0.into(), 595.into(), 842.into()],
+        });
+        kids.push(Object::Reference(page_id));
+    }
+    let count = kids.len() as i64;
+    doc.objects.insert(pages_id, Object::Dictionary(dictionary! { "Type" => "Pages", "Kids" => kids, "Count" => count }));
+    let catalog_id = doc.add_object(dictionary! { "Type" => "Catalog", "Pages" => pages_id });
+    doc.trailer.set("Root", catalog_id);
+    let mut pdf = Vec::new();
+    doc.save_to(&mut pdf).unwrap();
+    pdf
+}
+
+/// A one-page PDF padded past `size` bytes with an unreferenced stream, as a
+/// stand-in for a document too large to want in memory twice.
+pub fn large_pdf(size: usize) -> Vec<u8> {
+    let mut doc = Document::load_mem(&sample_pdf(1)).unwrap();
+    let line = b"0123456789 padding that never looks like a signature marker\n";
+    let padding = line.iter().copied().cycle().take(size).collect();
+    doc.add_object(Stream::new(dictionary! {}, padding));
+    let mut pdf = Vec::new();
+    doc.save_to(&mut pdf).unwrap();
+    pdf
+}
+
+/// A fresh Ed25519 key, which is quick to make.
+pub fn test_key() -> KeyPair {
+    KeyPair::generate(KeyAlgorithm::Ed25519).unwrap()
+}
+
+/// `pdf` with the first `/name<hex>` payload entry holding `from` changed to
+/// hold `to`.
+pub fn replace_payload_text(pdf: &[u8], name: &str, from: &str, to: &str) -> Vec<u8> {
+    let entry = |value: &str| format!("/{}<{}>", name, hex::encode_upper(value)).into_bytes();
+    let (from, to) = (entry(from), entry(to));
+    let at = pdf.windows(from.len()).position(|window| window == from).expect("payload entry not found");
+    [&pdf[..at], &to, &pdf[at + from.len()..]].concat()
+}
@@ -0,0 +1,127 @@
+//! Documents are signed only when their text meets the content policy, and
+//! the key is not unlocked for a document or options that are then refused.
+
+mod common;
+
+use sigillum_core::{api, SignOptions};
+use std::fs;
+use std::path::PathBuf;
+
+fn policy(require_text: &[&str], forbid_text: &[&str]) -> SignOptions {
+    let mut opts = SignOptions::default();
+    opts.name = "Alice".to_string();
+    opts.require_text = require_text.iter().map(|s| s.to_string()).collect();
+    opts.forbid_text = forbid_text.iter().map(|s| s.to_string()).collect();
+    opts
+}
+
+fn policy_error(opts: SignOptions) -> String {
+    let pdf = common::sample_pdf(2);
+    api::sign(&pdf, opts, &common::test_key()).unwrap_err().message().to_string()
+}
+
+/// A file in the temporary directory holding `contents`, unique to this test run.
+fn temp_file(name: &str, contents: &[u8]) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("sigillum-{}-{}", std::process::id(), name));
+    fs::write(&path, contents).unwrap();
+    path
+}
+
+#[test]
+fn signs_when_the_required_text_is_present() {
+    let opts = policy(&["Hello page 2"], &[]);
+    assert!(api::sign(&common::sample_pdf(2), opts, &common::test_key()).is_ok());
+}
+
+#[test]
+fn refuses_when_the_required_text_is_missing() {
+    let message = policy_error(policy(&["(?i)approved"], &[]));
+    assert!(message.contains("required text '(?i)approved' is missing"), "{}", message);
+}
+
+#[test]
+fn signs_when_the_forbidden_text_is_absent() {
+    let opts = policy(&[], &["DRAFT"]);
+    assert!(api::sign(&common::sample_pdf(1), opts, &common::test_key()).is_ok());
+}
+
+#[test]
+fn refuses_when_the_forbidden_text_is_present() {
+    let message = policy_error(policy(&[], &["(?i)hello PAGE \\d"]));
+    assert!(message.contains("forbidden text"), "{}", message);
+    assert!(message.contains("found \"Hello page 1\""), "{}", message);
+}
+
+#[test]
+fn reports_every_violation_at_once() {
+    let message = policy_error(policy(&["Approved"], &["Hello"]));
+    assert!(message.contains("required text 'Approved' is missing"), "{}", message);
+    assert!(message.contains("forbidden text 'Hello' is present"), "{}", message);
+}
+
+#[test]
+fn refuses_an_invalid_pattern() {
+    let message = policy_error(policy(&["(unclosed"], &[]));
+    assert!(message.contains("Invalid text pattern '(unclosed'"), "{}", message);
+}
+
+#[test]
+fn detached_signing_checks_the_file_text() {
+    let path = temp_file("policy-notes.txt", b"Status: DRAFT\n");
+    let key = common::test_key();
+    let refused = api::sign_detached(&path, &policy(&[], &["DRAFT"]), &key);
+    let signed = api::sign_detached(&path, &policy(&["Status"], &[]), &key);
+    fs::remove_file(&path).unwrap();
+    assert!(refused.unwrap_err().message().contains("forbidden text 'DRAFT'"));
+    assert!(signed.is_ok());
+}
+
+#[test]
+fn detached_signing_checks_the_policy_before_unlocking_the_key() {
+    let path = temp_file("policy-locked.pdf", &common::sample_pdf(1));
+    let mut key = common::test_key();
+    key.set_passphrase(None, Some("correct horse")).unwrap();
+    let refused = api::sign_detached(&path, &policy(&["Approved"], &[]), &key);
+    fs::remove_file(&path).unwrap();
+    assert!(refused.unwrap_err().message().contains("required text 'Approved' is missing"));
+}
+
+#[test]
+fn signing_checks_the_policy_before_unlocking_the_key() {
+    let mut key = common::test_key();
+    key.set_passphrase(None, Some("correct horse")).unwrap();
+    let refused = api::sign(&common::sample_pdf(1), policy(&["Approved"], &[]), &key);
+    assert!(refused.unwrap_err().message().contains("required text 'Approved' is missing"));
+}
+
+#[test]
+fn batch_signing_checks_the_policy_before_unlocking_the_key() {
+    let input = temp_file("policy-batch.pdf", &common::sample_pdf(1));
+    let output = input.with_file_name(format!("sigillum-{}-policy-batch-signed.pdf", std::process::id()));
+    let mut key = common::test_key();
+    key.set_passphrase(None, Some("correct horse")).unwrap();
+    let jobs = [api::BatchJob {
+        input: input.clone(),
+        output: output.clone(),
+    }];
+    let results = api::sign_batch(&jobs, &policy(&["Approved"], &[]), &key, |_, _| {}).unwrap();
+    fs::remove_file(&input).unwrap();
+    assert!(!output.exists());
+    let error = results[0].error.as_deref().unwrap();
+    assert!(error.contains("required text 'Approved' is missing"), "{}", error);
+}
+
+#[test]
+fn signing_checks_the_options_before_unlocking_the_key() {
+    let mut key = common::test_key();
+    key.set_passphrase(None, Some("correct horse")).unwrap();
+    let mut opts = policy(&[], &[]);
+    opts.key_url = Some("http://example.com/key.pem".to_string());
+    let refused = api::sign(&common::sample_pdf(1), opts, &key);
+    assert!(refused.unwrap_err().message().contains("must start with https://"));
+
+    let mut opts = policy(&[], &[]);
+    opts.timestamp_format = Some("%Y-%".to_string());
+    let refused = api::sign(&common::sample_pdf(1), opts, &key);
+    assert!(refused.unwrap_err().message().contains("Invalid timestamp format"));
+}
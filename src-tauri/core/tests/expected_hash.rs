@@ -0,0 +1,69 @@
+//! Checking a document's canonical hash against one supplied by the caller,
+//! as `verify --expect-hash` does.
+
+mod common;
+
+use sha2::{Digest, Sha256};
+use sigillum_core::{api, SignOptions, VerifyOptions};
+use std::io::Cursor;
+
+/// A signed document and the hash of the document as it was before signing.
+fn signed() -> (Vec<u8>, String) {
+    let pdf = common::sample_pdf(1);
+    let signed = api::sign(&pdf, SignOptions::default(), &common::test_key()).unwrap();
+    (signed.pdf, hex::encode(Sha256::digest(&pdf)))
+}
+
+fn expecting(hash: &str) -> VerifyOptions {
+    let mut opts = VerifyOptions::default();
+    opts.expected_hash = Some(hash.to_string());
+    opts
+}
+
+#[test]
+fn a_matching_hash_is_reported() {
+    let (pdf, hash) = signed();
+    let report = api::verify(&pdf, expecting(&hash)).unwrap();
+    assert_eq!(report.canonical_hash, hash);
+    assert_eq!(report.hash_matches, Some(true));
+}
+
+#[test]
+fn the_hash_may_carry_a_label_in_any_case() {
+    let (pdf, hash) = signed();
+    let labelled = format!("  SHA256: {}\n", hash.to_uppercase());
+    assert_eq!(api::verify(&pdf, expecting(&labelled)).unwrap().hash_matches, Some(true));
+}
+
+#[test]
+fn a_different_hash_is_reported() {
+    let (pdf, hash) = signed();
+    let other = hex::encode(Sha256::digest(b"another document"));
+    let report = api::verify(&pdf, expecting(&other)).unwrap();
+    assert_eq!(report.canonical_hash, hash);
+    assert_eq!(report.hash_matches, Some(false));
+}
+
+#[test]
+fn nothing_is_reported_without_an_expected_hash() {
+    let (pdf, _) = signed();
+    assert_eq!(api::verify(&pdf, VerifyOptions::default()).unwrap().hash_matches, None);
+}
+
+#[test]
+fn streaming_verification_checks_the_hash_too() {
+    let (pdf, hash) = signed();
+    let matching = api::verify_reader(Cursor::new(&pdf), expecting(&hash)).unwrap();
+    let different = api::verify_reader(Cursor::new(&pdf), expecting(&"0".repeat(64))).unwrap();
+    assert_eq!(matching.hash_matches, Some(true));
+    assert_eq!(different.hash_matches, Some(false));
+}
+
+#[test]
+fn an_unsigned_document_is_hashed_whole() {
+    let pdf = common::sample_pdf(1);
+    let hash = hex::encode(Sha256::digest(&pdf));
+    let report = api::verify(&pdf, expecting(&hash)).unwrap();
+    assert!(!report.is_signed());
+    assert_eq!(report.hash_matches, Some(true));
+}
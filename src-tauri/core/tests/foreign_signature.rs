@@ -0,0 +1,212 @@
+//! A standard signature another application made only verifies when it
+//! covers the whole document but its own value and its signed attributes are
+//! as signed.
+
+mod common;
+
+use cms::builder::{create_signing_time_attribute, SignedDataBuilder, SignerInfoBuilder};
+use cms::cert::{CertificateChoices, IssuerAndSerialNumber};
+use cms::content_info::ContentInfo;
+use cms::signed_data::{EncapsulatedContentInfo, SignedData, SignerIdentifier, SignerInfos};
+use lopdf::{dictionary, Document, Object, StringFormat};
+use p256::ecdsa::{DerSignature, SigningKey};
+use p256::pkcs8::{EncodePublicKey, LineEnding};
+use sha2::{Digest, Sha256};
+use sigillum_core::api::Integrity;
+use sigillum_core::{api, SignatureStatus, VerifyOptions};
+use std::str::FromStr;
+use std::time::Duration;
+use x509_cert::builder::{Builder, CertificateBuilder, Profile};
+use x509_cert::der::asn1::{SetOfVec, UtcTime};
+use x509_cert::der::oid::db::{rfc5911, rfc5912};
+use x509_cert::der::{Any, Decode, Encode};
+use x509_cert::name::Name;
+use x509_cert::serial_number::SerialNumber;
+use x509_cert::spki::{AlgorithmIdentifierOwned, SubjectPublicKeyInfoOwned};
+use x509_cert::time::{Time, Validity};
+use x509_cert::Certificate;
+
+/// Room reserved for the CMS signature, in bytes.
+const CONTENTS_SIZE: usize = 4096;
+
+/// The sample document with an unfilled signature field, and where its
+/// `/Contents` hex string starts and ends.
+fn unsigned() -> (Vec<u8>, usize, usize) {
+    let mut doc = Document::load_mem(&common::sample_pdf(1)).unwrap();
+    let page_id = doc.get_pages()[&1];
+    let signature_id = doc.add_object(dictionary! {
+        "Type" => "Sig",
+        "Filter" => "Adobe.PPKLite",
+        "SubFilter" => "adbe.pkcs7.detached",
+        "Name" => Object::string_literal("Other App"),
+        "ByteRange" => vec![0.into(), 1_000_000_000.into(), 1_000_000_000.into(), 1_000_000_000.into()],
+        "Contents" => Object::String(vec![0; CONTENTS_SIZE], StringFormat::Hexadecimal),
+    });
+    let field_id = doc.add_object(dictionary! {
+        "Type" => "Annot",
+        "Subtype" => "Widget",
+        "FT" => "Sig",
+        "T" => Object::string_literal("Signature1"),
+        "V" => signature_id,
+        "P" => page_id,
+        "Rect" => vec![0.into(), 0.into(), 0.into(), 0.into()],
+    });
+    let catalog = doc.catalog_mut().unwrap();
+    catalog.set("AcroForm", dictionary! { "Fields" => vec![field_id.into()], "SigFlags" => 3 });
+    let mut pdf = Vec::new();
+    doc.save_to(&mut pdf).unwrap();
+
+    let start = find(&pdf, b"/Contents<") + b"/Contents".len();
+    let end = start + 2 * CONTENTS_SIZE + 2;
+    assert_eq!(pdf[end - 1], b'>');
+    (pdf, start, end)
+}
+
+fn find(data: &[u8], needle: &[u8]) -> usize {
+    data.windows(needle.len()).position(|window| window == needle).unwrap()
+}
+
+/// A signer with a self-signed P-256 certificate, as another application's
+/// would be, and its public key as PEM.
+fn signer() -> (SigningKey, Certificate, String) {
+    let key = SigningKey::random(&mut rand::rngs::OsRng);
+    let spki = SubjectPublicKeyInfoOwned::from_key(*key.verifying_key()).unwrap();
+    let validity = Validity::from_now(Duration::from_secs(3600)).unwrap();
+    let subject = Name::from_str("CN=Other Signer").unwrap();
+    let certificate = CertificateBuilder::new(Profile::Root, SerialNumber::from(1u32), validity, subject, spki, &key)
+        .unwrap()
+        .build::<DerSignature>()
+        .unwrap();
+    let pem = key.verifying_key().to_public_key_pem(LineEnding::LF).unwrap();
+    (key, certificate, pem)
+}
+
+/// A detached CMS signature over the SHA-256 of `digest`, with the usual
+/// signed attributes.
+fn cms(key: &SigningKey, certificate: &Certificate, digest: &[u8]) -> Vec<u8> {
+    let content = EncapsulatedContentInfo {
+        econtent_type: rfc5911::ID_DATA,
+        econtent: None,
+    };
+    let digest_algorithm = AlgorithmIdentifierOwned {
+        oid: rfc5912::ID_SHA_256,
+        parameters: None,
+    };
+    let sid = SignerIdentifier::IssuerAndSerialNumber(IssuerAndSerialNumber {
+        issuer: certificate.tbs_certificate.issuer.clone(),
+        serial_number: certificate.tbs_certificate.serial_number.clone(),
+    });
+    let mut signer_info = SignerInfoBuilder::new(key, sid, digest_algorithm.clone(), &content, Some(digest)).unwrap();
+    signer_info.add_signed_attribute(create_signing_time_attribute().unwrap()).unwrap();
+    SignedDataBuilder::new(&content)
+        .add_digest_algorithm(digest_algorithm)
+        .unwrap()
+        .add_certificate(CertificateChoices::Certificate(certificate.clone()))
+        .unwrap()
+        .add_signer_info::<_, DerSignature>(signer_info)
+        .unwrap()
+        .build()
+        .unwrap()
+        .to_der()
+        .unwrap()
+}
+
+/// `pdf` with `byte_range` and `cms` filled in.
+fn fill(mut pdf: Vec<u8>, byte_range: [usize; 4], contents_start: usize, cms: &[u8]) -> Vec<u8> {
+    let range_at = find(&pdf, b"/ByteRange");
+    let open = range_at + pdf[range_at..].iter().position(|&b| b == b'[').unwrap();
+    let close = open + pdf[open..].iter().position(|&b| b == b']').unwrap();
+    let numbers = byte_range.map(|n| n.to_string()).join(" ");
+    let room = close - open - 1;
+    pdf[open + 1..close].copy_from_slice(format!("{:<room$}", numbers).as_bytes());
+
+    let hex = hex::encode_upper(cms);
+    pdf[contents_start + 1..contents_start + 1 + hex.len()].copy_from_slice(hex.as_bytes());
+    pdf
+}
+
+/// `pdf` signed over `byte_range`, which the signature value is not
+/// necessarily the only gap in, with the signed attributes passed through
+/// `alter` after signing.
+fn signed(byte_range: impl Fn(usize, usize, usize) -> [usize; 4], alter: impl Fn(&mut SignedData)) -> (Vec<u8>, String) {
+    let (pdf, start, end) = unsigned();
+    let byte_range = byte_range(start, end, pdf.len());
+    let (key, certificate, public_key) = signer();
+
+    // The ByteRange is covered too, so it is filled in before hashing.
+    let pdf = fill(pdf, byte_range, start, &[]);
+    let mut hasher = Sha256::new();
+    hasher.update(&pdf[byte_range[0]..byte_range[0] + byte_range[1]]);
+    hasher.update(&pdf[byte_range[2]..byte_range[2] + byte_range[3]]);
+    let signature = cms(&key, &certificate, &hasher.finalize());
+
+    let mut content_info = ContentInfo::from_der(&signature).unwrap();
+    let mut signed_data: SignedData = content_info.content.decode_as().unwrap();
+    alter(&mut signed_data);
+    content_info.content = Any::encode_from(&signed_data).unwrap();
+    (fill(pdf, byte_range, start, &content_info.to_der().unwrap()), public_key)
+}
+
+fn whole(start: usize, end: usize, length: usize) -> [usize; 4] {
+    [0, start, end, length - end]
+}
+
+fn verify(pdf: &[u8], public_key: String) -> api::ForeignSignatureReport {
+    let mut opts = VerifyOptions::default();
+    opts.public_key = Some(public_key);
+    let mut report = api::verify(pdf, opts).unwrap();
+    assert_eq!(report.foreign_signatures.len(), 1);
+    report.foreign_signatures.remove(0)
+}
+
+#[test]
+fn a_signature_over_all_but_its_value_verifies() {
+    let (pdf, public_key) = signed(whole, |_| {});
+    let report = verify(&pdf, public_key);
+    assert_eq!(report.status, SignatureStatus::Valid, "{:?}", report.status_reason);
+    assert_eq!(report.integrity, Some(Integrity::Intact));
+    assert!(report.covers_whole_document);
+}
+
+#[test]
+fn a_signature_leaving_out_more_than_its_value_is_invalid() {
+    // Sixteen bytes before the value, of the signature dictionary, are left
+    // out as well, so they could be changed.
+    let (pdf, public_key) = signed(|start, end, length| [0, start - 16, end, length - end], |_| {});
+    let report = verify(&pdf, public_key);
+    assert_eq!(report.status, SignatureStatus::Invalid);
+    assert_eq!(report.integrity, Some(Integrity::ModifiedAfterSigning));
+    assert!(report.status_reason.unwrap().contains("does not cover"));
+
+    // Or the start of the file.
+    let (pdf, public_key) = signed(|start, end, length| [16, start - 16, end, length - end], |_| {});
+    assert_eq!(verify(&pdf, public_key).status, SignatureStatus::Invalid);
+
+    // Or part of the value: a gap shorter than the value's hex string.
+    let (pdf, public_key) = signed(|start, end, length| [0, start + 2, end, length - end], |_| {});
+    assert_eq!(verify(&pdf, public_key).status, SignatureStatus::Invalid);
+}
+
+#[test]
+fn altered_signed_attributes_are_invalid() {
+    // Backdate the signing time after the fact.
+    let (pdf, public_key) = signed(whole, |signed_data| {
+        let mut signer_infos = signed_data.signer_infos.0.clone().into_vec();
+        let attributes = signer_infos[0].signed_attrs.take().unwrap();
+        let attributes = attributes
+            .into_vec()
+            .into_iter()
+            .map(|mut attribute| {
+                if attribute.oid == rfc5911::ID_SIGNING_TIME {
+                    let time = Time::UtcTime(UtcTime::from_unix_duration(Duration::from_secs(978_307_200)).unwrap());
+                    attribute.values = SetOfVec::try_from(vec![Any::encode_from(&time).unwrap()]).unwrap();
+                }
+                attribute
+            })
+            .collect::<Vec<_>>();
+        signer_infos[0].signed_attrs = Some(SetOfVec::try_from(attributes).unwrap());
+        signed_data.signer_infos = SignerInfos(SetOfVec::try_from(signer_infos).unwrap());
+    });
+    let report = verify(&pdf, public_key);
+    assert_eq!(report.status, SignatureStatus::Invalid, "{:?}", report.status_reason);
+}
@@ -0,0 +1,195 @@
+//! A revoked key's signatures are only accepted when a trusted timestamp
+//! authority vouches they were made before the revocation: the time the
+//! signer's own clock wrote can be set to anything.
+
+mod common;
+
+use chrono::{Duration, Utc};
+use cms::builder::{SignedDataBuilder, SignerInfoBuilder};
+use cms::cert::{CertificateChoices, IssuerAndSerialNumber};
+use cms::signed_data::{EncapsulatedContentInfo, SignerIdentifier};
+use p384::ecdsa::{DerSignature, SigningKey};
+use sigillum_core::{api, KeyAlgorithm, KeyPair, Revocation, SignOptions, SignatureStatus, TrustedKey, VerifyOptions};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::str::FromStr;
+use x509_cert::builder::{Builder, CertificateBuilder, Profile};
+use x509_cert::der::asn1::{GeneralizedTime, Int};
+use x509_cert::der::oid::db::{rfc5280, rfc5912};
+use x509_cert::der::oid::ObjectIdentifier;
+use x509_cert::der::pem::LineEnding;
+use x509_cert::der::{Any, Decode, Encode, EncodePem, Length, Tag};
+use x509_cert::ext::pkix::ExtendedKeyUsage;
+use x509_cert::name::Name;
+use x509_cert::serial_number::SerialNumber;
+use x509_cert::spki::{AlgorithmIdentifierOwned, SubjectPublicKeyInfoOwned};
+use x509_cert::time::Validity;
+use x509_cert::Certificate;
+use x509_tsp::{TimeStampReq, TspVersion, TstInfo};
+
+const ID_CT_TST_INFO: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.113549.1.9.16.1.4");
+
+/// A timestamp authority answering requests on localhost, and the PEM
+/// root its certificate chains to.
+struct Authority {
+    url: String,
+    root: String,
+}
+
+fn authority() -> Authority {
+    let root_key = SigningKey::random(&mut rand::rngs::OsRng);
+    let key = SigningKey::random(&mut rand::rngs::OsRng);
+    let validity = || Validity::from_now(std::time::Duration::from_secs(3600)).unwrap();
+    let spki = |key: &SigningKey| SubjectPublicKeyInfoOwned::from_key(*key.verifying_key()).unwrap();
+
+    let root_name = Name::from_str("CN=Test Root").unwrap();
+    let root = CertificateBuilder::new(Profile::Root, SerialNumber::from(1u32), validity(), root_name.clone(), spki(&root_key), &root_key)
+        .unwrap()
+        .build::<DerSignature>()
+        .unwrap();
+    let profile = Profile::Leaf {
+        issuer: root_name,
+        enable_key_agreement: false,
+        enable_key_encipherment: false,
+    };
+    let subject = Name::from_str("CN=Test TSA").unwrap();
+    let mut builder = CertificateBuilder::new(profile, SerialNumber::from(2u32), validity(), subject, spki(&key), &root_key).unwrap();
+    builder.add_extension(&ExtendedKeyUsage(vec![rfc5280::ID_KP_TIME_STAMPING])).unwrap();
+    let certificate = builder.build::<DerSignature>().unwrap();
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/", listener.local_addr().unwrap());
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line.trim().is_empty() {
+                    break;
+                }
+                if let Some((name, value)) = line.split_once(':') {
+                    if name.eq_ignore_ascii_case("content-length") {
+                        length = value.trim().parse().unwrap();
+                    }
+                }
+            }
+            let mut request = vec![0; length];
+            reader.read_exact(&mut request).unwrap();
+            let response = response(&TimeStampReq::from_der(&request).unwrap(), &key, &certificate);
+            write!(stream, "HTTP/1.1 200 OK\r\nContent-Type: application/timestamp-reply\r\nContent-Length: {}\r\nConnection: close\r\n\r\n", response.len()).unwrap();
+            stream.write_all(&response).unwrap();
+        }
+    });
+    Authority {
+        url,
+        root: root.to_pem(LineEnding::LF).unwrap(),
+    }
+}
+
+/// A granted `TimeStampResp` to `request`, timestamped now.
+fn response(request: &TimeStampReq, key: &SigningKey, certificate: &Certificate) -> Vec<u8> {
+    let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap();
+    let tst_info = TstInfo {
+        version: TspVersion::V1,
+        policy: ObjectIdentifier::new_unwrap("1.2.3.4"),
+        message_imprint: request.message_imprint.clone(),
+        serial_number: Int::new(&[1]).unwrap(),
+        gen_time: GeneralizedTime::from_unix_duration(std::time::Duration::from_secs(now.as_secs())).unwrap(),
+        accuracy: None,
+        ordering: false,
+        nonce: request.nonce.clone(),
+        tsa: None,
+        extensions: None,
+    };
+    let content = EncapsulatedContentInfo {
+        econtent_type: ID_CT_TST_INFO,
+        econtent: Some(Any::new(Tag::OctetString, tst_info.to_der().unwrap()).unwrap()),
+    };
+    let digest_algorithm = AlgorithmIdentifierOwned {
+        oid: rfc5912::ID_SHA_384,
+        parameters: None,
+    };
+    let sid = SignerIdentifier::IssuerAndSerialNumber(IssuerAndSerialNumber {
+        issuer: certificate.tbs_certificate.issuer.clone(),
+        serial_number: certificate.tbs_certificate.serial_number.clone(),
+    });
+    let signer_info = SignerInfoBuilder::new(key, sid, digest_algorithm.clone(), &content, None).unwrap();
+    let token = SignedDataBuilder::new(&content)
+        .add_digest_algorithm(digest_algorithm)
+        .unwrap()
+        .add_certificate(CertificateChoices::Certificate(certificate.clone()))
+        .unwrap()
+        .add_signer_info::<_, DerSignature>(signer_info)
+        .unwrap()
+        .build()
+        .unwrap()
+        .to_der()
+        .unwrap();
+
+    // TimeStampResp ::= SEQUENCE { status PKIStatusInfo, timeStampToken }
+    let granted = [0x30, 0x03, 0x02, 0x01, 0x00];
+    let body = [&granted[..], &token].concat();
+    let length = Length::try_from(body.len()).unwrap().to_der().unwrap();
+    [&[0x30][..], &length, &body].concat()
+}
+
+/// `key` as a trusted key revoked `from_now` from now.
+fn revoked(key: &KeyPair, from_now: Duration) -> TrustedKey {
+    TrustedKey {
+        name: "alice".to_string(),
+        public_key: key.public_key.clone(),
+        algorithm: key.algorithm,
+        revoked: Some(Revocation {
+            date: Utc::now() + from_now,
+            reason: "key compromised".to_string(),
+        }),
+    }
+}
+
+fn verify(pdf: &[u8], trusted: TrustedKey, tsa_roots: Vec<String>) -> api::VerifyReport {
+    let mut opts = VerifyOptions::default();
+    opts.trusted_keys = vec![trusted];
+    opts.tsa_roots = tsa_roots;
+    api::verify(pdf, opts).unwrap()
+}
+
+#[test]
+fn a_claimed_time_before_the_revocation_is_not_enough() {
+    let key = KeyPair::generate(KeyAlgorithm::P256).unwrap();
+    for pades in [false, true] {
+        let mut opts = SignOptions::default();
+        opts.pades = pades;
+        let pdf = api::sign(&common::sample_pdf(1), opts, &key).unwrap().pdf;
+
+        // The signature says it was made now, a day before the revocation.
+        let report = verify(&pdf, revoked(&key, Duration::days(1)), Vec::new());
+        assert_eq!(report.status(), SignatureStatus::Invalid, "pades: {}", pades);
+        let reasons = report.signatures.iter().map(|s| &s.status_reason).chain(report.foreign_signatures.iter().map(|s| &s.status_reason));
+        assert!(reasons.flatten().any(|reason| reason.contains("was revoked on")), "pades: {}", pades);
+    }
+}
+
+#[test]
+fn a_trusted_timestamp_before_the_revocation_is() {
+    let key = KeyPair::generate(KeyAlgorithm::P256).unwrap();
+    for pades in [false, true] {
+        let authority = authority();
+        let mut opts = SignOptions::default();
+        opts.pades = pades;
+        opts.tsa_url = Some(authority.url.clone());
+        let pdf = api::sign(&common::sample_pdf(1), opts, &key).unwrap().pdf;
+
+        let report = verify(&pdf, revoked(&key, Duration::days(1)), vec![authority.root.clone()]);
+        assert_eq!(report.status(), SignatureStatus::Valid, "pades: {}", pades);
+
+        // Not from an authority verifying trusts, the timestamp says nothing.
+        let report = verify(&pdf, revoked(&key, Duration::days(1)), Vec::new());
+        assert_eq!(report.status(), SignatureStatus::Invalid, "pades: {}", pades);
+
+        let report = verify(&pdf, revoked(&key, -Duration::days(1)), vec![authority.root]);
+        assert_eq!(report.status(), SignatureStatus::Invalid, "pades: {}", pades);
+    }
+}
@@ -0,0 +1,88 @@
+//! Any `threshold` shares of a split rebuild the key; fewer, or shares that
+//! do not belong together, do not.
+
+mod common;
+
+use sigillum_core::shamir::{self, KeyShare};
+use sigillum_core::ShamirScheme;
+
+fn split(scheme: &str) -> (Vec<u8>, Vec<KeyShare>) {
+    let private_key = common::test_key().private_key(None).unwrap();
+    let shares = shamir::split(&private_key, scheme.parse().unwrap()).unwrap();
+    (private_key.to_pkcs8_der().unwrap().to_vec(), shares)
+}
+
+/// Every subset of `shares`, as the shares it picks.
+fn subsets(shares: &[KeyShare]) -> impl Iterator<Item = Vec<KeyShare>> + '_ {
+    (0u32..1 << shares.len()).map(|mask| {
+        shares
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| mask & (1 << i) != 0)
+            .map(|(_, share)| share.clone())
+            .collect()
+    })
+}
+
+fn combine_error(shares: &[KeyShare]) -> String {
+    match shamir::combine(shares) {
+        Ok(_) => panic!("{} shares rebuilt the key", shares.len()),
+        Err(e) => e.to_string(),
+    }
+}
+
+#[test]
+fn every_subset_of_threshold_shares_rebuilds_the_key() {
+    for scheme in ["2of3", "3of5"] {
+        let (secret, shares) = split(scheme);
+        let threshold = scheme.parse::<ShamirScheme>().unwrap().threshold as usize;
+        for subset in subsets(&shares).filter(|subset| subset.len() >= threshold) {
+            let rebuilt = shamir::combine(&subset).unwrap();
+            assert_eq!(rebuilt.to_pkcs8_der().unwrap().to_vec(), secret, "{} from {} shares", scheme, subset.len());
+        }
+    }
+}
+
+#[test]
+fn fewer_than_threshold_shares_are_refused() {
+    let (_, shares) = split("3of5");
+    for subset in subsets(&shares).filter(|subset| subset.len() < 3) {
+        combine_error(&subset);
+    }
+}
+
+#[test]
+fn shares_of_different_splits_are_refused() {
+    let private_key = common::test_key().private_key(None).unwrap();
+    let scheme: ShamirScheme = "2of3".parse().unwrap();
+    let first = shamir::split(&private_key, scheme).unwrap();
+    let second = shamir::split(&private_key, scheme).unwrap();
+    let error = combine_error(&[first[0].clone(), second[1].clone()]);
+    assert!(error.contains("same split"), "{}", error);
+}
+
+#[test]
+fn a_share_given_twice_counts_once() {
+    let (_, shares) = split("2of3");
+    let error = combine_error(&[shares[0].clone(), shares[0].clone()]);
+    assert!(error.contains("only 1 were given"), "{}", error);
+}
+
+#[test]
+fn two_shares_with_one_index_are_refused() {
+    let (_, shares) = split("2of3");
+    let mut forged = shares[1].clone();
+    forged.index = shares[0].index;
+    let error = combine_error(&[shares[0].clone(), forged, shares[2].clone()]);
+    assert!(error.contains("claim to be share 1"), "{}", error);
+}
+
+#[test]
+fn shares_survive_their_files() {
+    let (secret, shares) = split("2of3");
+    let read: Vec<KeyShare> = shares[1..]
+        .iter()
+        .map(|share| KeyShare::from_json(&share.to_json().unwrap()).unwrap())
+        .collect();
+    assert_eq!(shamir::combine(&read).unwrap().to_pkcs8_der().unwrap().to_vec(), secret);
+}
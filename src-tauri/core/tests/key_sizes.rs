@@ -0,0 +1,59 @@
+//! RSA keys are only taken in the sizes Sigillum generates, so a weak key is
+//! neither imported nor trusted to verify.
+
+use rand::rngs::OsRng;
+use rsa::pkcs8::{EncodePrivateKey, EncodePublicKey, LineEnding};
+use rsa::{RsaPrivateKey, RsaPublicKey};
+use sigillum_core::keys::PublicKey;
+use sigillum_core::{KeyAlgorithm, KeyPair};
+
+fn rsa_key(bits: usize) -> RsaPrivateKey {
+    RsaPrivateKey::new(&mut OsRng, bits).unwrap()
+}
+
+#[test]
+fn a_2048_bit_key_is_imported_as_such() {
+    let der = rsa_key(2048).to_pkcs8_der().unwrap();
+    let keypair = KeyPair::import(der.as_bytes(), None).unwrap();
+    assert_eq!(keypair.algorithm, KeyAlgorithm::Rsa2048);
+}
+
+#[test]
+fn smaller_keys_are_not_imported() {
+    for bits in [1024, 1536] {
+        let der = rsa_key(bits).to_pkcs8_der().unwrap();
+        let error = KeyPair::import(der.as_bytes(), None).unwrap_err();
+        assert!(error.message().contains(&format!("{} bits", bits)), "{}", error.message());
+    }
+}
+
+#[test]
+fn smaller_keys_have_no_algorithm_to_verify_with() {
+    let pem = RsaPublicKey::from(&rsa_key(1024)).to_public_key_pem(LineEnding::LF).unwrap();
+    let public_key = PublicKey::from_public_key_pem(&pem).unwrap();
+    assert!(public_key.algorithm().is_err());
+}
+
+#[test]
+fn small_pkcs1_keys_are_refused_with_their_size() {
+    use rsa::pkcs1::EncodeRsaPrivateKey;
+    let pem = rsa_key(1024).to_pkcs1_pem(LineEnding::LF).unwrap();
+    let error = match sigillum_core::keys::PrivateKey::import(pem.as_bytes(), None) {
+        Err(error) => error,
+        Ok(_) => panic!("1024-bit key was accepted"),
+    };
+    assert!(error.message().contains("only 1024 bits"), "{}", error);
+}
+
+#[test]
+fn public_keys_give_their_real_size() {
+    let pem = RsaPublicKey::from(&rsa_key(1024)).to_public_key_pem(LineEnding::LF).unwrap();
+    assert_eq!(PublicKey::from_public_key_pem(&pem).unwrap().bits(), 1024);
+}
+
+#[test]
+fn sizes_sigillum_does_not_use_are_named_in_the_error() {
+    let pem = RsaPublicKey::from(&rsa_key(2560)).to_public_key_pem(LineEnding::LF).unwrap();
+    let error = PublicKey::from_public_key_pem(&pem).unwrap().algorithm().unwrap_err();
+    assert!(error.message().contains("RSA keys of 2560 bits are not supported"), "{}", error);
+}
@@ -0,0 +1,126 @@
+//! OpenPGP keys and signatures are the ones GnuPG makes and accepts.
+//!
+//! `fixtures/plain.pdf.asc` is a signature over `fixtures/plain.pdf` by the
+//! Ed25519 key in `fixtures/openpgp-key.pem`, created 2024-01-01; GnuPG 2.2
+//! accepts it with `gpg --verify` once `fixtures/openpgp-key.asc` is imported.
+
+mod common;
+
+use sigillum_core::keys::PublicKey;
+use sigillum_core::{openpgp, HashAlgorithm, KeyAlgorithm, KeyPair};
+use std::path::Path;
+use std::process::Command;
+
+const FIXTURE_FINGERPRINT: &str = "BDC8D3E373E703275D36FB40C3A0BF90D0EC68DC";
+
+fn fixture(name: &str) -> Vec<u8> {
+    std::fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures").join(name)).unwrap()
+}
+
+fn fixture_text(name: &str) -> String {
+    String::from_utf8(fixture(name)).unwrap()
+}
+
+/// A keypair presenting `public_key`, created at `created`: all a fingerprint
+/// depends on.
+fn published(public_key: &str, created: &str) -> KeyPair {
+    let mut key = common::test_key();
+    key.public_key = public_key.to_string();
+    key.created = Some(created.parse().unwrap());
+    key
+}
+
+/// A scratch GnuPG home, or `None` when GnuPG is not installed.
+fn gnupg_home(name: &str) -> Option<std::path::PathBuf> {
+    if Command::new("gpg").arg("--version").output().is_err() {
+        eprintln!("gpg is not installed; skipping");
+        return None;
+    }
+    let home = std::env::temp_dir().join(format!("sigillum-{}-{}", std::process::id(), name));
+    let _ = std::fs::remove_dir_all(&home);
+    std::fs::create_dir_all(&home).unwrap();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&home, std::fs::Permissions::from_mode(0o700)).unwrap();
+    }
+    Some(home)
+}
+
+fn gpg(home: &Path, args: &[&str]) -> std::process::Output {
+    Command::new("gpg").arg("--batch").arg("--homedir").arg(home).args(args).output().unwrap()
+}
+
+#[test]
+fn fingerprint_matches_the_rfc_9580_sample_key() {
+    // RFC 9580, appendix A.3: the sample version 4 Ed25519 key.
+    let key = published(
+        "-----BEGIN PUBLIC KEY-----\nMCowBQYDK2VwAyEAPwmJlL3ZFu1AUxl5NOSofIBzOhKA1i+AEJkuQ+47JAY=\n-----END PUBLIC KEY-----\n",
+        "2014-08-19T14:28:27Z",
+    );
+    assert_eq!(openpgp::fingerprint(&key).unwrap(), "C959BDBAFA32A2F89A153B678CFDE12197965A9A");
+}
+
+#[test]
+fn fingerprint_matches_the_one_gnupg_gives_the_fixture_key() {
+    let key = published(&fixture_text("openpgp-key.pem"), "2024-01-01T00:00:00Z");
+    assert_eq!(openpgp::fingerprint(&key).unwrap(), FIXTURE_FINGERPRINT);
+}
+
+#[test]
+fn the_fixture_signature_verifies() {
+    let public_key = PublicKey::from_public_key_pem(&fixture_text("openpgp-key.pem")).unwrap();
+    let signature = fixture_text("plain.pdf.asc");
+    let checked = openpgp::verify_detached(&fixture("plain.pdf")[..], &signature, &public_key).unwrap();
+    assert!(checked.valid);
+    assert_eq!(checked.hash_algorithm, HashAlgorithm::Sha256);
+    assert_eq!(checked.issuer.as_deref(), Some(FIXTURE_FINGERPRINT));
+
+    let mut changed = fixture("plain.pdf");
+    changed.push(b'\n');
+    assert!(!openpgp::verify_detached(&changed[..], &signature, &public_key).unwrap().valid);
+}
+
+#[test]
+fn gnupg_accepts_the_fixture_signature() {
+    let Some(home) = gnupg_home("openpgp-fixture") else { return };
+    let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+    let imported = gpg(&home, &["--import", fixtures.join("openpgp-key.asc").to_str().unwrap()]);
+    assert!(imported.status.success(), "{}", String::from_utf8_lossy(&imported.stderr));
+
+    let verified = gpg(
+        &home,
+        &[
+            "--verify",
+            fixtures.join("plain.pdf.asc").to_str().unwrap(),
+            fixtures.join("plain.pdf").to_str().unwrap(),
+        ],
+    );
+    let _ = std::fs::remove_dir_all(&home);
+    assert!(verified.status.success(), "{}", String::from_utf8_lossy(&verified.stderr));
+}
+
+#[test]
+fn gnupg_accepts_fresh_keys_and_signatures() {
+    let Some(home) = gnupg_home("openpgp-fresh") else { return };
+    let document = home.join("document.pdf");
+    std::fs::write(&document, common::sample_pdf(1)).unwrap();
+
+    for algorithm in [KeyAlgorithm::Ed25519, KeyAlgorithm::P256, KeyAlgorithm::Rsa2048] {
+        let key = KeyPair::generate(algorithm).unwrap();
+        let key_file = home.join("key.asc");
+        std::fs::write(&key_file, openpgp::public_key_block(&key, None, "Jane Doe <jane@example.com>").unwrap()).unwrap();
+        let imported = gpg(&home, &["--import", key_file.to_str().unwrap()]);
+        assert!(imported.status.success(), "{:?}: {}", algorithm, String::from_utf8_lossy(&imported.stderr));
+
+        let signature = openpgp::sign_detached(&std::fs::read(&document).unwrap()[..], &key, None, HashAlgorithm::Sha512).unwrap();
+        let signature_file = openpgp::signature_path_for(&document);
+        std::fs::write(&signature_file, signature).unwrap();
+        let verified = gpg(&home, &["--verify", signature_file.to_str().unwrap(), document.to_str().unwrap()]);
+        assert!(verified.status.success(), "{:?}: {}", algorithm, String::from_utf8_lossy(&verified.stderr));
+
+        let listed = gpg(&home, &["--with-colons", "--fingerprint", &openpgp::fingerprint(&key).unwrap()]);
+        assert!(listed.status.success(), "{:?}: {}", algorithm, String::from_utf8_lossy(&listed.stderr));
+    }
+    let _ = std::fs::remove_dir_all(&home);
+}
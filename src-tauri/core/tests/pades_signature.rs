@@ -0,0 +1,90 @@
+//! The standard signature is a detached CMS `SignedData` over every byte of
+//! the signed file except its own value.
+
+mod common;
+
+use cms::cert::CertificateChoices;
+use cms::content_info::ContentInfo;
+use cms::signed_data::SignedData;
+use lopdf::{Document, Object};
+use sha2::{Digest, Sha256};
+use sigillum_core::keys::PublicKey;
+use sigillum_core::{api, KeyAlgorithm, KeyPair, SignOptions, SignatureStatus, VerifyOptions};
+use x509_cert::der::oid::db::{rfc5911, rfc6268};
+use x509_cert::der::{Decode, Encode, SliceReader};
+
+/// The `/ByteRange` and `/Contents` of the one signature dictionary in `pdf`.
+fn signature_dictionary(pdf: &[u8]) -> (Vec<usize>, Vec<u8>) {
+    let doc = Document::load_mem(pdf).unwrap();
+    let mut found = doc.objects.values().filter_map(|object| {
+        let dict = object.as_dict().ok()?;
+        (dict.get(b"Type").ok()?.as_name().ok()? == b"Sig").then_some(dict)
+    });
+    let dict = found.next().expect("no signature dictionary");
+    assert!(found.next().is_none(), "more than one signature dictionary");
+    let range = dict
+        .get(b"ByteRange")
+        .unwrap()
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|value| value.as_i64().unwrap() as usize)
+        .collect();
+    let contents = match dict.get(b"Contents").unwrap() {
+        Object::String(bytes, _) => bytes.clone(),
+        other => panic!("/Contents is {:?}", other),
+    };
+    (range, contents)
+}
+
+#[test]
+fn the_standard_signature_covers_all_but_its_value() {
+    let key = KeyPair::generate(KeyAlgorithm::P256).unwrap();
+    let mut opts = SignOptions::default();
+    opts.pades = true;
+    let pdf = api::sign(&common::sample_pdf(1), opts, &key).unwrap().pdf;
+
+    let (range, contents) = signature_dictionary(&pdf);
+    let [start, first_length, second_start, second_length] = range[..] else {
+        panic!("/ByteRange is {:?}", range)
+    };
+    assert_eq!(start, 0);
+    assert_eq!(second_start + second_length, pdf.len());
+    // The gap is exactly the `/Contents` hex string, delimiters included.
+    let gap = &pdf[first_length..second_start];
+    assert_eq!(gap.first(), Some(&b'<'));
+    assert_eq!(gap.last(), Some(&b'>'));
+    assert_eq!(hex::decode(&gap[1..gap.len() - 1]).unwrap(), contents);
+
+    // The value is padded with zeros after the DER.
+    let content_info = ContentInfo::decode(&mut SliceReader::new(&contents).unwrap()).unwrap();
+    assert_eq!(content_info.content_type, rfc5911::ID_SIGNED_DATA);
+    let signed_data: SignedData = content_info.content.decode_as().unwrap();
+    assert!(signed_data.encap_content_info.econtent.is_none(), "the signature is not detached");
+    assert_eq!(signed_data.signer_infos.0.len(), 1);
+
+    let mut covered = Sha256::new();
+    covered.update(&pdf[..first_length]);
+    covered.update(&pdf[second_start..]);
+    let signer = signed_data.signer_infos.0.get(0).unwrap();
+    let message_digest = signer
+        .signed_attrs
+        .as_ref()
+        .unwrap()
+        .iter()
+        .find(|attribute| attribute.oid == rfc6268::ID_MESSAGE_DIGEST)
+        .expect("no message digest");
+    let digest = message_digest.values.get(0).unwrap().decode_as::<x509_cert::der::asn1::OctetString>().unwrap();
+    assert_eq!(digest.as_bytes(), covered.finalize().as_slice());
+
+    let certificates = signed_data.certificates.as_ref().unwrap();
+    let Some(CertificateChoices::Certificate(certificate)) = certificates.0.get(0) else {
+        panic!("no signer certificate")
+    };
+    let public_key = PublicKey::from_public_key_pem(&key.public_key).unwrap().to_public_key_der().unwrap();
+    assert_eq!(certificate.tbs_certificate.subject_public_key_info.to_der().unwrap(), public_key);
+
+    let mut verify = VerifyOptions::default();
+    verify.public_key = Some(key.public_key.clone());
+    assert_eq!(api::verify(&pdf, verify).unwrap().status(), SignatureStatus::Valid);
+}
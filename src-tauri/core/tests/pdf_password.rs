@@ -0,0 +1,78 @@
+//! A password-protected document can only be signed with its password, and
+//! the protection signing adds is the standard kind other readers open.
+
+mod common;
+
+use lopdf::Document;
+use sigillum_core::{api, KeyPair, ResignPolicy, SigillumError, SignOptions, SignatureStatus, VerifyOptions};
+
+/// The sample document, signed by `key` and protected with `password`.
+fn protected(key: &KeyPair, password: &str) -> Vec<u8> {
+    let mut opts = SignOptions::default();
+    opts.output_password = Some(password.to_string().into());
+    api::sign(&common::sample_pdf(1), opts, key).unwrap().pdf
+}
+
+/// Signs `pdf` again, opening it with `password`.
+fn sign_again(pdf: &[u8], key: &KeyPair, password: Option<&str>) -> Result<Vec<u8>, SigillumError> {
+    let mut opts = SignOptions::default();
+    opts.resign_policy = ResignPolicy::Append;
+    opts.password = password.map(|password| password.to_string().into());
+    Ok(api::sign(pdf, opts, key)?.pdf)
+}
+
+/// Whether lopdf, decrypting `pdf` itself with `password`, finds the sample
+/// page's text.
+fn lopdf_opens(pdf: &[u8], password: &[u8]) -> bool {
+    let mut doc = Document::load_mem(pdf).unwrap();
+    if doc.decrypt(password).is_err() {
+        return false;
+    }
+    let page = doc.get_pages()[&1];
+    let content = doc.get_page_content(page).unwrap();
+    content.windows(12).any(|window| window == b"Hello page 1")
+}
+
+#[test]
+fn signing_needs_the_password() {
+    let key = common::test_key();
+    let pdf = protected(&key, "secret");
+
+    let signed = sign_again(&pdf, &key, Some("secret")).unwrap();
+    let mut opts = VerifyOptions::default();
+    opts.public_key = Some(key.public_key.clone());
+    opts.password = Some("secret".to_string().into());
+    let report = api::verify(&signed, opts).unwrap();
+    assert_eq!(report.signatures.len(), 2);
+    assert_eq!(report.status(), SignatureStatus::Valid);
+
+    assert!(matches!(sign_again(&pdf, &key, Some("Secret")), Err(SigillumError::WrongPassword(_))));
+    assert!(matches!(sign_again(&pdf, &key, None), Err(SigillumError::PasswordRequired(_))));
+}
+
+#[test]
+fn lopdf_opens_what_signing_protects() {
+    let key = common::test_key();
+    let pdf = protected(&key, "secret");
+    assert!(Document::load_mem(&pdf).unwrap().is_encrypted());
+    assert!(lopdf_opens(&pdf, b"secret"));
+    assert!(!lopdf_opens(&pdf, b"Secret"));
+
+    // Appending a signature keeps it open to the same password.
+    let signed = sign_again(&pdf, &key, Some("secret")).unwrap();
+    assert!(lopdf_opens(&signed, b"secret"));
+}
+
+#[test]
+fn passwords_are_in_pdf_doc_encoding() {
+    let key = common::test_key();
+    let pdf = protected(&key, "pässwort");
+    assert!(lopdf_opens(&pdf, b"p\xe4sswort"));
+    assert!(!lopdf_opens(&pdf, "pässwort".as_bytes()));
+    sign_again(&pdf, &key, Some("pässwort")).unwrap();
+
+    let mut opts = SignOptions::default();
+    opts.output_password = Some("密码".to_string().into());
+    let error = api::sign(&common::sample_pdf(1), opts, &key).unwrap_err().to_string();
+    assert!(error.contains("Latin-1"), "{}", error);
+}
@@ -0,0 +1,101 @@
+//! Signing and verifying the way another Rust program would, through
+//! nothing but the crate's public API.
+
+use sigillum_core::api::{self, DetachedSignature};
+use sigillum_core::{KeyAlgorithm, KeyPair, SigillumError, SignOptions, SignatureStatus, VerifyOptions};
+use std::fs;
+use std::io::Cursor;
+
+const PDF: &[u8] = include_bytes!("fixtures/plain.pdf");
+
+fn options() -> SignOptions {
+    let mut opts = SignOptions::default();
+    opts.name = "Alice Jones".to_string();
+    opts.extra = "Finance".to_string();
+    opts
+}
+
+fn checked_against(key: &KeyPair) -> VerifyOptions {
+    let mut opts = VerifyOptions::default();
+    opts.public_key = Some(key.public_key.clone());
+    opts
+}
+
+#[test]
+fn sign_and_verify_a_pdf() {
+    for algorithm in [KeyAlgorithm::Ed25519, KeyAlgorithm::P256] {
+        let key = KeyPair::generate(algorithm).unwrap();
+        let signed = api::sign(PDF, options(), &key).unwrap();
+        assert!(signed.pdf.starts_with(PDF));
+        assert_eq!(signed.signature_info.signer_name, "Alice Jones");
+        assert_eq!(signed.signature_info.algorithm, Some(algorithm));
+
+        let report = api::verify(&signed.pdf, checked_against(&key)).unwrap();
+        assert_eq!(report.status(), SignatureStatus::Valid);
+        assert_eq!(report.signatures[0].signature_info.extra, "Finance");
+
+        let streamed = api::verify_reader(Cursor::new(&signed.pdf), checked_against(&key)).unwrap();
+        assert_eq!(streamed.status(), SignatureStatus::Valid);
+    }
+}
+
+#[test]
+fn another_key_does_not_verify() {
+    let signed = api::sign(PDF, options(), &KeyPair::generate(KeyAlgorithm::Ed25519).unwrap()).unwrap();
+    let other = KeyPair::generate(KeyAlgorithm::Ed25519).unwrap();
+    let report = api::verify(&signed.pdf, checked_against(&other)).unwrap();
+    assert_ne!(report.status(), SignatureStatus::Valid);
+}
+
+#[test]
+fn changes_after_signing_are_caught() {
+    let key = KeyPair::generate(KeyAlgorithm::Ed25519).unwrap();
+    let mut pdf = api::sign(PDF, options(), &key).unwrap().pdf;
+    let at = pdf.windows(9).position(|window| window == b"Quarterly").unwrap();
+    pdf[at..at + 9].copy_from_slice(b"Quarterlx");
+    let report = api::verify(&pdf, checked_against(&key)).unwrap();
+    assert_eq!(report.status(), SignatureStatus::Invalid);
+}
+
+#[test]
+fn an_unsigned_pdf_reports_unsigned() {
+    let report = api::verify(PDF, VerifyOptions::default()).unwrap();
+    assert!(!report.is_signed());
+    assert_eq!(report.status(), SignatureStatus::Unsigned);
+}
+
+#[test]
+fn failures_carry_a_stable_code() {
+    let key = KeyPair::generate(KeyAlgorithm::Ed25519).unwrap();
+    let error = api::sign(b"not a pdf", options(), &key).unwrap_err();
+    assert!(matches!(error, SigillumError::InvalidPdf(_)));
+    assert_eq!(error.code(), "invalid_pdf");
+}
+
+#[test]
+fn sign_and_verify_any_file_detached() {
+    let key = KeyPair::generate(KeyAlgorithm::Ed25519).unwrap();
+    let path = std::env::temp_dir().join(format!("sigillum-{}-public-api.txt", std::process::id()));
+    fs::write(&path, "Minutes of the meeting\n").unwrap();
+
+    let signature = api::sign_detached(&path, &options(), &key).unwrap();
+    let signature_path = DetachedSignature::path_for(&path);
+    signature.save(&signature_path).unwrap();
+    let loaded = DetachedSignature::load(&signature_path).unwrap();
+    let intact = api::verify_detached(&path, &loaded, checked_against(&key)).unwrap();
+    fs::write(&path, "Minutes of another meeting\n").unwrap();
+    let changed = api::verify_detached(&path, &loaded, checked_against(&key)).unwrap();
+    fs::remove_file(&path).unwrap();
+    fs::remove_file(&signature_path).unwrap();
+
+    assert_eq!(intact.status, SignatureStatus::Valid);
+    assert_eq!(changed.status, SignatureStatus::Invalid);
+}
+
+#[test]
+fn debug_output_leaves_out_the_private_key() {
+    let key = KeyPair::generate(KeyAlgorithm::Ed25519).unwrap();
+    let debug = format!("{:?}", key);
+    assert!(debug.contains("<redacted>"));
+    assert!(!debug.contains(&format!("{:?}", key.private_key.as_str())));
+}
@@ -0,0 +1,100 @@
+//! Recovery phrases are BIP39 phrases of a key's seed, checksum included.
+
+use sigillum_core::keys::PrivateKey;
+use sigillum_core::{mnemonic, KeyAlgorithm};
+
+/// The 256-bit vectors published with the BIP39 reference implementation
+/// (trezor/python-mnemonic, vectors.json).
+const VECTORS: &[(&str, &str)] = &[
+    (
+        "0000000000000000000000000000000000000000000000000000000000000000",
+        "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon \
+         abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon art",
+    ),
+    (
+        "7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f",
+        "legal winner thank year wave sausage worth useful legal winner thank year wave sausage worth useful \
+         legal winner thank year wave sausage worth title",
+    ),
+    (
+        "8080808080808080808080808080808080808080808080808080808080808080",
+        "letter advice cage absurd amount doctor acoustic avoid letter advice cage absurd amount doctor \
+         acoustic avoid letter advice cage absurd amount doctor acoustic bless",
+    ),
+    (
+        "ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff",
+        "zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo vote",
+    ),
+    (
+        "68a79eaca2324873eacc50cb9c6eca8cc68ea5d936f98787c60c7ebc74e6ce7c",
+        "hamster diagram private dutch cause delay private meat slide toddler razor book happy fancy gospel \
+         tennis maple dilemma loan word shrug inflict delay length",
+    ),
+];
+
+fn seed(key: &PrivateKey) -> [u8; 32] {
+    match key {
+        PrivateKey::Ed25519(key) => key.to_bytes(),
+        PrivateKey::P256(key) => key.to_bytes().into(),
+        _ => panic!("not a seeded key"),
+    }
+}
+
+fn phrase_error(phrase: &str) -> String {
+    match mnemonic::from_phrase(phrase, KeyAlgorithm::Ed25519) {
+        Ok(_) => panic!("{:?} was taken as a recovery phrase", phrase),
+        Err(e) => e.to_string(),
+    }
+}
+
+#[test]
+fn phrases_match_the_bip39_vectors() {
+    for (entropy, phrase) in VECTORS {
+        let entropy = hex::decode(entropy).unwrap();
+        let key = mnemonic::from_phrase(phrase, KeyAlgorithm::Ed25519).unwrap();
+        assert_eq!(seed(&key).as_slice(), entropy.as_slice(), "{}", phrase);
+        assert_eq!(mnemonic::to_phrase(&key).unwrap().as_str(), *phrase);
+    }
+}
+
+#[test]
+fn p256_keys_use_the_same_words() {
+    let (entropy, phrase) = VECTORS[4];
+    let key = mnemonic::from_phrase(phrase, KeyAlgorithm::P256).unwrap();
+    assert_eq!(hex::encode(seed(&key)), entropy);
+    assert_eq!(mnemonic::to_phrase(&key).unwrap().as_str(), phrase);
+
+    // Zero is not a P-256 private key.
+    let error = match mnemonic::from_phrase(VECTORS[0].1, KeyAlgorithm::P256) {
+        Ok(_) => panic!("the zero seed was taken as a P-256 key"),
+        Err(e) => e.to_string(),
+    };
+    assert!(error.contains("P-256"), "{}", error);
+}
+
+#[test]
+fn case_and_spacing_do_not_matter() {
+    let (entropy, phrase) = VECTORS[4];
+    let typed = format!("  {}\n", phrase.to_uppercase().replace(' ', "  "));
+    let key = mnemonic::from_phrase(&typed, KeyAlgorithm::Ed25519).unwrap();
+    assert_eq!(hex::encode(seed(&key)), entropy);
+}
+
+#[test]
+fn a_bad_checksum_is_rejected() {
+    // The vector with its last word, which carries the checksum, swapped for
+    // another.
+    let phrase = VECTORS[0].1.replace(" art", " abandon");
+    let error = phrase_error(&phrase);
+    assert!(error.starts_with("Invalid recovery phrase"), "{}", error);
+
+    let phrase = VECTORS[4].1.replace("hamster diagram", "diagram hamster");
+    phrase_error(&phrase);
+}
+
+#[test]
+fn phrases_of_other_lengths_and_unknown_words_are_rejected() {
+    let twelve = "legal winner thank year wave sausage worth useful legal winner thank yellow";
+    assert!(phrase_error(twelve).contains("24 words"));
+    phrase_error(&VECTORS[1].1.replace("legal", "legible"));
+}
@@ -0,0 +1,68 @@
+//! The signer details a signature covers cannot be moved between fields.
+
+mod common;
+
+use sigillum_core::api::{self, DIGEST_FORMAT};
+use sigillum_core::{SignOptions, SignatureStatus, VerifyOptions};
+
+fn signed_as(name: &str) -> (api::SignedPdf, VerifyOptions) {
+    let key = common::test_key();
+    let mut opts = SignOptions::default();
+    opts.name = name.to_string();
+    let signed = api::sign(&common::sample_pdf(1), opts, &key).unwrap();
+    let mut verify = VerifyOptions::default();
+    verify.public_key = Some(key.public_key.clone());
+    (signed, verify)
+}
+
+#[test]
+fn signs_in_the_current_format() {
+    let (signed, opts) = signed_as("Alice Jones");
+    assert_eq!(signed.signature_info.digest_format, DIGEST_FORMAT);
+    assert_eq!(api::verify(&signed.pdf, opts).unwrap().status(), SignatureStatus::Valid);
+}
+
+#[test]
+fn moving_bytes_between_fields_breaks_the_signature() {
+    let (signed, opts) = signed_as("Alice Jones");
+    let timestamp = &signed.signature_info.timestamp;
+    let (kept, moved) = timestamp.split_at(timestamp.len() - 2);
+    let pdf = common::replace_payload_text(&signed.pdf, "Signer", "Alice Jones", &format!("Alice Jones{}", kept));
+    let pdf = common::replace_payload_text(&pdf, "Timestamp", timestamp, moved);
+    assert_eq!(api::verify(&pdf, opts).unwrap().status(), SignatureStatus::Invalid);
+}
+
+#[test]
+fn moving_a_valid_time_into_the_name_breaks_the_signature() {
+    // Hashed back to back, "Alice 2001-01-01 00:00:00 UTC" + time + "" is
+    // "Alice " + "2001-01-01 00:00:00 UTC" + time: a signing time of the
+    // forger's choosing.
+    let (signed, opts) = signed_as("Alice 2001-01-01 00:00:00 UTC");
+    let timestamp = signed.signature_info.timestamp.clone();
+    let pdf = common::replace_payload_text(&signed.pdf, "Signer", "Alice 2001-01-01 00:00:00 UTC", "Alice ");
+    let pdf = common::replace_payload_text(&pdf, "Timestamp", &timestamp, "2001-01-01 00:00:00 UTC");
+    let pdf = common::replace_payload_text(&pdf, "Extra", "", &timestamp);
+    assert_eq!(api::verify(&pdf, opts).unwrap().status(), SignatureStatus::Invalid);
+}
+
+#[test]
+fn a_malformed_signing_time_is_refused() {
+    let (signed, opts) = signed_as("Alice");
+    let timestamp = signed.signature_info.timestamp.clone();
+    let pdf = common::replace_payload_text(&signed.pdf, "Timestamp", &timestamp, "yesterday");
+    let report = api::verify(&pdf, opts).unwrap();
+    assert_eq!(report.status(), SignatureStatus::Invalid);
+    assert!(report.signatures[0].status_reason.as_deref().unwrap().contains("signing time"));
+}
+
+#[test]
+fn a_signature_in_another_format_is_refused() {
+    let (signed, opts) = signed_as("Alice");
+    let from = format!("/DigestFormat {}", DIGEST_FORMAT).into_bytes();
+    let at = signed.pdf.windows(from.len()).position(|window| window == from).unwrap();
+    let mut pdf = signed.pdf.clone();
+    pdf[at + from.len() - 1] = b'1';
+    let report = api::verify(&pdf, opts).unwrap();
+    assert_eq!(report.status(), SignatureStatus::Invalid);
+    assert!(report.signatures[0].status_reason.as_deref().unwrap().contains("format 1"));
+}
@@ -0,0 +1,81 @@
+//! The update that adds a signature is covered by it, so nothing in that
+//! update can be swapped out once signed.
+
+mod common;
+
+use lopdf::{dictionary, Document, IncrementalDocument, Object, Stream};
+use sigillum_core::api::{self, Integrity};
+use sigillum_core::{SignOptions, SignatureStatus, VerifyOptions};
+
+fn verify_opts(key: &sigillum_core::KeyPair) -> VerifyOptions {
+    let mut opts = VerifyOptions::default();
+    opts.public_key = Some(key.public_key.clone());
+    opts
+}
+
+/// `signed` with its signing update written again, every object it defines
+/// kept as it was but page 1 drawn from new content.
+fn forge_signing_update(signed: &[u8], signed_length: usize) -> Vec<u8> {
+    let prefix = signed[..signed_length].to_vec();
+    let before = Document::load_mem(&prefix).unwrap();
+    let after = Document::load_mem(signed).unwrap();
+
+    let mut forged = IncrementalDocument::create_from(prefix, before.clone());
+    for (&id, object) in &after.objects {
+        if before.objects.get(&id) != Some(object) {
+            forged.new_document.objects.insert(id, object.clone());
+        }
+    }
+    let page_id = *after.get_pages().get(&1).unwrap();
+    let content = b"BT /F1 24 Tf 100 600 Td (PAY MALLORY 1000000) Tj ET".to_vec();
+    let content_id = forged.new_document.add_object(Stream::new(dictionary! {}, content));
+    let mut page = after.get_object(page_id).unwrap().as_dict().unwrap().clone();
+    page.set("Contents", content_id);
+    forged.new_document.objects.insert(page_id, Object::Dictionary(page));
+    forged.new_document.max_id = forged.new_document.max_id.max(after.max_id) + 1;
+    if let Ok(info) = after.trailer.get(b"Info") {
+        forged.new_document.trailer.set("Info", info.clone());
+    }
+
+    let mut pdf = Vec::new();
+    forged.save_to(&mut pdf).unwrap();
+    pdf
+}
+
+#[test]
+fn a_signing_update_that_redraws_a_page_is_caught() {
+    let key = common::test_key();
+    let signed = api::sign(&common::sample_pdf(1), SignOptions::default(), &key).unwrap();
+    let signed_length = signed.signature_info.signed_length.unwrap() as usize;
+    let forged = forge_signing_update(&signed.pdf, signed_length);
+    assert!(Document::load_mem(&forged).unwrap().extract_text(&[1]).unwrap().contains("PAY MALLORY"));
+
+    let report = api::verify(&forged, verify_opts(&key)).unwrap();
+    assert_eq!(report.status(), SignatureStatus::Invalid);
+    assert_eq!(report.signatures[0].integrity, Some(Integrity::ModifiedAfterSigning));
+}
+
+#[test]
+fn a_changed_byte_in_the_signing_update_is_caught() {
+    let key = common::test_key();
+    let signed = api::sign(&common::sample_pdf(1), SignOptions::default(), &key).unwrap();
+    let signed_length = signed.signature_info.signed_length.unwrap() as usize;
+    let mut pdf = signed.pdf.clone();
+    let at = signed_length + pdf[signed_length..].windows(3).position(|window| window == b"obj").unwrap();
+    pdf[at - 2] = if pdf[at - 2] == b'0' { b'1' } else { b'0' };
+
+    let report = api::verify(&pdf, verify_opts(&key)).unwrap();
+    assert_eq!(report.status(), SignatureStatus::Invalid);
+    assert_eq!(report.signatures[0].integrity, Some(Integrity::ModifiedAfterSigning));
+}
+
+#[test]
+fn pades_signatures_still_verify() {
+    let key = sigillum_core::KeyPair::generate(sigillum_core::KeyAlgorithm::P256).unwrap();
+    let mut opts = SignOptions::default();
+    opts.pades = true;
+    let signed = api::sign(&common::sample_pdf(1), opts, &key).unwrap();
+    let report = api::verify(&signed.pdf, verify_opts(&key)).unwrap();
+    assert_eq!(report.status(), SignatureStatus::Valid);
+    assert_eq!(report.signatures[0].integrity, Some(Integrity::Intact));
+}
@@ -0,0 +1,85 @@
+//! Verifying from a reader gives what verifying in memory gives, without
+//! holding the document in memory.
+
+mod common;
+
+use sigillum_core::{api, SignOptions, VerifyOptions};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::io::Cursor;
+
+const LARGE: usize = 24 << 20;
+
+/// Counts the bytes each thread has allocated, and the most it has held at
+/// once, so a test can see how much memory verifying takes while the other
+/// tests run beside it.
+struct CountingAllocator;
+
+thread_local! {
+    static HELD: Cell<usize> = const { Cell::new(0) };
+    static PEAK: Cell<usize> = const { Cell::new(0) };
+}
+
+fn track(change: impl Fn(usize) -> usize) {
+    // Threads being torn down have no counters left; their memory is not
+    // what is measured.
+    let _ = HELD.try_with(|held| {
+        held.set(change(held.get()));
+        let _ = PEAK.try_with(|peak| peak.set(peak.get().max(held.get())));
+    });
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        track(|held| held + layout.size());
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        track(|held| held.saturating_sub(layout.size()));
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// The most memory `f` holds at once on this thread beyond what was already
+/// held, along with its result.
+fn peak_memory<T>(f: impl FnOnce() -> T) -> (usize, T) {
+    let before = HELD.with(Cell::get);
+    PEAK.with(|peak| peak.set(before));
+    let result = f();
+    (PEAK.with(Cell::get) - before, result)
+}
+
+#[test]
+fn streaming_hashes_a_large_document_as_hashing_it_whole_does() {
+    let pdf = common::large_pdf(LARGE);
+    let streamed = api::verify_reader(Cursor::new(&pdf), VerifyOptions::default()).unwrap();
+    let in_memory = api::verify(&pdf, VerifyOptions::default()).unwrap();
+    assert_eq!(streamed.canonical_hash, in_memory.canonical_hash);
+    assert!(streamed.signatures.is_empty());
+}
+
+#[test]
+fn streaming_and_in_memory_verification_agree_on_a_large_document() {
+    let key = common::test_key();
+    let original = common::large_pdf(LARGE);
+    let signed = api::sign(&original, SignOptions::default(), &key).unwrap().pdf;
+    let mut opts = VerifyOptions::default();
+    opts.public_key = Some(key.public_key.clone());
+    opts.expected_hash = Some(api::verify(&original, VerifyOptions::default()).unwrap().canonical_hash);
+
+    let in_memory = api::verify(&signed, opts.clone()).unwrap();
+    let (peak, streamed) = peak_memory(|| api::verify_reader(Cursor::new(&signed), opts).unwrap());
+
+    assert_eq!(streamed.canonical_hash, in_memory.canonical_hash);
+    assert_eq!(streamed.hash_matches, Some(true));
+    assert_eq!(streamed.status(), in_memory.status());
+    assert_eq!(streamed.signatures.len(), 1);
+    assert_eq!(streamed.signatures[0].signature_info, in_memory.signatures[0].signature_info);
+    assert_eq!(streamed.signatures[0].status, in_memory.signatures[0].status);
+    assert_eq!(streamed.signatures[0].integrity, in_memory.signatures[0].integrity);
+    assert!(peak < signed.len() / 16, "held {} bytes at once", peak);
+}
@@ -0,0 +1,144 @@
+//! A timestamp is only trusted when its authority's certificate is for
+//! timestamping and chains to a root verifying was given.
+
+use cms::builder::{SignedDataBuilder, SignerInfoBuilder};
+use cms::cert::{CertificateChoices, IssuerAndSerialNumber};
+use cms::signed_data::{EncapsulatedContentInfo, SignerIdentifier};
+use p384::ecdsa::{DerSignature, SigningKey};
+use sha2::{Digest, Sha384};
+use sigillum_core::tsa;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime};
+use x509_cert::builder::{Builder, CertificateBuilder, Profile};
+use x509_cert::der::asn1::{GeneralizedTime, Int, OctetString};
+use x509_cert::der::oid::db::{rfc5280, rfc5912};
+use x509_cert::der::oid::ObjectIdentifier;
+use x509_cert::der::{Any, Encode, Tag};
+use x509_cert::ext::pkix::ExtendedKeyUsage;
+use x509_cert::name::Name;
+use x509_cert::serial_number::SerialNumber;
+use x509_cert::spki::{AlgorithmIdentifierOwned, SubjectPublicKeyInfoOwned};
+use x509_cert::time::Validity;
+use x509_cert::Certificate;
+use x509_tsp::{MessageImprint, TspVersion, TstInfo};
+
+const ID_CT_TST_INFO: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.113549.1.9.16.1.4");
+const SIGNATURE_VALUE: &[u8] = b"the signature value being timestamped";
+
+fn key() -> SigningKey {
+    SigningKey::random(&mut rand::rngs::OsRng)
+}
+
+fn root(key: &SigningKey) -> Certificate {
+    let subject = Name::from_str("CN=Test Root").unwrap();
+    let spki = SubjectPublicKeyInfoOwned::from_key(*key.verifying_key()).unwrap();
+    let validity = Validity::from_now(Duration::from_secs(3600)).unwrap();
+    CertificateBuilder::new(Profile::Root, SerialNumber::from(1u32), validity, subject, spki, key)
+        .unwrap()
+        .build::<DerSignature>()
+        .unwrap()
+}
+
+/// A certificate for `key` that `issuer_key` issues under `issuer`, for
+/// timestamping when `time_stamping` is set.
+fn tsa_certificate(key: &SigningKey, issuer: &Certificate, issuer_key: &SigningKey, time_stamping: bool) -> Certificate {
+    let profile = Profile::Leaf {
+        issuer: issuer.tbs_certificate.subject.clone(),
+        enable_key_agreement: false,
+        enable_key_encipherment: false,
+    };
+    let subject = Name::from_str("CN=Test TSA").unwrap();
+    let spki = SubjectPublicKeyInfoOwned::from_key(*key.verifying_key()).unwrap();
+    let validity = Validity::from_now(Duration::from_secs(3600)).unwrap();
+    let mut builder = CertificateBuilder::new(profile, SerialNumber::from(2u32), validity, subject, spki, issuer_key).unwrap();
+    if time_stamping {
+        builder.add_extension(&ExtendedKeyUsage(vec![rfc5280::ID_KP_TIME_STAMPING])).unwrap();
+    }
+    builder.build::<DerSignature>().unwrap()
+}
+
+/// A token over `SIGNATURE_VALUE` that `key`, whose certificate is `certificate`, signs.
+fn token(key: &SigningKey, certificate: &Certificate) -> Vec<u8> {
+    let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap();
+    let tst_info = TstInfo {
+        version: TspVersion::V1,
+        policy: ObjectIdentifier::new_unwrap("1.2.3.4"),
+        message_imprint: MessageImprint {
+            hash_algorithm: AlgorithmIdentifierOwned {
+                oid: rfc5912::ID_SHA_384,
+                parameters: None,
+            },
+            hashed_message: OctetString::new(Sha384::digest(SIGNATURE_VALUE).to_vec()).unwrap(),
+        },
+        serial_number: Int::new(&[1]).unwrap(),
+        gen_time: GeneralizedTime::from_unix_duration(Duration::from_secs(now.as_secs())).unwrap(),
+        accuracy: None,
+        ordering: false,
+        nonce: None,
+        tsa: None,
+        extensions: None,
+    };
+    let content = EncapsulatedContentInfo {
+        econtent_type: ID_CT_TST_INFO,
+        econtent: Some(Any::new(Tag::OctetString, tst_info.to_der().unwrap()).unwrap()),
+    };
+    let digest_algorithm = AlgorithmIdentifierOwned {
+        oid: rfc5912::ID_SHA_384,
+        parameters: None,
+    };
+    let sid = SignerIdentifier::IssuerAndSerialNumber(IssuerAndSerialNumber {
+        issuer: certificate.tbs_certificate.issuer.clone(),
+        serial_number: certificate.tbs_certificate.serial_number.clone(),
+    });
+    let signer_info = SignerInfoBuilder::new(key, sid, digest_algorithm.clone(), &content, None).unwrap();
+    SignedDataBuilder::new(&content)
+        .add_digest_algorithm(digest_algorithm)
+        .unwrap()
+        .add_certificate(CertificateChoices::Certificate(certificate.clone()))
+        .unwrap()
+        .add_signer_info::<_, DerSignature>(signer_info)
+        .unwrap()
+        .build()
+        .unwrap()
+        .to_der()
+        .unwrap()
+}
+
+#[test]
+fn a_p384_token_chaining_to_a_given_root_is_trusted() {
+    let (root_key, tsa_key) = (key(), key());
+    let root = root(&root_key);
+    let certificate = tsa_certificate(&tsa_key, &root, &root_key, true);
+    let timestamp = tsa::verify_timestamp(&token(&tsa_key, &certificate), SIGNATURE_VALUE, &[root]).unwrap();
+    assert!(timestamp.trusted);
+    assert_eq!(timestamp.authority, "CN=Test TSA");
+}
+
+#[test]
+fn a_token_without_a_trusted_root_is_not_trusted() {
+    let (root_key, tsa_key) = (key(), key());
+    let root = root(&root_key);
+    let certificate = tsa_certificate(&tsa_key, &root, &root_key, true);
+    let token = token(&tsa_key, &certificate);
+    assert!(!tsa::verify_timestamp(&token, SIGNATURE_VALUE, &[]).unwrap().trusted);
+
+    let other_root = self::root(&key());
+    assert!(!tsa::verify_timestamp(&token, SIGNATURE_VALUE, &[other_root]).unwrap().trusted);
+}
+
+#[test]
+fn a_certificate_not_for_timestamping_is_not_trusted() {
+    let (root_key, tsa_key) = (key(), key());
+    let root = root(&root_key);
+    let certificate = tsa_certificate(&tsa_key, &root, &root_key, false);
+    let timestamp = tsa::verify_timestamp(&token(&tsa_key, &certificate), SIGNATURE_VALUE, &[root]).unwrap();
+    assert!(!timestamp.trusted);
+}
+
+#[test]
+fn a_token_over_other_data_is_refused() {
+    let (root_key, tsa_key) = (key(), key());
+    let root = root(&root_key);
+    let certificate = tsa_certificate(&tsa_key, &root, &root_key, true);
+    assert!(tsa::verify_timestamp(&token(&tsa_key, &certificate), b"something else", &[root]).is_err());
+}
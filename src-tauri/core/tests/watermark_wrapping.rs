@@ -0,0 +1,25 @@
+//! Long watermark lines wrap on the page, and the signature still reads back
+//! the unwrapped text. How lines wrap is tested in `text_metrics`.
+
+mod common;
+
+use sigillum_core::{api, SignOptions, SignatureStatus, VerifyOptions};
+
+const LONG_EXTRA: &str = "Approved by the finance committee at its quarterly meeting, \
+    subject to the conditions set out in the attached schedule of amendments";
+
+#[test]
+fn a_wrapped_extra_line_reads_back_whole() {
+    let key = common::test_key();
+    let mut opts = SignOptions::default();
+    opts.name = "Alice Jones".to_string();
+    opts.extra = LONG_EXTRA.to_string();
+    opts.max_line_width = Some(150.0);
+    let signed = api::sign(&common::sample_pdf(1), opts, &key).unwrap();
+
+    let mut verify = VerifyOptions::default();
+    verify.public_key = Some(key.public_key.clone());
+    let report = api::verify(&signed.pdf, verify).unwrap();
+    assert_eq!(report.status(), SignatureStatus::Valid);
+    assert_eq!(report.signatures[0].signature_info.extra, LONG_EXTRA);
+}
@@ -1,56 +1,402 @@
-mod pdf_utils;
-
-use chrono::Utc;
-use digest::Digest;
-use lopdf::Document;
-use rand::rngs::OsRng;
-use rsa::{
-    pkcs8::{DecodePrivateKey, DecodePublicKey, EncodePrivateKey, EncodePublicKey, LineEnding},
-    RsaPrivateKey, RsaPublicKey,
-};
 use serde::{Deserialize, Serialize};
-use sha2::Sha256;
 use std::fs;
-use std::path::PathBuf;
-use tauri::{AppHandle, Manager};
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter, Manager};
+use tauri_plugin_clipboard_manager::ClipboardExt;
+use tokio::sync::Semaphore;
+use zeroize::Zeroizing;
 
-const KEY_SIZE: usize = 2048;
+use sigillum_core::appearance::{self, SignaturePlacement, StampTimeZone};
+use sigillum_core::settings::DEFAULT_KEY_UNLOCK_MINUTES;
+use sigillum_core::share::{self, PublicKeyFormat, QrContent};
+use sigillum_core::{api, backup, biometric, cert_store, certificate, diff, discovery, gnupg, keys, kms, manifest, openpgp, mnemonic, piv, pkcs11, preview, shamir, ssh_agent, tpm, trust_store, watch};
+use sigillum_core::{
+    Appearance, AuditEntry, AuditLog, CancellationToken, CertificateInfo, CertificateSubject, ExpiredKeyPolicy, HashAlgorithm, Identity, KeyAlgorithm, KeyInfo, KeyPair,
+    KeyStorage, KeyStore, Monitor, Pkcs11Config, Progress, ReportFormat, ResignPolicy, Settings, SignatureInfo, SignatureStatus, SigillumError, SigningDefaults, SigningDevice, TrustStore,
+    TrustedKey, VerificationReport,
+};
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct KeyPair {
-    pub public_key: String,
-    pub private_key: String,
+/// What every way of signing PDFs takes; the requests flatten it in.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SignSettings {
+    /// Left empty, the profile's default signer name is used.
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub extra: String,
+    #[serde(default)]
+    pub require_text: Vec<String>,
+    #[serde(default)]
+    pub forbid_text: Vec<String>,
+    #[serde(default)]
+    pub max_line_width: Option<f32>,
+    #[serde(default)]
+    pub appearance: Appearance,
+    /// A rectangle picked on one page for the signature, instead of the
+    /// appearance's pages and position.
+    #[serde(default)]
+    pub placement: Option<SignaturePlacement>,
+    #[serde(default)]
+    pub pades: bool,
+    #[serde(default)]
+    pub passphrase: Option<Zeroizing<String>>,
+    #[serde(default)]
+    pub hash_algorithm: HashAlgorithm,
+    /// What to do when the PDF is already signed.
+    #[serde(default)]
+    pub resign_policy: ResignPolicy,
+    /// Opens a password-protected PDF.
+    #[serde(default)]
+    pub password: Option<Zeroizing<String>>,
+    /// Encrypts the signed PDF with this password.
+    #[serde(default)]
+    pub output_password: Option<Zeroizing<String>>,
+    /// Fails rather than break a PDF/A document's conformance.
+    #[serde(default)]
+    pub preserve_pdfa: bool,
+    /// Flattens the form fields and locks the document against further
+    /// changes; implies `pades`.
+    #[serde(default)]
+    pub lock_after_signing: bool,
+    /// Key profile to sign with instead of the active one.
+    #[serde(default)]
+    pub profile: Option<String>,
+    /// Then also signs each signed PDF with this GnuPG key through gpg,
+    /// attaching the OpenPGP signature to it.
+    #[serde(default)]
+    pub gpg_key: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
-pub struct SignatureInfo {
-    pub signer_name: String,
-    pub timestamp: String,
+pub struct SignPdfRequest {
+    pub pdf_data: Vec<u8>,
+    #[serde(flatten)]
+    pub sign: SignSettings,
+    /// Names the operation in its `sign-progress` events and lets
+    /// `cancel_operation` stop it.
+    #[serde(default)]
+    pub operation_id: Option<String>,
+    /// Name of the document, for the audit log.
+    #[serde(default)]
+    pub file_name: Option<String>,
+}
+
+/// Like `SignPdfRequest`, but the file is read and written on the Rust side so
+/// large documents do not have to cross the IPC bridge.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SignPdfFileRequest {
+    pub input_path: PathBuf,
+    pub output_path: PathBuf,
+    #[serde(flatten)]
+    pub sign: SignSettings,
+    /// Names the operation in its `sign-progress` events and lets
+    /// `cancel_operation` stop it.
+    #[serde(default)]
+    pub operation_id: Option<String>,
+}
+
+/// Signs any file, leaving it untouched; the signature is written to
+/// `signature_path`, or next to the file as `<name>.sig`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SignDetachedRequest {
+    pub path: PathBuf,
+    #[serde(default)]
+    pub signature_path: Option<PathBuf>,
+    pub name: String,
     pub extra: String,
-    pub signature: String,
+    #[serde(default)]
+    pub passphrase: Option<Zeroizing<String>>,
+    #[serde(default)]
+    pub hash_algorithm: HashAlgorithm,
 }
 
+/// Lists several files in a manifest saved to `manifest_path` and signs it;
+/// the signature is written to `signature_path`, or next to the manifest
+/// with a `.sig` extension.
 #[derive(Debug, Serialize, Deserialize)]
-pub struct SignPdfRequest {
-    pub pdf_data: Vec<u8>,
+pub struct SignManifestRequest {
+    pub paths: Vec<PathBuf>,
+    pub manifest_path: PathBuf,
+    #[serde(default)]
+    pub signature_path: Option<PathBuf>,
     pub name: String,
     pub extra: String,
+    #[serde(default)]
+    pub passphrase: Option<Zeroizing<String>>,
+    #[serde(default)]
+    pub hash_algorithm: HashAlgorithm,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SignPdfBatchRequest {
+    pub input_paths: Vec<PathBuf>,
+    /// Where the signed copies go; next to each input when omitted.
+    #[serde(default)]
+    pub output_dir: Option<PathBuf>,
+    #[serde(flatten)]
+    pub sign: SignSettings,
+}
+
+/// Signs every PDF put into `input_dir` into `output_dir`, under the same
+/// names, until `stop_watch`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WatchFolderRequest {
+    pub input_dir: PathBuf,
+    pub output_dir: PathBuf,
+    #[serde(flatten)]
+    pub sign: SignSettings,
+}
+
+/// Payload of the `sign-batch-progress` event, sent as each file finishes.
+#[derive(Debug, Clone, Serialize)]
+pub struct SignBatchProgress {
+    pub completed: usize,
+    pub total: usize,
+    pub result: api::BatchResult,
+}
+
+/// Payload of the `verify-batch-progress` event, sent as each file finishes.
+#[derive(Debug, Clone, Serialize)]
+pub struct VerifyBatchProgress {
+    pub completed: usize,
+    pub total: usize,
+    pub result: api::BatchVerifyResult,
+}
+
+/// Payload of the `sign-progress` and `verify-progress` events.
+#[derive(Debug, Clone, Serialize)]
+pub struct OperationProgress {
+    pub operation_id: Option<String>,
+    #[serde(flatten)]
+    pub progress: Progress,
+}
+
+/// Cancellation tokens of the operations in flight, by operation id.
+#[derive(Default)]
+struct Operations(Mutex<HashMap<String, CancellationToken>>);
+
+/// A sign or verify command in progress. Its monitor reports through `event`;
+/// while it lives, `cancel_operation` can stop it by its id.
+struct Operation {
+    app: AppHandle,
+    id: Option<String>,
+    monitor: Monitor,
+}
+
+impl Operation {
+    fn start(app: &AppHandle, event: &'static str, id: Option<String>) -> Self {
+        let token = CancellationToken::new();
+        if let Some(id) = &id {
+            app.state::<Operations>().0.lock().unwrap().insert(id.clone(), token.clone());
+        }
+        let (emitter, operation_id) = (app.clone(), id.clone());
+        let monitor = Monitor::new(
+            move |progress| {
+                let payload = OperationProgress {
+                    operation_id: operation_id.clone(),
+                    progress,
+                };
+                if let Err(e) = emitter.emit(event, payload) {
+                    log::warn!("Failed to report progress: {}", e);
+                }
+            },
+            token,
+        );
+        Operation {
+            app: app.clone(),
+            id,
+            monitor,
+        }
+    }
+}
+
+impl Drop for Operation {
+    fn drop(&mut self) {
+        if let Some(id) = &self.id {
+            self.app.state::<Operations>().0.lock().unwrap().remove(id);
+        }
+    }
+}
+
+/// How many keygen, sign and verify jobs run at once; the rest queue.
+const MAX_WORKERS: usize = 4;
+
+/// Slots for the jobs slow enough to freeze the window if run on the IPC
+/// thread. Each holds one while it runs on a blocking thread.
+struct Workers(Arc<Semaphore>);
+
+impl Default for Workers {
+    fn default() -> Self {
+        Workers(Arc::new(Semaphore::new(MAX_WORKERS)))
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobState {
+    /// Waiting for a free worker.
+    Queued,
+    Running,
+    Done,
+    Failed,
+}
+
+/// Payload of the `operation-status` event.
+#[derive(Debug, Clone, Serialize)]
+pub struct OperationStatus {
+    pub operation_id: String,
+    pub state: JobState,
+}
+
+/// Runs `job` on a blocking thread once a worker is free. When the job has an
+/// `operation_id`, its state is reported through `operation-status` events so
+/// the window can follow several jobs at once.
+async fn run_job<T, F>(app: AppHandle, operation_id: Option<String>, job: F) -> Result<T, SigillumError>
+where
+    T: Send + 'static,
+    F: FnOnce(AppHandle) -> Result<T, SigillumError> + Send + 'static,
+{
+    let report = |state| {
+        if let Some(id) = &operation_id {
+            let payload = OperationStatus {
+                operation_id: id.clone(),
+                state,
+            };
+            if let Err(e) = app.emit("operation-status", payload) {
+                log::warn!("Failed to report job state: {}", e);
+            }
+        }
+    };
+
+    report(JobState::Queued);
+    let workers = app.state::<Workers>().0.clone();
+    let permit = workers
+        .acquire_owned()
+        .await
+        .map_err(|e| format!("Worker pool closed: {}", e))?;
+    report(JobState::Running);
+
+    let worker_app = app.clone();
+    let result = tauri::async_runtime::spawn_blocking(move || {
+        let _permit = permit;
+        job(worker_app)
+    })
+    .await
+    .map_err(|e| SigillumError::from(format!("Job failed: {}", e)))
+    .and_then(|result| result);
+    report(if result.is_ok() { JobState::Done } else { JobState::Failed });
+    result
+}
+
+/// Stops the folder being watched, if any.
+#[derive(Default)]
+struct FolderWatch(Mutex<Option<CancellationToken>>);
+
+/// The key unlocked by `unlock_key`, if any, which signs without its
+/// passphrase until it is locked again or its time is up.
+#[derive(Default)]
+struct KeySession(Mutex<Option<UnlockedKey>>);
+
+struct UnlockedKey {
+    profile: String,
+    /// The profile's public key when it was unlocked, so that a key put in
+    /// its place since is not signed for with this one.
+    public_key: String,
+    /// Plain PKCS#8 PEM, wiped when the session ends.
+    private_key: Zeroizing<String>,
+    until: Instant,
+}
+
+impl KeySession {
+    /// `keypair`, of `profile`, with its private key unlocked if this
+    /// session holds it.
+    fn unlock(&self, profile: &str, mut keypair: KeyPair) -> KeyPair {
+        let mut session = self.0.lock().unwrap();
+        if session.as_ref().is_some_and(|key| key.until <= Instant::now()) {
+            *session = None;
+        }
+        if let Some(key) = session.as_ref() {
+            if key.profile == profile && key.public_key == keypair.public_key && keypair.is_encrypted() {
+                keypair.private_key = key.private_key.clone();
+            }
+        }
+        keypair
+    }
+
+    fn status(&self) -> Option<KeyUnlockStatus> {
+        let session = self.0.lock().unwrap();
+        let key = session.as_ref()?;
+        Some(KeyUnlockStatus {
+            profile: key.profile.clone(),
+            seconds_left: key.until.checked_duration_since(Instant::now())?.as_secs(),
+        })
+    }
+}
+
+/// Which profile's key is unlocked, and for how much longer.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct KeyUnlockStatus {
+    pub profile: String,
+    pub seconds_left: u64,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct SignPdfResponse {
     pub signed_pdf: Vec<u8>,
     pub signature_info: SignatureInfo,
+    pub clock_warning: Option<String>,
+}
+
+/// The settings that can be changed from the app. The rest of `Settings`
+/// belongs to the key store.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AppSettings {
+    pub tsa_url: Option<String>,
+    pub ntp_servers: Vec<String>,
+    pub max_clock_skew: Option<u64>,
+    pub timestamp_format: Option<String>,
+    pub time_zone: Option<StampTimeZone>,
+    pub expired_key_policy: ExpiredKeyPolicy,
+    pub key_unlock_minutes: Option<u32>,
+    pub fetch_signer_keys: bool,
+}
+
+/// The NTP servers the clock is checked against when signing, and how far
+/// off it may be before signing warns.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NtpSettings {
+    pub servers: Vec<String>,
+    pub max_clock_skew: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProfileList {
+    pub profiles: Vec<String>,
+    pub active: String,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct VerifyPdfResponse {
     pub is_signed: bool,
-    pub signature_info: Option<SignatureInfo>,
+    /// Every signature on the document, oldest first.
+    pub signatures: Vec<api::SignatureReport>,
+    /// Standard signatures other applications added.
+    pub foreign_signatures: Vec<api::ForeignSignatureReport>,
+    /// OpenPGP signatures attached to the document.
+    pub pgp_signatures: Vec<api::PgpSignatureReport>,
     pub message: String,
+    pub canonical_hash: String,
+    pub hash_matches: Option<bool>,
+    /// The worst status among the signatures.
+    pub status: SignatureStatus,
 }
 
-fn get_key_path(app: &AppHandle) -> Result<PathBuf, String> {
+fn get_app_data_dir(app: &AppHandle) -> Result<PathBuf, SigillumError> {
     let path = app
         .path()
         .app_data_dir()
@@ -58,166 +404,1654 @@ fn get_key_path(app: &AppHandle) -> Result<PathBuf, String> {
     if !path.exists() {
         fs::create_dir_all(&path).map_err(|e| format!("Failed to create dir: {}", e))?;
     }
-    Ok(path.join("keypair.json"))
+    Ok(path)
+}
+
+fn get_key_store(app: &AppHandle) -> Result<KeyStore, SigillumError> {
+    KeyStore::open(get_app_data_dir(app)?)
+}
+
+/// The keypair of `profile`, or of the active profile if `None`.
+fn load_profile_keypair(app: &AppHandle, profile: Option<&str>) -> Result<KeyPair, SigillumError> {
+    let mut store = get_key_store(app)?;
+    if let Some(profile) = profile {
+        store.select(profile)?;
+    }
+    store.load()
+}
+
+/// Like `load_profile_keypair`, for signing with: a key unlocked by
+/// `unlock_key` needs no passphrase while it stays unlocked. Otherwise, with
+/// biometric unlock on, `opts` names the profile, so that without a
+/// passphrase Touch ID or Windows Hello unlocks the key for this one
+/// operation once the document has passed the checks made before signing.
+fn signing_keypair(app: &AppHandle, profile: Option<&str>, opts: &mut api::SignOptions) -> Result<KeyPair, SigillumError> {
+    let mut store = get_key_store(app)?;
+    if let Some(profile) = profile {
+        store.select(profile)?;
+    }
+    let profile = store.selected_profile();
+    if load_settings(app)?.biometric_unlock {
+        opts.biometric_profile = Some(profile.to_string());
+    }
+    Ok(app.state::<KeySession>().unlock(profile, store.load()?))
+}
+
+/// Like `signing_keypair`, unlocking the key with biometrics straight away,
+/// for signing that checks nothing first.
+fn unlocked_keypair(app: &AppHandle, passphrase: Option<&str>) -> Result<KeyPair, SigillumError> {
+    let mut opts = api::SignOptions::default();
+    let mut keypair = signing_keypair(app, None, &mut opts)?;
+    let passphrase_given = passphrase.is_some_and(|passphrase| !passphrase.is_empty());
+    let profile = opts
+        .biometric_profile
+        .filter(|profile| keypair.is_encrypted() && !passphrase_given && biometric::has_passphrase(profile));
+    if let Some(profile) = profile {
+        let passphrase = biometric::unlock_passphrase(&profile)?;
+        keypair.private_key = keypair.private_key(Some(passphrase.as_str()))?.to_pkcs8_pem()?;
+        log::info!("Key of profile '{}' unlocked with biometrics", profile);
+    }
+    Ok(keypair)
+}
+
+/// The options to sign with: `sign`'s, and the timestamp, clock and
+/// signing-time settings.
+fn sign_options(sign: &SignSettings, settings: Settings, monitor: Monitor) -> api::SignOptions {
+    let mut opts = api::SignOptions::default();
+    opts.name = sign.name.clone();
+    opts.extra = sign.extra.clone();
+    opts.require_text = sign.require_text.clone();
+    opts.forbid_text = sign.forbid_text.clone();
+    opts.max_line_width = sign.max_line_width;
+    opts.appearance = sign.appearance.clone();
+    opts.placement = sign.placement;
+    opts.pades = sign.pades;
+    opts.passphrase = sign.passphrase.clone();
+    opts.tsa_url = settings.tsa_url;
+    opts.ntp_servers = settings.ntp_servers;
+    opts.max_clock_skew = settings.max_clock_skew;
+    opts.timestamp_format = settings.timestamp_format;
+    opts.time_zone = settings.time_zone;
+    opts.hash_algorithm = sign.hash_algorithm;
+    opts.resign_policy = sign.resign_policy;
+    opts.password = sign.password.clone();
+    opts.output_password = sign.output_password.clone();
+    opts.preserve_pdfa = sign.preserve_pdfa;
+    opts.lock_after_signing = sign.lock_after_signing;
+    opts.monitor = monitor;
+    opts
+}
+
+/// The key `sign` signs with, found as `signing_keypair` does.
+fn sign_keypair(app: &AppHandle, sign: &SignSettings, opts: &mut api::SignOptions) -> Result<KeyPair, SigillumError> {
+    signing_keypair(app, sign.profile.as_deref(), opts)
+}
+
+/// Attaches an OpenPGP signature with `sign.gpg_key`, if it names one, to
+/// the signed PDF at `path`.
+fn attach_gpg_signature_to_file(path: &Path, sign: &SignSettings) -> Result<(), SigillumError> {
+    let Some(key_id) = &sign.gpg_key else {
+        return Ok(());
+    };
+    let pdf = fs::read(path).map_err(|e| SigillumError::Io(format!("Failed to read PDF: {}", e)))?;
+    let password = sign.output_password.as_ref().or(sign.password.as_ref());
+    let pdf = api::attach_gpg_signature(pdf, key_id, sign.hash_algorithm, password.map(|p| p.as_str()))?;
+    fs::write(path, pdf).map_err(|e| SigillumError::Io(format!("Failed to save PDF: {}", e)))
+}
+
+fn get_trust_store(app: &AppHandle) -> Result<TrustStore, SigillumError> {
+    TrustStore::open(get_app_data_dir(app)?)
+}
+
+fn load_settings(app: &AppHandle) -> Result<Settings, SigillumError> {
+    Settings::load(&get_app_data_dir(app)?)
+}
+
+/// Refuses an expired key, or only logs a warning, as the settings say. The
+/// app shows the key's expiry warning with its details.
+fn check_key_expiry(app: &AppHandle, keypair: &KeyPair) -> Result<(), SigillumError> {
+    if let Some(warning) = keypair.check_expiry(load_settings(app)?.expired_key_policy)? {
+        log::warn!("{}", warning);
+    }
+    Ok(())
 }
 
 #[tauri::command]
 fn has_key(app: AppHandle) -> bool {
-    match get_key_path(&app) {
-        Ok(path) => path.exists(),
+    match get_key_store(&app) {
+        Ok(store) => store.exists(),
         Err(_) => false,
     }
 }
 
+/// `bits` picks the size of an RSA key; `algorithm` defaults to RSA. With
+/// `tpm_pin`, the key is generated inside the TPM, whose token that PIN
+/// unlocks. Runs on the worker pool, reporting through `operation-status`
+/// events.
+#[tauri::command]
+async fn generate_keypair(
+    app: AppHandle,
+    algorithm: Option<KeyAlgorithm>,
+    bits: Option<u32>,
+    tpm_pin: Option<Zeroizing<String>>,
+    valid_days: Option<u32>,
+    operation_id: Option<String>,
+) -> Result<String, SigillumError> {
+    run_job(app, operation_id, move |app| generate_keypair_job(app, algorithm, bits, tpm_pin, valid_days)).await
+}
+
+fn generate_keypair_job(
+    app: AppHandle,
+    algorithm: Option<KeyAlgorithm>,
+    bits: Option<u32>,
+    tpm_pin: Option<Zeroizing<String>>,
+    valid_days: Option<u32>,
+) -> Result<String, SigillumError> {
+    let algorithm = algorithm.unwrap_or_default();
+    let algorithm = match bits {
+        Some(bits) => algorithm.with_bits(bits)?,
+        None => algorithm,
+    };
+    let mut keypair = match tpm_pin {
+        Some(pin) => KeyPair::generate_in_tpm(algorithm, &pin)?,
+        None => KeyPair::generate(algorithm)?,
+    };
+    keypair.set_validity(valid_days);
+    get_key_store(&app)?.save(&keypair)?;
+
+    log::info!("Keypair generated and saved");
+    Ok(keypair.public_key)
+}
+
+/// Imports a pasted private key in any format `KeyPair::import` reads. The
+/// public key is derived from it; when one is given too, it must match.
+#[tauri::command]
+fn import_key(
+    app: AppHandle,
+    private_key_pem: Zeroizing<String>,
+    public_key_pem: Option<String>,
+    passphrase: Option<Zeroizing<String>>,
+    algorithm: Option<KeyAlgorithm>,
+) -> Result<String, SigillumError> {
+    let keypair = KeyPair::import(private_key_pem.as_bytes(), passphrase.as_deref().map(String::as_str))?;
+    if let Some(public_key_pem) = public_key_pem.filter(|pem| !pem.trim().is_empty()) {
+        let given = keys::PublicKey::from_public_key_pem(&public_key_pem)?.to_public_key_pem()?;
+        if given != keypair.public_key {
+            return Err("The public key does not belong to the private key".into());
+        }
+    }
+    save_imported_key(&app, keypair, algorithm)
+}
+
+/// Like `import_key`, for a key file, which may also be DER.
+#[tauri::command]
+fn import_key_file(
+    app: AppHandle,
+    path: PathBuf,
+    passphrase: Option<Zeroizing<String>>,
+    algorithm: Option<KeyAlgorithm>,
+) -> Result<String, SigillumError> {
+    let data = fs::read(&path).map_err(|e| SigillumError::Io(format!("Failed to read key: {}", e)))?;
+    let keypair = KeyPair::import(&data, passphrase.as_deref().map(String::as_str))?;
+    save_imported_key(&app, keypair, algorithm)
+}
+
+/// Rebuilds a key from share files made by `export_key_shares`.
+#[tauri::command]
+fn import_key_shares(app: AppHandle, paths: Vec<PathBuf>) -> Result<String, SigillumError> {
+    let shares = paths
+        .iter()
+        .map(|path| {
+            let data = fs::read(path).map_err(|e| SigillumError::Io(format!("Failed to read {}: {}", path.display(), e)))?;
+            shamir::KeyShare::from_json(&data).map_err(|e| SigillumError::from(format!("{}: {}", path.display(), e)))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let keypair = KeyPair::import(&shamir::combine(&shares)?.to_pkcs8_der()?, None)?;
+    save_imported_key(&app, keypair, None)
+}
+
+/// Restores an Ed25519 or P-256 key of type `algorithm` from its recovery
+/// phrase.
+#[tauri::command]
+fn import_key_phrase(app: AppHandle, phrase: Zeroizing<String>, algorithm: KeyAlgorithm) -> Result<String, SigillumError> {
+    let private_key = mnemonic::from_phrase(&phrase, algorithm)?;
+    let keypair = KeyPair::import(&private_key.to_pkcs8_der()?, None)?;
+    save_imported_key(&app, keypair, None)
+}
+
+/// Makes the active profile sign with the RSA key on a PKCS#11 token. The
+/// token is opened with `pin` to read the public key; the PIN is not stored.
+#[tauri::command]
+fn import_pkcs11_key(app: AppHandle, config: Pkcs11Config, pin: Option<Zeroizing<String>>) -> Result<String, SigillumError> {
+    let keypair = KeyPair::from_pkcs11(config, pin.as_deref().map(String::as_str))?;
+    save_imported_key(&app, keypair, None)
+}
+
+/// The tokens in the slots of a PKCS#11 `module`.
+#[tauri::command]
+fn list_pkcs11_tokens(module: PathBuf) -> Result<Vec<pkcs11::TokenInfo>, SigillumError> {
+    pkcs11::tokens(&module)
+}
+
+/// The private keys on the token in `slot` of `module`; most tokens only
+/// show them once logged in with `pin`.
+#[tauri::command]
+fn list_pkcs11_keys(module: PathBuf, slot: u64, pin: Option<Zeroizing<String>>) -> Result<Vec<pkcs11::TokenKey>, SigillumError> {
+    pkcs11::keys(&module, slot, pin.as_deref().map(String::as_str))
+}
+
+/// The keys ssh-agent holds; only the `usable` ones can sign.
+#[tauri::command]
+fn list_ssh_agent_keys() -> Result<Vec<ssh_agent::AgentIdentity>, SigillumError> {
+    ssh_agent::identities()
+}
+
+/// Makes the active profile sign through ssh-agent with the Ed25519 key
+/// that has SSH `fingerprint`.
+#[tauri::command]
+fn import_ssh_agent_key(app: AppHandle, fingerprint: String) -> Result<String, SigillumError> {
+    let keypair = KeyPair::from_ssh_agent(&fingerprint)?;
+    save_imported_key(&app, keypair, None)
+}
+
+/// Makes the active profile sign with `key` in a cloud KMS, given as
+/// "aws:...", "gcp:..." or "azure:...", or in the macOS Keychain or Windows
+/// certificate store, as "keychain:<label>" or "certstore:<thumbprint>".
+#[tauri::command]
+fn import_kms_key(app: AppHandle, key: String) -> Result<String, SigillumError> {
+    let keypair = KeyPair::from_kms(key.parse::<kms::KmsKey>()?)?;
+    save_imported_key(&app, keypair, None)
+}
+
+/// The certificates in the Windows user certificate store that can sign, to
+/// import with `import_kms_key` as "certstore:<thumbprint>".
+#[tauri::command]
+fn list_store_certificates() -> Result<Vec<cert_store::StoreCertificate>, SigillumError> {
+    cert_store::certificates()
+}
+
+/// Whether keys can be generated in a TPM here, to offer the option.
+#[tauri::command]
+fn has_tpm() -> bool {
+    tpm::module().is_some()
+}
+
+/// The secret keys in the user's GnuPG keyring, to attach OpenPGP signatures
+/// with; none when gpg is not installed.
+#[tauri::command(async)]
+fn list_gpg_keys() -> Result<Vec<gnupg::GpgKey>, SigillumError> {
+    if !gnupg::is_available() {
+        return Ok(Vec::new());
+    }
+    gnupg::secret_keys()
+}
+
+/// The plugged-in PIV cards, such as YubiKeys, that can sign.
+#[tauri::command]
+fn list_signing_devices() -> Vec<SigningDevice> {
+    piv::devices()
+}
+
+/// Makes the active profile sign with the key in the digital signature slot
+/// of a card from `list_signing_devices`, and the certificate beside it.
 #[tauri::command]
-fn generate_keypair(app: AppHandle) -> Result<String, String> {
-    let mut rng = OsRng;
-    let private_key = RsaPrivateKey::new(&mut rng, KEY_SIZE).map_err(|e| format!("Failed to generate key: {}", e))?;
-    let public_key = RsaPublicKey::from(&private_key);
+fn import_signing_device(app: AppHandle, device: SigningDevice, pin: Option<Zeroizing<String>>) -> Result<String, SigillumError> {
+    let keypair = KeyPair::from_piv(&device.token, pin.as_deref().map(String::as_str))?;
+    save_imported_key(&app, keypair, None)
+}
+
+fn save_imported_key(app: &AppHandle, keypair: KeyPair, algorithm: Option<KeyAlgorithm>) -> Result<String, SigillumError> {
+    if let Some(expected) = algorithm.filter(|&a| a != keypair.algorithm) {
+        return Err(format!("Expected a {} key, but the key is {}", expected, keypair.algorithm).into());
+    }
+    get_key_store(app)?.save(&keypair)?;
 
-    let private_key_pem = private_key
-        .to_pkcs8_pem(LineEnding::LF)
-        .map_err(|e| format!("Failed to encode private key: {}", e))?
-        .to_string();
-    let public_key_pem = public_key
-        .to_public_key_pem(LineEnding::LF)
-        .map_err(|e| format!("Failed to encode public key: {}", e))?;
+    log::info!("Keypair imported and saved");
+    Ok(keypair.public_key)
+}
 
-    let keypair = KeyPair {
-        public_key: public_key_pem.clone(),
-        private_key: private_key_pem,
+/// Exports the current key, or version `version` of the profile's key.
+#[tauri::command]
+fn export_key(app: AppHandle, passphrase: Option<Zeroizing<String>>, version: Option<u32>) -> Result<Zeroizing<String>, SigillumError> {
+    let keypair = get_key_store(&app)?.load()?;
+    let keypair = match version {
+        Some(version) => keypair.key_version(version)?,
+        None => &keypair,
     };
+    keypair.export_private_key(passphrase.as_deref().map(String::as_str))
+}
 
-    let key_json = serde_json::to_string_pretty(&keypair).map_err(|e| format!("JSON error: {}", e))?;
-    let key_path = get_key_path(&app).map_err(|e| format!("Key path error: {}", e))?;
-    fs::write(&key_path, key_json).map_err(|e| format!("Write error: {}", e))?;
+/// Saves the key and its certificate to `path` as a PKCS#12 file protected
+/// by `password`, for other applications to sign with. Like `export_key`,
+/// it can export an earlier `version` of the key.
+#[tauri::command]
+fn export_key_pkcs12(
+    app: AppHandle,
+    path: PathBuf,
+    passphrase: Option<Zeroizing<String>>,
+    password: Zeroizing<String>,
+    version: Option<u32>,
+) -> Result<(), SigillumError> {
+    let keypair = get_key_store(&app)?.load()?;
+    let keypair = match version {
+        Some(version) => keypair.key_version(version)?,
+        None => &keypair,
+    };
+    let data = keypair.export_pkcs12(passphrase.as_deref().map(String::as_str), &password)?;
+    fs::write(&path, data).map_err(|e| SigillumError::Io(format!("Failed to save key: {}", e)))?;
 
-    log::info!("Keypair generated and saved");
-    Ok(public_key_pem)
+    log::info!("Key exported as PKCS#12 to {}", path.display());
+    Ok(())
 }
 
+/// The recovery phrase of the key, or of version `version` of it, for an
+/// Ed25519 or P-256 key.
 #[tauri::command]
-fn import_key(app: AppHandle, private_key_pem: String, public_key_pem: String) -> Result<String, String> {
-    let _private_key = RsaPrivateKey::from_pkcs8_pem(&private_key_pem)
-        .map_err(|e| format!("Invalid private key: {}", e))?;
-    let _public_key = RsaPublicKey::from_public_key_pem(&public_key_pem)
-        .map_err(|e| format!("Invalid public key: {}", e))?;
+fn export_key_phrase(app: AppHandle, passphrase: Option<Zeroizing<String>>, version: Option<u32>) -> Result<Zeroizing<String>, SigillumError> {
+    let keypair = get_key_store(&app)?.load()?;
+    let keypair = match version {
+        Some(version) => keypair.key_version(version)?,
+        None => &keypair,
+    };
+    mnemonic::to_phrase(&keypair.private_key(passphrase.as_deref().map(String::as_str))?)
+}
 
-    let keypair = KeyPair {
-        public_key: public_key_pem.clone(),
-        private_key: private_key_pem,
+/// Splits the key, or version `version` of it, into share files in `dir` as
+/// `scheme` ("3of5") says; any `threshold` of them rebuild it. Gives the
+/// paths written.
+#[tauri::command]
+fn export_key_shares(
+    app: AppHandle,
+    dir: PathBuf,
+    scheme: String,
+    passphrase: Option<Zeroizing<String>>,
+    version: Option<u32>,
+) -> Result<Vec<PathBuf>, SigillumError> {
+    let scheme: shamir::ShamirScheme = scheme.parse()?;
+    let store = get_key_store(&app)?;
+    let keypair = store.load()?;
+    let keypair = match version {
+        Some(version) => keypair.key_version(version)?,
+        None => &keypair,
     };
+    let shares = shamir::split(&keypair.private_key(passphrase.as_deref().map(String::as_str))?, scheme)?;
+    let mut paths = Vec::new();
+    for share in &shares {
+        let path = dir.join(format!("{}-{}", store.selected_profile(), share.file_name()));
+        fs::write(&path, share.to_json()?).map_err(|e| SigillumError::Io(format!("Failed to save {}: {}", path.display(), e)))?;
+        paths.push(path);
+    }
 
-    let key_json = serde_json::to_string_pretty(&keypair).map_err(|e| format!("JSON error: {}", e))?;
-    let key_path = get_key_path(&app).map_err(|e| format!("Key path error: {}", e))?;
-    fs::write(&key_path, key_json).map_err(|e| format!("Write error: {}", e))?;
+    log::info!("Key split into {} shares in {}", scheme.shares, dir.display());
+    Ok(paths)
+}
 
-    log::info!("Keypair imported and saved");
-    Ok(public_key_pem)
+/// The key as an armored OpenPGP public key for `user_id`, for recipients
+/// who check signatures with GnuPG.
+#[tauri::command]
+fn export_openpgp_key(app: AppHandle, user_id: String, passphrase: Option<Zeroizing<String>>) -> Result<String, SigillumError> {
+    let keypair = get_key_store(&app)?.load()?;
+    openpgp::public_key_block(&keypair, passphrase.as_deref().map(String::as_str), user_id.trim())
+}
+
+#[tauri::command]
+fn has_key_passphrase(app: AppHandle) -> Result<bool, SigillumError> {
+    Ok(get_key_store(&app)?.load()?.is_encrypted())
+}
+
+/// Unlocks the active profile's key with `passphrase` so that signing needs
+/// no passphrase for `minutes`, or as long as the settings say. Then the key
+/// is wiped from memory and a `key-locked` event sent.
+#[tauri::command]
+fn unlock_key(app: AppHandle, passphrase: Zeroizing<String>, minutes: Option<u32>) -> Result<KeyUnlockStatus, SigillumError> {
+    let store = get_key_store(&app)?;
+    let keypair = store.load()?;
+    if !keypair.is_encrypted() {
+        return Err("The key has no passphrase to unlock it with".into());
+    }
+    let private_key = keypair.private_key(Some(passphrase.as_str()))?.to_pkcs8_pem()?;
+    let minutes = minutes
+        .or(load_settings(&app)?.key_unlock_minutes)
+        .unwrap_or(DEFAULT_KEY_UNLOCK_MINUTES)
+        .max(1);
+    let duration = Duration::from_secs(u64::from(minutes) * 60);
+    let until = Instant::now() + duration;
+    let profile = store.selected_profile().to_string();
+    *app.state::<KeySession>().0.lock().unwrap() = Some(UnlockedKey {
+        profile: profile.clone(),
+        public_key: keypair.public_key,
+        private_key,
+        until,
+    });
+
+    let timer = app.clone();
+    tauri::async_runtime::spawn(async move {
+        tokio::time::sleep(duration).await;
+        let session = timer.state::<KeySession>();
+        let mut unlocked = session.0.lock().unwrap();
+        // Unless it was locked, or unlocked again, meanwhile.
+        if unlocked.as_ref().is_some_and(|key| key.until == until) {
+            *unlocked = None;
+            drop(unlocked);
+            log::info!("Key locked again after {} minutes", minutes);
+            if let Err(e) = timer.emit("key-locked", ()) {
+                log::warn!("Failed to report the key locked: {}", e);
+            }
+        }
+    });
+
+    log::info!("Key of profile '{}' unlocked for {} minutes", profile, minutes);
+    Ok(KeyUnlockStatus {
+        profile,
+        seconds_left: duration.as_secs(),
+    })
 }
 
+/// Wipes the key `unlock_key` unlocked, if any.
 #[tauri::command]
-fn export_key(app: AppHandle) -> Result<String, String> {
-    let key_path = get_key_path(&app).map_err(|e| format!("Key path error: {}", e))?;
-    let key_json = fs::read_to_string(&key_path).map_err(|e| format!("Read error: {}", e))?;
-    let keypair: KeyPair = serde_json::from_str(&key_json).map_err(|e| format!("JSON error: {}", e))?;
-    Ok(keypair.private_key)
+fn lock_key(app: AppHandle) {
+    if app.state::<KeySession>().0.lock().unwrap().take().is_some() {
+        log::info!("Key locked");
+        if let Err(e) = app.emit("key-locked", ()) {
+            log::warn!("Failed to report the key locked: {}", e);
+        }
+    }
+}
+
+/// Which profile's key is unlocked, if any, and for how much longer.
+#[tauri::command]
+fn key_unlock_status(app: AppHandle) -> Option<KeyUnlockStatus> {
+    app.state::<KeySession>().status()
+}
+
+/// Whether Touch ID or Windows Hello can unlock the active profile's key.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BiometricStatus {
+    /// Whether this device has Touch ID or Windows Hello set up.
+    pub available: bool,
+    /// Whether signing asks for it instead of the passphrase.
+    pub enabled: bool,
+    /// Whether the active profile's passphrase is kept for it.
+    pub passphrase_saved: bool,
+}
+
+#[tauri::command]
+fn biometric_status(app: AppHandle) -> Result<BiometricStatus, SigillumError> {
+    let store = get_key_store(&app)?;
+    Ok(BiometricStatus {
+        available: biometric::is_available(),
+        enabled: load_settings(&app)?.biometric_unlock,
+        passphrase_saved: biometric::has_passphrase(store.selected_profile()),
+    })
+}
+
+/// Keeps `passphrase` of the active profile's key where only Touch ID or
+/// Windows Hello can release it, and turns biometric unlock on, once the
+/// platform's prompt confirms the user.
+#[tauri::command(async)]
+fn enable_biometric_unlock(app: AppHandle, passphrase: Zeroizing<String>) -> Result<(), SigillumError> {
+    let store = get_key_store(&app)?;
+    let keypair = store.load()?;
+    if !keypair.is_encrypted() {
+        return Err("The key has no passphrase to unlock it with".into());
+    }
+    keypair.private_key(Some(passphrase.as_str()))?;
+    biometric::verify("turn on biometric unlock for your signing key")?;
+    biometric::save_passphrase(store.selected_profile(), &passphrase)?;
+
+    let dir = get_app_data_dir(&app)?;
+    let mut settings = Settings::load(&dir)?;
+    settings.biometric_unlock = true;
+    settings.save(&dir)?;
+
+    log::info!("Biometric unlock turned on for profile '{}'", store.selected_profile());
+    Ok(())
+}
+
+/// Turns biometric unlock off and removes every profile's kept passphrase.
+#[tauri::command]
+fn disable_biometric_unlock(app: AppHandle) -> Result<(), SigillumError> {
+    let dir = get_app_data_dir(&app)?;
+    let mut settings = Settings::load(&dir)?;
+    settings.biometric_unlock = false;
+    settings.save(&dir)?;
+    for profile in get_key_store(&app)?.profiles() {
+        biometric::forget_passphrase(profile)?;
+    }
+
+    log::info!("Biometric unlock turned off");
+    Ok(())
 }
 
 #[tauri::command]
-fn get_public_key(app: AppHandle) -> Result<String, String> {
-    let key_path = get_key_path(&app).map_err(|e| format!("Key path error: {}", e))?;
-    let key_json = fs::read_to_string(&key_path).map_err(|e| format!("Read error: {}", e))?;
-    let keypair: KeyPair = serde_json::from_str(&key_json).map_err(|e| format!("JSON error: {}", e))?;
+fn set_key_passphrase(
+    app: AppHandle,
+    current_passphrase: Option<Zeroizing<String>>,
+    new_passphrase: Option<Zeroizing<String>>,
+) -> Result<(), SigillumError> {
+    let mut store = get_key_store(&app)?;
+    let mut keypair = store.load()?;
+    keypair.set_passphrase(current_passphrase.as_deref().map(String::as_str), new_passphrase.as_deref().map(String::as_str))?;
+    store.save(&keypair)?;
+    // A kept passphrase follows the change, or goes with the passphrase.
+    let profile = store.selected_profile();
+    if biometric::has_passphrase(profile) {
+        match new_passphrase.as_deref().map(String::as_str).filter(|passphrase| !passphrase.is_empty()) {
+            Some(passphrase) => biometric::save_passphrase(profile, passphrase)?,
+            None => biometric::forget_passphrase(profile)?,
+        }
+    }
+
+    log::info!("Key passphrase updated");
+    Ok(())
+}
+
+#[tauri::command]
+fn get_key_storage(app: AppHandle) -> Result<KeyStorage, SigillumError> {
+    Ok(get_key_store(&app)?.storage())
+}
+
+#[tauri::command]
+fn migrate_key_storage(app: AppHandle, storage: KeyStorage) -> Result<(), SigillumError> {
+    get_key_store(&app)?.migrate(storage)?;
+
+    log::info!("Key storage switched to {}", storage);
+    Ok(())
+}
+
+#[tauri::command]
+fn get_settings(app: AppHandle) -> Result<AppSettings, SigillumError> {
+    let settings = load_settings(&app)?;
+    Ok(AppSettings {
+        tsa_url: settings.tsa_url,
+        ntp_servers: settings.ntp_servers,
+        max_clock_skew: settings.max_clock_skew,
+        timestamp_format: settings.timestamp_format,
+        time_zone: settings.time_zone,
+        expired_key_policy: settings.expired_key_policy,
+        key_unlock_minutes: settings.key_unlock_minutes,
+        fetch_signer_keys: settings.fetch_signer_keys,
+    })
+}
+
+/// Saves every setting in `settings`; unset ones go back to their defaults.
+#[tauri::command]
+fn set_settings(app: AppHandle, settings: AppSettings) -> Result<(), SigillumError> {
+    let timestamp_format = settings.timestamp_format.filter(|format| !format.trim().is_empty());
+    if let Some(format) = &timestamp_format {
+        appearance::check_timestamp_format(format)?;
+    }
+
+    let dir = get_app_data_dir(&app)?;
+    let mut stored = Settings::load(&dir)?;
+    stored.tsa_url = clean_tsa_url(settings.tsa_url)?;
+    stored.ntp_servers = clean_ntp_servers(settings.ntp_servers);
+    stored.max_clock_skew = settings.max_clock_skew;
+    stored.timestamp_format = timestamp_format;
+    stored.time_zone = settings.time_zone;
+    stored.expired_key_policy = settings.expired_key_policy;
+    stored.key_unlock_minutes = settings.key_unlock_minutes.filter(|&minutes| minutes > 0);
+    stored.fetch_signer_keys = settings.fetch_signer_keys;
+    stored.save(&dir)?;
+
+    log::info!("Settings saved");
+    Ok(())
+}
+
+/// A timestamp server URL as entered, or `None` when it is empty.
+fn clean_tsa_url(url: Option<String>) -> Result<Option<String>, SigillumError> {
+    let url = url.map(|u| u.trim().to_string()).filter(|u| !u.is_empty());
+    if let Some(url) = &url {
+        if !url.starts_with("http://") && !url.starts_with("https://") {
+            return Err("Timestamp server URL must start with http:// or https://".into());
+        }
+    }
+    Ok(url)
+}
+
+fn clean_ntp_servers(servers: Vec<String>) -> Vec<String> {
+    servers
+        .iter()
+        .map(|server| server.trim().to_string())
+        .filter(|server| !server.is_empty())
+        .collect()
+}
+
+#[tauri::command]
+fn get_tsa_url(app: AppHandle) -> Result<Option<String>, SigillumError> {
+    Ok(load_settings(&app)?.tsa_url)
+}
+
+/// Sets the timestamp authority used when signing; `None` or an empty URL
+/// turns timestamping off.
+#[tauri::command]
+fn set_tsa_url(app: AppHandle, url: Option<String>) -> Result<(), SigillumError> {
+    let url = clean_tsa_url(url)?;
+    
+    let dir = get_app_data_dir(&app)?;
+    let mut settings = Settings::load(&dir)?;
+    settings.tsa_url = url;
+    settings.save(&dir)?;
+
+    log::info!("Timestamp server set to {:?}", settings.tsa_url);
+    Ok(())
+}
+
+#[tauri::command]
+fn get_ntp_settings(app: AppHandle) -> Result<NtpSettings, SigillumError> {
+    let settings = load_settings(&app)?;
+    Ok(NtpSettings {
+        servers: settings.ntp_servers,
+        max_clock_skew: settings.max_clock_skew,
+    })
+}
+
+/// Sets the NTP servers the clock is checked against when signing; no
+/// servers turns the check off.
+#[tauri::command]
+fn set_ntp_settings(app: AppHandle, ntp: NtpSettings) -> Result<(), SigillumError> {
+    let dir = get_app_data_dir(&app)?;
+    let mut settings = Settings::load(&dir)?;
+    settings.ntp_servers = clean_ntp_servers(ntp.servers);
+    settings.max_clock_skew = ntp.max_clock_skew;
+    settings.save(&dir)?;
+
+    log::info!("NTP servers set to {:?}", settings.ntp_servers);
+    Ok(())
+}
+
+#[tauri::command]
+fn list_profiles(app: AppHandle) -> Result<ProfileList, SigillumError> {
+    let store = get_key_store(&app)?;
+    Ok(ProfileList {
+        profiles: store.profiles().to_vec(),
+        active: store.active_profile().to_string(),
+    })
+}
+
+#[tauri::command]
+async fn create_profile(
+    app: AppHandle,
+    name: String,
+    algorithm: Option<KeyAlgorithm>,
+    operation_id: Option<String>,
+) -> Result<String, SigillumError> {
+    run_job(app, operation_id, move |app| create_profile_job(app, name, algorithm)).await
+}
+
+fn create_profile_job(app: AppHandle, name: String, algorithm: Option<KeyAlgorithm>) -> Result<String, SigillumError> {
+    let keypair = KeyPair::generate(algorithm.unwrap_or_default())?;
+    get_key_store(&app)?.create_profile(&name, &keypair)?;
+
+    log::info!("Profile '{}' created", name);
     Ok(keypair.public_key)
 }
 
-fn compute_signature_hash(pdf_data: &[u8], name: &str, timestamp: &str, extra: &str) -> String {
-    let mut hasher = Sha256::new();
-    hasher.update(pdf_data);
-    hasher.update(name.as_bytes());
-    hasher.update(timestamp.as_bytes());
-    hasher.update(extra.as_bytes());
-    let hash = hasher.finalize();
-    format!("SHA256: {}", hex::encode(hash))
+#[tauri::command]
+fn delete_profile(app: AppHandle, name: String) -> Result<(), SigillumError> {
+    get_key_store(&app)?.delete_profile(&name)?;
+    if let Err(e) = biometric::forget_passphrase(&name) {
+        log::warn!("Failed to remove the passphrase kept for profile '{}': {}", name, e);
+    }
+
+    log::info!("Profile '{}' deleted", name);
+    Ok(())
 }
 
-fn create_watermark_text(name: &str, timestamp: &str, extra: &str, signature: &str) -> String {
-    if extra.is_empty() {
-        format!("Digitally signed by {}\n{}\nHash:{}", name, timestamp, signature)
-    } else {
-        format!("Digitally signed by {}\n{}\n{}\nHash:{}", name, timestamp, extra, signature)
+#[tauri::command]
+fn rename_profile(app: AppHandle, name: String, new_name: String) -> Result<(), SigillumError> {
+    get_key_store(&app)?.rename_profile(&name, &new_name)?;
+    if let Err(e) = biometric::forget_passphrase(&name) {
+        log::warn!("Failed to remove the passphrase kept for profile '{}': {}", name, e);
     }
+
+    log::info!("Profile '{}' renamed to '{}'", name, new_name);
+    Ok(())
+}
+
+#[tauri::command]
+fn get_profile_defaults(app: AppHandle, name: String) -> Result<SigningDefaults, SigillumError> {
+    Ok(load_profile_keypair(&app, Some(&name))?.defaults)
+}
+
+#[tauri::command]
+fn set_profile_defaults(app: AppHandle, name: String, defaults: SigningDefaults) -> Result<(), SigillumError> {
+    let mut store = get_key_store(&app)?;
+    store.select(&name)?;
+    store.set_defaults(defaults)?;
+
+    log::info!("Defaults of profile '{}' updated", name);
+    Ok(())
+}
+
+#[tauri::command]
+fn set_active_profile(app: AppHandle, name: String) -> Result<(), SigillumError> {
+    get_key_store(&app)?.set_active_profile(&name)?;
+
+    log::info!("Active profile set to '{}'", name);
+    Ok(())
+}
+
+#[tauri::command]
+fn get_public_key(app: AppHandle) -> Result<String, SigillumError> {
+    Ok(get_key_store(&app)?.load()?.public_key)
+}
+
+/// Saves the public key to `path` for recipients. Without a `format`, a
+/// `.der` path gets DER and anything else PEM.
+#[tauri::command]
+fn export_public_key_file(app: AppHandle, path: PathBuf, format: Option<PublicKeyFormat>) -> Result<(), SigillumError> {
+    let keypair = get_key_store(&app)?.load()?;
+    let format = format.unwrap_or_else(|| PublicKeyFormat::for_path(&path));
+    share::save_public_key(&keypair.public_key, &path, format)?;
+
+    log::info!("Public key exported to {}", path.display());
+    Ok(())
+}
+
+/// A DNS TXT record that lists a key for a domain.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DnsKeyRecord {
+    /// e.g. `sigillum._domainkey.example.com`.
+    pub name: String,
+    pub value: String,
+}
+
+/// The public key as handed to someone, with its fingerprint to check it by.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SharedPublicKey {
+    pub public_key: String,
+    /// SHA-256 of the public key, as colon-separated hex.
+    pub fingerprint: String,
+}
+
+/// Puts the public key on the clipboard as PEM, or only its fingerprint
+/// with `fingerprint`, and returns both.
+#[tauri::command]
+fn copy_public_key(app: AppHandle, fingerprint: Option<bool>) -> Result<SharedPublicKey, SigillumError> {
+    let keypair = get_key_store(&app)?.load()?;
+    let shared = SharedPublicKey {
+        fingerprint: keypair.info()?.fingerprint,
+        public_key: keypair.public_key,
+    };
+    let text = if fingerprint.unwrap_or(false) { &shared.fingerprint } else { &shared.public_key };
+    app.clipboard()
+        .write_text(text.as_str())
+        .map_err(|e| format!("Failed to copy to the clipboard: {}", e))?;
+    Ok(shared)
 }
 
+/// The TXT record for `domain`'s DNS that lists profile `name`'s key, so
+/// signatures naming the domain can be checked against it.
 #[tauri::command]
-fn sign_pdf(app: AppHandle, request: SignPdfRequest) -> Result<SignPdfResponse, String> {
-    let key_path = get_key_path(&app).map_err(|e| format!("Key path error: {}", e))?;
-    let key_json = fs::read_to_string(&key_path).map_err(|e| format!("Read error: {}", e))?;
-    let keypair: KeyPair = serde_json::from_str(&key_json).map_err(|e| format!("JSON error: {}", e))?;
+fn get_dns_key_record(app: AppHandle, name: String, domain: String) -> Result<DnsKeyRecord, SigillumError> {
+    let keypair = load_profile_keypair(&app, Some(&name))?;
+    let key = keys::PublicKey::from_public_key_pem(&keypair.public_key)?;
+    Ok(DnsKeyRecord {
+        name: discovery::dns_record_name(domain.trim())?,
+        value: discovery::dns_record(&key)?,
+    })
+}
+
+/// Saves the active profile's identity, going by `name`, to `path` as a
+/// `.sigillum-id` file for colleagues to trust.
+#[tauri::command]
+fn export_identity(app: AppHandle, path: PathBuf, name: String) -> Result<Identity, SigillumError> {
+    let keypair = get_key_store(&app)?.load()?;
+    let identity = Identity::new(&keypair, &name)?;
+    fs::write(&path, identity.to_json()?).map_err(|e| SigillumError::Io(format!("Failed to save identity: {}", e)))?;
+
+    log::info!("Identity exported to {}", path.display());
+    Ok(identity)
+}
+
+/// Trusts the key in the `.sigillum-id` file at `path`, under `name` or
+/// else the name in the file. Returns the identity as trusted, whose
+/// fingerprint is worth checking with its owner.
+#[tauri::command]
+fn import_identity(app: AppHandle, path: PathBuf, name: Option<String>) -> Result<Identity, SigillumError> {
+    let data = fs::read(&path).map_err(|e| SigillumError::Io(format!("Failed to read identity: {}", e)))?;
+    let mut identity = Identity::from_json(&data)?;
+    let trusted = get_trust_store(&app)?.add(name.as_deref().unwrap_or(&identity.name), &identity.public_key)?;
+    identity.name = trusted.name;
+
+    log::info!("Trusted key '{}' added from {}", identity.name, path.display());
+    Ok(identity)
+}
+
+/// A PNG QR code of the public key, or of its fingerprint and optionally
+/// where to fetch the key, to show on screen.
+#[tauri::command]
+fn export_public_key_qr(app: AppHandle, content: Option<QrContent>, fetch_url: Option<String>) -> Result<Vec<u8>, SigillumError> {
+    let keypair = get_key_store(&app)?.load()?;
+    share::public_key_qr(&keypair.public_key, content.unwrap_or_default(), fetch_url.as_deref())
+}
+
+#[tauri::command]
+fn get_key_info(app: AppHandle) -> Result<KeyInfo, SigillumError> {
+    get_key_store(&app)?.load()?.info()
+}
+
+/// Makes the active profile's key expire `valid_days` days from now, or
+/// never for `None`.
+#[tauri::command]
+fn set_key_expiry(app: AppHandle, valid_days: Option<u32>) -> Result<(), SigillumError> {
+    let mut store = get_key_store(&app)?;
+    let mut keypair = store.load()?;
+    keypair.set_validity(valid_days);
+    store.save(&keypair)?;
+
+    log::info!("Key expiry set to {:?}", keypair.expires);
+    Ok(())
+}
+
+/// Every version of the active profile's key, oldest first.
+#[tauri::command]
+fn list_key_versions(app: AppHandle) -> Result<Vec<KeyInfo>, SigillumError> {
+    get_key_store(&app)?.load()?.versions()
+}
+
+/// Generates a new key for the active profile, of the current key's type
+/// unless `algorithm` says otherwise, and retires the current one. Returns
+/// the new public key.
+#[tauri::command]
+async fn rotate_key(
+    app: AppHandle,
+    algorithm: Option<KeyAlgorithm>,
+    valid_days: Option<u32>,
+    operation_id: Option<String>,
+) -> Result<String, SigillumError> {
+    run_job(app, operation_id, move |app| rotate_key_job(app, algorithm, valid_days)).await
+}
+
+fn rotate_key_job(app: AppHandle, algorithm: Option<KeyAlgorithm>, valid_days: Option<u32>) -> Result<String, SigillumError> {
+    let mut store = get_key_store(&app)?;
+    let algorithm = match algorithm {
+        Some(algorithm) => algorithm,
+        None => store.load()?.algorithm,
+    };
+    let mut keypair = KeyPair::generate(algorithm)?;
+    keypair.set_validity(valid_days);
+    let version = store.rotate(keypair.clone())?;
+
+    log::info!("Key of profile '{}' rotated to version {}", store.selected_profile(), version);
+    Ok(keypair.public_key)
+}
+
+#[tauri::command]
+fn issue_certificate(
+    app: AppHandle,
+    subject: CertificateSubject,
+    validity_days: Option<u32>,
+    passphrase: Option<Zeroizing<String>>,
+) -> Result<CertificateInfo, SigillumError> {
+    let mut store = get_key_store(&app)?;
+    let mut keypair = store.load()?;
+    let info = keypair.issue_certificate(
+        &subject,
+        validity_days.unwrap_or(certificate::DEFAULT_VALIDITY_DAYS),
+        passphrase.as_deref().map(String::as_str),
+    )?;
+    store.save(&keypair)?;
+
+    log::info!("Certificate issued for {}", info.subject);
+    Ok(info)
+}
+
+#[tauri::command]
+fn get_certificate(app: AppHandle) -> Result<Option<CertificateInfo>, SigillumError> {
+    get_key_store(&app)?
+        .load()?
+        .certificate()?
+        .map(|certificate| certificate::info(&certificate))
+        .transpose()
+}
+
+#[tauri::command]
+fn export_certificate(app: AppHandle) -> Result<String, SigillumError> {
+    get_key_store(&app)?
+        .load()?
+        .certificate
+        .ok_or_else(|| "No certificate has been issued for this key".into())
+}
+
+/// Runs on the worker pool; progress is reported through `sign-progress`
+/// events and the job's state through `operation-status` events.
+#[tauri::command]
+async fn sign_pdf(app: AppHandle, request: SignPdfRequest) -> Result<SignPdfResponse, SigillumError> {
+    run_job(app, request.operation_id.clone(), move |app| sign_pdf_job(app, request)).await
+}
+
+fn sign_pdf_job(app: AppHandle, request: SignPdfRequest) -> Result<SignPdfResponse, SigillumError> {
+    let operation = Operation::start(&app, "sign-progress", request.operation_id);
     
-    let _private_key = RsaPrivateKey::from_pkcs8_pem(&keypair.private_key)
-        .map_err(|e| format!("Failed to parse private key: {}", e))?;
+    let mut opts = sign_options(&request.sign, load_settings(&app)?, operation.monitor.clone());
+    let keypair = sign_keypair(&app, &request.sign, &mut opts)?;
+    keypair.apply_defaults(&mut opts)?;
+    check_key_expiry(&app, &keypair)?;
+    let mut signed = api::sign(&request.pdf_data, opts, &keypair)?;
+    if let Some(key_id) = &request.sign.gpg_key {
+        let password = request.sign.output_password.as_ref().or(request.sign.password.as_ref());
+        signed.pdf = api::attach_gpg_signature(signed.pdf, key_id, request.sign.hash_algorithm, password.map(|p| p.as_str()))?;
+    }
+    let file_name = request.file_name.as_deref().unwrap_or("(unnamed)");
+    record_signing(&app, AuditEntry::new(file_name, &request.pdf_data, &signed.pdf, &signed.signature_info));
     
-    let timestamp = Utc::now().format("%Y-%m-%d %H:%M:%S UTC").to_string();
-    let signature_display = compute_signature_hash(&request.pdf_data, &request.name, &timestamp, &request.extra);
-    let watermark_text = create_watermark_text(&request.name, &timestamp, &request.extra, &signature_display);
+    Ok(SignPdfResponse {
+        signed_pdf: signed.pdf,
+        signature_info: signed.signature_info,
+        clock_warning: signed.clock_warning,
+    })
+}
+
+/// Path-based `sign_pdf`; the signed file is written straight to
+/// `output_path`.
+#[tauri::command]
+async fn sign_pdf_file(app: AppHandle, request: SignPdfFileRequest) -> Result<SignatureInfo, SigillumError> {
+    run_job(app, request.operation_id.clone(), move |app| sign_pdf_file_job(app, request)).await
+}
+
+fn sign_pdf_file_job(app: AppHandle, request: SignPdfFileRequest) -> Result<SignatureInfo, SigillumError> {
+    let operation = Operation::start(&app, "sign-progress", request.operation_id);
+    log::info!("Signing {}", request.input_path.display());
     
-    let mut doc = Document::load_mem(&request.pdf_data)
-        .map_err(|e| format!("Failed to load PDF: {}", e))?;
+    let mut opts = sign_options(&request.sign, load_settings(&app)?, operation.monitor.clone());
+    let keypair = sign_keypair(&app, &request.sign, &mut opts)?;
+    keypair.apply_defaults(&mut opts)?;
+    check_key_expiry(&app, &keypair)?;
+    let signed = api::sign_to_file(&request.input_path, &request.output_path, opts, &keypair)?;
+    attach_gpg_signature_to_file(&request.output_path, &request.sign)?;
+    record_signing(&app, AuditEntry::from_files(&request.input_path, &request.output_path, &signed.signature_info));
     
-    pdf_utils::add_watermark_to_pdf(&mut doc, &watermark_text)?;
+    Ok(signed.signature_info)
+}
+
+/// Returns where the signature was written.
+#[tauri::command(async)]
+fn sign_detached(app: AppHandle, request: SignDetachedRequest) -> Result<PathBuf, SigillumError> {
+    log::info!("Signing {} with a detached signature", request.path.display());
+
+    let mut opts = api::SignOptions::default();
+    opts.name = request.name;
+    opts.extra = request.extra;
+    opts.passphrase = request.passphrase;
+    opts.tsa_url = load_settings(&app)?.tsa_url;
+    opts.hash_algorithm = request.hash_algorithm;
+    let keypair = signing_keypair(&app, None, &mut opts)?;
+    keypair.apply_defaults(&mut opts)?;
+    check_key_expiry(&app, &keypair)?;
+    let signature = api::sign_detached(&request.path, &opts, &keypair)?;
+    let signature_path = request
+        .signature_path
+        .unwrap_or_else(|| api::DetachedSignature::path_for(&request.path));
+    signature.save(&signature_path)?;
+    record_signing(&app, AuditEntry::detached(&request.path, &signature_path, &signature));
+
+    Ok(signature_path)
+}
+
+/// Writes an OpenPGP signature of the file at `path`, such as a signed PDF
+/// just saved, next to it as `<name>.asc`. Returns where it was written.
+#[tauri::command(async)]
+fn sign_openpgp(app: AppHandle, path: PathBuf, passphrase: Option<Zeroizing<String>>, hash_algorithm: Option<HashAlgorithm>) -> Result<PathBuf, SigillumError> {
+    let keypair = unlocked_keypair(&app, passphrase.as_deref().map(String::as_str))?;
+    let file = fs::File::open(&path).map_err(|e| SigillumError::Io(format!("Failed to read file: {}", e)))?;
+    let signature = openpgp::sign_detached(BufReader::new(file), &keypair, passphrase.as_deref().map(String::as_str), hash_algorithm.unwrap_or_default())?;
+    let signature_path = openpgp::signature_path_for(&path);
+    fs::write(&signature_path, signature).map_err(|e| SigillumError::Io(format!("Failed to save signature: {}", e)))?;
+    log::info!("OpenPGP signature saved to {}", signature_path.display());
+    Ok(signature_path)
+}
+
+#[tauri::command(async)]
+fn sign_manifest(app: AppHandle, request: SignManifestRequest) -> Result<manifest::Manifest, SigillumError> {
+    log::info!("Signing a manifest of {} files", request.paths.len());
+
+    let mut opts = api::SignOptions::default();
+    opts.name = request.name;
+    opts.extra = request.extra;
+    opts.passphrase = request.passphrase;
+    opts.tsa_url = load_settings(&app)?.tsa_url;
+    opts.hash_algorithm = request.hash_algorithm;
+    let keypair = signing_keypair(&app, None, &mut opts)?;
+    keypair.apply_defaults(&mut opts)?;
+    check_key_expiry(&app, &keypair)?;
+    let signature_path = request
+        .signature_path
+        .unwrap_or_else(|| manifest::Manifest::signature_path_for(&request.manifest_path));
+    let (manifest, signature) =
+        manifest::sign_manifest(&request.paths, &request.manifest_path, &signature_path, &opts, &keypair)?;
+    record_signing(&app, AuditEntry::detached(&request.manifest_path, &signature_path, &signature));
+
+    Ok(manifest)
+}
+
+/// Runs off the main thread so the window stays responsive; progress is
+/// reported through `sign-batch-progress` events.
+#[tauri::command(async)]
+fn sign_pdf_batch(app: AppHandle, request: SignPdfBatchRequest) -> Result<Vec<api::BatchResult>, SigillumError> {
+    let mut outputs = HashSet::new();
+    let mut jobs = Vec::with_capacity(request.input_paths.len());
+    for input in request.input_paths {
+        let file_name = format!(
+            "{}_SIGNED.pdf",
+            input.file_stem().unwrap_or_default().to_string_lossy()
+        );
+        let dir = match &request.output_dir {
+            Some(dir) => dir.clone(),
+            None => input.parent().map(PathBuf::from).unwrap_or_default(),
+        };
+        let output = dir.join(file_name);
+        if !outputs.insert(output.clone()) {
+            return Err(format!("More than one file would be saved as {}", output.display()).into());
+        }
+        jobs.push(api::BatchJob { input, output });
+    }
+
+    let mut opts = sign_options(&request.sign, load_settings(&app)?, Monitor::default());
+    let keypair = sign_keypair(&app, &request.sign, &mut opts)?;
+    keypair.apply_defaults(&mut opts)?;
+    check_key_expiry(&app, &keypair)?;
+    let total = jobs.len();
+    log::info!("Signing a batch of {} PDFs", total);
+
+    let mut results = api::sign_batch(&jobs, &opts, &keypair, |completed, result| {
+        if let Some(info) = &result.signature_info {
+            record_signing(&app, AuditEntry::from_files(&result.input, &result.output, info));
+        }
+        let progress = SignBatchProgress {
+            completed,
+            total,
+            result: result.clone(),
+        };
+        if let Err(e) = app.emit("sign-batch-progress", progress) {
+            log::warn!("Failed to report batch progress: {}", e);
+        }
+    })?;
+    for result in results.iter_mut().filter(|result| result.signature_info.is_some()) {
+        if let Err(e) = attach_gpg_signature_to_file(&result.output, &request.sign) {
+            result.error = Some(format!("Signed, but the OpenPGP signature failed: {}", e));
+        }
+    }
+
+    let failed = results.iter().filter(|r| r.error.is_some()).count();
+    log::info!("Batch finished: {} signed, {} failed", total - failed, failed);
+    Ok(results)
+}
+
+/// Starts watching a folder in the background, where the watch holds one of
+/// the workers for as long as it runs. Each file signed or failed is
+/// reported through a `watch-signed` event carrying its `BatchResult`; when
+/// the watch ends, a `watch-stopped` event carries the error that ended it, if
+/// any. Only one folder is watched at a time.
+#[tauri::command]
+fn start_watch(app: AppHandle, request: WatchFolderRequest) -> Result<(), SigillumError> {
+    let mut opts = sign_options(&request.sign, load_settings(&app)?, Monitor::default());
+    let keypair = sign_keypair(&app, &request.sign, &mut opts)?;
+    keypair.apply_defaults(&mut opts)?;
+    check_key_expiry(&app, &keypair)?;
+
+    let token = CancellationToken::new();
+    {
+        let folder_watch = app.state::<FolderWatch>();
+        let mut current = folder_watch.0.lock().unwrap();
+        if current.is_some() {
+            return Err("A folder is already being watched".into());
+        }
+        *current = Some(token.clone());
+    }
+    log::info!("Watching {}", request.input_dir.display());
+
+    let (input_dir, output_dir, sign) = (request.input_dir, request.output_dir, request.sign);
+    tauri::async_runtime::spawn(async move {
+        let watched = input_dir.clone();
+        let outcome = run_job(app.clone(), None, move |app| {
+            watch::watch(&input_dir, &output_dir, &opts, &keypair, &token, |result| {
+                let mut result = result.clone();
+                if result.signature_info.is_some() {
+                    if let Err(e) = attach_gpg_signature_to_file(&result.output, &sign) {
+                        result.error = Some(format!("Signed, but the OpenPGP signature failed: {}", e));
+                    }
+                }
+                match (&result.signature_info, &result.error) {
+                    (Some(info), _) => record_signing(&app, AuditEntry::from_files(&result.input, &result.output, info)),
+                    (None, Some(e)) => log::warn!("Failed to sign {}: {}", result.input.display(), e),
+                    (None, None) => {}
+                }
+                if let Err(e) = app.emit("watch-signed", result) {
+                    log::warn!("Failed to report watched file: {}", e);
+                }
+            })
+        })
+        .await;
+        app.state::<FolderWatch>().0.lock().unwrap().take();
+        if let Err(e) = &outcome {
+            log::warn!("Stopped watching {}: {}", watched.display(), e);
+        }
+        if let Err(e) = app.emit("watch-stopped", outcome.err().map(|e| e.to_string())) {
+            log::warn!("Failed to report the end of the watch: {}", e);
+        }
+    });
+    Ok(())
+}
+
+/// Stops the watch started by `start_watch`; it ends within a moment, with a
+/// `watch-stopped` event. Does nothing when no folder is watched.
+#[tauri::command]
+fn stop_watch(app: AppHandle) {
+    if let Some(token) = app.state::<FolderWatch>().0.lock().unwrap().as_ref() {
+        log::info!("Stopping the folder watch");
+        token.cancel();
+    }
+}
+
+/// Runs on the worker pool; progress is reported through `verify-progress`
+/// events, named by `operation_id`, which `cancel_operation` also takes.
+#[tauri::command]
+async fn verify_pdf(
+    app: AppHandle,
+    pdf_data: Vec<u8>,
+    expected_hash: Option<String>,
+    public_key: Option<String>,
+    password: Option<Zeroizing<String>>,
+    operation_id: Option<String>,
+) -> Result<VerifyPdfResponse, SigillumError> {
+    run_job(app, operation_id.clone(), move |app| {
+        verify_pdf_job(app, pdf_data, expected_hash, public_key, password, operation_id)
+    })
+    .await
+}
+
+fn verify_pdf_job(
+    app: AppHandle,
+    pdf_data: Vec<u8>,
+    expected_hash: Option<String>,
+    public_key: Option<String>,
+    password: Option<Zeroizing<String>>,
+    operation_id: Option<String>,
+) -> Result<VerifyPdfResponse, SigillumError> {
+    log::info!("Verifying PDF, size: {} bytes", pdf_data.len());
+    let operation = Operation::start(&app, "verify-progress", operation_id);
     
-    let mut signed_pdf_bytes = Vec::new();
-    doc.save_to(&mut signed_pdf_bytes).map_err(|e| format!("Save error: {}", e))?;
+    let mut opts = verify_options(&app, expected_hash, public_key)?;
+    opts.password = password;
+    opts.monitor = operation.monitor.clone();
+    Ok(verify_response(api::verify(&pdf_data, opts)?))
+}
+
+/// Path-based `verify_pdf`: the file is streamed from disk instead of being
+/// sent over IPC.
+#[tauri::command]
+async fn verify_pdf_file(
+    app: AppHandle,
+    path: PathBuf,
+    expected_hash: Option<String>,
+    public_key: Option<String>,
+    password: Option<Zeroizing<String>>,
+    operation_id: Option<String>,
+) -> Result<VerifyPdfResponse, SigillumError> {
+    run_job(app, operation_id.clone(), move |app| {
+        verify_pdf_file_job(app, path, expected_hash, public_key, password, operation_id)
+    })
+    .await
+}
+
+fn verify_pdf_file_job(
+    app: AppHandle,
+    path: PathBuf,
+    expected_hash: Option<String>,
+    public_key: Option<String>,
+    password: Option<Zeroizing<String>>,
+    operation_id: Option<String>,
+) -> Result<VerifyPdfResponse, SigillumError> {
+    log::info!("Verifying PDF at {}", path.display());
+    let file = fs::File::open(&path).map_err(|e| SigillumError::Io(format!("Failed to read PDF: {}", e)))?;
+    let operation = Operation::start(&app, "verify-progress", operation_id);
     
-    Ok(SignPdfResponse {
-        signed_pdf: signed_pdf_bytes,
-        signature_info: SignatureInfo {
-            signer_name: request.name,
-            timestamp,
-            extra: request.extra,
-            signature: signature_display,
+    let mut opts = verify_options(&app, expected_hash, public_key)?;
+    opts.password = password;
+    opts.monitor = operation.monitor.clone();
+    Ok(verify_response(api::verify_reader(BufReader::new(file), opts)?))
+}
+
+/// Verifies each PDF at `paths` like `verify_pdf_file`, several at a time.
+/// Progress is reported through `verify-batch-progress` events.
+#[tauri::command(async)]
+fn verify_pdf_batch(
+    app: AppHandle,
+    paths: Vec<PathBuf>,
+    public_key: Option<String>,
+    password: Option<Zeroizing<String>>,
+) -> Result<api::BatchVerifySummary, SigillumError> {
+    let mut opts = verify_options(&app, None, public_key)?;
+    opts.password = password;
+    let total = paths.len();
+    log::info!("Verifying a batch of {} PDFs", total);
+
+    let summary = api::verify_batch(&paths, &opts, |completed, result| {
+        let progress = VerifyBatchProgress {
+            completed,
+            total,
+            result: result.clone(),
+        };
+        if let Err(e) = app.emit("verify-batch-progress", progress) {
+            log::warn!("Failed to report batch progress: {}", e);
+        }
+    });
+
+    log::info!(
+        "Batch verified: {} valid, {} invalid, {} unverified, {} unsigned, {} failed",
+        summary.valid, summary.invalid, summary.unverified, summary.unsigned, summary.failed
+    );
+    Ok(summary)
+}
+
+/// Verifies the PDF at `pdf_path` like `verify_pdf_file` and saves a report of
+/// the outcome, for filing with the document, to `output_path`.
+#[tauri::command(async)]
+fn export_verification_report(
+    app: AppHandle,
+    pdf_path: PathBuf,
+    output_path: PathBuf,
+    format: ReportFormat,
+    public_key: Option<String>,
+    password: Option<Zeroizing<String>>,
+) -> Result<(), SigillumError> {
+    let file = fs::File::open(&pdf_path).map_err(|e| SigillumError::Io(format!("Failed to read PDF: {}", e)))?;
+    let mut opts = verify_options(&app, None, public_key)?;
+    opts.password = password;
+    let report = api::verify_reader(BufReader::new(file), opts)?;
+    let data = VerificationReport::from_file(&pdf_path, report)?.render(format)?;
+    fs::write(&output_path, data).map_err(|e| SigillumError::Io(format!("Failed to save report: {}", e)))?;
+    log::info!("Verification report saved to {}", output_path.display());
+    Ok(())
+}
+
+/// The displayed size of each page in points, for picking where the
+/// signature goes.
+#[tauri::command(async)]
+fn get_page_sizes(pdf_data: Vec<u8>, password: Option<Zeroizing<String>>) -> Result<Vec<[f32; 2]>, SigillumError> {
+    api::page_sizes(&pdf_data, password.as_deref().map(String::as_str))
+}
+
+/// Basic facts about the PDF at `pdf_path`, for showing before it is signed.
+#[tauri::command(async)]
+fn inspect_pdf(pdf_path: PathBuf, password: Option<Zeroizing<String>>) -> Result<api::DocumentInfo, SigillumError> {
+    let pdf = fs::read(&pdf_path).map_err(|e| SigillumError::Io(format!("Failed to read PDF: {}", e)))?;
+    api::inspect(&pdf, password.as_deref().map(String::as_str))
+}
+
+/// `inspect_pdf` for a PDF that was dropped on the window rather than opened
+/// from a path.
+#[tauri::command(async)]
+fn inspect_pdf_data(pdf_data: Vec<u8>, password: Option<Zeroizing<String>>) -> Result<api::DocumentInfo, SigillumError> {
+    api::inspect(&pdf_data, password.as_deref().map(String::as_str))
+}
+
+/// Page `page` of the PDF at `pdf_path`, counting from 1, rendered at `dpi`
+/// as a PNG, for previewing the document and placing the signature on it.
+#[tauri::command(async)]
+fn render_page_preview(pdf_path: PathBuf, page: u32, dpi: f32, password: Option<Zeroizing<String>>) -> Result<Vec<u8>, SigillumError> {
+    let pdf = fs::read(&pdf_path).map_err(|e| SigillumError::Io(format!("Failed to read PDF: {}", e)))?;
+    preview::render_page(&pdf, password.as_deref().map(String::as_str), page, dpi)
+}
+
+/// `render_page_preview` for a PDF that was dropped on the window rather
+/// than opened from a path.
+#[tauri::command(async)]
+fn render_page_preview_data(pdf_data: Vec<u8>, page: u32, dpi: f32, password: Option<Zeroizing<String>>) -> Result<Vec<u8>, SigillumError> {
+    preview::render_page(&pdf_data, password.as_deref().map(String::as_str), page, dpi)
+}
+
+/// Takes the Sigillum signatures off the PDF at `input_path` and saves the
+/// result to `output_path`. Returns the signers whose signatures were removed.
+#[tauri::command(async)]
+fn remove_signature(input_path: PathBuf, output_path: PathBuf, password: Option<Zeroizing<String>>) -> Result<Vec<String>, SigillumError> {
+    let pdf = fs::read(&input_path).map_err(|e| SigillumError::Io(format!("Failed to read PDF: {}", e)))?;
+    let unsigned = api::remove_signature(&pdf, password.as_deref().map(String::as_str))?;
+    fs::write(&output_path, &unsigned.pdf).map_err(|e| SigillumError::Io(format!("Failed to save PDF: {}", e)))?;
+    log::info!("Removed {} signatures from {}", unsigned.removed.len(), input_path.display());
+    Ok(unsigned.removed.into_iter().map(|info| info.signer_name).collect())
+}
+
+/// Compares the PDF at `modified_path` with the original at `original_path`.
+#[tauri::command(async)]
+fn diff_pdfs(original_path: PathBuf, modified_path: PathBuf, password: Option<Zeroizing<String>>) -> Result<diff::PdfDiff, SigillumError> {
+    let read = |path: &PathBuf| fs::read(path).map_err(|e| SigillumError::Io(format!("Failed to read PDF: {}", e)));
+    diff::diff_pdfs(&read(&original_path)?, &read(&modified_path)?, password.as_deref().map(String::as_str))
+}
+
+/// Checks an OpenPGP signature, read from `signature_path` or from
+/// `<path>.asc`, against the file at `path` and `public_key` or our own key.
+#[tauri::command(async)]
+fn verify_openpgp(
+    app: AppHandle,
+    path: PathBuf,
+    signature_path: Option<PathBuf>,
+    public_key: Option<String>,
+) -> Result<openpgp::PgpVerification, SigillumError> {
+    let signature_path = signature_path.unwrap_or_else(|| openpgp::signature_path_for(&path));
+    let armored = fs::read_to_string(&signature_path).map_err(|e| SigillumError::Io(format!("Failed to read signature: {}", e)))?;
+    let public_key = match public_key {
+        Some(pem) => pem,
+        None => get_key_store(&app)?.load()?.public_key,
+    };
+    let file = fs::File::open(&path).map_err(|e| SigillumError::Io(format!("Failed to read file: {}", e)))?;
+    openpgp::verify_detached(BufReader::new(file), &armored, &keys::PublicKey::from_public_key_pem(&public_key)?)
+}
+
+/// Checks the manifest at `manifest_path` against its signature, read from
+/// `signature_path` or next to it, and the files it lists against the
+/// manifest, looking for them in `dir` or else the manifest's folder.
+#[tauri::command(async)]
+fn verify_manifest(
+    app: AppHandle,
+    manifest_path: PathBuf,
+    signature_path: Option<PathBuf>,
+    dir: Option<PathBuf>,
+    public_key: Option<String>,
+) -> Result<manifest::ManifestReport, SigillumError> {
+    log::info!("Verifying the manifest {}", manifest_path.display());
+    let signature_path = signature_path.unwrap_or_else(|| manifest::Manifest::signature_path_for(&manifest_path));
+    let signature = api::DetachedSignature::load(&signature_path)?;
+    let dir = dir.unwrap_or_else(|| manifest_path.parent().map(Path::to_path_buf).unwrap_or_default());
+
+    let opts = verify_options(&app, None, public_key)?;
+    manifest::verify_manifest(&manifest_path, &signature, &dir, opts)
+}
+
+/// Checks a detached signature, read from `signature_path` or from
+/// `<path>.sig`, against the file at `path`.
+#[tauri::command(async)]
+fn verify_detached(
+    app: AppHandle,
+    path: PathBuf,
+    signature_path: Option<PathBuf>,
+    public_key: Option<String>,
+) -> Result<api::SignatureReport, SigillumError> {
+    log::info!("Verifying the detached signature of {}", path.display());
+    let signature_path = signature_path.unwrap_or_else(|| api::DetachedSignature::path_for(&path));
+    let signature = api::DetachedSignature::load(&signature_path)?;
+
+    let opts = verify_options(&app, None, public_key)?;
+    api::verify_detached(&path, &signature, opts)
+}
+
+fn verify_options(
+    app: &AppHandle,
+    expected_hash: Option<String>,
+    public_key: Option<String>,
+) -> Result<api::VerifyOptions, SigillumError> {
+    let dir = get_app_data_dir(app)?;
+    let settings = Settings::load(&dir)?;
+    // Fall back to our own key, and the ones it replaced, so documents signed
+    // here verify out of the box
+    let (public_key, retired_keys) = match public_key {
+        Some(pem) => (Some(pem), Vec::new()),
+        None => match get_key_store(app)?.load() {
+            Ok(keypair) => (Some(keypair.public_key.clone()), keypair.retired_public_keys()),
+            Err(_) => (None, Vec::new()),
         },
+    };
+    
+    let mut opts = api::VerifyOptions::default();
+    opts.expected_hash = expected_hash;
+    opts.public_key = public_key;
+    opts.retired_keys = retired_keys;
+    opts.trusted_keys = get_trust_store(app)?.keys().to_vec();
+    opts.use_embedded_key = true;
+    opts.fetch_keys = settings.fetch_signer_keys;
+    opts.key_cache_dir = Some(discovery::cache_dir(&dir));
+    opts.tsa_roots = settings.tsa_roots;
+    Ok(opts)
+}
+
+/// Verifies strictly against `public_key_pem`, e.g. a key the signer sent,
+/// without falling back to the locally stored key. Reports progress like
+/// `verify_pdf`.
+#[tauri::command]
+async fn verify_pdf_with_key(
+    app: AppHandle,
+    pdf_data: Vec<u8>,
+    public_key_pem: String,
+    password: Option<Zeroizing<String>>,
+    operation_id: Option<String>,
+) -> Result<VerifyPdfResponse, SigillumError> {
+    run_job(app, operation_id.clone(), move |app| {
+        verify_pdf_with_key_job(app, pdf_data, public_key_pem, password, operation_id)
     })
+    .await
 }
 
+fn verify_pdf_with_key_job(
+    app: AppHandle,
+    pdf_data: Vec<u8>,
+    public_key_pem: String,
+    password: Option<Zeroizing<String>>,
+    operation_id: Option<String>,
+) -> Result<VerifyPdfResponse, SigillumError> {
+    log::info!("Verifying PDF against a supplied key, size: {} bytes", pdf_data.len());
+    keys::PublicKey::from_public_key_pem(&public_key_pem)?;
+    let operation = Operation::start(&app, "verify-progress", operation_id);
+
+    let mut opts = api::VerifyOptions::default();
+    opts.public_key = Some(public_key_pem);
+    opts.tsa_roots = load_settings(&app)?.tsa_roots;
+    opts.password = password;
+    opts.monitor = operation.monitor.clone();
+    Ok(verify_response(api::verify(&pdf_data, opts)?))
+}
+
+/// Stops the sign or verify operation started with `operation_id`, which then
+/// fails with "Operation cancelled". Does nothing once it has finished.
 #[tauri::command]
-fn verify_pdf(pdf_data: Vec<u8>) -> Result<VerifyPdfResponse, String> {
-    log::info!("Verifying PDF, size: {} bytes", pdf_data.len());
-    
-    if let Some((signer_name, timestamp, extra, signature)) = pdf_utils::extract_signature_info(&pdf_data) {
-        return Ok(VerifyPdfResponse {
-            is_signed: true,
-            signature_info: Some(SignatureInfo {
-                signer_name,
-                timestamp,
-                extra,
-                signature,
-            }),
-            message: "PDF has a digital signature".to_string(),
-        });
+fn cancel_operation(app: AppHandle, operation_id: String) {
+    if let Some(token) = app.state::<Operations>().0.lock().unwrap().get(&operation_id) {
+        log::info!("Cancelling operation {}", operation_id);
+        token.cancel();
     }
-    
-    Ok(VerifyPdfResponse {
-        is_signed: false,
-        signature_info: None,
-        message: "PDF does not contain a digital signature".to_string(),
-    })
+}
+
+fn verify_response(report: api::VerifyReport) -> VerifyPdfResponse {
+    let status = report.status();
+    let count = report.signatures.len() + report.foreign_signatures.len() + report.pgp_signatures.len();
+    let message = match (status, count) {
+        (SignatureStatus::Valid, 1) => "PDF has a valid digital signature".to_string(),
+        (SignatureStatus::Valid, count) => format!("PDF has {} valid digital signatures", count),
+        (SignatureStatus::Invalid, _) => "PDF signature is INVALID".to_string(),
+        (SignatureStatus::Unverified, _) => "PDF has a digital signature that could not be verified".to_string(),
+        (SignatureStatus::Unsigned, _) => "PDF does not contain a digital signature".to_string(),
+    };
+
+    VerifyPdfResponse {
+        is_signed: report.is_signed(),
+        signatures: report.signatures,
+        foreign_signatures: report.foreign_signatures,
+        pgp_signatures: report.pgp_signatures,
+        message,
+        canonical_hash: report.canonical_hash,
+        hash_matches: report.hash_matches,
+        status,
+    }
+}
+
+#[tauri::command]
+fn add_trusted_key(app: AppHandle, name: String, public_key_pem: String) -> Result<TrustedKey, SigillumError> {
+    let key = get_trust_store(&app)?.add(&name, &public_key_pem)?;
+
+    log::info!("Trusted key '{}' added", key.name);
+    Ok(key)
+}
+
+#[tauri::command]
+fn remove_trusted_key(app: AppHandle, name: String) -> Result<(), SigillumError> {
+    get_trust_store(&app)?.remove(&name)?;
+
+    log::info!("Trusted key '{}' removed", name);
+    Ok(())
+}
+
+/// Revokes the trusted key `name` from `date` (see
+/// `trust_store::parse_revocation_date`), or from now.
+#[tauri::command]
+fn revoke_trusted_key(app: AppHandle, name: String, reason: String, date: Option<String>) -> Result<TrustedKey, SigillumError> {
+    let date = date.as_deref().map(trust_store::parse_revocation_date).transpose()?;
+    let key = get_trust_store(&app)?.revoke(&name, &reason, date)?;
+
+    log::info!("Trusted key '{}' revoked", name);
+    Ok(key)
+}
+
+#[tauri::command]
+fn list_trusted_keys(app: AppHandle) -> Result<Vec<TrustedKey>, SigillumError> {
+    Ok(get_trust_store(&app)?.keys().to_vec())
+}
+
+/// Adds a signing to the audit log. The document is signed by now, so a
+/// failure is only logged.
+fn record_signing(app: &AppHandle, entry: Result<AuditEntry, SigillumError>) {
+    let recorded = entry.and_then(|entry| AuditLog::open(&get_app_data_dir(app)?).record(&entry));
+    if let Err(e) = recorded {
+        log::warn!("Failed to record signing in the audit log: {}", e);
+    }
+}
+
+/// Every signing recorded in the audit log, oldest first.
+#[tauri::command]
+fn list_signing_history(app: AppHandle) -> Result<Vec<AuditEntry>, SigillumError> {
+    AuditLog::open(&get_app_data_dir(&app)?).entries()
+}
+
+/// Copies the audit log (JSON Lines) to `path`.
+#[tauri::command]
+fn export_audit_log(app: AppHandle, path: PathBuf) -> Result<(), SigillumError> {
+    AuditLog::open(&get_app_data_dir(&app)?).export(&path)
+}
+
+/// Writes every profile, trusted key and setting to `path`, encrypted with
+/// `passphrase`.
+#[tauri::command(async)]
+fn backup_keys(app: AppHandle, path: PathBuf, passphrase: Zeroizing<String>) -> Result<(), SigillumError> {
+    let data = backup::backup(&get_app_data_dir(&app)?, &passphrase)?;
+    fs::write(&path, data).map_err(|e| SigillumError::Io(format!("Failed to save backup: {}", e)))?;
+    log::info!("Backup saved to {}", path.display());
+    Ok(())
+}
+
+#[tauri::command(async)]
+fn restore_keys(app: AppHandle, path: PathBuf, passphrase: Zeroizing<String>) -> Result<backup::RestoreSummary, SigillumError> {
+    let data = fs::read(&path).map_err(|e| SigillumError::Io(format!("Failed to read backup: {}", e)))?;
+    let summary = backup::restore(&get_app_data_dir(&app)?, &data, &passphrase)?;
+    log::info!(
+        "Restored {} profile(s) and {} trusted key(s) from {}",
+        summary.profiles.len(),
+        summary.trusted_keys.len(),
+        path.display()
+    );
+    Ok(summary)
 }
 
 pub fn run() {
     tauri::Builder::default()
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_fs::init())
+        .plugin(tauri_plugin_clipboard_manager::init())
+        .manage(Operations::default())
+        .manage(Workers::default())
+        .manage(FolderWatch::default())
+        .manage(KeySession::default())
         .invoke_handler(tauri::generate_handler![
             has_key,
             generate_keypair,
             import_key,
+            import_key_file,
+            import_key_shares,
+            import_key_phrase,
+            import_pkcs11_key,
+            list_pkcs11_tokens,
+            list_pkcs11_keys,
+            has_tpm,
+            list_gpg_keys,
+            list_ssh_agent_keys,
+            import_ssh_agent_key,
+            import_kms_key,
+            list_signing_devices,
+            list_store_certificates,
+            import_signing_device,
             export_key,
+            export_key_pkcs12,
+            export_key_shares,
+            export_key_phrase,
+            has_key_passphrase,
+            unlock_key,
+            lock_key,
+            key_unlock_status,
+            biometric_status,
+            enable_biometric_unlock,
+            disable_biometric_unlock,
+            set_key_passphrase,
+            get_key_storage,
+            migrate_key_storage,
+            get_settings,
+            set_settings,
+            get_tsa_url,
+            set_tsa_url,
+            get_ntp_settings,
+            set_ntp_settings,
+            list_profiles,
+            create_profile,
+            delete_profile,
+            set_active_profile,
+            rename_profile,
+            get_profile_defaults,
+            set_profile_defaults,
             get_public_key,
+            export_public_key_file,
+            export_public_key_qr,
+            copy_public_key,
+            get_dns_key_record,
+            export_identity,
+            import_identity,
+            get_key_info,
+            list_key_versions,
+            set_key_expiry,
+            rotate_key,
+            issue_certificate,
+            get_certificate,
+            export_certificate,
             sign_pdf,
+            sign_pdf_file,
+            sign_detached,
+            sign_pdf_batch,
+            start_watch,
+            stop_watch,
             verify_pdf,
+            verify_pdf_file,
+            verify_pdf_batch,
+            export_verification_report,
+            get_page_sizes,
+            inspect_pdf,
+            inspect_pdf_data,
+            render_page_preview,
+            render_page_preview_data,
+            diff_pdfs,
+            remove_signature,
+            verify_detached,
+            sign_manifest,
+            verify_manifest,
+            sign_openpgp,
+            verify_openpgp,
+            export_openpgp_key,
+            verify_pdf_with_key,
+            cancel_operation,
+            add_trusted_key,
+            remove_trusted_key,
+            revoke_trusted_key,
+            list_trusted_keys,
+            list_signing_history,
+            export_audit_log,
+            backup_keys,
+            restore_keys,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
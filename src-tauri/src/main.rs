@@ -1,14 +1,40 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-mod pdf_utils;
-
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use sigillum_core::appearance::{Color, PageSelection, Position, SignaturePlacement, Stamp, StampPlacement, StampTimeZone, DEFAULT_STAMP_WIDTH};
+use sigillum_core::{backup, cert_store, diff, discovery, kms, manifest, mnemonic, openpgp, piv, pkcs11, shamir, ssh_agent, trust_store, watch};
+use sigillum_core::share::{self, PublicKeyFormat, QrContent};
+use sigillum_core::certificate::{self, CertificateInfo};
+use sigillum_core::keys::{self, PrivateKey, PublicKey};
+use sigillum_core::{api, Appearance, AuditEntry, AuditLog, CancellationToken, CertificateSubject, ExpiredKeyPolicy, HashAlgorithm, Identity, KeyAlgorithm, KeyPair, KeyStorage, KeyStore, KeyShare, Monitor, Pkcs11Config, Progress, ReportFormat, ResignPolicy, Settings, ShamirScheme, SigillumError, SignatureStatus, TrustStore, VerificationReport};
+use serde::Serialize;
+use serde_json::json;
 use std::env;
 use std::fs;
-use std::path::PathBuf;
+use std::io::{self, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
 use std::process::exit;
+use std::sync::OnceLock;
+use zeroize::Zeroizing;
 
-const KEY_SIZE: usize = 2048;
+/// Exit statuses of `verify`, so scripts can tell its outcomes apart; clap
+/// itself exits with 2 on bad arguments.
+const EXIT_ERROR: i32 = 1;
+const EXIT_UNSIGNED: i32 = 3;
+const EXIT_INVALID: i32 = 4;
+const EXIT_UNVERIFIED: i32 = 5;
+/// Exit statuses of failures any command can have, one per error code;
+/// failures without one of their own exit with `EXIT_ERROR`.
+const EXIT_NO_KEY: i32 = 10;
+const EXIT_PASSPHRASE_REQUIRED: i32 = 11;
+const EXIT_WRONG_PASSPHRASE: i32 = 12;
+const EXIT_INVALID_PDF: i32 = 13;
+const EXIT_PASSWORD_REQUIRED: i32 = 14;
+const EXIT_WRONG_PASSWORD: i32 = 15;
+const EXIT_ALREADY_SIGNED: i32 = 16;
+const EXIT_CANCELLED: i32 = 17;
+const EXIT_IO: i32 = 18;
+const EXIT_KEY_EXPIRED: i32 = 19;
 
 #[derive(Parser)]
 #[command(name = "sigillum")]
@@ -17,186 +43,2917 @@ const KEY_SIZE: usize = 2048;
 struct Cli {
     #[command(subcommand)]
     command: Option<Commands>,
+
+    /// Read the passphrase that unlocks the key from this file instead of
+    /// prompting for it
+    #[arg(long, global = true)]
+    passphrase_file: Option<PathBuf>,
+}
+
+/// The contents of `--passphrase-file`, read once at startup.
+static PASSPHRASE_FILE: OnceLock<Zeroizing<String>> = OnceLock::new();
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    /// Human-readable report
+    Text,
+    /// A single JSON document on stdout, for scripts
+    Json,
 }
 
+// Parsed once per run, so the size of the `Sign` variant does not matter.
+#[allow(clippy::large_enum_variant)]
 #[derive(Subcommand)]
 enum Commands {
-    Keygen,
-    Export,
-    Sign {
+    Keygen {
+        /// Key type: rsa-2048, rsa-3072, rsa-4096, ed25519 or p256
+        #[arg(long, default_value = "rsa-2048")]
+        algorithm: KeyAlgorithm,
+
+        /// Key size in bits: 2048, 3072 or 4096 for RSA
         #[arg(long)]
-        name: String,
-        
-        #[arg(long, default_value = "")]
-        extra: String,
+        bits: Option<u32>,
+
+        /// Generate an RSA key inside the TPM through tpm2-pkcs11, from where it
+        /// cannot be copied
+        #[arg(long)]
+        tpm: bool,
+
+        /// User PIN of the TPM's token (prompted for if omitted)
+        #[arg(long, requires = "tpm")]
+        pin: Option<String>,
+
+        /// Generate a P-256 key in this Mac's Secure Enclave, from where it
+        /// cannot be copied; the Keychain signs with it
+        #[arg(long, conflicts_with = "tpm")]
+        secure_enclave: bool,
+
+        /// Make the key expire this many days from now
+        #[arg(long)]
+        valid_days: Option<u32>,
+
+        /// Key profile to use instead of the active one
+        #[arg(long)]
+        profile: Option<String>,
+    },
+    /// Import a private key from PEM, DER, OpenSSH or PKCS#12, or use the RSA
+    /// key on a PKCS#11 token or PIV card, an Ed25519 key in ssh-agent or a
+    /// key in a cloud KMS, Vault, the macOS Keychain or the Windows certificate
+    /// store; the public key is derived from it
+    Import {
+        /// Private key file: PKCS#8, PKCS#1, SEC1, OpenSSH, or a .p12/.pfx
+        /// file, whose certificate is imported too
+        #[arg(long, required_unless_present_any = ["pkcs11_module", "device", "ssh_agent", "kms", "shares", "phrase"])]
+        key: Option<PathBuf>,
+
+        #[command(flatten)]
+        token: TokenArgs,
+
+        /// Serial number of a PIV card such as a YubiKey, from `devices`, to
+        /// sign with the key and certificate in its digital signature slot
+        #[arg(long, conflicts_with_all = ["key", "pkcs11_module"])]
+        device: Option<String>,
+
+        /// SSH fingerprint (SHA256:...) of an Ed25519 key in ssh-agent, from
+        /// `devices --ssh-agent`, to sign with through the agent
+        #[arg(long, conflicts_with_all = ["key", "pkcs11_module", "device"])]
+        ssh_agent: Option<String>,
+
+        /// Key in a key management service to sign with, as aws:<key ARN>,
+        /// gcp:projects/.../cryptoKeyVersions/N,
+        /// azure:https://<vault>.vault.azure.net/keys/<name> or
+        /// vault:[https://<server>/]<mount>/<key name> for Vault's transit
+        /// engine, keychain:<label> for a key in the macOS Keychain or
+        /// certstore:<thumbprint> for a certificate in the Windows user store
+        /// (see `devices --cert-store`); credentials come from the provider's
+        /// environment variables or CLI
+        #[arg(long, conflicts_with_all = ["key", "pkcs11_module", "device", "ssh_agent"])]
+        kms: Option<String>,
+
+        /// Key share files made by `export --shamir`, to rebuild the key
+        /// from; as many as the split needs, from the same split
+        #[arg(long, num_args = 1.., conflicts_with_all = ["key", "pkcs11_module", "device", "ssh_agent", "kms"])]
+        shares: Vec<PathBuf>,
+
+        /// Restore an Ed25519 or P-256 key from its recovery phrase, from
+        /// `export --phrase`; the words are prompted for
+        #[arg(long, conflicts_with_all = ["key", "pkcs11_module", "device", "ssh_agent", "kms", "shares"])]
+        phrase: bool,
+
+        /// Key type the recovery phrase holds: ed25519 or p256
+        #[arg(long, requires = "phrase", default_value = "ed25519")]
+        algorithm: KeyAlgorithm,
+
+        /// Passphrase of an encrypted key, or the token PIN (prompted for if omitted)
+        #[arg(long)]
+        passphrase: Option<String>,
+
+        /// Key profile to import into instead of the active one
+        #[arg(long)]
+        profile: Option<String>,
+    },
+    /// List the plugged-in PIV cards, such as YubiKeys, that can sign, the
+    /// tokens and keys of a PKCS#11 module, the keys in ssh-agent or the
+    /// certificates in the Windows certificate store
+    Devices {
+        /// PKCS#11 module whose tokens and their private keys to list instead
+        #[arg(long)]
+        pkcs11_module: Option<PathBuf>,
+
+        /// List the keys ssh-agent holds instead
+        #[arg(long, conflicts_with = "pkcs11_module")]
+        ssh_agent: bool,
+
+        /// List the certificates with a private key in the Windows user
+        /// certificate store instead
+        #[arg(long, conflicts_with_all = ["pkcs11_module", "ssh_agent"])]
+        cert_store: bool,
+
+        /// Token PIN, for tokens that only show their keys once logged in
+        #[arg(long, requires = "pkcs11_module")]
+        pin: Option<String>,
+
+        /// How to list the devices: text or json
+        #[arg(long, value_enum, default_value = "text")]
+        format: OutputFormat,
+    },
+    /// Print the private key as PEM, or save it with its certificate as a
+    /// PKCS#12 file
+    Export {
+        /// Passphrase of a protected key (prompted for if omitted)
+        #[arg(long)]
+        passphrase: Option<String>,
+
+        /// Save a .p12/.pfx file here instead of printing the key
+        #[arg(long)]
+        pkcs12: Option<PathBuf>,
+
+        /// Password for the PKCS#12 file (prompted for if omitted)
+        #[arg(long, requires = "pkcs12")]
+        pkcs12_password: Option<String>,
+
+        /// Split the key into share files instead, e.g. 3of5 for five shares
+        /// any three of which rebuild it with `import --shares`
+        #[arg(long, conflicts_with = "pkcs12")]
+        shamir: Option<ShamirScheme>,
+
+        /// Print the 24-word recovery phrase of an Ed25519 or P-256 key
+        /// instead, to write down as a paper backup
+        #[arg(long, conflicts_with_all = ["pkcs12", "shamir"])]
+        phrase: bool,
+
+        /// Directory to save the share files in
+        #[arg(long, requires = "shamir", default_value = ".")]
+        out_dir: PathBuf,
+
+        /// Key profile to use instead of the active one
+        #[arg(long)]
+        profile: Option<String>,
+
+        /// Export this version of the profile's key instead of the current
+        /// one; a retired key takes the passphrase it had when retired
+        #[arg(long)]
+        version: Option<u32>,
+    },
+    /// Set, change or remove the passphrase protecting the private key
+    Passphrase {
+        /// Store the private key unprotected again
+        #[arg(long)]
+        remove: bool,
+    },
+    /// Show the key's certificate, or issue a new self-signed one that goes
+    /// into every document you sign
+    Cert {
+        /// Your name; issues a new certificate when given
+        #[arg(long)]
+        common_name: Option<String>,
+
+        #[arg(long, requires = "common_name")]
+        organization: Option<String>,
+
+        #[arg(long, requires = "common_name")]
+        email: Option<String>,
+
+        /// How long the new certificate is valid
+        #[arg(long, default_value_t = certificate::DEFAULT_VALIDITY_DAYS)]
+        days: u32,
+
+        /// Print the certificate as PEM, e.g. to send it to someone
+        #[arg(long)]
+        pem: bool,
+
+        /// Passphrase of a protected key (prompted for if omitted)
+        #[arg(long)]
+        passphrase: Option<String>,
+
+        /// Key profile to use instead of the active one
+        #[arg(long)]
+        profile: Option<String>,
+    },
+    /// Show or change where the keypair is stored, moving an existing key
+    KeyStorage {
+        /// Move the key to this backend: "file" or "keychain"
+        #[arg(long)]
+        migrate_to: Option<KeyStorage>,
+    },
+    Sign {
+        #[command(flatten)]
+        sign: SignArgs,
+
+        /// PDF to sign, or - for stdin
+        #[arg(long, required_unless_present = "input_dir")]
+        input: Option<PathBuf>,
         
+        /// Where the signed PDF goes, or - for stdout
+        #[arg(long, required_unless_present = "input_dir")]
+        output: Option<PathBuf>,
+
+        /// Sign every PDF in this directory instead of a single file
+        #[arg(long, conflicts_with_all = ["input", "output"], requires = "output_dir")]
+        input_dir: Option<PathBuf>,
+
+        /// Where signed copies from --input-dir are written, under the same names
+        #[arg(long, requires = "input_dir")]
+        output_dir: Option<PathBuf>,
+
+        /// Also write an OpenPGP signature of the signed PDF to the output name
+        /// plus .asc, for recipients who check with gpg --verify
+        #[arg(long, conflicts_with = "input_dir")]
+        pgp: bool,
+
+        /// Then also sign the PDF with this GnuPG key (fingerprint, key ID or
+        /// user ID) through gpg, attaching the OpenPGP signature to it
+        #[arg(long, conflicts_with = "input_dir")]
+        gpg_key: Option<String>,
+
+        /// How to report the result: text or json
+        #[arg(long, value_enum, default_value = "text")]
+        format: OutputFormat,
+    },
+    /// Keep watching a directory and sign every PDF that is put into it
+    Watch {
+        #[command(flatten)]
+        sign: SignArgs,
+
+        /// Directory to watch for new PDFs
+        #[arg(long)]
+        input_dir: PathBuf,
+
+        /// Where signed copies are written, under the same names
+        #[arg(long)]
+        output_dir: PathBuf,
+    },
+    /// Check the signatures on a PDF
+    #[command(after_help = "Exit status:
+  0  every signature is valid
+  1  the PDF could not be read or checked
+  2  invalid arguments
+  3  the PDF is not signed
+  4  a signature is invalid, or the canonical hash does not match --expect-hash
+  5  a signature could not be verified, e.g. because the signer's key is not trusted
+
+With --dir, the worst status among the PDFs: 4, then 1, 5 and 3.")]
+    Verify {
+        /// PDF to check, or - for stdin
+        #[arg(long, required_unless_present = "dir")]
+        file: Option<PathBuf>,
+
+        /// Check every PDF in this folder and its subfolders instead
+        #[arg(long, conflicts_with_all = ["file", "expect_hash", "report"])]
+        dir: Option<PathBuf>,
+
+        /// Password of an encrypted PDF
+        #[arg(long)]
+        password: Option<String>,
+
+        /// How to report the result: text or json
+        #[arg(long, value_enum, default_value = "text")]
+        format: OutputFormat,
+
+        /// Fail unless the document's canonical hash equals this hex digest
+        #[arg(long)]
+        expect_hash: Option<String>,
+
+        /// Check the signature against this public key (PEM) instead of our own
+        #[arg(long)]
+        pubkey: Option<PathBuf>,
+
+        /// Also save a verification report here: a PDF for a .pdf file, JSON otherwise
+        #[arg(long)]
+        report: Option<PathBuf>,
+
+        /// Fetch the key each signature says its signer publishes, over HTTPS
+        /// or in DNS, and check it is the one that signed
+        #[arg(long)]
+        fetch_key: bool,
+    },
+    /// Sign any file (ZIP, DOCX, ...) into a separate .sig file, leaving it untouched
+    SignFile {
+        /// Signer name (defaults to the profile's)
+        #[arg(long)]
+        name: Option<String>,
+
+        /// Extra text to sign along (defaults to the profile's)
+        #[arg(long)]
+        extra: Option<String>,
+
         #[arg(long)]
         input: PathBuf,
-        
+
+        /// Where the signature is written (defaults to the input name plus .sig)
+        #[arg(long)]
+        output: Option<PathBuf>,
+
+        /// Passphrase of a protected key (prompted for if omitted)
+        #[arg(long)]
+        passphrase: Option<String>,
+
+        /// Key profile to use instead of the active one
+        #[arg(long)]
+        profile: Option<String>,
+
+        /// RFC 3161 timestamp server to vouch for the signing time
+        /// (defaults to the one set in the app)
+        #[arg(long)]
+        tsa_url: Option<String>,
+
+        /// Digest to sign: sha256, sha384, sha512 or blake3
+        #[arg(long = "hash", default_value = "sha256")]
+        hash_algorithm: HashAlgorithm,
+    },
+    /// Check a file against its detached signature, Sigillum's own or an
+    /// OpenPGP .asc one
+    VerifyFile {
+        #[arg(long)]
+        file: PathBuf,
+
+        /// The signature to check (defaults to the file name plus .sig, or
+        /// plus .asc when there is no .sig)
+        #[arg(long)]
+        signature: Option<PathBuf>,
+
+        /// Check the signature against this public key (PEM) instead of our own
+        #[arg(long)]
+        pubkey: Option<PathBuf>,
+    },
+    /// Compare a signed or modified PDF with the original
+    #[command(after_help = "Exit status:
+  0  only signing changed the document
+  1  a PDF could not be read
+  2  invalid arguments
+  4  the document was changed in other ways")]
+    Diff {
+        /// The PDF as it was before signing
+        #[arg(long)]
+        original: PathBuf,
+
+        /// The signed or modified PDF
+        #[arg(long)]
+        modified: PathBuf,
+
+        /// Password of an encrypted PDF
+        #[arg(long)]
+        password: Option<String>,
+
+        /// How to report the result: text or json
+        #[arg(long, value_enum, default_value = "text")]
+        format: OutputFormat,
+    },
+    /// Take the Sigillum signatures off a PDF, giving back the document as it
+    /// was before it was signed
+    Unsign {
+        /// Signed PDF, or - for stdin
+        #[arg(long)]
+        input: PathBuf,
+
+        /// Where the unsigned PDF goes, or - for stdout
         #[arg(long)]
         output: PathBuf,
+
+        /// Password of an encrypted PDF
+        #[arg(long)]
+        password: Option<String>,
     },
-    Verify {
+    /// Show the page count, page sizes, PDF version, encryption, existing
+    /// signatures and producer of a PDF
+    Inspect {
+        /// PDF to inspect, or - for stdin
         #[arg(long)]
         file: PathBuf,
+
+        /// Password of an encrypted PDF
+        #[arg(long)]
+        password: Option<String>,
+
+        /// How to report the result: text or json
+        #[arg(long, value_enum, default_value = "text")]
+        format: OutputFormat,
     },
-}
+    /// Sign several files at once through a manifest of their names, sizes
+    /// and SHA-256 digests, or check such a manifest
+    Manifest {
+        #[command(subcommand)]
+        action: ManifestAction,
+    },
+    /// Inspect the signing key
+    Key {
+        #[command(subcommand)]
+        action: KeyAction,
+    },
+    /// Create, list, rename and delete key profiles, pick the active one and
+    /// set each one's signer name and extra text
+    Profile {
+        #[command(subcommand)]
+        action: ProfileAction,
+    },
+    /// Manage the public keys of other signers whose documents you verify
+    Trust {
+        #[command(subcommand)]
+        action: TrustAction,
+    },
+    /// List everything signed with this tool, from the audit log
+    History {
+        /// Copy the audit log (JSON Lines) to this file instead
+        #[arg(long)]
+        export: Option<PathBuf>,
 
-fn get_app_data_dir() -> Result<PathBuf, String> {
-    let base_dir = if cfg!(target_os = "windows") {
-        env::var("APPDATA").map(PathBuf::from).map_err(|_| "APPDATA not set")?
-    } else if cfg!(target_os = "macos") {
-        let home = env::var("HOME").map(PathBuf::from).map_err(|_| "HOME not set")?;
-        home.join("Library/Application Support")
-    } else {
-        let home = env::var("HOME").map(PathBuf::from).map_err(|_| "HOME not set")?;
-        home.join(".local/share")
-    };
-    
-    let app_dir = base_dir.join("com.sigillum.app");
-    if !app_dir.exists() {
-        fs::create_dir_all(&app_dir).map_err(|e| format!("Failed to create app dir: {}", e))?;
-    }
-    Ok(app_dir)
-}
+        /// How to list the entries: text or json
+        #[arg(long, value_enum, default_value = "text")]
+        format: OutputFormat,
+    },
+    /// Save every key profile, trusted key and setting to one
+    /// passphrase-encrypted file, to move them to another machine
+    Backup {
+        /// Where the backup goes (.sigillum-backup)
+        #[arg(long)]
+        output: PathBuf,
+
+        /// Passphrase to encrypt the backup with (prompted for if omitted)
+        #[arg(long)]
+        passphrase: Option<String>,
+    },
+    /// Restore a backup; profiles and trusted keys that already exist are kept
+    Restore {
+        /// The .sigillum-backup file
+        #[arg(long)]
+        input: PathBuf,
 
-fn get_key_path() -> Result<PathBuf, String> {
-    Ok(get_app_data_dir()?.join("keypair.json"))
+        /// Passphrase the backup was encrypted with (prompted for if omitted)
+        #[arg(long)]
+        passphrase: Option<String>,
+    },
 }
 
-fn run_keygen() -> Result<String, String> {
-    use rsa::{pkcs8::{EncodePrivateKey, EncodePublicKey, LineEnding}, RsaPrivateKey, RsaPublicKey};
-    use rand::rngs::OsRng;
-    
-    let mut rng = OsRng;
-    let private_key = RsaPrivateKey::new(&mut rng, KEY_SIZE).map_err(|e| format!("Failed to generate key: {}", e))?;
-    let public_key = RsaPublicKey::from(&private_key);
-
-    let private_key_pem = private_key
-        .to_pkcs8_pem(LineEnding::LF)
-        .map_err(|e| format!("Failed to encode private key: {}", e))?
-        .to_string();
-    let public_key_pem = public_key
-        .to_public_key_pem(LineEnding::LF)
-        .map_err(|e| format!("Failed to encode public key: {}", e))?;
-
-    let keypair = serde_json::json!({
-        "public_key": public_key_pem.clone(),
-        "private_key": private_key_pem,
-    });
+// How documents are signed, shared by `sign` and `watch`.
+#[derive(Args)]
+struct SignArgs {
+    /// Signer name for the watermark (defaults to the profile's)
+    #[arg(long)]
+    name: Option<String>,
 
-    let key_json = serde_json::to_string_pretty(&keypair).map_err(|e| format!("JSON error: {}", e))?;
-    let key_path = get_key_path().map_err(|e| format!("Key path error: {}", e))?;
-    fs::write(&key_path, key_json).map_err(|e| format!("Write error: {}", e))?;
+    /// Extra watermark text (defaults to the profile's)
+    #[arg(long)]
+    extra: Option<String>,
 
-    println!("Keypair generated and saved successfully!");
-    Ok(public_key_pem)
-}
+    /// Refuse to sign unless the document text matches this regex (repeatable)
+    #[arg(long = "require-text")]
+    require_text: Vec<String>,
 
-fn run_export() -> Result<String, String> {
-    let key_path = get_key_path().map_err(|e| format!("Key path error: {}", e))?;
-    
-    if !key_path.exists() {
-        return Err("No keypair found. Please run --keygen first.".to_string());
-    }
-    
-    let key_json = fs::read_to_string(&key_path).map_err(|e| format!("Read error: {}", e))?;
-    let keypair: serde_json::Value = serde_json::from_str(&key_json).map_err(|e| format!("JSON error: {}", e))?;
-    
-    let private_key = keypair["private_key"].as_str().ok_or("Invalid key file")?;
-    println!("{}", private_key);
-    Ok(private_key.to_string())
+    /// Refuse to sign if the document text matches this regex (repeatable)
+    #[arg(long = "forbid-text")]
+    forbid_text: Vec<String>,
+
+    /// Wrap watermark lines wider than this many points
+    #[arg(long)]
+    max_line_width: Option<f32>,
+
+    /// Pages that get the watermark: all, first, last or a list like 1,3-5 or first,last
+    #[arg(long, default_value = "all")]
+    pages: PageSelection,
+
+    /// Where the watermark goes: top-left, top-right, bottom-left, bottom-right or center
+    #[arg(long, default_value = "top-left")]
+    position: Position,
+
+    /// Put the watermark, and the field of a standard PDF signature, in this
+    /// rectangle instead, given as PAGE:X,Y,WIDTH,HEIGHT in points from the
+    /// top-left corner of the page as displayed
+    #[arg(long, conflicts_with_all = ["pages", "position"])]
+    placement: Option<SignaturePlacement>,
+
+    /// Watermark font size in points
+    #[arg(long, default_value_t = 8.0)]
+    font_size: f32,
+
+    /// Turn the watermark this many degrees counter-clockwise
+    #[arg(long, default_value_t = 0.0, allow_negative_numbers = true)]
+    rotation: f32,
+
+    /// Watermark text color as #rrggbb
+    #[arg(long, default_value = "#000000")]
+    color: Color,
+
+    /// Watermark opacity, from 0 (invisible) to 1 (opaque)
+    #[arg(long, default_value_t = 1.0)]
+    opacity: f32,
+
+    /// PNG image, such as a seal or a handwritten signature, to draw beside the watermark
+    #[arg(long)]
+    stamp_image: Option<PathBuf>,
+
+    /// Width of the stamp image in points
+    #[arg(long, default_value_t = DEFAULT_STAMP_WIDTH, requires = "stamp_image")]
+    stamp_width: f32,
+
+    /// Side of the watermark text the stamp goes on: left, right, above or below
+    #[arg(long, default_value = "left", requires = "stamp_image")]
+    stamp_placement: StampPlacement,
+
+    /// Draw a QR code of the signature beside the watermark, for checking printed copies
+    #[arg(long)]
+    qr_code: bool,
+
+    /// Also add a standard PDF signature that PDF viewers can validate
+    #[arg(long)]
+    pades: bool,
+
+    /// Passphrase of a protected key, or the token PIN (prompted for if omitted)
+    #[arg(long)]
+    passphrase: Option<String>,
+
+    /// Key profile to use instead of the active one
+    #[arg(long, conflicts_with = "pkcs11_module")]
+    profile: Option<String>,
+
+    #[command(flatten)]
+    token: TokenArgs,
+
+    /// RFC 3161 timestamp server to vouch for the signing time
+    /// (defaults to the one set in the app)
+    #[arg(long)]
+    tsa_url: Option<String>,
+
+    /// HTTPS URL where the signing key is published, for verifiers to fetch
+    /// it from (defaults to the profile's)
+    #[arg(long)]
+    key_url: Option<String>,
+
+    /// Email address or domain whose DNS lists the signing key (defaults to
+    /// the profile's)
+    #[arg(long)]
+    key_domain: Option<String>,
+
+    /// NTP server to check the local clock against, e.g. pool.ntp.org; repeat
+    /// for fallbacks (defaults to the ones set in the app)
+    #[arg(long = "ntp-server")]
+    ntp_servers: Vec<String>,
+
+    /// Seconds the local clock may be off from the NTP time before a warning
+    #[arg(long)]
+    max_clock_skew: Option<u64>,
+
+    /// strftime format of the signing time in the watermark, e.g.
+    /// "%d %B %Y %H:%M %Z" (defaults to the one set in the app)
+    #[arg(long)]
+    timestamp_format: Option<String>,
+
+    /// Time zone of the signing time in the watermark: utc, local or a name
+    /// such as Europe/Paris (defaults to the one set in the app)
+    #[arg(long)]
+    timezone: Option<StampTimeZone>,
+
+    /// Digest to sign: sha256, sha384, sha512 or blake3
+    #[arg(long = "hash", default_value = "sha256")]
+    hash_algorithm: HashAlgorithm,
+
+    /// What to do with a PDF that is already signed: error, append (co-sign)
+    /// or replace (drop the existing signatures)
+    #[arg(long, default_value = "error")]
+    if_signed: ResignPolicy,
+
+    /// Password of an encrypted PDF; the signed copy keeps it
+    #[arg(long)]
+    password: Option<String>,
+
+    /// Encrypt the signed PDF with this password instead
+    #[arg(long)]
+    output_password: Option<String>,
+
+    /// Fail instead of signing a PDF/A document in a way that breaks its
+    /// conformance
+    #[arg(long)]
+    preserve_pdfa: bool,
+
+    /// Flatten the form fields and lock the document, so PDF viewers flag any
+    /// later change (adds a standard PDF signature, as --pades does)
+    #[arg(long = "lock")]
+    lock_after_signing: bool,
 }
 
-fn compute_signature_hash(pdf_data: &[u8], name: &str, timestamp: &str, extra: &str) -> String {
-    use sha2::Digest;
-    let mut hasher = sha2::Sha256::new();
-    hasher.update(pdf_data);
-    hasher.update(name.as_bytes());
-    hasher.update(timestamp.as_bytes());
-    hasher.update(extra.as_bytes());
-    let hash = hasher.finalize();
-    format!("SHA256: {}", hex::encode(hash))
+// A key on a PKCS#11 token, shared by `import`, `sign` and `watch`.
+#[derive(Args)]
+struct TokenArgs {
+    /// PKCS#11 module of a token holding the RSA key, e.g. /usr/lib/softhsm/libsofthsm2.so
+    #[arg(long)]
+    pkcs11_module: Option<PathBuf>,
+
+    /// Slot of the token
+    #[arg(long, default_value_t = 0, requires = "pkcs11_module")]
+    pkcs11_slot: u64,
+
+    /// Label of the key on the token (defaults to the first private key)
+    #[arg(long, requires = "pkcs11_module")]
+    pkcs11_key_label: Option<String>,
 }
 
-fn create_watermark_text(name: &str, timestamp: &str, extra: &str, signature: &str) -> String {
-    if extra.is_empty() {
-        format!("Digitally signed by {}\n{}\nHash:{}", name, timestamp, signature)
-    } else {
-        format!("Digitally signed by {}\n{}\n{}\nHash:{}", name, timestamp, extra, signature)
+impl TokenArgs {
+    fn config(self) -> Option<Pkcs11Config> {
+        self.pkcs11_module.map(|module| Pkcs11Config {
+            module,
+            slot: self.pkcs11_slot,
+            key_label: self.pkcs11_key_label,
+        })
     }
 }
 
-fn run_sign(name: String, extra: String, input: PathBuf, output: PathBuf) -> Result<(), String> {
-    use rsa::pkcs8::DecodePrivateKey;
-    use chrono::Utc;
-    
-    let key_path = get_key_path().map_err(|e| format!("Key path error: {}", e))?;
-    
-    if !key_path.exists() {
-        return Err("No keypair found. Please run --keygen first.".to_string());
-    }
-    
-    let key_json = fs::read_to_string(&key_path).map_err(|e| format!("Read error: {}", e))?;
-    let keypair: serde_json::Value = serde_json::from_str(&key_json).map_err(|e| format!("JSON error: {}", e))?;
-    
-    let private_key_pem = keypair["private_key"].as_str().ok_or("Invalid key file")?;
-    let _private_key = rsa::RsaPrivateKey::from_pkcs8_pem(private_key_pem)
-        .map_err(|e| format!("Failed to parse private key: {}", e))?;
-    
-    let pdf_data = fs::read(&input).map_err(|e| format!("Failed to read PDF: {}", e))?;
-    
-    let timestamp = Utc::now().format("%Y-%m-%d %H:%M:%S UTC").to_string();
-    let signature_display = compute_signature_hash(&pdf_data, &name, &timestamp, &extra);
-    let watermark_text = create_watermark_text(&name, &timestamp, &extra, &signature_display);
-    
-    let mut doc = lopdf::Document::load_mem(&pdf_data)
-        .map_err(|e| format!("Failed to load PDF: {}", e))?;
-    
-    pdf_utils::add_watermark_to_pdf(&mut doc, &watermark_text)?;
-    
-    doc.save(&output).map_err(|e| format!("Failed to save PDF: {}", e))?;
-    
-    println!("PDF signed successfully!");
-    println!("Output: {}", output.display());
-    println!("Signer: {}", name);
-    println!("Timestamp: {}", timestamp);
-    if !extra.is_empty() {
-        println!("Extra: {}", extra);
-    }
-    println!("Signature: {}", signature_display);
-    
-    Ok(())
+/// Where the key to sign with comes from.
+enum KeySource {
+    /// A key profile, or the active one if `None`.
+    Profile(Option<String>),
+    /// A token named on the command line.
+    Token(Pkcs11Config),
 }
 
-fn run_verify(file: PathBuf) -> Result<(), String> {
-    let pdf_data = fs::read(&file).map_err(|e| format!("Failed to read PDF: {}", e))?;
-    
-    if let Some((signer_name, timestamp, extra, signature)) = pdf_utils::extract_signature_info(&pdf_data) {
-        println!("✓ PDF has a digital signature");
-        println!("");
-        println!("Signer: {}", signer_name);
-        println!("Timestamp: {}", timestamp);
-        println!("Extra: {}", extra);
-        println!("Signature: {}", signature);
-        Ok(())
-    } else {
-        println!("✗ PDF does not contain a digital signature");
-        exit(1);
+impl SignArgs {
+    /// The signing options, and where the key to sign with comes from.
+    fn into_options(self) -> Result<(api::SignOptions, KeySource), SigillumError> {
+        let stamp = read_stamp(self.stamp_image, self.stamp_width, self.stamp_placement)?;
+        let mut opts = api::SignOptions::default();
+        opts.name = self.name.unwrap_or_default();
+        opts.extra = self.extra.unwrap_or_default();
+        opts.require_text = self.require_text;
+        opts.forbid_text = self.forbid_text;
+        opts.max_line_width = self.max_line_width;
+        opts.placement = self.placement;
+        opts.appearance = Appearance {
+            pages: self.pages,
+            position: self.position,
+            font_size: self.font_size,
+            rotation: self.rotation,
+            color: self.color,
+            opacity: self.opacity,
+            stamp,
+            qr_code: self.qr_code,
+        };
+        opts.pades = self.pades;
+        opts.passphrase = self.passphrase.map(Zeroizing::new);
+        opts.key_url = self.key_url;
+        opts.key_domain = self.key_domain;
+        opts.tsa_url = self.tsa_url;
+        opts.ntp_servers = self.ntp_servers;
+        opts.max_clock_skew = self.max_clock_skew;
+        opts.timestamp_format = self.timestamp_format;
+        opts.time_zone = self.timezone;
+        opts.hash_algorithm = self.hash_algorithm;
+        opts.resign_policy = self.if_signed;
+        opts.password = self.password.map(Zeroizing::new);
+        opts.output_password = self.output_password.map(Zeroizing::new);
+        opts.preserve_pdfa = self.preserve_pdfa;
+        opts.lock_after_signing = self.lock_after_signing;
+        let source = match self.token.config() {
+            Some(config) => KeySource::Token(config),
+            None => KeySource::Profile(self.profile),
+        };
+        Ok((opts, source))
     }
 }
 
-fn main() {
-    let cli = Cli::parse();
-    
-    let result = match cli.command {
-        Some(Commands::Keygen) => run_keygen(),
-        Some(Commands::Export) => run_export(),
-        Some(Commands::Sign { name, extra, input, output }) => {
-            run_sign(name, extra, input, output).map(|_| "".to_string())
+#[derive(Subcommand)]
+enum KeyAction {
+    /// Show the key's type, size, creation date and fingerprint
+    Info {
+        /// Key profile to use instead of the active one
+        #[arg(long)]
+        profile: Option<String>,
+
+        /// How to report the key: text or json
+        #[arg(long, value_enum, default_value = "text")]
+        format: OutputFormat,
+    },
+    /// Print the public key as an OpenPGP key, for recipients to gpg --import
+    /// before checking .asc signatures
+    ExportPgp {
+        /// Who the key belongs to, e.g. "Jane Doe <jane@example.com>"
+        #[arg(long)]
+        user_id: String,
+
+        /// Passphrase of a protected key (prompted for if omitted)
+        #[arg(long)]
+        passphrase: Option<String>,
+
+        /// Key profile to use instead of the active one
+        #[arg(long)]
+        profile: Option<String>,
+    },
+    /// Save the public key for the people who verify your documents
+    ExportPublic {
+        /// Where the key is written (printed as PEM if omitted)
+        #[arg(long)]
+        out: Option<PathBuf>,
+
+        /// pem or der (defaults to der for a .der file, pem otherwise)
+        #[arg(long)]
+        format: Option<PublicKeyFormat>,
+
+        /// Also save a QR code of the key as a PNG, to show on screen
+        #[arg(long)]
+        qr: Option<PathBuf>,
+
+        /// Put only the key's fingerprint in the QR code, for checking a
+        /// key received some other way
+        #[arg(long, requires = "qr")]
+        qr_fingerprint: bool,
+
+        /// With --qr-fingerprint, also put the URL the key can be fetched
+        /// from in the QR code, so a phone camera opens it
+        #[arg(long, requires = "qr_fingerprint")]
+        qr_url: Option<String>,
+
+        /// Key profile to use instead of the active one
+        #[arg(long)]
+        profile: Option<String>,
+
+        /// Export this version of the profile's key instead of the current one
+        #[arg(long)]
+        version: Option<u32>,
+    },
+    /// Save a .sigillum-id identity file (public key, name, fingerprint and
+    /// certificate) for colleagues to add to their trusted keys
+    ExportId {
+        /// The name you go by (defaults to the profile's signer name)
+        #[arg(long)]
+        name: Option<String>,
+
+        /// Where the identity is written (defaults to "<name>.sigillum-id")
+        #[arg(long)]
+        out: Option<PathBuf>,
+
+        /// Key profile to use instead of the active one
+        #[arg(long)]
+        profile: Option<String>,
+    },
+    /// Print the DNS TXT record that lists the key for a domain, so
+    /// verifiers can check signatures naming the domain against it
+    DnsRecord {
+        /// Email address or domain to publish the key for (defaults to the
+        /// profile's key domain)
+        #[arg(long)]
+        domain: Option<String>,
+
+        /// Key profile to use instead of the active one
+        #[arg(long)]
+        profile: Option<String>,
+    },
+    /// Generate a new key for the profile and retire the current one, which
+    /// still verifies what it signed and can still be exported
+    Rotate {
+        /// Key type of the new key (defaults to the current key's)
+        #[arg(long)]
+        algorithm: Option<KeyAlgorithm>,
+
+        /// Key size in bits: 2048, 3072 or 4096 for RSA
+        #[arg(long)]
+        bits: Option<u32>,
+
+        /// Make the new key expire this many days from now
+        #[arg(long)]
+        valid_days: Option<u32>,
+
+        /// Key profile to use instead of the active one
+        #[arg(long)]
+        profile: Option<String>,
+    },
+    /// Show or change when the key expires, and whether an expired key may
+    /// still sign
+    Expiry {
+        /// Make the key expire this many days from now
+        #[arg(long, conflicts_with = "never")]
+        days: Option<u32>,
+
+        /// Make the key never expire
+        #[arg(long)]
+        never: bool,
+
+        /// What signing does with an expired key, for every profile: refuse
+        /// or warn
+        #[arg(long)]
+        policy: Option<ExpiredKeyPolicy>,
+
+        /// Key profile to use instead of the active one
+        #[arg(long)]
+        profile: Option<String>,
+    },
+    /// List every version of the profile's key, retired ones included
+    Versions {
+        /// Key profile to use instead of the active one
+        #[arg(long)]
+        profile: Option<String>,
+
+        /// How to list the versions: text or json
+        #[arg(long, value_enum, default_value = "text")]
+        format: OutputFormat,
+    },
+}
+
+#[derive(Subcommand)]
+enum ManifestAction {
+    /// List the files in a manifest and sign it
+    Sign {
+        /// The files to list; they are listed by name, so no two may share one
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+
+        /// Signer name (defaults to the profile's)
+        #[arg(long)]
+        name: Option<String>,
+
+        /// Extra text to sign along (defaults to the profile's)
+        #[arg(long)]
+        extra: Option<String>,
+
+        /// Where the manifest is written
+        #[arg(long, default_value = "manifest.json")]
+        output: PathBuf,
+
+        /// Where its signature is written (defaults to the manifest name with
+        /// a .sig extension)
+        #[arg(long)]
+        signature: Option<PathBuf>,
+
+        /// Passphrase of a protected key (prompted for if omitted)
+        #[arg(long)]
+        passphrase: Option<String>,
+
+        /// Key profile to use instead of the active one
+        #[arg(long)]
+        profile: Option<String>,
+
+        /// RFC 3161 timestamp server to vouch for the signing time
+        /// (defaults to the one set in the app)
+        #[arg(long)]
+        tsa_url: Option<String>,
+
+        /// Digest to sign: sha256, sha384, sha512 or blake3
+        #[arg(long = "hash", default_value = "sha256")]
+        hash_algorithm: HashAlgorithm,
+    },
+    /// Check a manifest's signature and that every file it lists is unchanged
+    #[command(after_help = "Exit status:
+  0  the manifest and every file check out
+  1  the manifest could not be read, or a file is missing or changed
+  2  invalid arguments
+  4  the manifest's signature is invalid")]
+    Verify {
+        #[arg(long, default_value = "manifest.json")]
+        manifest: PathBuf,
+
+        /// The manifest's signature (defaults to the manifest name with a
+        /// .sig extension)
+        #[arg(long)]
+        signature: Option<PathBuf>,
+
+        /// Folder holding the files (defaults to the manifest's folder)
+        #[arg(long)]
+        dir: Option<PathBuf>,
+
+        /// Check the signature against this public key (PEM) instead of our own
+        #[arg(long)]
+        pubkey: Option<PathBuf>,
+
+        /// How to report the result: text or json
+        #[arg(long, value_enum, default_value = "text")]
+        format: OutputFormat,
+    },
+}
+
+#[derive(Subcommand)]
+enum ProfileAction {
+    /// List the profiles, marking the active one
+    List,
+    /// Create a profile with a newly generated key
+    Create {
+        name: String,
+
+        /// Key type: rsa-2048, rsa-3072, rsa-4096, ed25519 or p256
+        #[arg(long, default_value = "rsa-2048")]
+        algorithm: KeyAlgorithm,
+    },
+    /// Give a profile a new name
+    Rename {
+        name: String,
+
+        new_name: String,
+    },
+    /// Delete a profile and its key
+    Delete {
+        name: String,
+    },
+    /// Make a profile the one that signs by default
+    Use {
+        name: String,
+    },
+    /// Set the signer name, extra text, key URL and key domain a profile
+    /// signs with when `--name`, `--extra`, `--key-url` and `--key-domain`
+    /// are left out; an empty value clears it
+    Set {
+        /// Profile to change instead of the active one
+        #[arg(long)]
+        profile: Option<String>,
+
+        #[arg(long)]
+        signer_name: Option<String>,
+
+        #[arg(long)]
+        extra: Option<String>,
+
+        /// HTTPS URL where the profile's public key is published
+        #[arg(long)]
+        key_url: Option<String>,
+
+        /// Email address or domain whose DNS lists the profile's public key
+        #[arg(long)]
+        key_domain: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum TrustAction {
+    /// Trust a signer's public key under a name
+    Add {
+        name: String,
+
+        /// PEM file holding the signer's public key
+        #[arg(long)]
+        key: PathBuf,
+    },
+    /// Trust the key in a colleague's .sigillum-id identity file
+    ImportId {
+        file: PathBuf,
+
+        /// Name to trust the key under instead of the one in the file
+        #[arg(long)]
+        name: Option<String>,
+    },
+    /// Stop trusting the named key
+    Remove {
+        name: String,
+    },
+    /// Mark the named key revoked: documents it signed from then on fail
+    /// verification
+    Revoke {
+        name: String,
+
+        /// Why the key is revoked, e.g. "key compromised"
+        #[arg(long)]
+        reason: String,
+
+        /// When the revocation takes effect, e.g. 2024-01-31 (UTC); now by default
+        #[arg(long)]
+        date: Option<String>,
+    },
+    /// List trusted keys
+    List,
+}
+
+fn get_app_data_dir() -> Result<PathBuf, SigillumError> {
+    let base_dir = if cfg!(target_os = "windows") {
+        env::var("APPDATA").map(PathBuf::from).map_err(|_| "APPDATA not set")?
+    } else if cfg!(target_os = "macos") {
+        let home = env::var("HOME").map(PathBuf::from).map_err(|_| "HOME not set")?;
+        home.join("Library/Application Support")
+    } else {
+        let home = env::var("HOME").map(PathBuf::from).map_err(|_| "HOME not set")?;
+        home.join(".local/share")
+    };
+    
+    let app_dir = base_dir.join("com.sigillum.app");
+    if !app_dir.exists() {
+        fs::create_dir_all(&app_dir).map_err(|e| format!("Failed to create app dir: {}", e))?;
+    }
+    Ok(app_dir)
+}
+
+fn get_key_store() -> Result<KeyStore, SigillumError> {
+    KeyStore::open(get_app_data_dir()?)
+}
+
+/// The key store with `profile` selected, or the active profile if `None`.
+fn get_profile_store(profile: Option<&str>) -> Result<KeyStore, SigillumError> {
+    let mut store = get_key_store()?;
+    if let Some(profile) = profile {
+        store.select(profile)?;
+    }
+    Ok(store)
+}
+
+fn get_trust_store() -> Result<TrustStore, SigillumError> {
+    TrustStore::open(get_app_data_dir()?)
+}
+
+fn run_keygen(
+    algorithm: KeyAlgorithm,
+    bits: Option<u32>,
+    tpm: bool,
+    pin: Option<String>,
+    secure_enclave: bool,
+    valid_days: Option<u32>,
+    profile: Option<String>,
+) -> Result<String, SigillumError> {
+    let algorithm = match bits {
+        Some(bits) => algorithm.with_bits(bits)?,
+        None => algorithm,
+    };
+    let mut keypair = if tpm {
+        let pin = token_pin(pin.map(Zeroizing::new))?.unwrap_or_default();
+        KeyPair::generate_in_tpm(algorithm, &pin)?
+    } else if secure_enclave {
+        KeyPair::generate_in_secure_enclave(algorithm)?
+    } else {
+        KeyPair::generate(algorithm)?
+    };
+    keypair.set_validity(valid_days);
+    let mut store = get_profile_store(profile.as_deref())?;
+    store.save(&keypair)?;
+
+    println!(
+        "{} keypair generated {}and saved to profile '{}' successfully!",
+        algorithm,
+        if keypair.pkcs11.is_some() {
+            "in the TPM "
+        } else if keypair.kms.is_some() {
+            "in the Secure Enclave "
+        } else {
+            ""
+        },
+        store.selected_profile()
+    );
+    Ok(keypair.public_key)
+}
+
+fn run_import(
+    key: Option<PathBuf>,
+    token: TokenArgs,
+    device: Option<String>,
+    ssh_agent: Option<String>,
+    kms: Option<String>,
+    passphrase: Option<String>,
+    profile: Option<String>,
+) -> Result<String, SigillumError> {
+    let passphrase = passphrase.map(Zeroizing::new);
+    let keypair = if let Some(key) = kms {
+        KeyPair::from_kms(key.parse()?)?
+    } else if let Some(fingerprint) = ssh_agent {
+        KeyPair::from_ssh_agent(&fingerprint)?
+    } else if let Some(serial) = device {
+        let device = piv::devices()
+            .into_iter()
+            .find(|device| device.serial == serial)
+            .ok_or_else(|| format!("No PIV card with serial number {} that can sign is plugged in", serial))?;
+        let pin = token_pin(passphrase)?;
+        KeyPair::from_piv(&device.token, pin.as_deref().map(String::as_str))?
+    } else {
+        match (token.config(), key) {
+            (Some(config), _) => {
+                let pin = token_pin(passphrase)?;
+                KeyPair::from_pkcs11(config, pin.as_deref().map(String::as_str))?
+            }
+            (None, Some(key)) => {
+                let data = fs::read(&key).map_err(|e| SigillumError::Io(format!("Failed to read key: {}", e)))?;
+                let passphrase = match passphrase {
+                    None if PrivateKey::import_needs_passphrase(&data) => Some(
+                        read_passphrase("Key passphrase: ").map_err(|e| SigillumError::PassphraseRequired(format!("Failed to read passphrase: {}", e)))?,
+                    ),
+                    given => given,
+                };
+                KeyPair::import(&data, passphrase.as_deref().map(String::as_str))?
+            }
+            (None, None) => return Err("Give either --key, --shares, --pkcs11-module, --device, --ssh-agent or --kms".into()),
+        }
+    };
+    let mut store = get_profile_store(profile.as_deref())?;
+    store.save(&keypair)?;
+
+    let source = if keypair.pkcs11.is_some() {
+        "on the token is now used by"
+    } else if keypair.ssh_agent.is_some() {
+        "in ssh-agent is now used by"
+    } else if let Some(key) = &keypair.kms {
+        match key {
+            kms::KmsKey::Keychain { .. } => "in the Keychain is now used by",
+            kms::KmsKey::CertStore { .. } => "of the certificate in your store is now used by",
+            _ => "in the key management service is now used by",
+        }
+    } else {
+        "imported into"
+    };
+    println!(
+        "{} key {} profile '{}' successfully!",
+        keypair.algorithm,
+        source,
+        store.selected_profile()
+    );
+    Ok(keypair.public_key)
+}
+
+fn run_import_shares(paths: Vec<PathBuf>, profile: Option<String>) -> Result<String, SigillumError> {
+    let shares = paths
+        .iter()
+        .map(|path| {
+            let data = fs::read(path).map_err(|e| SigillumError::Io(format!("Failed to read {}: {}", path.display(), e)))?;
+            KeyShare::from_json(&data).map_err(|e| SigillumError::from(format!("{}: {}", path.display(), e)))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let keypair = KeyPair::import(&shamir::combine(&shares)?.to_pkcs8_der()?, None)?;
+    let mut store = get_profile_store(profile.as_deref())?;
+    store.save(&keypair)?;
+    println!(
+        "{} key rebuilt from {} shares into profile '{}' successfully!",
+        keypair.algorithm,
+        shares.len(),
+        store.selected_profile()
+    );
+    Ok(keypair.public_key)
+}
+
+fn run_import_phrase(algorithm: KeyAlgorithm, profile: Option<String>) -> Result<String, SigillumError> {
+    let phrase = rpassword::prompt_password("Recovery phrase: ")
+        .map(Zeroizing::new)
+        .map_err(|e| SigillumError::Io(format!("Failed to read recovery phrase: {}", e)))?;
+    let private_key = mnemonic::from_phrase(&phrase, algorithm)?;
+    let keypair = KeyPair::import(&private_key.to_pkcs8_der()?, None)?;
+    let mut store = get_profile_store(profile.as_deref())?;
+    store.save(&keypair)?;
+    println!(
+        "{} key restored from its recovery phrase into profile '{}' successfully!",
+        keypair.algorithm,
+        store.selected_profile()
+    );
+    Ok(keypair.public_key)
+}
+
+fn run_devices(format: OutputFormat) -> Result<(), SigillumError> {
+    let devices = piv::devices();
+    if format == OutputFormat::Json {
+        print_json(&devices);
+        return Ok(());
+    }
+    if devices.is_empty() {
+        println!("No PIV card with a certificate in its digital signature slot is plugged in");
+    }
+    for device in &devices {
+        println!("{}  {}", device.serial, device.name);
+        println!("  Certificate: {}", device.certificate.subject);
+        println!("  Valid until: {}", device.certificate.not_after);
+        println!("  Module:      {} (slot {})", device.token.module.display(), device.token.slot);
+    }
+    Ok(())
+}
+
+fn run_ssh_agent_keys(format: OutputFormat) -> Result<(), SigillumError> {
+    let keys = ssh_agent::identities()?;
+    if format == OutputFormat::Json {
+        print_json(&keys);
+        return Ok(());
+    }
+    if keys.is_empty() {
+        println!("ssh-agent holds no keys; add one with ssh-add");
+    }
+    for key in &keys {
+        let note = if key.usable { "" } else { "  (cannot sign here; only Ed25519 keys can)" };
+        println!("{}  {}  {}{}", key.fingerprint, key.key_type, key.comment, note);
+    }
+    Ok(())
+}
+
+fn run_cert_store_certificates(format: OutputFormat) -> Result<(), SigillumError> {
+    let certificates = cert_store::certificates()?;
+    if format == OutputFormat::Json {
+        print_json(&certificates);
+        return Ok(());
+    }
+    if certificates.is_empty() {
+        println!("No certificate in your store has a private key");
+    }
+    for certificate in &certificates {
+        println!(
+            "{}  {}  (issued by {}, until {})",
+            certificate.thumbprint, certificate.certificate.subject, certificate.certificate.issuer, certificate.certificate.not_after
+        );
+    }
+    Ok(())
+}
+
+fn run_pkcs11_devices(module: &Path, pin: Option<&str>, format: OutputFormat) -> Result<(), SigillumError> {
+    let mut tokens = Vec::new();
+    for token in pkcs11::tokens(module)? {
+        let keys = pkcs11::keys(module, token.slot, pin)?;
+        tokens.push((token, keys));
+    }
+    if format == OutputFormat::Json {
+        let tokens: Vec<_> = tokens.iter().map(|(token, keys)| json!({ "token": token, "keys": keys })).collect();
+        print_json(&tokens);
+        return Ok(());
+    }
+    if tokens.is_empty() {
+        println!("No token is present in the module's slots");
+    }
+    for (token, keys) in &tokens {
+        println!("Slot {}: {} ({} {}, serial {})", token.slot, token.label, token.manufacturer, token.model, token.serial);
+        if keys.is_empty() {
+            println!("  No private keys{}", if pin.is_none() { "; give --pin to see keys the token hides" } else { "" });
+        }
+        for key in keys {
+            let kind = match key.bits {
+                Some(bits) => format!("{}-{}", key.key_type, bits),
+                None => key.key_type.clone(),
+            };
+            println!("  {}  {}  (id {})", key.label, kind, key.id);
+        }
+    }
+    Ok(())
+}
+
+fn load_keypair(profile: Option<&str>) -> Result<KeyPair, SigillumError> {
+    let store = get_profile_store(profile)?;
+    
+    if !store.exists() {
+        return Err(SigillumError::NoKey("No keypair found. Please run --keygen first.".to_string()));
+    }
+    
+    store.load()
+}
+
+/// Returns the passphrase to unlock `keypair` with, prompting on the terminal
+/// when the key is protected and none was given on the command line.
+fn key_passphrase(keypair: &KeyPair, given: Option<Zeroizing<String>>) -> Result<Option<Zeroizing<String>>, SigillumError> {
+    if keypair.pkcs11.is_some() {
+        return token_pin(given);
+    }
+    if !keypair.is_encrypted() || given.is_some() {
+        return Ok(given);
+    }
+    read_passphrase("Key passphrase: ")
+        .map(Some)
+        .map_err(|e| SigillumError::PassphraseRequired(format!("Failed to read passphrase: {}", e)))
+}
+
+/// The key passphrase from `--passphrase-file`, or else prompted for with
+/// `prompt`.
+fn read_passphrase(prompt: &str) -> io::Result<Zeroizing<String>> {
+    match PASSPHRASE_FILE.get() {
+        Some(passphrase) => Ok(passphrase.clone()),
+        None => rpassword::prompt_password(prompt).map(Zeroizing::new),
+    }
+}
+
+/// Returns the PIN to log in to a token with, prompting for it when none was
+/// given on the command line.
+fn token_pin(given: Option<Zeroizing<String>>) -> Result<Option<Zeroizing<String>>, SigillumError> {
+    if given.is_some() {
+        return Ok(given);
+    }
+    rpassword::prompt_password("Token PIN: ")
+        .map(|pin| Some(Zeroizing::new(pin)))
+        .map_err(|e| SigillumError::PassphraseRequired(format!("Failed to read PIN: {}", e)))
+}
+
+/// Loads the key to sign with and fills in the passphrase or token PIN that
+/// unlocks it.
+fn signing_keypair(source: KeySource, opts: &mut api::SignOptions) -> Result<KeyPair, SigillumError> {
+    let keypair = match source {
+        KeySource::Profile(profile) => load_keypair(profile.as_deref())?,
+        KeySource::Token(config) => {
+            opts.passphrase = token_pin(opts.passphrase.take())?;
+            KeyPair::from_pkcs11(config, opts.passphrase.as_deref().map(String::as_str))?
+        }
+    };
+    keypair.apply_defaults(opts)?;
+    check_key_expiry(&keypair)?;
+    opts.passphrase = key_passphrase(&keypair, opts.passphrase.take())?;
+    if keypair.pkcs11.is_some() {
+        opts.monitor = Monitor::new(
+            |progress| {
+                if progress == Progress::AwaitingToken {
+                    eprintln!("Signing on the token; touch it if it is flashing...");
+                }
+            },
+            CancellationToken::new(),
+        );
+    }
+    Ok(keypair)
+}
+
+/// Version `version` of the profile's key, or its current key if `None`.
+fn load_key_version(profile: Option<&str>, version: Option<u32>) -> Result<KeyPair, SigillumError> {
+    let keypair = load_keypair(profile)?;
+    match version {
+        Some(version) => Ok(keypair.key_version(version)?.clone()),
+        None => Ok(keypair),
+    }
+}
+
+fn run_export(passphrase: Option<String>, profile: Option<String>, version: Option<u32>) -> Result<(), SigillumError> {
+    let keypair = load_key_version(profile.as_deref(), version)?;
+    let passphrase = key_passphrase(&keypair, passphrase.map(Zeroizing::new))?;
+    println!("{}", *keypair.export_private_key(passphrase.as_deref().map(String::as_str))?);
+    Ok(())
+}
+
+fn run_export_phrase(passphrase: Option<String>, profile: Option<String>, version: Option<u32>) -> Result<(), SigillumError> {
+    let keypair = load_key_version(profile.as_deref(), version)?;
+    let passphrase = key_passphrase(&keypair, passphrase.map(Zeroizing::new))?;
+    println!("{}", *mnemonic::to_phrase(&keypair.private_key(passphrase.as_deref().map(String::as_str))?)?);
+    eprintln!(
+        "Anyone with these words has your key. Write them down, keep them somewhere safe, and restore with `import --phrase --algorithm {}`.",
+        keypair.algorithm
+    );
+    Ok(())
+}
+
+fn run_export_shares(
+    scheme: ShamirScheme,
+    out_dir: &Path,
+    passphrase: Option<String>,
+    profile: Option<String>,
+    version: Option<u32>,
+) -> Result<(), SigillumError> {
+    let keypair = load_key_version(profile.as_deref(), version)?;
+    let passphrase = key_passphrase(&keypair, passphrase.map(Zeroizing::new))?;
+    let shares = shamir::split(&keypair.private_key(passphrase.as_deref().map(String::as_str))?, scheme)?;
+    fs::create_dir_all(out_dir).map_err(|e| SigillumError::Io(format!("Failed to create {}: {}", out_dir.display(), e)))?;
+    let prefix = get_profile_store(profile.as_deref())?.selected_profile().to_string();
+    for share in &shares {
+        let path = out_dir.join(format!("{}-{}", prefix, share.file_name()));
+        fs::write(&path, share.to_json()?).map_err(|e| SigillumError::Io(format!("Failed to save {}: {}", path.display(), e)))?;
+        println!("{}", path.display());
+    }
+    println!(
+        "Key split into {} shares; any {} of them rebuild it with `import --shares`. Keep each share in a different place.",
+        scheme.shares, scheme.threshold
+    );
+    Ok(())
+}
+
+fn run_export_pkcs12(
+    path: PathBuf,
+    passphrase: Option<String>,
+    password: Option<String>,
+    profile: Option<String>,
+    version: Option<u32>,
+) -> Result<(), SigillumError> {
+    let keypair = load_key_version(profile.as_deref(), version)?;
+    let passphrase = key_passphrase(&keypair, passphrase.map(Zeroizing::new))?;
+    let password = match password {
+        Some(password) => Zeroizing::new(password),
+        None => {
+            let read_error = |e: std::io::Error| SigillumError::Io(format!("Failed to read password: {}", e));
+            let first = Zeroizing::new(rpassword::prompt_password("PKCS#12 password: ").map_err(read_error)?);
+            let second = Zeroizing::new(rpassword::prompt_password("Repeat PKCS#12 password: ").map_err(read_error)?);
+            if first != second {
+                return Err("Passwords do not match".into());
+            }
+            first
+        }
+    };
+    let data = keypair.export_pkcs12(passphrase.as_deref().map(String::as_str), &password)?;
+    fs::write(&path, data).map_err(|e| SigillumError::Io(format!("Failed to save key: {}", e)))?;
+    println!("Key and certificate saved to {}", path.display());
+    Ok(())
+}
+
+fn run_passphrase(remove: bool) -> Result<(), SigillumError> {
+    let mut keypair = load_keypair(None)?;
+    if keypair.pkcs11.is_some() {
+        return Err("The key is on a PKCS#11 token; change its PIN with the token's own tools".into());
+    }
+    let current = if keypair.is_encrypted() {
+        Some(read_passphrase("Current passphrase: ").map_err(|e| SigillumError::Io(format!("Failed to read passphrase: {}", e)))?)
+    } else {
+        None
+    };
+    
+    let new_passphrase = if remove {
+        None
+    } else {
+        let first = Zeroizing::new(rpassword::prompt_password("New passphrase: ").map_err(|e| SigillumError::Io(format!("Failed to read passphrase: {}", e)))?);
+        let second = Zeroizing::new(rpassword::prompt_password("Repeat new passphrase: ").map_err(|e| SigillumError::Io(format!("Failed to read passphrase: {}", e)))?);
+        if first != second {
+            return Err("Passphrases do not match".into());
+        }
+        if first.is_empty() {
+            return Err("Passphrase must not be empty; use --remove to drop protection".into());
+        }
+        Some(first)
+    };
+    
+    keypair.set_passphrase(current.as_deref().map(String::as_str), new_passphrase.as_deref().map(String::as_str))?;
+    get_key_store()?.save(&keypair)?;
+    
+    if remove {
+        println!("Passphrase removed; the private key is stored unprotected.");
+    } else {
+        println!("Passphrase set.");
+    }
+    Ok(())
+}
+
+/// Fills in what was not given on the command line from the app's settings:
+/// the timestamp and NTP servers and how the signing time is shown.
+/// Refuses an expired key, or only warns about it, as the settings say; a key
+/// about to expire gets a warning too.
+fn check_key_expiry(keypair: &KeyPair) -> Result<(), SigillumError> {
+    let settings = Settings::load(&get_app_data_dir()?)?;
+    if let Some(warning) = keypair.check_expiry(settings.expired_key_policy)? {
+        eprintln!("Warning: {}", warning);
+    }
+    Ok(())
+}
+
+fn apply_settings(opts: &mut api::SignOptions) -> Result<(), SigillumError> {
+    let settings = Settings::load(&get_app_data_dir()?)?;
+    if opts.tsa_url.is_none() {
+        opts.tsa_url = settings.tsa_url;
+    }
+    if opts.ntp_servers.is_empty() {
+        opts.ntp_servers = settings.ntp_servers;
+    }
+    if opts.max_clock_skew.is_none() {
+        opts.max_clock_skew = settings.max_clock_skew;
+    }
+    if opts.timestamp_format.is_none() {
+        opts.timestamp_format = settings.timestamp_format;
+    }
+    if opts.time_zone.is_none() {
+        opts.time_zone = settings.time_zone;
+    }
+    Ok(())
+}
+
+fn run_sign(
+    mut opts: api::SignOptions,
+    input: PathBuf,
+    output: PathBuf,
+    pgp: bool,
+    gpg_key: Option<&str>,
+    source: KeySource,
+    format: OutputFormat,
+) -> Result<(), SigillumError> {
+    if pgp && is_std_stream(&output) {
+        return Err("--pgp needs --output to be a file".into());
+    }
+    let keypair = signing_keypair(source, &mut opts)?;
+    apply_settings(&mut opts)?;
+    let (passphrase, hash_algorithm) = (opts.passphrase.clone(), opts.hash_algorithm);
+    let password = opts.output_password.clone().or_else(|| opts.password.clone());
+    if pgp || gpg_key.is_some() {
+        openpgp::check_hash(hash_algorithm)?;
+    }
+    let attach_gpg = |pdf: Vec<u8>| match gpg_key {
+        Some(key_id) => api::attach_gpg_signature(pdf, key_id, hash_algorithm, password.as_deref().map(String::as_str)),
+        None => Ok(pdf),
+    };
+    
+    // With the PDF going to stdout, the report goes to stderr.
+    let to_stdout = is_std_stream(&output);
+    let (info, clock_warning) = if is_std_stream(&input) || to_stdout {
+        let pdf_data = read_pdf(&input)?;
+        let signed = api::sign(&pdf_data, opts, &keypair)?;
+        let signed_pdf = attach_gpg(signed.pdf)?;
+        let file_name = if is_std_stream(&input) { "(stdin)".into() } else { input.to_string_lossy() };
+        let entry = AuditEntry::new(&file_name, &pdf_data, &signed_pdf, &signed.signature_info);
+        if to_stdout {
+            let mut stdout = io::stdout().lock();
+            stdout
+                .write_all(&signed_pdf)
+                .and_then(|_| stdout.flush())
+                .map_err(|e| SigillumError::Io(format!("Failed to save PDF: {}", e)))?;
+        } else {
+            fs::write(&output, &signed_pdf).map_err(|e| SigillumError::Io(format!("Failed to save PDF: {}", e)))?;
+        }
+        record_signing(entry);
+        (signed.signature_info, signed.clock_warning)
+    } else {
+        // File to file, the document is streamed rather than held in memory
+        // more than once.
+        let signed = api::sign_to_file(&input, &output, opts, &keypair)?;
+        if gpg_key.is_some() {
+            let signed_pdf = attach_gpg(read_pdf(&output)?)?;
+            fs::write(&output, signed_pdf).map_err(|e| SigillumError::Io(format!("Failed to save PDF: {}", e)))?;
+        }
+        record_signing(AuditEntry::from_files(&input, &output, &signed.signature_info));
+        (signed.signature_info, signed.clock_warning)
+    };
+    let pgp_path = if pgp {
+        let signed_pdf = fs::File::open(&output).map_err(|e| SigillumError::Io(format!("Failed to read PDF: {}", e)))?;
+        let signature = openpgp::sign_detached(BufReader::new(signed_pdf), &keypair, passphrase.as_deref().map(String::as_str), hash_algorithm)?;
+        let path = openpgp::signature_path_for(&output);
+        fs::write(&path, signature).map_err(|e| SigillumError::Io(format!("Failed to save OpenPGP signature: {}", e)))?;
+        Some(path)
+    } else {
+        None
+    };
+    if let Some(warning) = &clock_warning {
+        eprintln!("Warning: {}", warning);
+    }
+    let say = |line: String| {
+        if to_stdout {
+            eprintln!("{}", line)
+        } else {
+            println!("{}", line)
+        }
+    };
+    
+    if format == OutputFormat::Json {
+        say(to_json(&api::BatchResult {
+            input,
+            output,
+            signature_info: Some(info),
+            error: None,
+        }));
+        return Ok(());
+    }
+    say("PDF signed successfully!".to_string());
+    if !to_stdout {
+        say(format!("Output: {}", output.display()));
+    }
+    if let Some(key_id) = gpg_key {
+        say(format!("OpenPGP signature by {} attached", key_id));
+    }
+    if let Some(path) = &pgp_path {
+        say(format!("OpenPGP signature: {}", path.display()));
+    }
+    say(format!("Signer: {}", info.signer_name));
+    say(format!("Timestamp: {}", info.timestamp));
+    if let Some(check) = &info.clock_check {
+        say(format!("Network time: {} from {} (clock off by {} ms)", check.network_time, check.server, check.skew_ms));
+    }
+    if !info.extra.is_empty() {
+        say(format!("Extra: {}", info.extra));
+    }
+    if let Some(url) = &info.key_url {
+        say(format!("Key URL: {}", url));
+    }
+    if let Some(identity) = &info.key_domain {
+        say(format!("Key domain: {}", identity));
+    }
+    say(format!("Signature: {}", info.signature));
+    
+    Ok(())
+}
+
+/// `-` stands for stdin or stdout, for use in pipelines.
+fn is_std_stream(path: &Path) -> bool {
+    path == Path::new("-")
+}
+
+fn read_pdf(path: &Path) -> Result<Vec<u8>, SigillumError> {
+    if is_std_stream(path) {
+        let mut data = Vec::new();
+        io::stdin().lock().read_to_end(&mut data).map_err(|e| SigillumError::Io(format!("Failed to read PDF: {}", e)))?;
+        Ok(data)
+    } else {
+        fs::read(path).map_err(|e| SigillumError::Io(format!("Failed to read PDF: {}", e)))
+    }
+}
+
+fn read_stamp(path: Option<PathBuf>, width: f32, placement: StampPlacement) -> Result<Option<Stamp>, SigillumError> {
+    let Some(path) = path else {
+        return Ok(None);
+    };
+    let png = fs::read(&path).map_err(|e| SigillumError::Io(format!("Failed to read stamp image: {}", e)))?;
+    Ok(Some(Stamp { png, width, placement }))
+}
+
+fn run_sign_batch(
+    mut opts: api::SignOptions,
+    input_dir: PathBuf,
+    output_dir: PathBuf,
+    source: KeySource,
+    format: OutputFormat,
+) -> Result<(), SigillumError> {
+    let keypair = signing_keypair(source, &mut opts)?;
+    apply_settings(&mut opts)?;
+
+    let entries = fs::read_dir(&input_dir).map_err(|e| SigillumError::Io(format!("Failed to read directory: {}", e)))?;
+    // Signed copies keep their names, so they would overwrite the originals.
+    if fs::canonicalize(&output_dir).ok() == fs::canonicalize(&input_dir).ok() {
+        return Err("--output-dir must be a different directory from --input-dir".into());
+    }
+    let mut inputs = Vec::new();
+    for entry in entries {
+        let path = entry.map_err(|e| SigillumError::Io(format!("Failed to read directory: {}", e)))?.path();
+        let is_pdf = path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("pdf"));
+        if path.is_file() && is_pdf {
+            inputs.push(path);
+        }
+    }
+    if inputs.is_empty() {
+        return Err(format!("No PDF files found in {}", input_dir.display()).into());
+    }
+    inputs.sort();
+
+    let jobs: Vec<api::BatchJob> = inputs
+        .into_iter()
+        .map(|input| api::BatchJob {
+            output: output_dir.join(input.file_name().unwrap_or_default()),
+            input,
+        })
+        .collect();
+    let total = jobs.len();
+
+    let results = api::sign_batch(&jobs, &opts, &keypair, |completed, result| {
+        if let Some(info) = &result.signature_info {
+            record_signing(AuditEntry::from_files(&result.input, &result.output, info));
+        }
+        if format == OutputFormat::Json {
+            return;
+        }
+        let file = result.input.file_name().unwrap_or_default().to_string_lossy();
+        match &result.error {
+            None => println!("[{}/{}] ✓ {}", completed, total, file),
+            Some(e) => println!("[{}/{}] ✗ {}: {}", completed, total, file, e),
+        }
+    })?;
+
+    let failed = results.iter().filter(|r| r.error.is_some()).count();
+    if format == OutputFormat::Json {
+        print_json(&results);
+        if failed > 0 {
+            exit(EXIT_ERROR);
+        }
+        return Ok(());
+    }
+    println!();
+    println!("Signed {} of {} PDFs into {}", total - failed, total, output_dir.display());
+    if failed > 0 {
+        return Err(format!("{} PDF(s) could not be signed", failed).into());
+    }
+    Ok(())
+}
+
+fn run_watch(mut opts: api::SignOptions, input_dir: PathBuf, output_dir: PathBuf, source: KeySource) -> Result<(), SigillumError> {
+    let keypair = signing_keypair(source, &mut opts)?;
+    apply_settings(&mut opts)?;
+
+    println!(
+        "Watching {} and signing into {}; press Ctrl+C to stop",
+        input_dir.display(),
+        output_dir.display()
+    );
+    // Nothing cancels the watch here; it runs until the process is stopped.
+    let token = CancellationToken::new();
+    watch::watch(&input_dir, &output_dir, &opts, &keypair, &token, |result| {
+        let file = result.input.file_name().unwrap_or_default().to_string_lossy();
+        match (&result.signature_info, &result.error) {
+            (Some(info), _) => {
+                record_signing(AuditEntry::from_files(&result.input, &result.output, info));
+                println!("✓ {} -> {}", file, result.output.display());
+            }
+            (None, Some(e)) => eprintln!("✗ {}: {}", file, e),
+            (None, None) => {}
+        }
+    })
+}
+
+struct CertArgs {
+    subject: Option<CertificateSubject>,
+    days: u32,
+    pem: bool,
+    passphrase: Option<String>,
+    profile: Option<String>,
+}
+
+fn run_cert(args: CertArgs) -> Result<(), SigillumError> {
+    let mut store = get_profile_store(args.profile.as_deref())?;
+    let mut keypair = load_keypair(args.profile.as_deref())?;
+    if let Some(subject) = &args.subject {
+        let passphrase = key_passphrase(&keypair, args.passphrase.map(Zeroizing::new))?;
+        keypair.issue_certificate(subject, args.days, passphrase.as_deref().map(String::as_str))?;
+        store.save(&keypair)?;
+        println!("Certificate issued for profile '{}'.", store.selected_profile());
+    }
+
+    let Some(certificate) = keypair.certificate()? else {
+        return Err("No certificate yet; issue one with --common-name".into());
+    };
+    if args.pem {
+        print!("{}", certificate::to_pem(&certificate)?);
+    } else {
+        print_certificate(&certificate::info(&certificate)?, "");
+    }
+    Ok(())
+}
+
+fn print_certificate(info: &CertificateInfo, indent: &str) {
+    println!("{}Subject: {}", indent, info.subject);
+    if info.issuer != info.subject {
+        println!("{}Issuer: {}", indent, info.issuer);
+    }
+    println!("{}Fingerprint (SHA-256): {}", indent, info.fingerprint);
+    println!("{}Valid: {} to {}", indent, info.not_before, info.not_after);
+}
+
+fn run_key_storage(migrate_to: Option<KeyStorage>) -> Result<(), SigillumError> {
+    let mut store = get_key_store()?;
+    if let Some(target) = migrate_to {
+        store.migrate(target)?;
+        println!("Key storage switched to {}.", target);
+    } else {
+        println!("Key storage: {}", store.storage());
+    }
+    Ok(())
+}
+
+/// An explicit key is checked strictly; otherwise our own key and the ones it
+/// replaced, the trusted keys and the signer's certificate are all accepted.
+fn verify_options(expected_hash: Option<String>, pubkey: Option<PathBuf>) -> Result<api::VerifyOptions, SigillumError> {
+    let mut opts = api::VerifyOptions::default();
+    opts.expected_hash = expected_hash;
+    opts.tsa_roots = Settings::load(&get_app_data_dir()?)?.tsa_roots;
+    match pubkey {
+        Some(path) => {
+            let pem = fs::read_to_string(&path).map_err(|e| SigillumError::Io(format!("Failed to read public key: {}", e)))?;
+            PublicKey::from_public_key_pem(&pem)?;
+            opts.public_key = Some(pem);
+        }
+        None => {
+            let own_key = get_key_store()?.load().ok();
+            opts.retired_keys = own_key.as_ref().map(KeyPair::retired_public_keys).unwrap_or_default();
+            opts.public_key = own_key.map(|k| k.public_key);
+            opts.trusted_keys = get_trust_store()?.keys().to_vec();
+            opts.use_embedded_key = true;
+        }
+    }
+    Ok(opts)
+}
+
+/// `opts`, also fetching the keys signers publish when `fetch_key` is set.
+fn key_fetching(mut opts: api::VerifyOptions, fetch_key: bool) -> Result<api::VerifyOptions, SigillumError> {
+    if !fetch_key {
+        return Ok(opts);
+    }
+    opts.fetch_keys = true;
+    opts.key_cache_dir = Some(discovery::cache_dir(&get_app_data_dir()?));
+    Ok(opts)
+}
+
+fn run_verify(
+    file: PathBuf,
+    password: Option<String>,
+    expect_hash: Option<String>,
+    pubkey: Option<PathBuf>,
+    report_path: Option<PathBuf>,
+    fetch_key: bool,
+    format: OutputFormat,
+) -> Result<(), SigillumError> {
+    let mut opts = key_fetching(verify_options(expect_hash.clone(), pubkey)?, fetch_key)?;
+    opts.password = password.map(Zeroizing::new);
+    // Stdin cannot be seeked, so it is read whole.
+    let report = if is_std_stream(&file) {
+        let pdf = read_pdf(&file)?;
+        let report = api::verify(&pdf, opts)?;
+        if let Some(path) = &report_path {
+            save_report(path, &VerificationReport::new("(stdin)", &pdf, report.clone()))?;
+        }
+        report
+    } else {
+        let pdf_file = fs::File::open(&file).map_err(|e| SigillumError::Io(format!("Failed to read PDF: {}", e)))?;
+        let report = api::verify_reader(BufReader::new(pdf_file), opts)?;
+        if let Some(path) = &report_path {
+            save_report(path, &VerificationReport::from_file(&file, report.clone())?)?;
+        }
+        report
+    };
+    let exit_code = match report.status() {
+        _ if report.hash_matches == Some(false) => EXIT_INVALID,
+        SignatureStatus::Valid => 0,
+        SignatureStatus::Invalid => EXIT_INVALID,
+        SignatureStatus::Unverified => EXIT_UNVERIFIED,
+        SignatureStatus::Unsigned => EXIT_UNSIGNED,
+    };
+
+    if format == OutputFormat::Json {
+        print_json(&json!({
+            "file": file,
+            "signed": report.is_signed(),
+            "status": report.status(),
+            "valid": exit_code == 0,
+            "canonical_hash": report.canonical_hash,
+            "hash_matches": report.hash_matches,
+            "signatures": report.signatures,
+            "foreign_signatures": report.foreign_signatures,
+            "pgp_signatures": report.pgp_signatures,
+            "exit_code": exit_code,
+        }));
+        exit(exit_code);
+    }
+    
+    match report.hash_matches {
+        Some(true) => println!("✓ Canonical hash matches the expected hash"),
+        Some(false) => {
+            println!("✗ Canonical hash does not match the expected hash");
+            println!("Expected: {}", expect_hash.unwrap_or_default().trim());
+            println!("Actual:   {}", report.canonical_hash);
+        }
+        None => {}
+    }
+    
+    if !report.is_signed() {
+        println!("✗ PDF does not contain a digital signature");
+        println!("Canonical hash: {}", report.canonical_hash);
+        exit(exit_code);
+    }
+
+    let count = report.signatures.len();
+    for (index, signature) in report.signatures.iter().enumerate() {
+        if count > 1 {
+            println!("Signature {} of {}:", index + 1, count);
+        }
+        print_signature_report(signature, "PDF");
+        println!();
+    }
+    for signature in &report.foreign_signatures {
+        print_foreign_signature_report(signature);
+        println!();
+    }
+    for signature in &report.pgp_signatures {
+        print_pgp_signature_report(signature);
+        println!();
+    }
+    println!("Canonical hash: {}", report.canonical_hash);
+    if exit_code != 0 {
+        exit(exit_code);
+    }
+    Ok(())
+}
+
+fn run_verify_dir(dir: PathBuf, password: Option<String>, pubkey: Option<PathBuf>, fetch_key: bool, format: OutputFormat) -> Result<(), SigillumError> {
+    let mut opts = key_fetching(verify_options(None, pubkey)?, fetch_key)?;
+    opts.password = password.map(Zeroizing::new);
+
+    let mut paths = Vec::new();
+    collect_pdfs(&dir, &mut paths)?;
+    if paths.is_empty() {
+        return Err(format!("No PDF files found in {}", dir.display()).into());
+    }
+    paths.sort();
+    let total = paths.len();
+
+    let summary = api::verify_batch(&paths, &opts, |completed, result| {
+        if format == OutputFormat::Json {
+            return;
+        }
+        let file = result.path.strip_prefix(&dir).unwrap_or(&result.path).display();
+        match (result.status, &result.error) {
+            (Some(SignatureStatus::Valid), _) => println!("[{}/{}] ✓ {}", completed, total, file),
+            (Some(SignatureStatus::Invalid), _) => println!("[{}/{}] ✗ {}: invalid", completed, total, file),
+            (Some(SignatureStatus::Unverified), _) => println!("[{}/{}] ? {}: unverified", completed, total, file),
+            (Some(SignatureStatus::Unsigned), _) => println!("[{}/{}] - {}: not signed", completed, total, file),
+            (None, error) => println!("[{}/{}] ✗ {}: {}", completed, total, file, error.as_deref().unwrap_or_default()),
+        }
+    });
+
+    // The worst outcome decides, in the same order as a single document.
+    let exit_code = if summary.invalid > 0 {
+        EXIT_INVALID
+    } else if summary.failed > 0 {
+        EXIT_ERROR
+    } else if summary.unverified > 0 {
+        EXIT_UNVERIFIED
+    } else if summary.unsigned > 0 {
+        EXIT_UNSIGNED
+    } else {
+        0
+    };
+
+    if format == OutputFormat::Json {
+        print_json(&summary);
+    } else {
+        println!();
+        println!(
+            "{} PDFs: {} valid, {} invalid, {} unverified, {} unsigned, {} unreadable",
+            total, summary.valid, summary.invalid, summary.unverified, summary.unsigned, summary.failed
+        );
+    }
+    if exit_code != 0 {
+        exit(exit_code);
+    }
+    Ok(())
+}
+
+/// Adds the PDFs in `dir` and its subfolders to `paths`.
+fn collect_pdfs(dir: &Path, paths: &mut Vec<PathBuf>) -> Result<(), SigillumError> {
+    let entries = fs::read_dir(dir).map_err(|e| SigillumError::Io(format!("Failed to read directory: {}", e)))?;
+    for entry in entries {
+        let path = entry.map_err(|e| SigillumError::Io(format!("Failed to read directory: {}", e)))?.path();
+        if path.is_dir() {
+            collect_pdfs(&path, paths)?;
+        } else if path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("pdf")) {
+            paths.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn save_report(path: &Path, report: &VerificationReport) -> Result<(), SigillumError> {
+    let data = report.render(ReportFormat::for_path(path))?;
+    fs::write(path, data).map_err(|e| SigillumError::Io(format!("Failed to save report: {}", e)))
+}
+
+fn to_json(value: &impl Serialize) -> String {
+    serde_json::to_string_pretty(value).unwrap_or_else(|e| {
+        eprintln!("Error: JSON error: {}", e);
+        exit(EXIT_ERROR);
+    })
+}
+
+fn print_json(value: &impl Serialize) {
+    println!("{}", to_json(value));
+}
+
+/// In JSON mode, reports a failure as `{"error": ..., "code": ...}` on
+/// stdout too, so scripts always get a JSON document to parse.
+fn json_error(result: Result<(), SigillumError>, format: OutputFormat) -> Result<(), SigillumError> {
+    if let (Err(e), OutputFormat::Json) = (&result, format) {
+        print_json(&json!({ "error": e.message(), "code": e.code() }));
+        exit(exit_code(e));
+    }
+    result
+}
+
+fn exit_code(error: &SigillumError) -> i32 {
+    match error {
+        SigillumError::NoKey(_) => EXIT_NO_KEY,
+        SigillumError::PassphraseRequired(_) => EXIT_PASSPHRASE_REQUIRED,
+        SigillumError::WrongPassphrase(_) => EXIT_WRONG_PASSPHRASE,
+        SigillumError::InvalidPdf(_) => EXIT_INVALID_PDF,
+        SigillumError::PasswordRequired(_) => EXIT_PASSWORD_REQUIRED,
+        SigillumError::WrongPassword(_) => EXIT_WRONG_PASSWORD,
+        SigillumError::AlreadySigned(_) => EXIT_ALREADY_SIGNED,
+        SigillumError::KeyExpired(_) => EXIT_KEY_EXPIRED,
+        SigillumError::Cancelled(_) => EXIT_CANCELLED,
+        SigillumError::Io(_) => EXIT_IO,
+        SigillumError::Other(_) => EXIT_ERROR,
+    }
+}
+
+/// `subject` names what was signed, e.g. "PDF".
+fn print_signature_report(report: &api::SignatureReport, subject: &str) {
+    let info = &report.signature_info;
+    match report.status {
+        SignatureStatus::Valid => println!("✓ {} has a valid digital signature", subject),
+        SignatureStatus::Invalid => println!("✗ {} signature is INVALID", subject),
+        _ => println!("? {} has a digital signature that could not be verified", subject),
+    }
+    if let Some(reason) = &report.status_reason {
+        println!("  {}", reason);
+    }
+    match report.integrity {
+        Some(api::Integrity::Intact) => println!("  Document is intact"),
+        Some(api::Integrity::ModifiedAfterSigning) => println!("  Document was modified after signing"),
+        None => println!("  Document integrity cannot be checked (signed by an older version)"),
+    }
+    if let Some(trusted) = &report.trusted_signer {
+        println!("  Signed with the trusted key '{}'", trusted);
+    }
+    if let Some(timestamp) = &report.timestamp {
+        let untrusted = if timestamp.trusted { "" } else { " (authority not trusted)" };
+        println!("  Timestamped {} by {}{}", timestamp.time, timestamp.authority, untrusted);
+    }
+    if let Some(fingerprint) = &report.signer_fingerprint {
+        let trust = if report.key_trusted { "trusted" } else { "not trusted" };
+        println!("  Signer key ({}): {}", trust, fingerprint);
+    }
+    if let Some(certificate) = &report.certificate {
+        println!("  Signer certificate:");
+        print_certificate(certificate, "    ");
+    }
+    if let Some(discovery) = &report.key_discovery {
+        match &discovery.problem {
+            None => println!("  Key published at {} matches", discovery.url),
+            Some(problem) => println!("  Key published at {} not confirmed: {}", discovery.url, problem),
+        }
+    }
+    if let Some(discovery) = &report.domain_key {
+        match &discovery.problem {
+            None => println!("  Key listed in DNS for {}", discovery.identity),
+            Some(problem) => println!("  Key in DNS for {} not confirmed: {}", discovery.identity, problem),
+        }
+    }
+    println!();
+    println!("Signer: {}", info.signer_name);
+    println!("Timestamp: {}", info.timestamp);
+    if let Some(check) = &info.clock_check {
+        println!("Network time: {} from {} (clock off by {} ms)", check.network_time, check.server, check.skew_ms);
+    }
+    println!("Extra: {}", if info.extra.is_empty() { "(none)" } else { &info.extra });
+    if let Some(url) = &info.key_url {
+        println!("Key URL: {}", url);
+    }
+    if let Some(identity) = &info.key_domain {
+        println!("Key domain: {}", identity);
+    }
+    println!("Signature: {}", info.signature);
+    if let Some(algorithm) = info.algorithm {
+        println!("Algorithm: {}", algorithm);
+    }
+    println!("Hash algorithm: {}", info.hash_algorithm);
+    if let Some(fingerprint) = &info.key_fingerprint {
+        println!("Signed with key {}", keys::short_fingerprint(fingerprint));
+    }
+    if let Some(fingerprint) = &info.ssh_fingerprint {
+        println!("SSH key: {}", fingerprint);
+    }
+}
+
+fn print_foreign_signature_report(report: &api::ForeignSignatureReport) {
+    match report.status {
+        SignatureStatus::Valid => println!("✓ Signature field '{}' is valid", report.field_name),
+        SignatureStatus::Invalid => println!("✗ Signature field '{}' is INVALID", report.field_name),
+        _ => println!("? Signature field '{}' could not be verified", report.field_name),
+    }
+    if let Some(reason) = &report.status_reason {
+        println!("  {}", reason);
+    }
+    match report.integrity {
+        Some(api::Integrity::Intact) => println!("  Document is intact"),
+        Some(api::Integrity::ModifiedAfterSigning) => println!("  Document was modified after signing"),
+        None => println!("  Document integrity cannot be checked"),
+    }
+    if let Some(trusted) = &report.trusted_signer {
+        println!("  Signed with the trusted key '{}'", trusted);
+    }
+    if let Some(timestamp) = &report.timestamp {
+        let untrusted = if timestamp.trusted { "" } else { " (authority not trusted)" };
+        println!("  Timestamped {} by {}{}", timestamp.time, timestamp.authority, untrusted);
+    }
+    if let Some(certificate) = &report.certificate {
+        println!("  Signer certificate:");
+        print_certificate(certificate, "    ");
+    }
+    println!();
+    println!("Signer: {}", report.signer_name.as_deref().unwrap_or("(unknown)"));
+    if let Some(time) = &report.signing_time {
+        println!("Signed at: {}", time);
+    }
+    if let Some(reason) = &report.reason {
+        println!("Reason: {}", reason);
+    }
+    if let Some(location) = &report.location {
+        println!("Location: {}", location);
+    }
+    if let Some(sub_filter) = &report.sub_filter {
+        println!("Format: {}", sub_filter);
+    }
+    if let Some(algorithm) = report.hash_algorithm {
+        println!("Hash algorithm: {}", algorithm);
+    }
+    if let Some(bits) = report.key_bits {
+        println!("Key size: {} bits", bits);
+    }
+    let ranges: Vec<String> = report
+        .byte_range
+        .iter()
+        .map(|(offset, length)| format!("{}+{}", offset, length))
+        .collect();
+    let coverage = if report.covers_whole_document { "whole document" } else { "part of the document" };
+    println!("Covers: {} ({})", coverage, ranges.join(", "));
+}
+
+fn print_pgp_signature_report(report: &api::PgpSignatureReport) {
+    match report.status {
+        SignatureStatus::Valid => println!("✓ OpenPGP signature '{}' is valid", report.file_name),
+        SignatureStatus::Invalid => println!("✗ OpenPGP signature '{}' is INVALID", report.file_name),
+        _ => println!("? OpenPGP signature '{}' could not be verified", report.file_name),
+    }
+    if let Some(reason) = &report.status_reason {
+        println!("  {}", reason);
+    }
+    match report.integrity {
+        Some(api::Integrity::Intact) => println!("  Document is intact"),
+        Some(api::Integrity::ModifiedAfterSigning) => println!("  Document was modified after signing"),
+        None => println!("  Document integrity cannot be checked"),
+    }
+    println!();
+    println!("Signer: {}", report.signer.as_deref().unwrap_or("(unknown)"));
+    if let Some(time) = &report.signing_time {
+        println!("Signed at: {}", time);
+    }
+    if let Some(fingerprint) = &report.fingerprint {
+        println!("Signer key: {}", fingerprint);
+    }
+    println!("Covers: the first {} bytes", report.signed_length);
+}
+
+fn run_sign_file(
+    mut opts: api::SignOptions,
+    input: PathBuf,
+    output: Option<PathBuf>,
+    profile: Option<String>,
+) -> Result<(), SigillumError> {
+    let keypair = load_keypair(profile.as_deref())?;
+    keypair.apply_defaults(&mut opts)?;
+    check_key_expiry(&keypair)?;
+    opts.passphrase = key_passphrase(&keypair, opts.passphrase.take())?;
+    apply_settings(&mut opts)?;
+
+    let signature = api::sign_detached(&input, &opts, &keypair)?;
+    let output = output.unwrap_or_else(|| api::DetachedSignature::path_for(&input));
+    signature.save(&output)?;
+    record_signing(AuditEntry::detached(&input, &output, &signature));
+
+    println!("File signed successfully!");
+    println!("Signature: {}", output.display());
+    println!("Signer: {}", signature.signer);
+    println!("Timestamp: {}", signature.timestamp);
+    if !signature.extra.is_empty() {
+        println!("Extra: {}", signature.extra);
+    }
+    println!("Digest: {}", signature.digest);
+
+    Ok(())
+}
+
+fn run_verify_file(file: PathBuf, signature: Option<PathBuf>, pubkey: Option<PathBuf>) -> Result<(), SigillumError> {
+    let signature_path = signature.unwrap_or_else(|| {
+        let path = api::DetachedSignature::path_for(&file);
+        let pgp_path = openpgp::signature_path_for(&file);
+        if !path.exists() && pgp_path.exists() {
+            pgp_path
+        } else {
+            path
+        }
+    });
+    let text = fs::read_to_string(&signature_path).unwrap_or_default();
+    if openpgp::is_armored_signature(&text) {
+        return run_verify_openpgp(&file, &text, pubkey);
+    }
+    let signature = api::DetachedSignature::load(&signature_path)?;
+    let report = api::verify_detached(&file, &signature, verify_options(None, pubkey)?)?;
+
+    print_signature_report(&report, "File");
+    if report.status == SignatureStatus::Invalid {
+        exit(1);
+    }
+    Ok(())
+}
+
+fn run_manifest(action: ManifestAction) -> Result<(), SigillumError> {
+    match action {
+        ManifestAction::Sign {
+            files,
+            name,
+            extra,
+            output,
+            signature,
+            passphrase,
+            profile,
+            tsa_url,
+            hash_algorithm,
+        } => {
+            let keypair = load_keypair(profile.as_deref())?;
+            let mut opts = api::SignOptions::default();
+            opts.name = name.unwrap_or_default();
+            opts.extra = extra.unwrap_or_default();
+            opts.passphrase = key_passphrase(&keypair, passphrase.map(Zeroizing::new))?;
+            opts.tsa_url = tsa_url;
+            opts.hash_algorithm = hash_algorithm;
+            keypair.apply_defaults(&mut opts)?;
+            check_key_expiry(&keypair)?;
+            apply_settings(&mut opts)?;
+
+            let signature_path = signature.unwrap_or_else(|| manifest::Manifest::signature_path_for(&output));
+            let (manifest, signature) = manifest::sign_manifest(&files, &output, &signature_path, &opts, &keypair)?;
+            record_signing(AuditEntry::detached(&output, &signature_path, &signature));
+
+            println!("Manifest signed successfully!");
+            println!("Manifest: {}", output.display());
+            println!("Signature: {}", signature_path.display());
+            println!("Signer: {}", signature.signer);
+            println!("Timestamp: {}", signature.timestamp);
+            println!("Files: {}", manifest.files.len());
+            for file in &manifest.files {
+                println!("  {} ({} bytes, SHA-256 {})", file.name, file.size, file.sha256);
+            }
+            Ok(())
+        }
+        ManifestAction::Verify {
+            manifest,
+            signature,
+            dir,
+            pubkey,
+            format,
+        } => json_error(run_verify_manifest(manifest, signature, dir, pubkey, format), format),
+    }
+}
+
+fn run_verify_manifest(
+    manifest_path: PathBuf,
+    signature: Option<PathBuf>,
+    dir: Option<PathBuf>,
+    pubkey: Option<PathBuf>,
+    format: OutputFormat,
+) -> Result<(), SigillumError> {
+    let signature_path = signature.unwrap_or_else(|| manifest::Manifest::signature_path_for(&manifest_path));
+    let signature = api::DetachedSignature::load(&signature_path)?;
+    let dir = dir.unwrap_or_else(|| manifest_path.parent().map(Path::to_path_buf).unwrap_or_default());
+    let report = manifest::verify_manifest(&manifest_path, &signature, &dir, verify_options(None, pubkey)?)?;
+    let exit_code = if report.signature.status == SignatureStatus::Invalid {
+        EXIT_INVALID
+    } else if !report.passed() {
+        EXIT_ERROR
+    } else {
+        0
+    };
+
+    if format == OutputFormat::Json {
+        print_json(&report);
+        exit(exit_code);
+    }
+    print_signature_report(&report.signature, "Manifest");
+    println!();
+    println!("Files:");
+    for file in &report.files {
+        let state = match file.state {
+            manifest::FileState::Intact => "✓ intact",
+            manifest::FileState::Modified => "✗ MODIFIED",
+            manifest::FileState::Missing => "✗ MISSING",
+        };
+        println!("  {} {}", state, file.name);
+    }
+    if exit_code != 0 {
+        exit(exit_code);
+    }
+    Ok(())
+}
+
+fn run_verify_openpgp(file: &Path, armored: &str, pubkey: Option<PathBuf>) -> Result<(), SigillumError> {
+    let pem = match pubkey {
+        Some(path) => fs::read_to_string(&path).map_err(|e| SigillumError::Io(format!("Failed to read public key: {}", e)))?,
+        None => load_keypair(None)?.public_key,
+    };
+    let data = fs::File::open(file).map_err(|e| SigillumError::Io(format!("Failed to read file: {}", e)))?;
+    let report = openpgp::verify_detached(BufReader::new(data), armored, &PublicKey::from_public_key_pem(&pem)?)?;
+
+    if report.valid {
+        println!("✓ File has a valid OpenPGP signature");
+    } else {
+        println!("✗ File OpenPGP signature is INVALID");
+    }
+    if let Some(created) = report.created {
+        println!("  Made: {}", created.format("%Y-%m-%d %H:%M:%S UTC"));
+    }
+    if let Some(issuer) = &report.issuer {
+        println!("  Issuer key: {}", issuer);
+    }
+    println!("  Hash algorithm: {}", report.hash_algorithm);
+    if !report.valid {
+        exit(EXIT_ERROR);
+    }
+    Ok(())
+}
+
+fn run_unsign(input: PathBuf, output: PathBuf, password: Option<String>) -> Result<(), SigillumError> {
+    let unsigned = api::remove_signature(&read_pdf(&input)?, password.as_deref())?;
+    let signers: Vec<&str> = unsigned.removed.iter().map(|info| info.signer_name.as_str()).collect();
+
+    if is_std_stream(&output) {
+        let mut stdout = io::stdout().lock();
+        stdout
+            .write_all(&unsigned.pdf)
+            .and_then(|_| stdout.flush())
+            .map_err(|e| SigillumError::Io(format!("Failed to save PDF: {}", e)))?;
+        eprintln!("Removed the signatures of {}", signers.join(", "));
+    } else {
+        fs::write(&output, &unsigned.pdf).map_err(|e| SigillumError::Io(format!("Failed to save PDF: {}", e)))?;
+        println!("Removed the signatures of {}", signers.join(", "));
+        println!("Output: {}", output.display());
+    }
+    Ok(())
+}
+
+fn run_diff(original: PathBuf, modified: PathBuf, password: Option<String>, format: OutputFormat) -> Result<(), SigillumError> {
+    let diff = diff::diff_pdfs(&read_pdf(&original)?, &read_pdf(&modified)?, password.as_deref())?;
+    let exit_code = if diff.only_signature_changes { 0 } else { EXIT_INVALID };
+
+    if format == OutputFormat::Json {
+        print_json(&diff);
+        exit(exit_code);
+    }
+
+    if diff.only_signature_changes {
+        println!("✓ Only signing changed the document");
+    } else {
+        println!("✗ The document was changed beyond signing");
+    }
+    if diff.original_page_count != diff.modified_page_count {
+        println!("  Pages: {} -> {}", diff.original_page_count, diff.modified_page_count);
+    }
+    if !diff.original_bytes_kept {
+        println!("  The original file is not kept at the start of the modified one");
+    }
+    for (label, objects) in [
+        ("Added", &diff.added_objects),
+        ("Removed", &diff.removed_objects),
+        ("Changed", &diff.changed_objects),
+    ] {
+        let signature = objects.iter().filter(|object| object.signature_change).count();
+        println!("{} objects: {} ({} by signing)", label, objects.len(), signature);
+        for object in objects.iter().filter(|object| !object.signature_change) {
+            println!("  {} ({})", object.id, object.kind);
+        }
+    }
+    if exit_code != 0 {
+        exit(exit_code);
+    }
+    Ok(())
+}
+
+fn run_inspect(file: PathBuf, password: Option<String>, format: OutputFormat) -> Result<(), SigillumError> {
+    let info = api::inspect(&read_pdf(&file)?, password.as_deref())?;
+    if format == OutputFormat::Json {
+        print_json(&info);
+        return Ok(());
+    }
+
+    println!("PDF version: {}", info.version);
+    println!("Pages: {}", info.page_count);
+    for (number, [width, height]) in info.page_sizes.iter().enumerate() {
+        println!("  {}: {} x {} pt", number + 1, width, height);
+    }
+    match (info.encrypted, info.locked) {
+        (false, _) => println!("Encrypted: no"),
+        (true, false) => println!("Encrypted: yes"),
+        (true, true) => println!("Encrypted: yes (give --password to read its metadata and signatures)"),
+    }
+    for (label, value) in [
+        ("Title", &info.title),
+        ("Author", &info.author),
+        ("Creator", &info.creator),
+        ("Producer", &info.producer),
+    ] {
+        if let Some(value) = value {
+            println!("{}: {}", label, value);
+        }
+    }
+    println!("Sigillum signatures: {}", info.signatures.len());
+    for signature in &info.signatures {
+        println!("  {} at {}", signature.signer_name, signature.timestamp);
+    }
+    println!("Other signatures: {}", info.other_signatures);
+    Ok(())
+}
+
+/// Adds a signing to the audit log. The document is signed by now, so a
+/// failure is only warned about.
+fn record_signing(entry: Result<AuditEntry, SigillumError>) {
+    let recorded = entry.and_then(|entry| AuditLog::open(&get_app_data_dir()?).record(&entry));
+    if let Err(e) = recorded {
+        eprintln!("Warning: the signing was not recorded in the audit log: {}", e);
+    }
+}
+
+fn run_history(export: Option<PathBuf>, format: OutputFormat) -> Result<(), SigillumError> {
+    let log = AuditLog::open(&get_app_data_dir()?);
+    if let Some(destination) = export {
+        log.export(&destination)?;
+        println!("Audit log exported to {}", destination.display());
+        return Ok(());
+    }
+
+    let entries = log.entries()?;
+    if format == OutputFormat::Json {
+        print_json(&entries);
+        return Ok(());
+    }
+    if entries.is_empty() {
+        println!("Nothing has been signed yet");
+    }
+    for entry in &entries {
+        let kind = if entry.detached { " (detached)" } else { "" };
+        println!("{}  {}{}  signed by {}", entry.timestamp, entry.file_name, kind, entry.signer);
+        println!("  Input:  {}", entry.input_sha256);
+        println!("  Output: {}", entry.output_sha256);
+        println!("  Key:    {}", entry.key_fingerprint);
+    }
+    Ok(())
+}
+
+fn run_backup(output: PathBuf, passphrase: Option<String>) -> Result<(), SigillumError> {
+    let passphrase = match passphrase {
+        Some(passphrase) => Zeroizing::new(passphrase),
+        None => {
+            let first = Zeroizing::new(rpassword::prompt_password("Backup passphrase: ").map_err(|e| SigillumError::Io(format!("Failed to read passphrase: {}", e)))?);
+            let second = Zeroizing::new(rpassword::prompt_password("Repeat backup passphrase: ").map_err(|e| SigillumError::Io(format!("Failed to read passphrase: {}", e)))?);
+            if first != second {
+                return Err("Passphrases do not match".into());
+            }
+            first
+        }
+    };
+    let data = backup::backup(&get_app_data_dir()?, &passphrase)?;
+    fs::write(&output, data).map_err(|e| SigillumError::Io(format!("Failed to save backup: {}", e)))?;
+    println!("Backup saved to {}", output.display());
+    Ok(())
+}
+
+fn run_restore(input: PathBuf, passphrase: Option<String>) -> Result<(), SigillumError> {
+    let data = fs::read(&input).map_err(|e| SigillumError::Io(format!("Failed to read backup: {}", e)))?;
+    let passphrase = Zeroizing::new(match passphrase {
+        Some(passphrase) => passphrase,
+        None => rpassword::prompt_password("Backup passphrase: ").map_err(|e| SigillumError::Io(format!("Failed to read passphrase: {}", e)))?,
+    });
+    let summary = backup::restore(&get_app_data_dir()?, &data, &passphrase)?;
+
+    println!("Restored {} profile(s) and {} trusted key(s).", summary.profiles.len(), summary.trusted_keys.len());
+    for profile in &summary.skipped_profiles {
+        println!("Kept the existing profile '{}'", profile);
+    }
+    for key in &summary.skipped_trusted_keys {
+        println!("Kept the existing trusted key '{}'", key);
+    }
+    Ok(())
+}
+
+fn run_key(action: KeyAction) -> Result<(), SigillumError> {
+    match action {
+        KeyAction::Info { profile, format } => {
+            let store = get_profile_store(profile.as_deref())?;
+            let info = store.load()?.info()?;
+            if format == OutputFormat::Json {
+                print_json(&info);
+                return Ok(());
+            }
+            println!("Profile: {}", store.selected_profile());
+            println!("Version: {}", info.version);
+            println!("Algorithm: {} ({} bits)", info.algorithm, info.bits);
+            match info.created {
+                Some(created) => println!("Created: {}", created.format("%Y-%m-%d %H:%M:%S UTC")),
+                None => println!("Created: unknown"),
+            }
+            println!("Fingerprint (SHA-256): {}", info.fingerprint);
+            println!("Short fingerprint: {}", info.short_fingerprint);
+            match info.expires {
+                Some(expires) => println!("Expires: {}", expires.format("%Y-%m-%d %H:%M:%S UTC")),
+                None => println!("Expires: never"),
+            }
+            if let Some(warning) = &info.expiry_warning {
+                println!("Warning: {}", warning);
+            }
+            println!("Passphrase protected: {}", if info.encrypted { "yes" } else { "no" });
+            println!("Certificate: {}", if info.has_certificate { "issued" } else { "none" });
+            if let Ok(fingerprint) = openpgp::fingerprint(&store.load()?) {
+                println!("OpenPGP fingerprint: {}", fingerprint);
+            }
+        }
+        KeyAction::ExportPgp {
+            user_id,
+            passphrase,
+            profile,
+        } => {
+            let keypair = load_keypair(profile.as_deref())?;
+            let passphrase = key_passphrase(&keypair, passphrase.map(Zeroizing::new))?;
+            print!("{}", openpgp::public_key_block(&keypair, passphrase.as_deref().map(String::as_str), &user_id)?);
+        }
+        KeyAction::ExportPublic {
+            out,
+            format,
+            qr,
+            qr_fingerprint,
+            qr_url,
+            profile,
+            version,
+        } => {
+            let keypair = load_key_version(profile.as_deref(), version)?;
+            match &out {
+                Some(path) => {
+                    let format = format.unwrap_or_else(|| PublicKeyFormat::for_path(path));
+                    share::save_public_key(&keypair.public_key, path, format)?;
+                    println!("Public key saved to {} ({}).", path.display(), format);
+                }
+                None if format == Some(PublicKeyFormat::Der) => {
+                    return Err("DER is binary; give --out to save it to a file".into());
+                }
+                // With the key on stdout, messages go to stderr.
+                None => print!("{}", keypair.public_key),
+            }
+            if let Some(path) = qr {
+                let content = if qr_fingerprint { QrContent::Fingerprint } else { QrContent::PublicKey };
+                let png = share::public_key_qr(&keypair.public_key, content, qr_url.as_deref())?;
+                fs::write(&path, png).map_err(|e| SigillumError::Io(format!("Failed to save QR code: {}", e)))?;
+                let message = format!("QR code saved to {}.", path.display());
+                if out.is_some() {
+                    println!("{}", message);
+                } else {
+                    eprintln!("{}", message);
+                }
+            }
+        }
+        KeyAction::ExportId { name, out, profile } => {
+            let keypair = load_keypair(profile.as_deref())?;
+            let name = name
+                .or_else(|| keypair.defaults.signer_name.clone())
+                .ok_or("Give --name, or set the profile's signer name")?;
+            let identity = Identity::new(&keypair, &name)?;
+            let path = out.unwrap_or_else(|| PathBuf::from(identity.file_name()));
+            fs::write(&path, identity.to_json()?).map_err(|e| SigillumError::Io(format!("Failed to save identity: {}", e)))?;
+            println!("Identity of '{}' saved to {}.", identity.name, path.display());
+            println!("Fingerprint: {}", identity.fingerprint);
+        }
+        KeyAction::DnsRecord { domain, profile } => {
+            let keypair = load_keypair(profile.as_deref())?;
+            let domain = domain
+                .or_else(|| keypair.defaults.key_domain.clone())
+                .ok_or("Give --domain, or set the profile's key domain")?;
+            let key = PublicKey::from_public_key_pem(&keypair.public_key)?;
+            println!("{}. IN TXT \"{}\"", discovery::dns_record_name(&domain)?, discovery::dns_record(&key)?);
+        }
+        KeyAction::Rotate {
+            algorithm,
+            bits,
+            valid_days,
+            profile,
+        } => {
+            let mut store = get_profile_store(profile.as_deref())?;
+            let algorithm = match algorithm {
+                Some(algorithm) => algorithm,
+                None => store.load()?.algorithm,
+            };
+            let algorithm = match bits {
+                Some(bits) => algorithm.with_bits(bits)?,
+                None => algorithm,
+            };
+            let mut keypair = KeyPair::generate(algorithm)?;
+            keypair.set_validity(valid_days);
+            let version = store.rotate(keypair)?;
+            println!(
+                "Profile '{}' now signs with a new {} key (version {}); version {} is retired.",
+                store.selected_profile(),
+                algorithm,
+                version,
+                version - 1
+            );
+        }
+        KeyAction::Expiry {
+            days,
+            never,
+            policy,
+            profile,
+        } => {
+            let mut store = get_profile_store(profile.as_deref())?;
+            let mut keypair = store.load()?;
+            if days.is_some() || never {
+                keypair.set_validity(days);
+                store.save(&keypair)?;
+            }
+            let dir = get_app_data_dir()?;
+            let mut settings = Settings::load(&dir)?;
+            if let Some(policy) = policy {
+                settings.expired_key_policy = policy;
+                settings.save(&dir)?;
+            }
+            match keypair.expires {
+                Some(expires) => println!("Expires: {}", expires.format("%Y-%m-%d %H:%M:%S UTC")),
+                None => println!("Expires: never"),
+            }
+            if let Some(warning) = keypair.expiry_warning() {
+                println!("Warning: {}", warning);
+            }
+            println!(
+                "Signing with an expired key: {}",
+                match settings.expired_key_policy {
+                    ExpiredKeyPolicy::Refuse => "refused",
+                    ExpiredKeyPolicy::Warn => "allowed, with a warning",
+                }
+            );
+        }
+        KeyAction::Versions { profile, format } => {
+            let versions = load_keypair(profile.as_deref())?.versions()?;
+            if format == OutputFormat::Json {
+                print_json(&versions);
+                return Ok(());
+            }
+            for info in versions {
+                let created = info.created.map_or("unknown".to_string(), |created| created.format("%Y-%m-%d").to_string());
+                match info.retired {
+                    Some(retired) => println!(
+                        "{}  {}  created {}, retired {}  {}",
+                        info.version,
+                        info.algorithm,
+                        created,
+                        retired.format("%Y-%m-%d"),
+                        info.short_fingerprint
+                    ),
+                    None => println!("{}  {}  created {}, current  {}", info.version, info.algorithm, created, info.short_fingerprint),
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn run_profile(action: ProfileAction) -> Result<(), SigillumError> {
+    let mut store = get_key_store()?;
+    match action {
+        ProfileAction::List => {
+            let active = store.active_profile().to_string();
+            for profile in store.profiles().to_vec() {
+                store.select(&profile)?;
+                let marker = if profile == active { "*" } else { " " };
+                match store.load() {
+                    Ok(KeyPair { algorithm, defaults, .. }) => match defaults.signer_name {
+                        Some(signer_name) => println!("{} {} ({}) signs as '{}'", marker, profile, algorithm, signer_name),
+                        None => println!("{} {} ({})", marker, profile, algorithm),
+                    },
+                    Err(_) => println!("{} {} (no key)", marker, profile),
+                }
+            }
+        }
+        ProfileAction::Create { name, algorithm } => {
+            let keypair = KeyPair::generate(algorithm)?;
+            store.create_profile(&name, &keypair)?;
+            println!("Created profile '{}' with a new {} key.", name, algorithm);
+        }
+        ProfileAction::Rename { name, new_name } => {
+            store.rename_profile(&name, &new_name)?;
+            println!("Renamed profile '{}' to '{}'.", name, new_name);
+        }
+        ProfileAction::Delete { name } => {
+            store.delete_profile(&name)?;
+            println!("Deleted profile '{}'.", name);
+        }
+        ProfileAction::Use { name } => {
+            store.set_active_profile(&name)?;
+            println!("Now signing with profile '{}'.", name);
+        }
+        ProfileAction::Set {
+            profile,
+            signer_name,
+            extra,
+            key_url,
+            key_domain,
+        } => {
+            if let Some(profile) = &profile {
+                store.select(profile)?;
+            }
+            // Only what is given changes; an empty value clears the default.
+            let mut defaults = store.load()?.defaults;
+            if let Some(signer_name) = signer_name {
+                defaults.signer_name = Some(signer_name).filter(|name| !name.trim().is_empty());
+            }
+            if let Some(extra) = extra {
+                defaults.extra = Some(extra).filter(|extra| !extra.is_empty());
+            }
+            if let Some(key_url) = key_url {
+                defaults.key_url = Some(key_url);
+            }
+            if let Some(key_domain) = key_domain {
+                defaults.key_domain = Some(key_domain);
+            }
+            store.set_defaults(defaults)?;
+            println!("Updated the defaults of profile '{}'.", store.selected_profile());
+        }
+    }
+    Ok(())
+}
+
+fn run_trust(action: TrustAction) -> Result<(), SigillumError> {
+    let mut store = get_trust_store()?;
+    match action {
+        TrustAction::Add { name, key } => {
+            let pem = fs::read_to_string(&key).map_err(|e| SigillumError::Io(format!("Failed to read public key: {}", e)))?;
+            let trusted = store.add(&name, &pem)?;
+            println!("Now trusting {} key '{}'.", trusted.algorithm, trusted.name);
+        }
+        TrustAction::ImportId { file, name } => {
+            let data = fs::read(&file).map_err(|e| SigillumError::Io(format!("Failed to read identity: {}", e)))?;
+            let identity = Identity::from_json(&data)?;
+            let trusted = store.add(name.as_deref().unwrap_or(&identity.name), &identity.public_key)?;
+            println!("Now trusting {} key '{}'.", trusted.algorithm, trusted.name);
+            println!("Fingerprint: {}", identity.fingerprint);
+            println!("Check it with {} before relying on their signatures.", identity.name);
+        }
+        TrustAction::Remove { name } => {
+            store.remove(&name)?;
+            println!("Removed trusted key '{}'.", name);
+        }
+        TrustAction::Revoke { name, reason, date } => {
+            let date = date.as_deref().map(trust_store::parse_revocation_date).transpose()?;
+            let revoked = store.revoke(&name, &reason, date)?;
+            if let Some(revocation) = &revoked.revoked {
+                println!("Revoked trusted key '{}' as of {}.", name, revocation.date.format("%Y-%m-%d %H:%M:%S UTC"));
+            }
+        }
+        TrustAction::List => {
+            if store.keys().is_empty() {
+                println!("No trusted keys.");
+            }
+            for key in store.keys() {
+                match &key.revoked {
+                    Some(revocation) => println!("{} ({}), {}", key.name, key.algorithm, revocation),
+                    None => println!("{} ({})", key.name, key.algorithm),
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn main() {
+    let cli = Cli::parse();
+    if let Some(path) = &cli.passphrase_file {
+        match fs::read_to_string(path) {
+            Ok(contents) => {
+                let contents = Zeroizing::new(contents);
+                let passphrase = contents.strip_suffix('\n').unwrap_or(&contents);
+                let passphrase = passphrase.strip_suffix('\r').unwrap_or(passphrase);
+                let _ = PASSPHRASE_FILE.set(Zeroizing::new(passphrase.to_string()));
+            }
+            Err(e) => {
+                eprintln!("Error: Failed to read passphrase file: {}", e);
+                exit(EXIT_IO);
+            }
+        }
+    }
+    
+    let result = match cli.command {
+        Some(Commands::Keygen {
+            algorithm,
+            bits,
+            tpm,
+            pin,
+            secure_enclave,
+            valid_days,
+            profile,
+        }) => run_keygen(algorithm, bits, tpm, pin, secure_enclave, valid_days, profile),
+        Some(Commands::Import { shares, profile, .. }) if !shares.is_empty() => run_import_shares(shares, profile),
+        Some(Commands::Import {
+            phrase: true,
+            algorithm,
+            profile,
+            ..
+        }) => run_import_phrase(algorithm, profile),
+        Some(Commands::Import {
+            key,
+            token,
+            device,
+            ssh_agent,
+            kms,
+            passphrase,
+            profile,
+            ..
+        }) => run_import(key, token, device, ssh_agent, kms, passphrase, profile),
+        Some(Commands::Devices {
+            pkcs11_module,
+            ssh_agent,
+            cert_store,
+            pin,
+            format,
+        }) => match pkcs11_module {
+            Some(module) => run_pkcs11_devices(&module, pin.as_deref(), format).map(|_| "".to_string()),
+            None if ssh_agent => run_ssh_agent_keys(format).map(|_| "".to_string()),
+            None if cert_store => run_cert_store_certificates(format).map(|_| "".to_string()),
+            None => run_devices(format).map(|_| "".to_string()),
+        },
+        Some(Commands::Export {
+            passphrase,
+            pkcs12,
+            pkcs12_password,
+            shamir,
+            phrase,
+            out_dir,
+            profile,
+            version,
+        }) => match (pkcs12, shamir) {
+            (Some(path), _) => run_export_pkcs12(path, passphrase, pkcs12_password, profile, version).map(|_| "".to_string()),
+            (None, Some(scheme)) => run_export_shares(scheme, &out_dir, passphrase, profile, version).map(|_| "".to_string()),
+            (None, None) if phrase => run_export_phrase(passphrase, profile, version).map(|_| "".to_string()),
+            (None, None) => run_export(passphrase, profile, version).map(|_| "".to_string()),
+        },
+        Some(Commands::Passphrase { remove }) => run_passphrase(remove).map(|_| "".to_string()),
+        Some(Commands::Cert {
+            common_name,
+            organization,
+            email,
+            days,
+            pem,
+            passphrase,
+            profile,
+        }) => {
+            let subject = common_name.map(|common_name| CertificateSubject {
+                common_name,
+                organization,
+                email,
+            });
+            run_cert(CertArgs {
+                subject,
+                days,
+                pem,
+                passphrase,
+                profile,
+            })
+            .map(|_| "".to_string())
+        }
+        Some(Commands::KeyStorage { migrate_to }) => run_key_storage(migrate_to).map(|_| "".to_string()),
+        Some(Commands::Sign {
+            sign,
+            input,
+            output,
+            input_dir,
+            output_dir,
+            pgp,
+            gpg_key,
+            format,
+        }) => {
+            let result = sign.into_options().and_then(|(opts, source)| {
+                match (input_dir, output_dir, input, output) {
+                    (Some(input_dir), Some(output_dir), _, _) => {
+                        run_sign_batch(opts, input_dir, output_dir, source, format)
+                    }
+                    (_, _, Some(input), Some(output)) => {
+                        run_sign(opts, input, output, pgp, gpg_key.as_deref(), source, format)
+                    }
+                    _ => Err("Give either --input and --output, or --input-dir and --output-dir".into()),
+                }
+            });
+            json_error(result, format).map(|_| "".to_string())
+        }
+        Some(Commands::Watch {
+            sign,
+            input_dir,
+            output_dir,
+        }) => sign
+            .into_options()
+            .and_then(|(opts, source)| run_watch(opts, input_dir, output_dir, source))
+            .map(|_| "".to_string()),
+        Some(Commands::Verify {
+            file,
+            dir,
+            password,
+            format,
+            expect_hash,
+            pubkey,
+            report,
+            fetch_key,
+        }) => {
+            let result = match (dir, file) {
+                (Some(dir), _) => run_verify_dir(dir, password, pubkey, fetch_key, format),
+                (None, Some(file)) => run_verify(file, password, expect_hash, pubkey, report, fetch_key, format),
+                (None, None) => Err("Either --file or --dir is required".into()),
+            };
+            json_error(result, format).map(|_| "".to_string())
+        }
+        Some(Commands::SignFile {
+            name,
+            extra,
+            input,
+            output,
+            passphrase,
+            profile,
+            tsa_url,
+            hash_algorithm,
+        }) => {
+            let mut opts = api::SignOptions::default();
+            opts.name = name.unwrap_or_default();
+            opts.extra = extra.unwrap_or_default();
+            opts.passphrase = passphrase.map(Zeroizing::new);
+            opts.tsa_url = tsa_url;
+            opts.hash_algorithm = hash_algorithm;
+            run_sign_file(opts, input, output, profile).map(|_| "".to_string())
+        }
+        Some(Commands::VerifyFile { file, signature, pubkey }) => {
+            run_verify_file(file, signature, pubkey).map(|_| "".to_string())
+        }
+        Some(Commands::Diff {
+            original,
+            modified,
+            password,
+            format,
+        }) => json_error(run_diff(original, modified, password, format), format).map(|_| "".to_string()),
+        Some(Commands::Unsign { input, output, password }) => run_unsign(input, output, password).map(|_| "".to_string()),
+        Some(Commands::Inspect { file, password, format }) => {
+            json_error(run_inspect(file, password, format), format).map(|_| "".to_string())
         }
-        Some(Commands::Verify { file }) => {
-            run_verify(file).map(|_| "".to_string())
+        Some(Commands::Manifest { action }) => run_manifest(action).map(|_| "".to_string()),
+        Some(Commands::Key { action }) => run_key(action).map(|_| "".to_string()),
+        Some(Commands::Profile { action }) => run_profile(action).map(|_| "".to_string()),
+        Some(Commands::Trust { action }) => run_trust(action).map(|_| "".to_string()),
+        Some(Commands::Backup { output, passphrase }) => run_backup(output, passphrase).map(|_| "".to_string()),
+        Some(Commands::Restore { input, passphrase }) => run_restore(input, passphrase).map(|_| "".to_string()),
+        Some(Commands::History { export, format }) => {
+            json_error(run_history(export, format), format).map(|_| "".to_string())
         }
         None => {
             sigillum_lib::run();
@@ -208,7 +2965,7 @@ fn main() {
         Ok(_) => {}
         Err(e) => {
             eprintln!("Error: {}", e);
-            exit(1);
+            exit(exit_code(&e));
         }
     }
 }
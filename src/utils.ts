@@ -1,3 +1,7 @@
+import { getCurrentWebview } from "@tauri-apps/api/webview";
+import { open } from "@tauri-apps/plugin-dialog";
+import { stat } from "@tauri-apps/plugin-fs";
+
 export function formatFileSize(bytes: number): string {
   if (bytes < 1024) return bytes + " B";
   if (bytes < 1024 * 1024) return (bytes / 1024).toFixed(1) + " KB";
@@ -56,15 +60,48 @@ export function toggleDropZoneContent(dropZone: HTMLElement, showContent: boolea
   if (content) content.classList.toggle("hidden", !showContent);
 }
 
+/**
+ * A PDF picked to sign or verify. One chosen in the open dialog or dropped on
+ * the window comes with its `path`, so the Rust side reads it from disk rather
+ * than taking its bytes over IPC; only a file the webview hands over without a
+ * path is kept as a `File`.
+ */
+export type PdfSource = { name: string; path: string } | { name: string; file: File };
+
+const isPdf = (name: string) => /\.pdf$/i.test(name);
+
+const fileName = (path: string) => path.split(/[\\/]/).pop() ?? path;
+
 export function setupDropZone(
   dropZone: HTMLElement,
-  fileInput: HTMLInputElement,
-  onFileSelect: (file: File) => void,
+  onFileSelect: (source: PdfSource) => void,
   onFileInfo: HTMLElement | null,
   fileNameEl: HTMLElement | null,
   fileSizeEl: HTMLElement | null
 ) {
-  dropZone.addEventListener("click", () => fileInput.click());
+  const select = (source: PdfSource, size: Promise<number | null>) =>
+    handleFileSelect(source, size, onFileSelect, onFileInfo, fileNameEl, fileSizeEl);
+  const selectPath = (path: string) =>
+    select({ name: fileName(path), path }, stat(path).then((info) => info.size, () => null));
+
+  dropZone.addEventListener("click", async () => {
+    const path = await open({ multiple: false, filters: [{ name: "PDF", extensions: ["pdf"] }] });
+    if (path) selectPath(path);
+  });
+
+  // Tauri takes files dropped on the window and reports their paths; the
+  // webview's own drop event only sees them where that is turned off.
+  const over = (position: { x: number; y: number }) => {
+    const target = document.elementFromPoint(position.x / window.devicePixelRatio, position.y / window.devicePixelRatio);
+    return target !== null && dropZone.contains(target);
+  };
+  getCurrentWebview().onDragDropEvent(({ payload }) => {
+    const hovered = (payload.type === "enter" || payload.type === "over") && over(payload.position);
+    dropZone.classList.toggle("drag-over", hovered);
+    if (payload.type === "drop" && over(payload.position) && payload.paths.length > 0) {
+      selectPath(payload.paths[0]);
+    }
+  });
 
   dropZone.addEventListener("dragover", (e) => {
     e.preventDefault();
@@ -78,35 +115,50 @@ export function setupDropZone(
   dropZone.addEventListener("drop", (e) => {
     e.preventDefault();
     dropZone.classList.remove("drag-over");
-    const files = e.dataTransfer?.files;
-    if (files && files.length > 0) {
-      handleFileSelect(files[0], onFileSelect, onFileInfo, fileNameEl, fileSizeEl);
-    }
-  });
-
-  fileInput.addEventListener("change", () => {
-    if (fileInput.files && fileInput.files.length > 0) {
-      handleFileSelect(fileInput.files[0], onFileSelect, onFileInfo, fileNameEl, fileSizeEl);
-    }
+    const file = e.dataTransfer?.files[0];
+    if (file) select({ name: file.name, file }, Promise.resolve(file.size));
   });
 }
 
-function handleFileSelect(
-  file: File,
-  onFileSelect: (file: File) => void,
+async function handleFileSelect(
+  source: PdfSource,
+  size: Promise<number | null>,
+  onFileSelect: (source: PdfSource) => void,
   onFileInfo: HTMLElement | null,
   fileNameEl: HTMLElement | null,
   fileSizeEl: HTMLElement | null
 ) {
-  if (!file.type.includes("pdf")) {
+  if (!isPdf(source.name)) {
     return;
   }
-  onFileSelect(file);
+  onFileSelect(source);
   if (onFileInfo) onFileInfo.classList.remove("hidden");
-  if (fileNameEl) fileNameEl.textContent = file.name;
-  if (fileSizeEl) fileSizeEl.textContent = formatFileSize(file.size);
+  if (fileNameEl) fileNameEl.textContent = source.name;
+  if (fileSizeEl) fileSizeEl.textContent = "";
   const dropZone = onFileInfo?.parentElement as HTMLElement | null;
   if (dropZone) toggleDropZoneContent(dropZone, false);
+  const bytes = await size;
+  if (fileSizeEl && bytes !== null && fileNameEl?.textContent === source.name) {
+    fileSizeEl.textContent = formatFileSize(bytes);
+  }
+}
+
+/** A failure reported by a command, with a code that stays the same across releases. */
+export interface CommandError {
+  code: string;
+  message: string;
+}
+
+function isCommandError(error: unknown): error is CommandError {
+  return typeof error === "object" && error !== null && "code" in error && "message" in error;
+}
+
+export function errorMessage(error: unknown): string {
+  return isCommandError(error) ? error.message : String(error);
+}
+
+export function errorCode(error: unknown): string | null {
+  return isCommandError(error) ? error.code : null;
 }
 
 export function showTemporarySuccess(